@@ -0,0 +1,17 @@
+//! Payloads always have the report header in front, the value little-endian
+//! and zero padding up to the requested length.
+
+#![no_main]
+
+use hyperx_pilot_lib::fuzzing;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: (u8, u8, u16, u8)| {
+    let (report_id, selector, value, length) = data;
+    let length = usize::from(length);
+    let payload = fuzzing::build_feature_payload(report_id, selector, value, length);
+
+    assert_eq!(payload.len(), length.max(4));
+    assert_eq!(payload[..4], [report_id, selector, value as u8, (value >> 8) as u8]);
+    assert!(payload[4..].iter().all(|&byte| byte == 0));
+});
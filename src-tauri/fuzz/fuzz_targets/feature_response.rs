@@ -0,0 +1,24 @@
+//! Malformed or truncated responses are rejected or decoded, never panic,
+//! and percentages derived from them stay in range.
+
+#![no_main]
+
+use hyperx_pilot_lib::fuzzing;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: (u8, u8, &[u8])| {
+    let (report_id, selector, response) = data;
+    let Some(value) = fuzzing::parse_response(report_id, selector, response) else {
+        return;
+    };
+    assert!(fuzzing::percent(value) <= 100);
+
+    // A payload we built must decode to the value it carries.
+    let payload = fuzzing::build_feature_payload(report_id, selector, value, response.len());
+    if report_id != selector {
+        assert_eq!(
+            fuzzing::parse_response(report_id, selector, &payload),
+            Some(value)
+        );
+    }
+});
@@ -0,0 +1,14 @@
+//! Arbitrary report descriptors, as returned by a misbehaving device, must
+//! not panic the descriptor parser.
+
+#![no_main]
+
+use hyperx_pilot_lib::fuzzing;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|descriptor: &[u8]| {
+    for length in fuzzing::feature_lengths(descriptor).into_values() {
+        // At most u32::MAX bits, plus the report ID byte.
+        assert!(length <= (u32::MAX as usize).div_ceil(8) + 1);
+    }
+});
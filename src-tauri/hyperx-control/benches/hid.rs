@@ -0,0 +1,112 @@
+//! Costs of the HID operations: enumeration, opening a device and feature
+//! report round trips. By default they run against a mock device that
+//! answers every report at once, which measures the crate's own overhead;
+//! with `HYPERX_BENCH_HARDWARE=1` they measure the first plugged-in headset
+//! instead, including the OS and the firmware. Hardware runs only read, so
+//! they leave the headset's settings alone.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use hidapi::HidError;
+use hyperx_control::mock::{self, MockBackend};
+use hyperx_control::{self as hyperx, DeviceId, FeatureKind};
+use std::env;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Set to measure the plugged-in headset instead of the mock.
+const HARDWARE_VAR: &str = "HYPERX_BENCH_HARDWARE";
+/// How long each benchmark runs after warming up; every iteration is a
+/// whole HID operation, which takes milliseconds on hardware.
+const MEASURE: Duration = Duration::from_secs(2);
+
+/// A device that answers every read with the last report sent to it, so
+/// writes read back their own value and succeed.
+struct Echo {
+    device_id: DeviceId,
+    last: Mutex<Vec<u8>>,
+}
+
+impl MockBackend for Echo {
+    fn connected(&self) -> Vec<DeviceId> {
+        vec![self.device_id]
+    }
+
+    fn send_feature_report(&self, _device_id: DeviceId, data: &[u8]) -> Result<(), HidError> {
+        *self.last.lock().unwrap() = data.to_vec();
+        Ok(())
+    }
+
+    fn get_feature_report(
+        &self,
+        _device_id: DeviceId,
+        buffer: &mut [u8],
+    ) -> Result<usize, HidError> {
+        let last = self.last.lock().unwrap();
+        let length = last.len().min(buffer.len());
+        buffer[..length].copy_from_slice(&last[..length]);
+        Ok(length)
+    }
+}
+
+fn hid(c: &mut Criterion) {
+    let hardware = env::var_os(HARDWARE_VAR).is_some();
+    let device_id = if hardware {
+        let Some(&device_id) = hyperx::connected_devices().unwrap_or_default().first() else {
+            eprintln!("no supported headset is plugged in");
+            return;
+        };
+        device_id
+    } else {
+        let device_id = hyperx::supported_devices()
+            .iter()
+            .map(|device| device.id)
+            .find(|&id| hyperx::devices().get(id).supports(FeatureKind::Sidetone))
+            .expect("a model with sidetone");
+        let echo = Box::leak(Box::new(Echo {
+            device_id,
+            last: Mutex::new(Vec::new()),
+        }));
+        mock::install(echo);
+        device_id
+    };
+    println!(
+        "{device_id} ({})",
+        if hardware { "hardware" } else { "mock" }
+    );
+
+    let device = hyperx::devices().get(device_id);
+    let mut group = c.benchmark_group(if hardware { "hardware" } else { "mock" });
+    group.bench_function("enumerate", |b| {
+        b.iter(|| {
+            hyperx::refresh_devices()?;
+            hyperx::connected_devices()
+        })
+    });
+    group.bench_function("open + read_sidetone", |b| {
+        b.iter(|| {
+            hyperx::devices().release(device_id);
+            device.read_sidetone_state()
+        })
+    });
+    group.bench_function("read_sidetone", |b| b.iter(|| device.read_sidetone_state()));
+    group.bench_function("read_firmware_version", |b| {
+        b.iter(|| device.read_firmware_version(hyperx::FirmwareTarget::Headset))
+    });
+    if !hardware {
+        let mut enabled = false;
+        group.bench_function("set_sidetone", |b| {
+            b.iter(|| {
+                enabled = !enabled;
+                device.set_sidetone(enabled)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().measurement_time(MEASURE);
+    targets = hid
+}
+criterion_main!(benches);
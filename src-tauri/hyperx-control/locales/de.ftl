@@ -0,0 +1,45 @@
+# Meldungen von ControlError und OpenDiagnosis, siehe src/i18n.rs, in
+# Fluent-Syntax (https://projectfluent.org); `{ $name }` setzt der Code ein.
+
+## Fehler
+
+error-hid-init = HID-API konnte nicht initialisiert werden: { $source }
+error-device-open = Gerät konnte nicht geöffnet werden (VID={ $vendor_id }, PID={ $product_id }): { $reason }
+error-report-send = Feature-Report konnte nicht gesendet werden (ID={ $report_id }, Selektor={ $selector }): { $source }
+error-report-read = Feature-Report konnte nicht gelesen werden (ID={ $report_id }, Selektor={ $selector }): { $source }
+error-unsupported-feature = Gerät { $device_id } unterstützt { $capability } nicht
+error-read-only-feature = { $feature } eines Headsets lässt sich nicht einstellen
+error-rejected = Das Gerät hat den Wert abgelehnt (ID={ $report_id }, Selektor={ $selector }, Status={ $status })
+error-not-applied = Das Gerät hat den Wert nicht übernommen (ID={ $report_id }, Selektor={ $selector }): es meldet { $actual } statt { $expected }
+error-device-busy = Die Einstellung wurde nicht übernommen, weil { $process } das Gerät steuert
+error-invalid-report = Ungültiger Feature-Report: { $reason }
+error-unsupported-button-action = Die Taste { $button } lässt sich nicht auf { $action } stellen
+error-flashing = { $device_id } wird gerade geflasht; versuche es nach dem Firmware-Update erneut
+error-unreadable = { $device_id } hat { $feature } nicht gemeldet, daher wurde nichts geändert
+
+remedy-device-busy = Beende { $process } (auch Tray-Symbol und Hintergrunddienst), damit es HyperX Pilot nicht mehr überschreibt, und versuche es erneut.
+hint-libusb = Bleiben die hidraw-Geräte unzugänglich, greift ein Build mit dem Feature `libusb` stattdessen über /dev/bus/usb auf das Headset zu.
+
+## Warum ein Gerät nicht geöffnet werden konnte
+
+diagnosis-not-connected = Gerät ist nicht verbunden
+diagnosis-missing-udev-rule = Zugriff auf { $node } verweigert; keine udev-Regel installiert
+diagnosis-not-in-group = Zugriff auf { $node } verweigert; Benutzer ist nicht in der Gruppe '{ $group }'
+diagnosis-reconnect-required = Zugriff auf { $node } trotz installierter udev-Regeln verweigert
+diagnosis-sandbox-restricted = Die { $sandbox }-Sandbox gibt keine HID-Geräte frei
+diagnosis-input-monitoring-denied = Die Berechtigung für Eingabeüberwachung wurde nicht erteilt
+diagnosis-driver-missing = Das Headset ist verbunden, aber seine Steuerschnittstelle hat keinen Treiber
+diagnosis-device-busy = Gerät wird von { $process } verwendet
+diagnosis-device-busy-unknown = Gerät wird von einem anderen Prozess verwendet
+diagnosis-unknown = Unbekannte Ursache
+
+remedy-not-connected = Schließe das Headset per USB an und stelle sicher, dass es eingeschaltet ist.
+remedy-missing-udev-rule = Installiere die udev-Regeln von HyperX Pilot, dann trenne das Headset und schließe es wieder an.
+remedy-not-in-group = Füge deinen Benutzer der Gruppe '{ $group }' hinzu (z. B. `sudo usermod -aG { $group } $USER`) und melde dich neu an.
+remedy-reconnect-required = Trenne das Headset und schließe es wieder an, damit die installierten udev-Regeln greifen.
+remedy-sandbox-restricted = Erlaube dem { $sandbox }-Paket den Gerätezugriff, indem du `{ $grant_command }` auf dem Host ausführst, und starte die App neu.
+remedy-input-monitoring-denied = Erlaube HyperX Pilot unter Systemeinstellungen → Datenschutz & Sicherheit → Eingabeüberwachung und starte die App neu.
+remedy-driver-missing = Installiere HPs Treiberpaket für dieses Headset (im NGENUITY-Installer enthalten, oder lass Windows Update nach { $hardware_ids } suchen) und schließe das Headset wieder an.
+remedy-device-busy-process = Schließe { $process }, das das Headset verwendet, und versuche es erneut.
+remedy-device-busy-unknown = Schließe andere Headset-Software (etwa NGENUITY), die das Gerät verwenden könnte.
+remedy-unknown = Schließe das Headset erneut an und versuche es noch einmal.
@@ -0,0 +1,45 @@
+# Messages of ControlError and OpenDiagnosis, see src/i18n.rs, in Fluent
+# syntax (https://projectfluent.org); `{ $name }` is filled in by the code.
+
+## Errors
+
+error-hid-init = failed to initialise HID API: { $source }
+error-device-open = unable to open device (VID={ $vendor_id }, PID={ $product_id }): { $reason }
+error-report-send = failed to send feature report (id={ $report_id }, selector={ $selector }): { $source }
+error-report-read = failed to read feature report (id={ $report_id }, selector={ $selector }): { $source }
+error-unsupported-feature = device { $device_id } does not support { $capability }
+error-read-only-feature = the { $feature } of a headset cannot be set
+error-rejected = the device rejected the value (id={ $report_id }, selector={ $selector }, status={ $status })
+error-not-applied = the device did not apply the value (id={ $report_id }, selector={ $selector }): it reads back { $actual } instead of { $expected }
+error-device-busy = the setting was not applied because { $process } controls the device
+error-invalid-report = invalid feature report: { $reason }
+error-unsupported-button-action = the { $button } button cannot be set to { $action }
+error-flashing = { $device_id } is being flashed; try again when the firmware update is done
+error-unreadable = { $device_id } did not report its { $feature }, so nothing was changed
+
+remedy-device-busy = Quit { $process } (including its tray icon and background service) so it stops overriding HyperX Pilot, then try again.
+hint-libusb = If hidraw nodes stay inaccessible, a build with the `libusb` feature accesses the headset through /dev/bus/usb instead.
+
+## Why a device could not be opened
+
+diagnosis-not-connected = device is not connected
+diagnosis-missing-udev-rule = permission denied on { $node }; no udev rule is installed
+diagnosis-not-in-group = permission denied on { $node }; user is not in group '{ $group }'
+diagnosis-reconnect-required = permission denied on { $node } despite installed udev rules
+diagnosis-sandbox-restricted = the { $sandbox } sandbox does not expose HID devices
+diagnosis-input-monitoring-denied = Input Monitoring permission has not been granted
+diagnosis-driver-missing = the headset is connected but its control interface has no driver
+diagnosis-device-busy = device is in use by { $process }
+diagnosis-device-busy-unknown = device is in use by another process
+diagnosis-unknown = unknown cause
+
+remedy-not-connected = Connect the headset via USB and make sure it is switched on.
+remedy-missing-udev-rule = Install the HyperX Pilot udev rules, then unplug and reconnect the headset.
+remedy-not-in-group = Add your user to the '{ $group }' group (e.g. `sudo usermod -aG { $group } $USER`) and log in again.
+remedy-reconnect-required = Unplug and reconnect the headset so the installed udev rules take effect.
+remedy-sandbox-restricted = Grant the { $sandbox } package device access by running `{ $grant_command }` on the host, then restart the app.
+remedy-input-monitoring-denied = Allow HyperX Pilot under System Settings → Privacy & Security → Input Monitoring, then restart the app.
+remedy-driver-missing = Install HP's driver package for this headset (NGENUITY's installer includes it, or let Windows Update search for { $hardware_ids }), then reconnect the headset.
+remedy-device-busy-process = Close { $process }, which is using the headset, then try again.
+remedy-device-busy-unknown = Close other headset software (such as NGENUITY) that may be using the device.
+remedy-unknown = Reconnect the headset and try again.
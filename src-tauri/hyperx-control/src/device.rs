@@ -0,0 +1,1438 @@
+//! Talking to a device: the shared HID context, finding and opening the
+//! control interface of each unit, and the [`Device`] that runs one
+//! operation at a time over handles kept open between them.
+
+use crate::dfu::dfu_protocol;
+use crate::features::{
+    anc_level, boom_position, build_feature_payload, features, pairings_feature, parse_response,
+    percent, validate_feature, wireless_mode, write_status, BoomPosition, Capability, FeatureKind,
+    FirmwareVersion, ReportBuffer, WirelessMode,
+};
+use crate::mock::{self, MockBackend};
+use crate::{
+    find_descriptor, host, metrics, recorder, report_descriptor, ControlError, DeviceDescriptor,
+    DeviceId, DeviceMetadata, FeatureReport, OpenDiagnosis, DEVICE_CATALOG, MIN_PAYLOAD_LENGTH,
+    REPORT_LENGTH, SIMULATED_CATALOG,
+};
+use hidapi::{DeviceInfo, HidApi, HidDevice};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ffi::CString;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, trace};
+
+/// USB vendor IDs HyperX headsets enumerate under: HP's, and Kingston's from
+/// before HP bought HyperX.
+const VENDOR_IDS: &[u16] = &[0x03F0, 0x0951];
+
+/// Create a HID context with the per-platform open behaviour applied, listing
+/// only the devices of [`VENDOR_IDS`].
+fn new_api() -> Result<HidApi, ControlError> {
+    // libusb only finds devices when it may scan at init; the other backends
+    // skip the scan of every HID device on the system.
+    let api = if cfg!(feature = "libusb") {
+        HidApi::new()
+    } else {
+        HidApi::new_without_enumerate()
+    };
+    let mut api = api.map_err(|source| ControlError::HidInit { source })?;
+    enumerate(&mut api).map_err(|source| ControlError::HidInit { source })?;
+    // IOKit opens HID devices exclusively by default, which locks out
+    // NGENUITY and the system's own consumer-control handling while we hold
+    // the handle.
+    #[cfg(target_os = "macos")]
+    api.set_open_exclusive(false);
+    Ok(api)
+}
+
+/// Replace the device list of `api` with the devices of [`VENDOR_IDS`],
+/// asking hidapi for those vendors only.
+fn enumerate(api: &mut HidApi) -> Result<(), hidapi::HidError> {
+    api.reset_devices()?;
+    for &vendor_id in VENDOR_IDS {
+        api.add_devices(vendor_id, 0)?;
+    }
+    Ok(())
+}
+
+/// The HID context every open and enumeration goes through, created by the
+/// first caller that needs it rather than at startup. It lists only the
+/// devices of [`VENDOR_IDS`], and the list is updated by [`refresh_devices`].
+static HID_API: Mutex<Option<HidApi>> = Mutex::new(None);
+
+/// Run `f` with the shared HID context, created on first use. Its device
+/// list is the one of the last [`refresh_devices`].
+pub fn with_api<T>(f: impl FnOnce(&HidApi) -> T) -> Result<T, ControlError> {
+    let mut api = HID_API.lock().unwrap();
+    if api.is_none() {
+        *api = Some(new_api()?);
+    }
+    Ok(f(api.as_ref().expect("HID context was just created")))
+}
+
+/// Enumerate the HID devices again, after a hotplug, into the shared
+/// context. Does nothing with a mock backend installed.
+pub fn refresh_devices() -> Result<(), ControlError> {
+    if mock::is_active() {
+        return Ok(());
+    }
+    let present = {
+        let mut api = HID_API.lock().unwrap();
+        match api.as_mut() {
+            Some(api) => enumerate(api).map_err(|source| ControlError::HidInit { source })?,
+            None => *api = Some(new_api()?),
+        }
+        present_devices(api.as_ref().expect("HID context was just created"))
+    };
+    devices().forget_removed(&present);
+    Ok(())
+}
+
+/// Buffer size the OS expects for `feature` on this interface. Windows'
+/// `HidD_SetFeature` only accepts the interface's `FeatureReportByteLength`,
+/// the largest feature report it declares, while hidraw and IOKit take the
+/// report's own size.
+fn report_length(device: &HidDevice, feature: FeatureReport) -> usize {
+    declared_feature_lengths(device)
+        .and_then(|lengths| os_report_length(&lengths, feature.report_id))
+        .filter(|&length| length >= MIN_PAYLOAD_LENGTH)
+        .unwrap_or(feature.length)
+}
+
+/// Feature report sizes from the interface's report descriptor, see
+/// [`report_descriptor::feature_lengths`].
+fn declared_feature_lengths(device: &HidDevice) -> Option<HashMap<u8, usize>> {
+    let mut descriptor = vec![0u8; hidapi::MAX_REPORT_DESCRIPTOR_SIZE];
+    let size = device.get_report_descriptor(&mut descriptor).ok()?;
+    Some(report_descriptor::feature_lengths(&descriptor[..size]))
+}
+
+/// The buffer size for `report_id` given the declared lengths, see [`report_length`].
+fn os_report_length(lengths: &HashMap<u8, usize>, report_id: u8) -> Option<usize> {
+    if cfg!(windows) {
+        lengths.values().max().copied()
+    } else {
+        lengths.get(&report_id).copied()
+    }
+}
+
+fn open_device(api: &HidApi, vendor_id: u16, product_id: u16) -> Result<HidDevice, ControlError> {
+    api.open(vendor_id, product_id)
+        .map_err(|source| ControlError::DeviceOpen {
+            vendor_id,
+            product_id,
+            diagnosis: host::get().diagnose_open_failure(api, vendor_id, product_id, &source),
+            source,
+        })
+}
+
+/// One HID interface of a device, as `(interface number, usage page, usage)`.
+/// Every unit of a model lists the same ones.
+type InterfaceKey = (i32, u16, u16);
+
+/// Unit numbers handed out per USB id, by serial number or path.
+static UNITS: Mutex<BTreeMap<(u16, u16), BTreeMap<String, u8>>> = Mutex::new(BTreeMap::new());
+/// The control interface per USB id, once its report descriptor confirmed it.
+static CONTROL_INTERFACES: Mutex<BTreeMap<(u16, u16), InterfaceKey>> = Mutex::new(BTreeMap::new());
+
+fn interface_key(info: &DeviceInfo) -> InterfaceKey {
+    (info.interface_number(), info.usage_page(), info.usage())
+}
+
+/// How likely an interface carries the vendor reports: HyperX puts them on a
+/// vendor-defined usage page, next to keyboard and consumer control
+/// interfaces for the media keys whose writes all fail.
+fn interface_score(key: InterfaceKey) -> i32 {
+    match key {
+        (_, 0xFF00..=0xFFFF, _) => 2,
+        // Keyboard, mouse and consumer control.
+        (_, 0x01, 0x02 | 0x06) | (_, 0x0C, _) => -1,
+        _ => 0,
+    }
+}
+
+/// Report IDs the app sends to the headset or its receiver.
+fn control_reports(device_id: DeviceId, target: FirmwareTarget) -> Vec<u8> {
+    let descriptor = find_descriptor(device_id);
+    let features: Vec<Option<FeatureReport>> = match target {
+        FirmwareTarget::Headset => features(descriptor)
+            .into_iter()
+            .map(|(_, feature)| feature)
+            .chain([
+                descriptor.factory_reset_feature,
+                descriptor.hardware_feature,
+                descriptor.identify_feature,
+                pairings_feature(device_id),
+            ])
+            .collect(),
+        FirmwareTarget::Dongle => descriptor
+            .receiver
+            .map(|receiver| {
+                vec![
+                    receiver.pairing_feature,
+                    receiver.link_feature,
+                    receiver.game_mode_feature,
+                ]
+            })
+            .unwrap_or_default(),
+    };
+    features
+        .into_iter()
+        .flatten()
+        .map(|feature| feature.report_id)
+        .chain(dfu_protocol(device_id, target).map(|dfu| dfu.report_id))
+        .collect()
+}
+
+/// The interface of `vendor_id`/`product_id` to send `reports` to. Interfaces
+/// are tried best [`interface_score`] first, and the first whose report
+/// descriptor declares one of `reports` wins and is remembered. When no
+/// descriptor can be read, e.g. without permission, the best score is used
+/// for now.
+fn control_interface(
+    api: &HidApi,
+    vendor_id: u16,
+    product_id: u16,
+    reports: &[u8],
+) -> Option<InterfaceKey> {
+    if let Some(&key) = CONTROL_INTERFACES
+        .lock()
+        .unwrap()
+        .get(&(vendor_id, product_id))
+    {
+        return Some(key);
+    }
+    let mut candidates: Vec<(InterfaceKey, &DeviceInfo)> = Vec::new();
+    for info in api
+        .device_list()
+        .filter(|info| info.vendor_id() == vendor_id && info.product_id() == product_id)
+    {
+        if !candidates
+            .iter()
+            .any(|&(key, _)| key == interface_key(info))
+        {
+            candidates.push((interface_key(info), info));
+        }
+    }
+    candidates.sort_by_key(|&(key, _)| std::cmp::Reverse(interface_score(key)));
+    let confirmed = candidates.iter().find(|(_, info)| {
+        info.open_device(api)
+            .ok()
+            .and_then(|device| declared_feature_lengths(&device))
+            .is_some_and(|lengths| reports.iter().any(|id| lengths.contains_key(id)))
+    });
+    match confirmed {
+        Some(&(key, _)) => {
+            trace!(?key, "control interface found");
+            CONTROL_INTERFACES
+                .lock()
+                .unwrap()
+                .insert((vendor_id, product_id), key);
+            Some(key)
+        }
+        None => candidates.first().map(|&(key, _)| key),
+    }
+}
+
+/// The plugged-in units of `target` of `device_id`, and the path of the
+/// control interface of each. A headset keeps its unit while the app runs;
+/// a new one takes the lowest unit that is not plugged in.
+fn units(api: &HidApi, device_id: DeviceId, target: FirmwareTarget) -> Vec<(u8, CString)> {
+    let Some((vendor_id, product_id)) = firmware_usb_id(device_id, target) else {
+        return Vec::new();
+    };
+    let reports = control_reports(device_id, target);
+    let Some(interface) = control_interface(api, vendor_id, product_id, &reports) else {
+        return Vec::new();
+    };
+    let mut candidates: Vec<(String, CString)> = api
+        .device_list()
+        .filter(|info| {
+            info.vendor_id() == vendor_id
+                && info.product_id() == product_id
+                && interface_key(info) == interface
+        })
+        .map(|info| {
+            let path = info.path().to_owned();
+            let key = match info.serial_number() {
+                Some(serial) if !serial.is_empty() => serial.to_string(),
+                _ => path.to_string_lossy().into_owned(),
+            };
+            (key, path)
+        })
+        .collect();
+    candidates.sort();
+    candidates.dedup_by(|a, b| a.0 == b.0);
+
+    let mut registry = UNITS.lock().unwrap();
+    let known = registry.entry((vendor_id, product_id)).or_default();
+    let mut present: BTreeSet<u8> = candidates
+        .iter()
+        .filter_map(|(key, _)| known.get(key).copied())
+        .collect();
+    candidates
+        .into_iter()
+        .filter_map(|(key, path)| {
+            let unit = match known.get(&key) {
+                Some(&unit) => unit,
+                None => {
+                    let unit = (0..=u8::MAX).find(|unit| !present.contains(unit))?;
+                    present.insert(unit);
+                    known.retain(|_, &mut other| other != unit);
+                    known.insert(key, unit);
+                    unit
+                }
+            };
+            Some((unit, path))
+        })
+        .collect()
+}
+
+/// Open the control interface of `target` of `device_id`. The first unit
+/// falls back to opening by USB id, which also explains why that failed.
+fn open_unit(
+    api: &HidApi,
+    device_id: DeviceId,
+    target: FirmwareTarget,
+) -> Result<HidDevice, ControlError> {
+    let (vendor_id, product_id) =
+        firmware_usb_id(device_id, target).ok_or(ControlError::UnsupportedFeature {
+            device_id,
+            capability: Capability::Receiver,
+        })?;
+    let unit = device_id.unit;
+    let path = units(api, device_id, target)
+        .into_iter()
+        .find(|&(other, _)| other == unit)
+        .map(|(_, path)| path);
+    match path {
+        Some(path) => api
+            .open_path(&path)
+            .map_err(|source| ControlError::DeviceOpen {
+                vendor_id,
+                product_id,
+                diagnosis: host::get().diagnose_open_failure(api, vendor_id, product_id, &source),
+                source,
+            }),
+        None if unit == 0 => open_device(api, vendor_id, product_id),
+        None => Err(ControlError::DeviceOpen {
+            vendor_id,
+            product_id,
+            source: hidapi::HidError::HidApiError {
+                message: "this headset is not plugged in".into(),
+            },
+            diagnosis: OpenDiagnosis::NotConnected,
+        }),
+    }
+}
+
+/// An open device: real hardware, or a device of the installed mock backend.
+/// Every report goes through here, so it is also where recordings are made.
+pub(crate) struct Handle {
+    pub(crate) device_id: DeviceId,
+    inner: HandleInner,
+    /// When the last operation over this handle finished.
+    last_used: Instant,
+}
+
+enum HandleInner {
+    Hid { device: HidDevice },
+    Mock(&'static dyn MockBackend),
+}
+
+impl Handle {
+    pub(crate) fn open(device_id: DeviceId) -> Result<Self, ControlError> {
+        Handle::open_target(device_id, FirmwareTarget::Headset)
+    }
+
+    /// Open the headset or its receiver. Mock backends get the reports of
+    /// both under the headset's ID.
+    pub(crate) fn open_target(
+        device_id: DeviceId,
+        target: FirmwareTarget,
+    ) -> Result<Self, ControlError> {
+        let (vendor_id, product_id) =
+            firmware_usb_id(device_id, target).ok_or(ControlError::UnsupportedFeature {
+                device_id,
+                capability: Capability::Receiver,
+            })?;
+        metrics::timed_call("open", || {
+            Handle::open_untimed(device_id, target, vendor_id, product_id)
+        })
+    }
+
+    fn open_untimed(
+        device_id: DeviceId,
+        target: FirmwareTarget,
+        vendor_id: u16,
+        product_id: u16,
+    ) -> Result<Self, ControlError> {
+        let Some(backend) = mock::backend() else {
+            // A headset plugged in since the last refresh is not listed yet.
+            let device = match with_api(|api| open_unit(api, device_id, target))? {
+                Err(ControlError::DeviceOpen { .. }) => {
+                    refresh_devices()?;
+                    with_api(|api| open_unit(api, device_id, target))??
+                }
+                result => result?,
+            };
+            if recorder::is_recording() {
+                if let Ok(Some(serial)) = device.get_serial_number_string() {
+                    recorder::add_secret(&serial);
+                }
+            }
+            return Ok(Handle {
+                device_id,
+                inner: HandleInner::Hid { device },
+                last_used: Instant::now(),
+            });
+        };
+        if !backend.connected().contains(&device_id) {
+            return Err(ControlError::DeviceOpen {
+                vendor_id,
+                product_id,
+                source: hidapi::HidError::HidApiError {
+                    message: "simulated device is disconnected".into(),
+                },
+                diagnosis: OpenDiagnosis::NotConnected,
+            });
+        }
+        Ok(Handle {
+            device_id,
+            inner: HandleInner::Mock(backend),
+            last_used: Instant::now(),
+        })
+    }
+
+    pub(crate) fn send_feature_report(&self, data: &[u8]) -> Result<(), hidapi::HidError> {
+        let result = metrics::timed_call("send_feature_report", || match &self.inner {
+            HandleInner::Hid { device, .. } => device.send_feature_report(data),
+            HandleInner::Mock(backend) => backend.send_feature_report(self.device_id, data),
+        });
+        trace!(payload = %recorder::hex(data), ok = result.is_ok(), "send_feature_report");
+        let outcome = result.as_ref().map(|()| None);
+        recorder::record(
+            self.device_id,
+            recorder::Operation::SendFeatureReport,
+            data,
+            outcome,
+        );
+        result
+    }
+
+    pub(crate) fn get_feature_report(&self, buffer: &mut [u8]) -> Result<usize, hidapi::HidError> {
+        let request = recorder::is_recording().then(|| buffer.to_vec());
+        let result = metrics::timed_call("get_feature_report", || match &self.inner {
+            HandleInner::Hid { device, .. } => device.get_feature_report(buffer),
+            HandleInner::Mock(backend) => backend.get_feature_report(self.device_id, buffer),
+        });
+        match &result {
+            Ok(length) => trace!(
+                response = %recorder::hex(&buffer[..(*length).min(buffer.len())]),
+                "get_feature_report"
+            ),
+            Err(err) => trace!(error = %err, "get_feature_report"),
+        }
+        if let Some(request) = request {
+            let outcome = match &result {
+                Ok(length) => Ok(Some(&buffer[..(*length).min(buffer.len())])),
+                Err(err) => Err(err),
+            };
+            recorder::record(
+                self.device_id,
+                recorder::Operation::GetFeatureReport,
+                &request,
+                outcome,
+            );
+        }
+        result
+    }
+
+    /// Wait up to `timeout` for an input report; 0 bytes when none came.
+    pub(crate) fn read_input_report(
+        &self,
+        buffer: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize, hidapi::HidError> {
+        let millis = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+        let result = match &self.inner {
+            HandleInner::Hid { device, .. } => device.read_timeout(buffer, millis),
+            HandleInner::Mock(backend) => {
+                backend.read_input_report(self.device_id, buffer, timeout)
+            }
+        };
+        if let Ok(length @ 1..) = result {
+            trace!(payload = %recorder::hex(&buffer[..length]), "read_input_report");
+        }
+        result
+    }
+
+    pub(crate) fn report_length(&self, feature: FeatureReport) -> usize {
+        match &self.inner {
+            HandleInner::Hid { device, .. } => report_length(device, feature),
+            HandleInner::Mock(_) => feature.length,
+        }
+    }
+
+    /// `bcdDevice` of the opened interface; `None` for mock devices.
+    pub(crate) fn release_number(&self) -> Option<u16> {
+        match &self.inner {
+            HandleInner::Hid { device, .. } => device
+                .get_device_info()
+                .ok()
+                .map(|info| info.release_number()),
+            HandleInner::Mock(_) => None,
+        }
+    }
+
+    /// Manufacturer, product and serial number strings of the opened
+    /// interface; all `None` for mock devices.
+    pub(crate) fn strings(&self) -> (Option<String>, Option<String>, Option<String>) {
+        let HandleInner::Hid { device, .. } = &self.inner else {
+            return (None, None, None);
+        };
+        let Ok(info) = device.get_device_info() else {
+            return (None, None, None);
+        };
+        let string = |value: Option<&str>| value.map(str::to_string).filter(|s| !s.is_empty());
+        (
+            string(info.manufacturer_string()),
+            string(info.product_string()),
+            string(info.serial_number()),
+        )
+    }
+
+    /// Declared feature report sizes; `None` when unknown, as for mock devices.
+    pub(crate) fn feature_lengths(&self) -> Option<HashMap<u8, usize>> {
+        match &self.inner {
+            HandleInner::Hid { device, .. } => declared_feature_lengths(device),
+            HandleInner::Mock(_) => None,
+        }
+    }
+
+    /// A competing controller blamed for a failed or reverted write.
+    fn busy(&self, descriptor: DeviceDescriptor) -> Option<ControlError> {
+        match &self.inner {
+            HandleInner::Hid { .. } => with_api(|api| {
+                host::get().find_controller(api, descriptor.vendor_id, descriptor.product_id)
+            })
+            .ok()
+            .flatten()
+            .map(|process| ControlError::DeviceBusy { process }),
+            HandleInner::Mock(_) => None,
+        }
+    }
+}
+
+/// HID backend hidapi was compiled against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HidBackend {
+    /// Linux `/dev/hidraw*` nodes.
+    Hidraw,
+    /// Linux libusb access through `/dev/bus/usb`, for systems where hidraw is unusable.
+    Libusb,
+    /// The platform's own HID stack (Windows, macOS).
+    Native,
+}
+
+pub const fn hid_backend() -> HidBackend {
+    if cfg!(not(target_os = "linux")) {
+        HidBackend::Native
+    } else if cfg!(feature = "libusb") {
+        HidBackend::Libusb
+    } else {
+        HidBackend::Hidraw
+    }
+}
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// In dry-run mode every write is logged instead of sent and reported as
+/// successful. Devices are still opened and read.
+pub fn set_dry_run(enabled: bool) {
+    DRY_RUN.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Return a static list of known HyperX devices, plus the simulated ones
+/// while a mock backend is installed.
+pub fn supported_devices() -> &'static [DeviceMetadata] {
+    if mock::is_active() {
+        SIMULATED_CATALOG
+    } else {
+        DEVICE_CATALOG
+    }
+}
+
+/// USB vendor and product id of a device, as `(vendor_id, product_id)`.
+pub fn usb_id(device_id: DeviceId) -> (u16, u16) {
+    let descriptor = find_descriptor(device_id);
+    (descriptor.vendor_id, descriptor.product_id)
+}
+
+/// What a firmware image is for: the headset, or the USB receiver of a
+/// wireless model.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FirmwareTarget {
+    #[default]
+    Headset,
+    Dongle,
+}
+
+impl FirmwareTarget {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FirmwareTarget::Headset => "headset",
+            FirmwareTarget::Dongle => "dongle",
+        }
+    }
+}
+
+/// The headset, then its receiver on wireless models.
+pub fn firmware_targets(device_id: DeviceId) -> Vec<FirmwareTarget> {
+    let mut targets = vec![FirmwareTarget::Headset];
+    if find_descriptor(device_id).receiver.is_some() {
+        targets.push(FirmwareTarget::Dongle);
+    }
+    targets
+}
+
+/// USB vendor and product id of `target`; `None` for the receiver of a wired model.
+pub fn firmware_usb_id(device_id: DeviceId, target: FirmwareTarget) -> Option<(u16, u16)> {
+    let descriptor = find_descriptor(device_id);
+    match target {
+        FirmwareTarget::Headset => Some((descriptor.vendor_id, descriptor.product_id)),
+        FirmwareTarget::Dongle => descriptor
+            .receiver
+            .map(|receiver| (descriptor.vendor_id, receiver.product_id)),
+    }
+}
+
+/// Supported devices plugged in as of the last [`refresh_devices`], every
+/// unit of a model on its own.
+pub fn connected_devices() -> Result<Vec<DeviceId>, ControlError> {
+    if let Some(backend) = mock::backend() {
+        return Ok(backend.connected());
+    }
+    with_api(present_devices)
+}
+
+/// [`connected_devices`] through an existing `api`.
+pub fn present_devices(api: &HidApi) -> Vec<DeviceId> {
+    if let Some(backend) = mock::backend() {
+        return backend.connected();
+    }
+    DEVICE_CATALOG
+        .iter()
+        .flat_map(|device| {
+            units(api, device.id, FirmwareTarget::Headset)
+                .into_iter()
+                .map(move |(unit, _)| DeviceId {
+                    model: device.id.model,
+                    unit,
+                })
+        })
+        .collect()
+}
+
+/// [`supported_devices`] with an entry for every further unit of a model
+/// that is plugged in more than once, for the device lists of the frontend
+/// and the integrations.
+pub fn list_devices() -> Vec<DeviceMetadata> {
+    let present = connected_devices().unwrap_or_default();
+    supported_devices()
+        .iter()
+        .flat_map(|device| {
+            let units = present
+                .iter()
+                .filter(|id| id.model == device.id.model && id.unit > 0)
+                .map(|&id| DeviceMetadata { id, ..*device });
+            std::iter::once(*device).chain(units)
+        })
+        .collect()
+}
+
+/// Who is waiting for a device operation. A device runs one operation at a
+/// time; when it becomes free, waiting interactive operations go before
+/// background ones, so a mute toggle does not queue behind battery polling.
+/// An operation already under way is never interrupted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Priority {
+    /// Asked for by the user; the default.
+    #[default]
+    Interactive,
+    /// Polling and other work nobody is waiting on.
+    Background,
+}
+
+thread_local! {
+    static PRIORITY: Cell<Priority> = const { Cell::new(Priority::Interactive) };
+}
+
+/// Run `f` with the device operations it makes on this thread at `priority`.
+pub fn with_priority<T>(priority: Priority, f: impl FnOnce() -> T) -> T {
+    struct Restore(Priority);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            PRIORITY.set(self.0);
+        }
+    }
+    let _restore = Restore(PRIORITY.replace(priority));
+    f()
+}
+
+/// Most devices [`DeviceManager::query`] talks to at once.
+const MAX_PARALLEL_QUERIES: usize = 4;
+
+/// Hands out the [`Device`] of each device id, so everything that talks to
+/// one headset shares its open handles and cached state.
+pub struct DeviceManager {
+    devices: Mutex<Option<HashMap<DeviceId, Arc<Device>>>>,
+}
+
+static DEVICES: DeviceManager = DeviceManager {
+    devices: Mutex::new(None),
+};
+
+/// The app's [`DeviceManager`].
+pub fn devices() -> &'static DeviceManager {
+    &DEVICES
+}
+
+impl DeviceManager {
+    /// The device with `device_id`, whether or not it is plugged in.
+    pub fn get(&self, device_id: DeviceId) -> Arc<Device> {
+        self.devices
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .entry(device_id)
+            .or_insert_with(|| Arc::new(Device::new(device_id)))
+            .clone()
+    }
+
+    /// Close the handles of `device_id`, e.g. once it was unplugged. The next
+    /// operation opens it again; the cached state is kept.
+    pub fn release(&self, device_id: DeviceId) {
+        let device = self
+            .devices
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|devices| devices.get(&device_id).cloned());
+        if let Some(device) = device {
+            device.handles.lock().unwrap().clear();
+            device.read_at.lock().unwrap().clear();
+        }
+    }
+
+    /// Close the handles of known devices missing from `present` and drop
+    /// their cached readings, so a reading taken just before the unplug is
+    /// not returned. A device being flashed keeps its handles; its session
+    /// holds them.
+    fn forget_removed(&self, present: &[DeviceId]) {
+        let removed: Vec<Arc<Device>> = self
+            .devices
+            .lock()
+            .unwrap()
+            .iter()
+            .flatten()
+            .filter(|(device_id, _)| !present.contains(device_id))
+            .map(|(_, device)| device.clone())
+            .collect();
+        for device in removed {
+            device.read_at.lock().unwrap().clear();
+            if let Ok(mut handles) = device.handles.try_lock() {
+                handles.clear();
+            }
+        }
+    }
+
+    /// Run `query` on the device of each of `device_ids`, on up to
+    /// [`MAX_PARALLEL_QUERIES`] threads, so a slow device, such as a wireless
+    /// headset that is out of range, only holds up its own result. The
+    /// results are in the order of `device_ids`.
+    pub fn query<T: Send>(
+        &self,
+        device_ids: &[DeviceId],
+        query: impl Fn(&Device) -> T + Sync,
+    ) -> Vec<T> {
+        let devices: Vec<Arc<Device>> = device_ids.iter().map(|&id| self.get(id)).collect();
+        // Mock backends answer at once, and in tests are only installed for
+        // the calling thread.
+        if devices.len() < 2 || mock::is_active() {
+            return devices.iter().map(|device| query(device)).collect();
+        }
+        let next = AtomicUsize::new(0);
+        let results: Vec<Mutex<Option<T>>> = devices.iter().map(|_| Mutex::new(None)).collect();
+        let priority = PRIORITY.get();
+        thread::scope(|scope| {
+            for _ in 0..MAX_PARALLEL_QUERIES.min(devices.len()) {
+                scope.spawn(|| {
+                    with_priority(priority, || loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(device) = devices.get(index) else {
+                            break;
+                        };
+                        *results[index].lock().unwrap() = Some(query(device));
+                    })
+                });
+            }
+        });
+        results
+            .into_iter()
+            .map(|result| {
+                result
+                    .into_inner()
+                    .unwrap()
+                    .expect("every device was queried")
+            })
+            .collect()
+    }
+
+    /// Close every handle no operation has used for `idle`, so other tools
+    /// such as firmware updaters can open the device. Devices in the middle
+    /// of an operation are skipped; the next operation reopens what was closed.
+    pub fn release_idle(&self, idle: Duration) {
+        let devices: Vec<Arc<Device>> = self
+            .devices
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(HashMap::values)
+            .cloned()
+            .collect();
+        for device in devices {
+            let Ok(mut handles) = device.handles.try_lock() else {
+                continue;
+            };
+            handles.retain(|target, handle| {
+                let keep = handle.last_used.elapsed() < idle;
+                if !keep {
+                    trace!(device_id = %device.id, ?target, "closing idle handle");
+                }
+                keep
+            });
+        }
+    }
+}
+
+/// One headset, with its receiver on wireless models. Operations on it run
+/// one at a time, over handles that stay open between them.
+pub struct Device {
+    pub(crate) id: DeviceId,
+    pub(crate) descriptor: DeviceDescriptor,
+    /// Dropped after a failed report, which usually means the device was
+    /// unplugged, and opened again by the next operation.
+    pub(crate) handles: Mutex<HashMap<FirmwareTarget, Handle>>,
+    /// The last value written to or read from each feature.
+    pub(crate) state: Mutex<HashMap<FeatureKind, u16>>,
+    /// When each feature was last read from the device, see [`READ_CACHE_TTL`].
+    pub(crate) read_at: Mutex<HashMap<FeatureKind, Instant>>,
+    /// Interactive operations waiting for `handles`, see [`Priority`];
+    /// `interactive_done` is notified when the last of them got it.
+    interactive_waiting: Mutex<usize>,
+    interactive_done: Condvar,
+    /// Set while a [`DfuSession`] holds `handles`; other operations fail
+    /// instead of waiting for the whole flash.
+    pub(crate) flashing: AtomicBool,
+}
+
+/// How long a value read from a device answers further reads of the same
+/// feature, so a frontend re-render asking five times sends one report.
+/// Writes, raw reports and unplugging the device drop it sooner.
+const READ_CACHE_TTL: Duration = Duration::from_millis(500);
+
+/// What a [`Device`] last wrote or read, without asking the device again;
+/// `None` for what it has not seen yet.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeviceState {
+    pub sidetone: Option<bool>,
+    pub volume: Option<u8>,
+    /// Corrected like [`Device::read_battery`].
+    pub battery: Option<u8>,
+    pub mute_led: Option<bool>,
+    pub firmware: Option<FirmwareVersion>,
+    pub wireless_mode: Option<WirelessMode>,
+    pub multipoint: Option<bool>,
+    pub output_mute: Option<bool>,
+    pub voice_prompts: Option<bool>,
+    pub led_brightness: Option<u8>,
+    pub notification_beeps: Option<bool>,
+    pub mute_beep_volume: Option<u8>,
+    pub boom: Option<BoomPosition>,
+    pub anc: Option<bool>,
+    pub anc_level: Option<u8>,
+    pub ambient: Option<bool>,
+    pub auto_answer: Option<bool>,
+    pub game_mode: Option<bool>,
+}
+
+impl Device {
+    fn new(id: DeviceId) -> Self {
+        Device {
+            id,
+            descriptor: find_descriptor(id),
+            handles: Mutex::new(HashMap::new()),
+            state: Mutex::new(HashMap::new()),
+            read_at: Mutex::new(HashMap::new()),
+            interactive_waiting: Mutex::new(0),
+            interactive_done: Condvar::new(),
+            flashing: AtomicBool::new(false),
+        }
+    }
+
+    /// The cached values, see [`DeviceState`].
+    pub fn state(&self) -> DeviceState {
+        let state = self.state.lock().unwrap();
+        let value = |kind| state.get(&kind).copied();
+        let flag = |kind| value(kind).map(|value| value != 0);
+        DeviceState {
+            sidetone: flag(FeatureKind::Sidetone),
+            volume: value(FeatureKind::Volume).map(percent),
+            battery: value(FeatureKind::Battery)
+                .map(|value| host::get().correct_battery(self.id, percent(value))),
+            mute_led: flag(FeatureKind::MuteLed),
+            firmware: value(FeatureKind::Firmware).map(FirmwareVersion::from_bcd),
+            wireless_mode: value(FeatureKind::WirelessMode).and_then(wireless_mode),
+            multipoint: flag(FeatureKind::Multipoint),
+            output_mute: flag(FeatureKind::OutputMute),
+            voice_prompts: flag(FeatureKind::VoicePrompts),
+            led_brightness: value(FeatureKind::LedBrightness).map(percent),
+            notification_beeps: flag(FeatureKind::NotificationBeeps),
+            mute_beep_volume: value(FeatureKind::MuteBeepVolume).map(percent),
+            boom: value(FeatureKind::Boom).and_then(boom_position),
+            anc: flag(FeatureKind::Anc),
+            anc_level: value(FeatureKind::AncLevel).map(anc_level),
+            ambient: flag(FeatureKind::Ambient),
+            auto_answer: flag(FeatureKind::AutoAnswer),
+            game_mode: flag(FeatureKind::GameMode),
+        }
+    }
+
+    /// Run `operation` on the open handle of `target`, opening it first if
+    /// needed. Mock devices come and go with the simulator, so they are
+    /// opened for every operation.
+    pub(crate) fn with_handle<T>(
+        &self,
+        target: FirmwareTarget,
+        operation: impl FnOnce(&Handle) -> Result<T, ControlError>,
+    ) -> Result<T, ControlError> {
+        if self.flashing.load(Ordering::SeqCst) {
+            return Err(ControlError::Flashing { device_id: self.id });
+        }
+        if mock::is_active() {
+            let handle = Handle::open_target(self.id, target)
+                .inspect_err(|_| self.read_at.lock().unwrap().clear())?;
+            let result = operation(&handle);
+            if lost_device(&result) {
+                self.read_at.lock().unwrap().clear();
+            }
+            return result;
+        }
+        let mut handles = self.lock_handles();
+        let handle = match handles.entry(target) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => match Handle::open_target(self.id, target) {
+                Ok(handle) => entry.insert(handle),
+                Err(err) => {
+                    self.read_at.lock().unwrap().clear();
+                    return Err(err);
+                }
+            },
+        };
+        let result = operation(handle);
+        handle.last_used = Instant::now();
+        if lost_device(&result) {
+            handles.remove(&target);
+            self.read_at.lock().unwrap().clear();
+        }
+        result
+    }
+
+    /// Take the device for one operation, letting interactive operations
+    /// that are waiting go first when this thread runs in the background.
+    pub(crate) fn lock_handles(&self) -> MutexGuard<'_, HashMap<FirmwareTarget, Handle>> {
+        match PRIORITY.get() {
+            Priority::Interactive => {
+                *self.interactive_waiting.lock().unwrap() += 1;
+                let handles = self.handles.lock().unwrap();
+                let mut waiting = self.interactive_waiting.lock().unwrap();
+                *waiting -= 1;
+                if *waiting == 0 {
+                    self.interactive_done.notify_all();
+                }
+                handles
+            }
+            Priority::Background => {
+                let waiting = self.interactive_waiting.lock().unwrap();
+                drop(
+                    self.interactive_done
+                        .wait_while(waiting, |waiting| *waiting > 0)
+                        .unwrap(),
+                );
+                self.handles.lock().unwrap()
+            }
+        }
+    }
+
+    /// The report of `kind`, and whether it is on the headset or its receiver.
+    pub(crate) fn feature(
+        &self,
+        kind: FeatureKind,
+    ) -> Result<(FirmwareTarget, FeatureReport), ControlError> {
+        let (target, feature) = match kind {
+            FeatureKind::GameMode => (
+                FirmwareTarget::Dongle,
+                self.descriptor
+                    .receiver
+                    .and_then(|receiver| receiver.game_mode_feature),
+            ),
+            kind => (
+                FirmwareTarget::Headset,
+                features(self.descriptor)
+                    .into_iter()
+                    .find_map(|(other, feature)| feature.filter(|_| other == kind)),
+            ),
+        };
+        Ok((target, validate_feature(self.id, kind.into(), feature)?))
+    }
+
+    /// Send a 16-bit little-endian value for a feature. A value the
+    /// firmware refuses, going by the status byte of models that have one,
+    /// fails with [`ControlError::Rejected`], and one the device reads back
+    /// differently with [`ControlError::NotApplied`]. A failed write, or one
+    /// not applied, is blamed on a competing controller when one is running.
+    /// The value is only kept as the device's state once it was read back;
+    /// without a readable response the state is forgotten.
+    pub(crate) fn write(&self, kind: FeatureKind, value: u16) -> Result<(), ControlError> {
+        let (target, feature) = self.feature(kind)?;
+        let descriptor = self.descriptor;
+        self.read_at.lock().unwrap().remove(&kind);
+
+        metrics::timed(self.id, "write", || {
+            self.with_handle(target, |device| {
+                let busy = || device.busy(descriptor);
+
+                let payload = build_feature_payload(feature, value, device.report_length(feature));
+                if is_dry_run() {
+                    info!(payload = %recorder::hex(&payload), "dry run: not sending");
+                    return Ok(());
+                }
+                if let Err(source) = device.send_feature_report(&payload) {
+                    return Err(busy().unwrap_or(ControlError::ReportSend {
+                        report_id: feature.report_id,
+                        selector: feature.selector,
+                        source,
+                    }));
+                }
+                let response = read_response(device, feature);
+                let status = response
+                    .as_deref()
+                    .ok()
+                    .filter(|_| descriptor.write_status)
+                    .and_then(|response| write_status(feature, response));
+                if let Some(status) = status.filter(|&status| status != 0) {
+                    return Err(ControlError::Rejected {
+                        report_id: feature.report_id,
+                        selector: feature.selector,
+                        status,
+                    });
+                }
+                let mut state = self.state.lock().unwrap();
+                match response.map(|response| parse_response(feature, &response)) {
+                    Ok(Some(actual)) if actual == value => {
+                        state.insert(kind, value);
+                        Ok(())
+                    }
+                    Ok(Some(actual)) => {
+                        state.insert(kind, actual);
+                        drop(state);
+                        Err(busy().unwrap_or(ControlError::NotApplied {
+                            report_id: feature.report_id,
+                            selector: feature.selector,
+                            expected: value,
+                            actual,
+                        }))
+                    }
+                    _ => {
+                        state.remove(&kind);
+                        Ok(())
+                    }
+                }
+            })
+        })
+    }
+
+    /// Read back a feature's 16-bit value; `Ok(None)` when the response
+    /// cannot be interpreted (selector mismatch, truncated data). A value
+    /// read within the last [`READ_CACHE_TTL`] is returned without asking
+    /// the device again.
+    pub(crate) fn read(&self, kind: FeatureKind) -> Result<Option<u16>, ControlError> {
+        let (target, feature) = self.feature(kind)?;
+        if let Some(value) = self.cached(kind) {
+            return Ok(Some(value));
+        }
+
+        let value = metrics::timed(self.id, "read", || {
+            self.with_handle(target, |device| read_value(device, feature))
+        })?;
+        if let Some(value) = value {
+            self.state.lock().unwrap().insert(kind, value);
+            self.read_at.lock().unwrap().insert(kind, Instant::now());
+        }
+        Ok(value)
+    }
+
+    fn cached(&self, kind: FeatureKind) -> Option<u16> {
+        let read_at = self.read_at.lock().unwrap().get(&kind).copied()?;
+        if read_at.elapsed() >= READ_CACHE_TTL {
+            return None;
+        }
+        self.state.lock().unwrap().get(&kind).copied()
+    }
+}
+
+/// Whether `result` failed because the device went away or was taken by
+/// another program, so cached readings may be from before.
+fn lost_device<T>(result: &Result<T, ControlError>) -> bool {
+    matches!(
+        result,
+        Err(ControlError::ReportSend { .. }
+            | ControlError::ReportRead { .. }
+            | ControlError::DeviceBusy { .. })
+    )
+}
+
+pub(crate) fn read_value(
+    device: &Handle,
+    feature: FeatureReport,
+) -> Result<Option<u16>, ControlError> {
+    Ok(parse_response(feature, &read_response(device, feature)?))
+}
+
+/// A feature report response, cut to the length the device answered with.
+fn read_response(device: &Handle, feature: FeatureReport) -> Result<ReportBuffer, ControlError> {
+    let mut buffer = ReportBuffer::zeroed(device.report_length(feature));
+    buffer[0] = feature.report_id;
+    let length =
+        device
+            .get_feature_report(&mut buffer)
+            .map_err(|source| ControlError::ReportRead {
+                report_id: feature.report_id,
+                selector: feature.selector,
+                source,
+            })?;
+    buffer.truncate(length);
+    Ok(buffer)
+}
+
+/// Outcome of reading one feature in [`Device::run_self_test`].
+#[derive(Debug, Clone, Serialize)]
+pub struct FeatureCheck {
+    pub feature: FeatureKind,
+    pub report_id: u8,
+    pub passed: bool,
+    /// Time from sending the read request to having the response.
+    pub round_trip_ms: f64,
+    /// The raw value the device answered with.
+    pub value: Option<u16>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub device_id: DeviceId,
+    /// Every feature check passed.
+    pub passed: bool,
+    /// Stopped before checking every feature; never counts as passed.
+    pub cancelled: bool,
+    pub open_ms: f64,
+    pub features: Vec<FeatureCheck>,
+}
+
+impl Device {
+    /// Open the device and read every feature its descriptor advertises,
+    /// for support triage and for checking a new descriptor against real
+    /// hardware. A check fails when the report ID is missing from the
+    /// device's report descriptor, the read fails, or the response does not
+    /// carry the feature's selector. The device is opened anew, so the open
+    /// is timed too. Once `cancelled` returns true the test stops before the
+    /// next feature, reporting the checks it made.
+    pub fn run_self_test(
+        &self,
+        cancelled: impl Fn() -> bool,
+    ) -> Result<SelfTestReport, ControlError> {
+        let device_id = self.id;
+        metrics::timed(device_id, "self_test", || {
+            let started = Instant::now();
+            let device = Handle::open(device_id)?;
+            let open_ms = millis(started.elapsed());
+            let declared = device.feature_lengths();
+
+            let mut checks = Vec::new();
+            let mut stopped = false;
+            for (kind, feature) in features(self.descriptor) {
+                let Some(feature) = feature else {
+                    continue;
+                };
+                if cancelled() {
+                    stopped = true;
+                    break;
+                }
+                let mut check = FeatureCheck {
+                    feature: kind,
+                    report_id: feature.report_id,
+                    passed: false,
+                    round_trip_ms: 0.0,
+                    value: None,
+                    error: None,
+                };
+                if declared
+                    .as_ref()
+                    .is_some_and(|lengths| !lengths.contains_key(&feature.report_id))
+                {
+                    check.error = Some("report ID is not in the device's report descriptor".into());
+                    checks.push(check);
+                    continue;
+                }
+                let started = Instant::now();
+                let result = read_value(&device, feature);
+                check.round_trip_ms = millis(started.elapsed());
+                match result {
+                    Ok(Some(value)) => {
+                        check.passed = true;
+                        check.value = Some(value);
+                    }
+                    Ok(None) => {
+                        check.error = Some(format!(
+                            "response does not start with selector 0x{:02X}",
+                            feature.selector
+                        ));
+                    }
+                    Err(err) => check.error = Some(err.to_string()),
+                }
+                checks.push(check);
+            }
+            Ok(SelfTestReport {
+                device_id,
+                passed: !stopped && checks.iter().all(|check| check.passed),
+                cancelled: stopped,
+                open_ms,
+                features: checks,
+            })
+        })
+    }
+}
+
+fn millis(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64() * 1000.0
+}
+
+/// Buffer size for a raw report of `requested` bytes, checked against the
+/// declared lengths when the device's report descriptor could be read.
+fn raw_report_length(
+    lengths: Option<HashMap<u8, usize>>,
+    report_id: u8,
+    requested: Option<usize>,
+) -> Result<usize, ControlError> {
+    let invalid = |reason: String| ControlError::InvalidReport { reason };
+    if requested == Some(0) {
+        return Err(invalid("a report needs at least its report ID".into()));
+    }
+    let Some(lengths) = lengths else {
+        return Ok(requested.unwrap_or(REPORT_LENGTH));
+    };
+    if !lengths.contains_key(&report_id) {
+        let mut declared: Vec<_> = lengths.keys().map(|id| format!("0x{id:02X}")).collect();
+        declared.sort();
+        return Err(invalid(format!(
+            "the device declares no feature report 0x{report_id:02X} (declared: {})",
+            declared.join(", ")
+        )));
+    }
+    let expected = os_report_length(&lengths, report_id).unwrap_or(REPORT_LENGTH);
+    match requested {
+        Some(requested) if requested > expected => Err(invalid(format!(
+            "{requested} bytes given, report 0x{report_id:02X} takes {expected} including the ID"
+        ))),
+        _ => Ok(expected),
+    }
+}
+
+impl Device {
+    /// Send `data` (report ID first) as is, zero-padded to the declared
+    /// report length; returns the bytes sent. For protocol exploration only.
+    pub fn send_raw_feature_report(&self, data: &[u8]) -> Result<Vec<u8>, ControlError> {
+        // Whatever the report changed is unknown.
+        self.read_at.lock().unwrap().clear();
+        metrics::timed(self.id, "raw_write", || {
+            self.with_handle(FirmwareTarget::Headset, |device| {
+                let report_id = data.first().copied().unwrap_or(0);
+                let mut payload = data.to_vec();
+                let length =
+                    raw_report_length(device.feature_lengths(), report_id, Some(data.len()))?;
+                payload.resize(length, 0);
+                if is_dry_run() {
+                    info!(payload = %recorder::hex(&payload), "dry run: not sending");
+                    return Ok(payload);
+                }
+                device.send_feature_report(&payload).map_err(|source| {
+                    ControlError::ReportSend {
+                        report_id,
+                        selector: payload.get(1).copied().unwrap_or(0),
+                        source,
+                    }
+                })?;
+                Ok(payload)
+            })
+        })
+    }
+
+    /// Read feature report `report_id`; `length` defaults to the declared
+    /// size. Returns the response as the OS delivered it.
+    pub fn get_raw_feature_report(
+        &self,
+        report_id: u8,
+        length: Option<usize>,
+    ) -> Result<Vec<u8>, ControlError> {
+        metrics::timed(self.id, "raw_read", || {
+            self.with_handle(FirmwareTarget::Headset, |device| {
+                let length = raw_report_length(device.feature_lengths(), report_id, length)?;
+                let mut buffer = vec![0u8; length];
+                buffer[0] = report_id;
+                let received = device.get_feature_report(&mut buffer).map_err(|source| {
+                    ControlError::ReportRead {
+                        report_id,
+                        selector: 0,
+                        source,
+                    }
+                })?;
+                buffer.truncate(received);
+                Ok(buffer)
+            })
+        })
+    }
+}
+
+/// `DEVPKEY_Device_ContainerId` of the HID interface as a `u128`, which the
+/// audio endpoints of the same headset share. `Ok(None)` when the property is missing.
+#[cfg(windows)]
+pub fn container_id(device_id: DeviceId) -> Result<Option<u128>, ControlError> {
+    if mock::is_active() {
+        return Ok(None);
+    }
+    let device = with_api(|api| open_unit(api, device_id, FirmwareTarget::Headset))??;
+    Ok(device.get_container_id().ok().map(|guid| {
+        (u128::from(guid.data1) << 96)
+            | (u128::from(guid.data2) << 80)
+            | (u128::from(guid.data3) << 64)
+            | u128::from(u64::from_be_bytes(guid.data4))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::TestHeadset;
+    use crate::Model;
+
+    /// A device of its own for each test, since [`devices`] is shared.
+    fn headset(unit: u8) -> (Arc<Device>, &'static TestHeadset) {
+        let device_id = DeviceId {
+            model: Model::SimulatedWireless,
+            unit,
+        };
+        let headset = TestHeadset::install(device_id);
+        headset.set(FeatureKind::Volume, 40);
+        (devices().get(device_id), headset)
+    }
+
+    #[test]
+    fn reads_within_the_ttl_ask_the_device_once() {
+        let (device, headset) = headset(30);
+        for _ in 0..5 {
+            assert_eq!(device.read_volume().unwrap(), Some(40));
+        }
+        assert_eq!(headset.reads(), 1);
+    }
+
+    #[test]
+    fn expired_reading_is_read_again() {
+        let (device, headset) = headset(31);
+        device.read_volume().unwrap();
+        thread::sleep(READ_CACHE_TTL);
+        device.read_volume().unwrap();
+        assert_eq!(headset.reads(), 2);
+    }
+
+    #[test]
+    fn write_drops_the_cached_reading() {
+        let (device, headset) = headset(32);
+        device.read_volume().unwrap();
+        device.set_volume(55).unwrap();
+        let reads = headset.reads();
+        assert_eq!(device.read_volume().unwrap(), Some(55));
+        assert_eq!(headset.reads(), reads + 1);
+    }
+
+    #[test]
+    fn release_drops_cached_readings() {
+        let (device, headset) = headset(33);
+        device.read_volume().unwrap();
+        devices().release(device.id);
+        device.read_volume().unwrap();
+        assert_eq!(headset.reads(), 2);
+    }
+
+    #[test]
+    fn unplug_drops_cached_readings() {
+        let (device, headset) = headset(34);
+        device.read_volume().unwrap();
+        let present: Vec<DeviceId> = devices()
+            .devices
+            .lock()
+            .unwrap()
+            .iter()
+            .flatten()
+            .map(|(&device_id, _)| device_id)
+            .filter(|&device_id| device_id != device.id)
+            .collect();
+        devices().forget_removed(&present);
+        device.read_volume().unwrap();
+        assert_eq!(headset.reads(), 2);
+    }
+
+    #[test]
+    fn failed_open_drops_cached_readings() {
+        let (device, headset) = headset(35);
+        device.read_volume().unwrap();
+        headset.set_connected(false);
+        let err = device.set_sidetone(true).unwrap_err();
+        assert!(matches!(err, ControlError::DeviceOpen { .. }), "{err:?}");
+        headset.set_connected(true);
+        device.read_volume().unwrap();
+        assert_eq!(headset.reads(), 2);
+    }
+
+    #[test]
+    fn failed_report_drops_cached_readings() {
+        let (device, headset) = headset(36);
+        device.read_volume().unwrap();
+        headset.fail_writes(FeatureKind::Sidetone);
+        let err = device.set_sidetone(true).unwrap_err();
+        assert!(matches!(err, ControlError::ReportSend { .. }), "{err:?}");
+        device.read_volume().unwrap();
+        assert_eq!(headset.reads(), 2);
+    }
+}
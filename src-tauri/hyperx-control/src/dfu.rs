@@ -0,0 +1,369 @@
+//! Firmware updates through the bootloader's DFU report, see [`DfuSession`],
+//! and the parts of its protocol the simulator answers with.
+
+use crate::device::{Device, FirmwareTarget, Handle};
+use crate::features::{response_body, Capability};
+use crate::{
+    find_descriptor, recorder, ControlError, DeviceId, DfuProtocol, FeatureReport, REPORT_LENGTH,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::Ordering;
+use std::sync::MutexGuard;
+
+/// Bootloader commands, the byte after the report ID of every DFU report.
+const DFU_ENTER: u8 = 0x01;
+const DFU_DATA: u8 = 0x02;
+const DFU_FINISH: u8 = 0x03;
+const DFU_ABORT: u8 = 0x04;
+const DFU_STATUS: u8 = 0x05;
+const DFU_READ: u8 = 0x06;
+/// Report ID, command, offset or size, length and checksum around each chunk.
+const DFU_CHUNK_OVERHEAD: usize = 11;
+
+/// What the bootloader says about the last DFU report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DfuStatus {
+    Ok,
+    ChecksumError,
+    /// The chunk does not start where the bootloader expects the next one.
+    BadOffset,
+    /// The finished image failed the bootloader's own checks.
+    Rejected,
+    Unknown(u8),
+}
+
+impl DfuStatus {
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            0 => DfuStatus::Ok,
+            1 => DfuStatus::ChecksumError,
+            2 => DfuStatus::BadOffset,
+            3 => DfuStatus::Rejected,
+            other => DfuStatus::Unknown(other),
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            DfuStatus::Ok => 0,
+            DfuStatus::ChecksumError => 1,
+            DfuStatus::BadOffset => 2,
+            DfuStatus::Rejected => 3,
+            DfuStatus::Unknown(byte) => byte,
+        }
+    }
+}
+
+impl fmt::Display for DfuStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DfuStatus::Ok => write!(f, "ok"),
+            DfuStatus::ChecksumError => write!(f, "checksum error"),
+            DfuStatus::BadOffset => write!(f, "unexpected offset"),
+            DfuStatus::Rejected => write!(f, "image rejected"),
+            DfuStatus::Unknown(byte) => write!(f, "unknown status 0x{byte:02X}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DfuState {
+    pub in_bootloader: bool,
+    pub status: DfuStatus,
+    /// Offset of the next chunk the bootloader expects.
+    pub next_offset: u32,
+}
+
+/// CRC-32 (IEEE) as used for DFU chunks and images.
+pub fn dfu_checksum(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+pub(crate) fn dfu_protocol(device_id: DeviceId, target: FirmwareTarget) -> Option<DfuProtocol> {
+    let descriptor = find_descriptor(device_id);
+    match target {
+        FirmwareTarget::Headset => descriptor.dfu,
+        FirmwareTarget::Dongle => descriptor.receiver.and_then(|receiver| receiver.dfu),
+    }
+}
+
+/// Whether `report_id` is a DFU report of the headset or its receiver, for
+/// the simulator's bootloader.
+pub fn is_dfu_report(device_id: DeviceId, report_id: u8) -> bool {
+    [FirmwareTarget::Headset, FirmwareTarget::Dongle]
+        .into_iter()
+        .filter_map(|target| dfu_protocol(device_id, target))
+        .any(|dfu| dfu.report_id == report_id)
+}
+
+/// Whether `target`'s bootloader can read back its installed firmware.
+pub fn supports_firmware_backup(device_id: DeviceId, target: FirmwareTarget) -> bool {
+    dfu_protocol(device_id, target).is_some_and(|dfu| dfu.readback)
+}
+
+/// An open connection to a device's bootloader. Every report is
+/// `[report ID, command, ...]`:
+///
+/// - enter: image size and checksum, both `u32` little-endian; the device
+///   switches to its bootloader and keeps running the old image until a
+///   finished upload passes its checks
+/// - data: offset (`u32`), length (`u8`), the chunk and its checksum
+/// - finish: the image checksum again; the bootloader verifies and reboots
+/// - abort: discard what was received and return to the old image
+/// - read: offset (`u32`) and length (`u8`) of a block of the installed
+///   image, on bootloaders with read-back; the next read of the report
+///   returns `[report ID, read command, image size (u32), length (u8), the
+///   block, its checksum]`
+///
+/// Otherwise reading the report returns `[report ID, status command, in
+/// bootloader, status, next offset (u32)]`.
+///
+/// The session holds the device for as long as it is open: operations that
+/// were running finish first, and later ones fail with
+/// [`ControlError::Flashing`] until it is dropped, so no other report reaches
+/// the bootloader halfway through an upload.
+pub struct DfuSession<'a> {
+    device: &'a Device,
+    handles: MutexGuard<'a, HashMap<FirmwareTarget, Handle>>,
+    target: FirmwareTarget,
+    handle: Handle,
+    report: FeatureReport,
+}
+
+impl<'a> DfuSession<'a> {
+    pub fn open(device: &'a Device, target: FirmwareTarget) -> Result<Self, ControlError> {
+        let report_id = dfu_protocol(device.id, target)
+            .ok_or(ControlError::UnsupportedFeature {
+                device_id: device.id,
+                capability: Capability::FirmwareUpdate,
+            })?
+            .report_id;
+        let handles = device.lock_handles();
+        let handle = Handle::open_target(device.id, target)?;
+        device.flashing.store(true, Ordering::SeqCst);
+        Ok(DfuSession {
+            device,
+            handles,
+            target,
+            handle,
+            report: FeatureReport {
+                report_id,
+                selector: DFU_STATUS,
+                length: REPORT_LENGTH,
+            },
+        })
+    }
+
+    /// Payload bytes per data report. Fails when the device's reports are too
+    /// short to carry any.
+    pub fn chunk_size(&self) -> Result<usize, ControlError> {
+        let length = self.handle.report_length(self.report);
+        match length.saturating_sub(DFU_CHUNK_OVERHEAD) {
+            0 => Err(ControlError::InvalidReport {
+                reason: format!(
+                    "bootloader reports of {length} bytes leave no room for image data"
+                ),
+            }),
+            size => Ok(size.min(usize::from(u8::MAX))),
+        }
+    }
+
+    fn send(&self, command: u8, body: &[u8]) -> Result<(), ControlError> {
+        let mut payload = vec![0u8; self.handle.report_length(self.report)];
+        let Some(slot) = payload.get_mut(2..2 + body.len()) else {
+            return Err(ControlError::InvalidReport {
+                reason: format!(
+                    "{} bytes do not fit a bootloader report of {} bytes",
+                    2 + body.len(),
+                    payload.len()
+                ),
+            });
+        };
+        slot.copy_from_slice(body);
+        payload[0] = self.report.report_id;
+        payload[1] = command;
+        self.handle
+            .send_feature_report(&payload)
+            .map_err(|source| ControlError::ReportSend {
+                report_id: self.report.report_id,
+                selector: command,
+                source,
+            })
+    }
+
+    /// The response body after the report ID, which must start with `command`
+    /// and hold at least `length` bytes after it.
+    fn read(&self, command: u8, length: usize) -> Result<Vec<u8>, ControlError> {
+        let mut buffer = vec![0u8; self.handle.report_length(self.report)];
+        buffer[0] = self.report.report_id;
+        let read_error = |source| ControlError::ReportRead {
+            report_id: self.report.report_id,
+            selector: command,
+            source,
+        };
+        let received = self
+            .handle
+            .get_feature_report(&mut buffer)
+            .map_err(read_error)?;
+        let report = FeatureReport {
+            selector: command,
+            ..self.report
+        };
+        let body = response_body(report, &buffer[..received.min(buffer.len())]);
+        if body.len() <= length || body[0] != command {
+            return Err(read_error(hidapi::HidError::HidApiError {
+                message: format!("unexpected bootloader response {}", recorder::hex(body)),
+            }));
+        }
+        Ok(body.to_vec())
+    }
+
+    pub fn state(&self) -> Result<DfuState, ControlError> {
+        let body = self.read(DFU_STATUS, 6)?;
+        Ok(DfuState {
+            in_bootloader: body[1] != 0,
+            status: DfuStatus::from_byte(body[2]),
+            next_offset: u32::from_le_bytes([body[3], body[4], body[5], body[6]]),
+        })
+    }
+
+    pub fn enter(&self, image: &[u8]) -> Result<DfuState, ControlError> {
+        let mut body = (image.len() as u32).to_le_bytes().to_vec();
+        body.extend(dfu_checksum(image).to_le_bytes());
+        self.send(DFU_ENTER, &body)?;
+        self.state()
+    }
+
+    /// Send `chunk` (at most [`DfuSession::chunk_size`] bytes) for `offset`.
+    pub fn write_chunk(&self, offset: usize, chunk: &[u8]) -> Result<DfuState, ControlError> {
+        let mut body = (offset as u32).to_le_bytes().to_vec();
+        body.push(chunk.len() as u8);
+        body.extend_from_slice(chunk);
+        body.extend(dfu_checksum(chunk).to_le_bytes());
+        self.send(DFU_DATA, &body)?;
+        self.state()
+    }
+
+    pub fn finish(&self, image: &[u8]) -> Result<DfuState, ControlError> {
+        self.send(DFU_FINISH, &dfu_checksum(image).to_le_bytes())?;
+        self.state()
+    }
+
+    pub fn abort(&self) -> Result<(), ControlError> {
+        self.send(DFU_ABORT, &[])
+    }
+
+    /// Up to `length` bytes of the installed image at `offset`, with the
+    /// image's total size. `Ok(None)` when the block arrived damaged.
+    pub fn read_block(
+        &self,
+        offset: usize,
+        length: usize,
+    ) -> Result<Option<(usize, Vec<u8>)>, ControlError> {
+        let mut request = (offset as u32).to_le_bytes().to_vec();
+        request.push(length.min(self.chunk_size()?) as u8);
+        self.send(DFU_READ, &request)?;
+        let body = self.read(DFU_READ, 9)?;
+        let size = u32::from_le_bytes([body[1], body[2], body[3], body[4]]) as usize;
+        let length = usize::from(body[5]);
+        let (Some(block), Some(checksum)) =
+            (body.get(6..6 + length), body.get(6 + length..10 + length))
+        else {
+            return Ok(None);
+        };
+        let checksum = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        Ok((dfu_checksum(block) == checksum).then(|| (size, block.to_vec())))
+    }
+}
+
+impl Drop for DfuSession<'_> {
+    fn drop(&mut self) {
+        // The device may have rebooted into its new image, which the old
+        // handle no longer reaches.
+        self.handles.remove(&self.target);
+        self.device.flashing.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Parsed DFU report of the simulator's bootloader.
+pub enum DfuCommand<'a> {
+    Enter {
+        size: u32,
+        checksum: u32,
+    },
+    Data {
+        offset: u32,
+        chunk: &'a [u8],
+        checksum: u32,
+    },
+    Finish {
+        checksum: u32,
+    },
+    Abort,
+    Read {
+        offset: u32,
+        length: u8,
+    },
+}
+
+impl<'a> DfuCommand<'a> {
+    /// Parse a report sent by [`DfuSession`], report ID first.
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let u32_at = |at: usize| -> Option<u32> {
+            Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+        };
+        match *data.get(1)? {
+            DFU_ENTER => Some(DfuCommand::Enter {
+                size: u32_at(2)?,
+                checksum: u32_at(6)?,
+            }),
+            DFU_DATA => {
+                let length = usize::from(*data.get(6)?);
+                Some(DfuCommand::Data {
+                    offset: u32_at(2)?,
+                    chunk: data.get(7..7 + length)?,
+                    checksum: u32_at(7 + length)?,
+                })
+            }
+            DFU_FINISH => Some(DfuCommand::Finish {
+                checksum: u32_at(2)?,
+            }),
+            DFU_ABORT => Some(DfuCommand::Abort),
+            DFU_READ => Some(DfuCommand::Read {
+                offset: u32_at(2)?,
+                length: *data.get(6)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The status response the simulator's bootloader answers with.
+pub fn dfu_status_response(report_id: u8, state: DfuState) -> Vec<u8> {
+    let mut response = vec![
+        report_id,
+        DFU_STATUS,
+        u8::from(state.in_bootloader),
+        state.status.to_byte(),
+    ];
+    response.extend(state.next_offset.to_le_bytes());
+    response
+}
+
+/// The simulator's answer to a read of `block` from an image of `size` bytes.
+pub fn dfu_read_response(report_id: u8, size: usize, block: &[u8]) -> Vec<u8> {
+    let mut response = vec![report_id, DFU_READ];
+    response.extend((size as u32).to_le_bytes());
+    response.push(block.len() as u8);
+    response.extend_from_slice(block);
+    response.extend(dfu_checksum(block).to_le_bytes());
+    response
+}
@@ -0,0 +1,119 @@
+//! Why a headset could not be opened. Working that out takes the platform's
+//! permission model into account, so it is left to the [`crate::host::Host`];
+//! this is what it answers with.
+
+use crate::i18n;
+use serde::Serialize;
+use std::fmt;
+
+/// Best guess at why a device could not be opened, with a fix the user can apply.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "cause", rename_all = "snake_case")]
+pub enum OpenDiagnosis {
+    /// No HID interface with the expected VID/PID is enumerated.
+    NotConnected,
+    /// The hidraw node is root-only and no HyperX Pilot udev rule is installed.
+    MissingUdevRule {
+        node: String,
+    },
+    /// The hidraw node belongs to a group the current user is not a member of.
+    NotInGroup {
+        node: String,
+        group: String,
+    },
+    /// Rules are installed, but the node still carries the old permissions.
+    ReconnectRequired {
+        node: String,
+    },
+    /// A Flatpak/Snap sandbox hides the HID nodes from the app.
+    SandboxRestricted {
+        sandbox: String,
+        grant_command: String,
+    },
+    /// macOS refused the open because Input Monitoring was not granted.
+    InputMonitoringDenied,
+    /// USB functions with the headset's VID/PID are present, but no usable HID
+    /// interface (Windows, usually a missing vendor driver package).
+    DriverMissing {
+        hardware_ids: Vec<String>,
+    },
+    /// Another process holds the device exclusively; `process` names it when known.
+    DeviceBusy {
+        process: Option<String>,
+    },
+    Unknown,
+}
+
+impl OpenDiagnosis {
+    /// What went wrong in `locale`, see [`crate::i18n`].
+    pub fn message(&self, locale: &str) -> String {
+        let translate = |id, args: &[(&str, &dyn fmt::Display)]| i18n::translate(locale, id, args);
+        match self {
+            OpenDiagnosis::NotConnected => translate("diagnosis-not-connected", &[]),
+            OpenDiagnosis::MissingUdevRule { node } => {
+                translate("diagnosis-missing-udev-rule", &[("node", node)])
+            }
+            OpenDiagnosis::NotInGroup { node, group } => translate(
+                "diagnosis-not-in-group",
+                &[("node", node), ("group", group)],
+            ),
+            OpenDiagnosis::ReconnectRequired { node } => {
+                translate("diagnosis-reconnect-required", &[("node", node)])
+            }
+            OpenDiagnosis::SandboxRestricted { sandbox, .. } => {
+                translate("diagnosis-sandbox-restricted", &[("sandbox", sandbox)])
+            }
+            OpenDiagnosis::InputMonitoringDenied => {
+                translate("diagnosis-input-monitoring-denied", &[])
+            }
+            OpenDiagnosis::DriverMissing { .. } => translate("diagnosis-driver-missing", &[]),
+            OpenDiagnosis::DeviceBusy {
+                process: Some(process),
+            } => translate("diagnosis-device-busy", &[("process", process)]),
+            OpenDiagnosis::DeviceBusy { process: None } => {
+                translate("diagnosis-device-busy-unknown", &[])
+            }
+            OpenDiagnosis::Unknown => translate("diagnosis-unknown", &[]),
+        }
+    }
+
+    /// Suggested fix in `locale`, phrased for end users.
+    pub fn remedy(&self, locale: &str) -> String {
+        let translate = |id, args: &[(&str, &dyn fmt::Display)]| i18n::translate(locale, id, args);
+        match self {
+            OpenDiagnosis::NotConnected => translate("remedy-not-connected", &[]),
+            OpenDiagnosis::MissingUdevRule { .. } => translate("remedy-missing-udev-rule", &[]),
+            OpenDiagnosis::NotInGroup { group, .. } => {
+                translate("remedy-not-in-group", &[("group", group)])
+            }
+            OpenDiagnosis::ReconnectRequired { .. } => translate("remedy-reconnect-required", &[]),
+            OpenDiagnosis::SandboxRestricted {
+                sandbox,
+                grant_command,
+            } => translate(
+                "remedy-sandbox-restricted",
+                &[("sandbox", sandbox), ("grant_command", grant_command)],
+            ),
+            OpenDiagnosis::InputMonitoringDenied => {
+                translate("remedy-input-monitoring-denied", &[])
+            }
+            OpenDiagnosis::DriverMissing { hardware_ids } => translate(
+                "remedy-driver-missing",
+                &[("hardware_ids", &hardware_ids.join(", "))],
+            ),
+            OpenDiagnosis::DeviceBusy {
+                process: Some(process),
+            } => translate("remedy-device-busy-process", &[("process", process)]),
+            OpenDiagnosis::DeviceBusy { process: None } => {
+                translate("remedy-device-busy-unknown", &[])
+            }
+            OpenDiagnosis::Unknown => translate("remedy-unknown", &[]),
+        }
+    }
+}
+
+impl fmt::Display for OpenDiagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message(i18n::DEFAULT_LOCALE))
+    }
+}
@@ -0,0 +1,1958 @@
+//! What a device can do: the features and capabilities of each model, how
+//! their values are laid out in feature reports, and the [`Device`] methods
+//! that read and write them.
+
+use crate::device::{devices, is_dry_run, read_value, Device, FirmwareTarget, Handle};
+use crate::dfu::dfu_protocol;
+use crate::{
+    find_descriptor, host, metrics, recorder, ControlError, DeviceDescriptor, DeviceId,
+    FeatureReport, BUTTONS_ASSIGN, BUTTONS_SELECT, MIN_PAYLOAD_LENGTH, PAIRINGS_REMOVE,
+    PAIRINGS_SELECT,
+};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// What a feature report of a device controls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeatureKind {
+    Sidetone,
+    Volume,
+    Battery,
+    MuteLed,
+    Firmware,
+    WirelessMode,
+    Multipoint,
+    OutputMute,
+    VoicePrompts,
+    LedBrightness,
+    NotificationBeeps,
+    MuteBeepVolume,
+    Boom,
+    Anc,
+    AncLevel,
+    Ambient,
+    AutoAnswer,
+    /// On the receiver, so not part of the headset's [`features`].
+    GameMode,
+}
+
+impl FeatureKind {
+    pub const ALL: [FeatureKind; 18] = [
+        FeatureKind::Sidetone,
+        FeatureKind::Volume,
+        FeatureKind::Battery,
+        FeatureKind::MuteLed,
+        FeatureKind::Firmware,
+        FeatureKind::WirelessMode,
+        FeatureKind::Multipoint,
+        FeatureKind::OutputMute,
+        FeatureKind::VoicePrompts,
+        FeatureKind::LedBrightness,
+        FeatureKind::NotificationBeeps,
+        FeatureKind::MuteBeepVolume,
+        FeatureKind::Boom,
+        FeatureKind::Anc,
+        FeatureKind::AncLevel,
+        FeatureKind::Ambient,
+        FeatureKind::AutoAnswer,
+        FeatureKind::GameMode,
+    ];
+
+    /// The serialised name, e.g. `mute_led`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FeatureKind::Sidetone => "sidetone",
+            FeatureKind::Volume => "volume",
+            FeatureKind::Battery => "battery",
+            FeatureKind::MuteLed => "mute_led",
+            FeatureKind::Firmware => "firmware",
+            FeatureKind::WirelessMode => "wireless_mode",
+            FeatureKind::Multipoint => "multipoint",
+            FeatureKind::OutputMute => "output_mute",
+            FeatureKind::VoicePrompts => "voice_prompts",
+            FeatureKind::LedBrightness => "led_brightness",
+            FeatureKind::NotificationBeeps => "notification_beeps",
+            FeatureKind::MuteBeepVolume => "mute_beep_volume",
+            FeatureKind::Boom => "boom",
+            FeatureKind::Anc => "anc",
+            FeatureKind::AncLevel => "anc_level",
+            FeatureKind::Ambient => "ambient",
+            FeatureKind::AutoAnswer => "auto_answer",
+            FeatureKind::GameMode => "game_mode",
+        }
+    }
+}
+
+/// Something a model may be able to do, named by
+/// [`ControlError::UnsupportedFeature`] when it cannot, so frontends can hide
+/// the control instead of showing an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Sidetone,
+    Volume,
+    Battery,
+    MuteLed,
+    /// A firmware version report; every device tells its version from the
+    /// USB descriptor otherwise.
+    Firmware,
+    WirelessMode,
+    /// Also covers managing the Bluetooth pairings.
+    Multipoint,
+    OutputMute,
+    VoicePrompts,
+    LedBrightness,
+    NotificationBeeps,
+    MuteBeepVolume,
+    /// Reporting the position of a flip-to-mute microphone boom.
+    Boom,
+    /// Any of voice prompts, LED brightness and notification beeps, which
+    /// the app's "do not disturb" turns off together.
+    DoNotDisturb,
+    /// Active noise cancellation, including its level.
+    Anc,
+    /// The ambient (transparency) mode.
+    Ambient,
+    /// Answering Bluetooth calls when the headset is put on.
+    AutoAnswer,
+    /// Giving buttons other functions.
+    ButtonRemapping,
+    /// Reporting button presses, which the app can bind macros to, and
+    /// turns of the volume dial.
+    ButtonEvents,
+    GameMode,
+    FactoryReset,
+    Identify,
+    /// A USB receiver with its own firmware.
+    Receiver,
+    ReceiverPairing,
+    HeadsetLink,
+    FirmwareUpdate,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 26] = [
+        Capability::Sidetone,
+        Capability::Volume,
+        Capability::Battery,
+        Capability::MuteLed,
+        Capability::Firmware,
+        Capability::WirelessMode,
+        Capability::Multipoint,
+        Capability::OutputMute,
+        Capability::VoicePrompts,
+        Capability::LedBrightness,
+        Capability::NotificationBeeps,
+        Capability::MuteBeepVolume,
+        Capability::Boom,
+        Capability::DoNotDisturb,
+        Capability::Anc,
+        Capability::Ambient,
+        Capability::AutoAnswer,
+        Capability::ButtonRemapping,
+        Capability::ButtonEvents,
+        Capability::GameMode,
+        Capability::FactoryReset,
+        Capability::Identify,
+        Capability::Receiver,
+        Capability::ReceiverPairing,
+        Capability::HeadsetLink,
+        Capability::FirmwareUpdate,
+    ];
+
+    /// The serialised name, e.g. `factory_reset`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Capability::Sidetone => "sidetone",
+            Capability::Volume => "volume",
+            Capability::Battery => "battery",
+            Capability::MuteLed => "mute_led",
+            Capability::Firmware => "firmware",
+            Capability::WirelessMode => "wireless_mode",
+            Capability::Multipoint => "multipoint",
+            Capability::OutputMute => "output_mute",
+            Capability::VoicePrompts => "voice_prompts",
+            Capability::LedBrightness => "led_brightness",
+            Capability::NotificationBeeps => "notification_beeps",
+            Capability::MuteBeepVolume => "mute_beep_volume",
+            Capability::Boom => "boom",
+            Capability::DoNotDisturb => "do_not_disturb",
+            Capability::Anc => "anc",
+            Capability::Ambient => "ambient",
+            Capability::AutoAnswer => "auto_answer",
+            Capability::ButtonRemapping => "button_remapping",
+            Capability::ButtonEvents => "button_events",
+            Capability::GameMode => "game_mode",
+            Capability::FactoryReset => "factory_reset",
+            Capability::Identify => "identify",
+            Capability::Receiver => "receiver",
+            Capability::ReceiverPairing => "receiver_pairing",
+            Capability::HeadsetLink => "headset_link",
+            Capability::FirmwareUpdate => "firmware_update",
+        }
+    }
+}
+
+impl From<FeatureKind> for Capability {
+    fn from(kind: FeatureKind) -> Self {
+        match kind {
+            FeatureKind::Sidetone => Capability::Sidetone,
+            FeatureKind::Volume => Capability::Volume,
+            FeatureKind::Battery => Capability::Battery,
+            FeatureKind::MuteLed => Capability::MuteLed,
+            FeatureKind::Firmware => Capability::Firmware,
+            FeatureKind::WirelessMode => Capability::WirelessMode,
+            FeatureKind::Multipoint => Capability::Multipoint,
+            FeatureKind::OutputMute => Capability::OutputMute,
+            FeatureKind::VoicePrompts => Capability::VoicePrompts,
+            FeatureKind::LedBrightness => Capability::LedBrightness,
+            FeatureKind::NotificationBeeps => Capability::NotificationBeeps,
+            FeatureKind::MuteBeepVolume => Capability::MuteBeepVolume,
+            FeatureKind::Boom => Capability::Boom,
+            FeatureKind::Anc | FeatureKind::AncLevel => Capability::Anc,
+            FeatureKind::Ambient => Capability::Ambient,
+            FeatureKind::AutoAnswer => Capability::AutoAnswer,
+            FeatureKind::GameMode => Capability::GameMode,
+        }
+    }
+}
+
+/// The feature `report_id` (and `selector`, when known) belongs to, with the
+/// selector the device answers with.
+pub fn feature_kind(
+    device_id: DeviceId,
+    report_id: u8,
+    selector: Option<u8>,
+) -> Option<(FeatureKind, u8)> {
+    features(find_descriptor(device_id))
+        .into_iter()
+        .find_map(|(kind, feature)| {
+            let feature = feature?;
+            let matches = feature.report_id == report_id
+                && selector.is_none_or(|selector| selector == feature.selector);
+            matches.then_some((kind, feature.selector))
+        })
+}
+
+pub(crate) fn features(descriptor: DeviceDescriptor) -> [(FeatureKind, Option<FeatureReport>); 17] {
+    [
+        (FeatureKind::Sidetone, descriptor.sidetone_feature),
+        (FeatureKind::Volume, descriptor.volume_feature),
+        (FeatureKind::Battery, descriptor.battery_feature),
+        (FeatureKind::MuteLed, descriptor.mute_led_feature),
+        (FeatureKind::Firmware, descriptor.firmware_feature),
+        (FeatureKind::WirelessMode, descriptor.wireless_mode_feature),
+        (
+            FeatureKind::Multipoint,
+            descriptor
+                .multipoint
+                .map(|multipoint| multipoint.enabled_feature),
+        ),
+        (FeatureKind::OutputMute, descriptor.output_mute_feature),
+        (FeatureKind::VoicePrompts, descriptor.voice_prompts_feature),
+        (
+            FeatureKind::LedBrightness,
+            descriptor.led_brightness_feature,
+        ),
+        (
+            FeatureKind::NotificationBeeps,
+            descriptor.notification_beeps_feature,
+        ),
+        (FeatureKind::MuteBeepVolume, descriptor.mute_beep_feature),
+        (FeatureKind::Boom, descriptor.boom_feature),
+        (
+            FeatureKind::Anc,
+            descriptor.anc.map(|anc| anc.enabled_feature),
+        ),
+        (
+            FeatureKind::AncLevel,
+            descriptor.anc.map(|anc| anc.level_feature),
+        ),
+        (
+            FeatureKind::Ambient,
+            descriptor.anc.and_then(|anc| anc.ambient_feature),
+        ),
+        (FeatureKind::AutoAnswer, descriptor.auto_answer_feature),
+    ]
+}
+
+/// `feature`, or the error naming `capability` when the model lacks it, so
+/// nothing is sent that the firmware could misread.
+pub(crate) fn validate_feature(
+    device_id: DeviceId,
+    capability: Capability,
+    feature: Option<FeatureReport>,
+) -> Result<FeatureReport, ControlError> {
+    feature.ok_or(ControlError::UnsupportedFeature {
+        device_id,
+        capability,
+    })
+}
+
+/// Longest report a [`ReportBuffer`] holds without allocating; HyperX
+/// feature reports are 62 bytes plus the report ID.
+const INLINE_REPORT_LENGTH: usize = 64;
+
+/// A report of up to `N` bytes on the stack, so the write path behind
+/// sliders sends reports without allocating. Longer reports, which only
+/// Windows' padding to an interface's largest report asks for, fall back to
+/// the heap.
+pub(crate) struct ReportBuffer<const N: usize = INLINE_REPORT_LENGTH> {
+    inline: [u8; N],
+    heap: Vec<u8>,
+    length: usize,
+}
+
+impl<const N: usize> ReportBuffer<N> {
+    pub(crate) fn zeroed(length: usize) -> Self {
+        ReportBuffer {
+            inline: [0; N],
+            heap: if length > N {
+                vec![0; length]
+            } else {
+                Vec::new()
+            },
+            length,
+        }
+    }
+
+    pub(crate) fn truncate(&mut self, length: usize) {
+        self.length = self.length.min(length);
+        self.heap.truncate(self.length);
+    }
+}
+
+impl<const N: usize> Deref for ReportBuffer<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        if self.heap.is_empty() {
+            &self.inline[..self.length]
+        } else {
+            &self.heap
+        }
+    }
+}
+
+impl<const N: usize> DerefMut for ReportBuffer<N> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        if self.heap.is_empty() {
+            &mut self.inline[..self.length]
+        } else {
+            &mut self.heap
+        }
+    }
+}
+
+pub(crate) fn build_feature_payload(
+    report: FeatureReport,
+    value: u16,
+    length: usize,
+) -> ReportBuffer {
+    let mut payload = ReportBuffer::zeroed(length.max(MIN_PAYLOAD_LENGTH));
+    payload[0] = report.report_id;
+    payload[1] = report.selector;
+    payload[2] = (value & 0xFF) as u8;
+    payload[3] = (value >> 8) as u8;
+    payload
+}
+
+/// Body of a feature report response starting at the selector byte. hidraw
+/// and Windows keep the report ID in front, but some IOKit paths strip it.
+pub(crate) fn response_body(report: FeatureReport, response: &[u8]) -> &[u8] {
+    match response.first() {
+        Some(&first) if first == report.report_id && first != report.selector => &response[1..],
+        _ => response,
+    }
+}
+
+/// The 16-bit value in a feature report response, `None` when the selector
+/// does not match or the response is too short.
+pub(crate) fn parse_response(feature: FeatureReport, response: &[u8]) -> Option<u16> {
+    let body = response_body(feature, response);
+    if body.len() < 3 || body[0] != feature.selector {
+        return None;
+    }
+    Some(u16::from_le_bytes([body[1], body[2]]))
+}
+
+/// The status byte following the value in a response, see
+/// [`DeviceDescriptor::write_status`].
+pub(crate) fn write_status(feature: FeatureReport, response: &[u8]) -> Option<u8> {
+    let body = response_body(feature, response);
+    if body.first() != Some(&feature.selector) {
+        return None;
+    }
+    body.get(3).copied()
+}
+
+/// A percentage reported by the device, clamped to 100.
+pub(crate) fn percent(value: u16) -> u8 {
+    value.min(100) as u8
+}
+
+impl Device {
+    /// Whether the device can do sidetone itself; others need the software
+    /// fallback.
+    pub fn has_hardware_sidetone(&self) -> bool {
+        self.descriptor.sidetone_feature.is_some()
+    }
+
+    /// Toggle the sidetone feature.
+    pub fn set_sidetone(&self, enabled: bool) -> Result<(), ControlError> {
+        self.write(FeatureKind::Sidetone, u16::from(enabled))
+    }
+
+    /// Read the current sidetone state. Returns `Ok(Some(true/false))` when the device reports a valid value,
+    /// `Ok(None)` when the response cannot be interpreted (selector mismatch, truncated data).
+    pub fn read_sidetone_state(&self) -> Result<Option<bool>, ControlError> {
+        let value = self.read(FeatureKind::Sidetone)?;
+        Ok(value.map(|value| value != 0))
+    }
+
+    /// Whether the headset reports and accepts its headphone volume over HID.
+    pub fn has_hardware_volume(&self) -> bool {
+        self.descriptor.volume_feature.is_some()
+    }
+
+    /// Set the headphone volume in percent (clamped to 100).
+    pub fn set_volume(&self, percent: u8) -> Result<(), ControlError> {
+        self.write(FeatureKind::Volume, u16::from(percent.min(100)))
+    }
+
+    /// Current headphone volume in percent, see [`Device::read_sidetone_state`]
+    /// for `Ok(None)`.
+    pub fn read_volume(&self) -> Result<Option<u8>, ControlError> {
+        let value = self.read(FeatureKind::Volume)?;
+        Ok(value.map(percent))
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.descriptor.battery_feature.is_some()
+    }
+
+    /// Battery charge in percent, see [`Device::read_sidetone_state`] for
+    /// `Ok(None)`. Corrected by the device's calibration curve, if it has one.
+    pub fn read_battery(&self) -> Result<Option<u8>, ControlError> {
+        let value = self.read(FeatureKind::Battery)?;
+        Ok(value.map(|value| host::get().correct_battery(self.id, percent(value))))
+    }
+
+    /// Whether the headset reports charge changes on its own, see
+    /// [`Device::input_listener`].
+    pub fn pushes_battery(&self) -> bool {
+        self.descriptor.battery_input.is_some()
+    }
+
+    /// Whether the headset reports its button presses or volume dial turns,
+    /// see [`Device::input_listener`].
+    pub fn pushes_buttons(&self) -> bool {
+        self.descriptor.button_input.is_some() || self.descriptor.dial_input.is_some()
+    }
+
+    /// Listen for the battery, button and dial reports the headset sends on
+    /// its own, over a handle of the listener's own, so waiting for them does
+    /// not hold up other operations. Fails naming [`Capability::ButtonEvents`]
+    /// when the headset sends none.
+    pub fn input_listener(&self) -> Result<InputListener, ControlError> {
+        let descriptor = self.descriptor;
+        if !self.pushes_battery() && !self.pushes_buttons() {
+            return Err(ControlError::UnsupportedFeature {
+                device_id: self.id,
+                capability: Capability::ButtonEvents,
+            });
+        }
+        Ok(InputListener {
+            handle: Handle::open(self.id)?,
+            battery: descriptor.battery_input,
+            buttons: descriptor.button_input,
+            dial: descriptor.dial_input,
+        })
+    }
+
+    pub fn has_mute_led(&self) -> bool {
+        self.descriptor.mute_led_feature.is_some()
+    }
+
+    pub fn set_mute_led(&self, lit: bool) -> Result<(), ControlError> {
+        self.write(FeatureKind::MuteLed, u16::from(lit))
+    }
+
+    /// Whether the headset can mute its headphones itself, independently of
+    /// the OS output volume and mute.
+    pub fn has_output_mute(&self) -> bool {
+        self.descriptor.output_mute_feature.is_some()
+    }
+
+    /// Mute or unmute the headphones at the headset; the OS still plays to
+    /// it and shows the endpoint as unmuted.
+    pub fn set_output_mute(&self, muted: bool) -> Result<(), ControlError> {
+        self.write(FeatureKind::OutputMute, u16::from(muted))
+    }
+
+    /// Whether the headphones are muted at the headset, including by its own
+    /// mute control; see [`Device::read_sidetone_state`] for `Ok(None)`.
+    pub fn read_output_mute(&self) -> Result<Option<bool>, ControlError> {
+        let value = self.read(FeatureKind::OutputMute)?;
+        Ok(value.map(|value| value != 0))
+    }
+
+    pub fn set_voice_prompts(&self, enabled: bool) -> Result<(), ControlError> {
+        self.write(FeatureKind::VoicePrompts, u16::from(enabled))
+    }
+
+    /// See [`Device::read_sidetone_state`] for `Ok(None)`.
+    pub fn read_voice_prompts(&self) -> Result<Option<bool>, ControlError> {
+        let value = self.read(FeatureKind::VoicePrompts)?;
+        Ok(value.map(|value| value != 0))
+    }
+
+    /// Set the LED brightness in percent (clamped to 100); 0 turns the LEDs
+    /// off.
+    pub fn set_led_brightness(&self, percent: u8) -> Result<(), ControlError> {
+        self.write(FeatureKind::LedBrightness, u16::from(percent.min(100)))
+    }
+
+    /// See [`Device::read_sidetone_state`] for `Ok(None)`.
+    pub fn read_led_brightness(&self) -> Result<Option<u8>, ControlError> {
+        let value = self.read(FeatureKind::LedBrightness)?;
+        Ok(value.map(percent))
+    }
+
+    pub fn set_notification_beeps(&self, enabled: bool) -> Result<(), ControlError> {
+        self.write(FeatureKind::NotificationBeeps, u16::from(enabled))
+    }
+
+    /// See [`Device::read_sidetone_state`] for `Ok(None)`.
+    pub fn read_notification_beeps(&self) -> Result<Option<bool>, ControlError> {
+        let value = self.read(FeatureKind::NotificationBeeps)?;
+        Ok(value.map(|value| value != 0))
+    }
+
+    /// Set the volume of the mute and unmute beep in percent (clamped to
+    /// 100); 0 silences it.
+    pub fn set_mute_beep_volume(&self, percent: u8) -> Result<(), ControlError> {
+        self.write(FeatureKind::MuteBeepVolume, u16::from(percent.min(100)))
+    }
+
+    /// See [`Device::read_sidetone_state`] for `Ok(None)`.
+    pub fn read_mute_beep_volume(&self) -> Result<Option<u8>, ControlError> {
+        let value = self.read(FeatureKind::MuteBeepVolume)?;
+        Ok(value.map(percent))
+    }
+
+    /// Whether the headset reports where its flip-to-mute boom is.
+    pub fn has_boom_position(&self) -> bool {
+        self.descriptor.boom_feature.is_some()
+    }
+
+    /// Where the microphone boom is, see [`Device::read_sidetone_state`] for
+    /// `Ok(None)`.
+    pub fn read_boom_position(&self) -> Result<Option<BoomPosition>, ControlError> {
+        let value = self.read(FeatureKind::Boom)?;
+        Ok(value.and_then(boom_position))
+    }
+}
+
+/// Where a flip-to-mute microphone boom is; flipped up, the headset mutes
+/// the microphone itself, whatever the OS shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoomPosition {
+    /// Flipped up: muted.
+    Up,
+    /// At the mouth: live.
+    Down,
+}
+
+pub(crate) fn boom_position(value: u16) -> Option<BoomPosition> {
+    match value {
+        0 => Some(BoomPosition::Down),
+        1 => Some(BoomPosition::Up),
+        _ => None,
+    }
+}
+
+/// How a button was pressed, as the headset tells presses apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ButtonPress {
+    Single,
+    Double,
+    /// Held down for about a second.
+    Long,
+}
+
+impl ButtonPress {
+    /// The serialised name, e.g. `double`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ButtonPress::Single => "single",
+            ButtonPress::Double => "double",
+            ButtonPress::Long => "long",
+        }
+    }
+
+    fn from_code(code: u8) -> Option<ButtonPress> {
+        match code {
+            1 => Some(ButtonPress::Single),
+            2 => Some(ButtonPress::Double),
+            3 => Some(ButtonPress::Long),
+            _ => None,
+        }
+    }
+}
+
+/// A report a headset sent on its own, see [`Device::input_listener`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputReport {
+    /// The charge in percent, corrected like [`Device::read_battery`].
+    Battery(u8),
+    Button {
+        button: Button,
+        press: ButtonPress,
+    },
+    /// The volume dial was turned by `steps`, positive for louder.
+    Dial {
+        steps: i8,
+    },
+}
+
+/// Battery, button and dial reports of one headset, see
+/// [`Device::input_listener`].
+pub struct InputListener {
+    handle: Handle,
+    battery: Option<FeatureReport>,
+    buttons: Option<FeatureReport>,
+    dial: Option<FeatureReport>,
+}
+
+impl InputListener {
+    /// Wait up to `timeout` for the next battery, button or dial report, or
+    /// `Ok(None)` when none came. Other input reports, and presses of
+    /// buttons this version does not know, are skipped. Fails once the
+    /// headset was unplugged.
+    pub fn next(&self, timeout: Duration) -> Result<Option<InputReport>, ControlError> {
+        let length = [self.battery, self.buttons, self.dial]
+            .into_iter()
+            .flatten()
+            .map(|feature| feature.length)
+            .max()
+            .unwrap_or(MIN_PAYLOAD_LENGTH);
+        // A failed read is named after the battery report where there is one.
+        let (report_id, selector) = self
+            .battery
+            .or(self.buttons)
+            .or(self.dial)
+            .map_or((0, 0), |feature| (feature.report_id, feature.selector));
+        let mut buffer: ReportBuffer = ReportBuffer::zeroed(length);
+        let read = self
+            .handle
+            .read_input_report(&mut buffer, timeout)
+            .map_err(|source| ControlError::ReportRead {
+                report_id,
+                selector,
+                source,
+            })?;
+        buffer.truncate(read);
+        let is_report = |feature: &FeatureReport| buffer.first() == Some(&feature.report_id);
+        if let Some(feature) = self.buttons.filter(is_report) {
+            let Some(value) = parse_response(feature, &buffer) else {
+                return Ok(None);
+            };
+            let [button, press] = value.to_le_bytes();
+            return Ok(Button::from_code(button)
+                .zip(ButtonPress::from_code(press))
+                .map(|(button, press)| InputReport::Button { button, press }));
+        }
+        if let Some(feature) = self.dial.filter(is_report) {
+            return Ok(
+                parse_response(feature, &buffer).map(|value| InputReport::Dial {
+                    steps: value.to_le_bytes()[0] as i8,
+                }),
+            );
+        }
+        match self.battery.filter(is_report) {
+            Some(feature) => Ok(parse_response(feature, &buffer)
+                .map(|value| InputReport::Battery(self.record_battery(value)))),
+            None => Ok(None),
+        }
+    }
+
+    /// Cache a reported charge like a read, and return it in percent.
+    fn record_battery(&self, value: u16) -> u8 {
+        let device_id = self.handle.device_id;
+        let device = devices().get(device_id);
+        device
+            .state
+            .lock()
+            .unwrap()
+            .insert(FeatureKind::Battery, value);
+        device
+            .read_at
+            .lock()
+            .unwrap()
+            .insert(FeatureKind::Battery, Instant::now());
+        host::get().correct_battery(device_id, percent(value))
+    }
+}
+
+/// The radio a dual-mode headset uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WirelessMode {
+    /// 2.4 GHz through the USB receiver.
+    Dongle,
+    Bluetooth,
+}
+
+pub(crate) fn wireless_mode(value: u16) -> Option<WirelessMode> {
+    match value {
+        0 => Some(WirelessMode::Dongle),
+        1 => Some(WirelessMode::Bluetooth),
+        _ => None,
+    }
+}
+
+impl Device {
+    /// Whether the headset can switch between its receiver and Bluetooth.
+    pub fn has_wireless_modes(&self) -> bool {
+        self.descriptor.wireless_mode_feature.is_some()
+    }
+
+    /// Switch the headset's radio. Leaving the receiver for Bluetooth drops
+    /// the headset's link to the receiver, so later commands sent through it
+    /// fail until the headset is switched back with its power button
+    /// combination.
+    pub fn set_wireless_mode(&self, mode: WirelessMode) -> Result<(), ControlError> {
+        self.write(
+            FeatureKind::WirelessMode,
+            u16::from(mode == WirelessMode::Bluetooth),
+        )
+    }
+
+    /// The active radio, see [`Device::read_sidetone_state`] for `Ok(None)`.
+    pub fn read_wireless_mode(&self) -> Result<Option<WirelessMode>, ControlError> {
+        let value = self.read(FeatureKind::WirelessMode)?;
+        Ok(value.and_then(wireless_mode))
+    }
+}
+
+pub(crate) fn pairings_feature(device_id: DeviceId) -> Option<FeatureReport> {
+    find_descriptor(device_id)
+        .multipoint
+        .map(|multipoint| multipoint.pairings_feature)
+}
+
+/// Report ID and selector of the Bluetooth pairings report, for the simulator.
+pub fn pairings_report(device_id: DeviceId) -> Option<(u8, u8)> {
+    pairings_feature(device_id).map(|feature| (feature.report_id, feature.selector))
+}
+
+/// A Bluetooth source the headset is paired with.
+#[derive(Debug, Clone, Serialize)]
+pub struct BluetoothPairing {
+    /// Where the headset keeps it; pass it to
+    /// [`Device::remove_bluetooth_pairing`].
+    pub slot: u8,
+    /// E.g. `A4:C1:38:12:34:56`.
+    pub address: String,
+    pub name: String,
+    pub connected: bool,
+}
+
+/// Send a pairings command for `slot`.
+fn pairings_command(
+    device: &Handle,
+    feature: FeatureReport,
+    command: u8,
+    slot: u8,
+) -> Result<(), ControlError> {
+    let value = u16::from_le_bytes([command, slot]);
+    let payload = build_feature_payload(feature, value, device.report_length(feature));
+    device
+        .send_feature_report(&payload)
+        .map_err(|source| ControlError::ReportSend {
+            report_id: feature.report_id,
+            selector: feature.selector,
+            source,
+        })
+}
+
+/// Read the selected slot; returns the pairing and how many there are, or
+/// `None` when the response is not a pairing.
+fn read_pairing(
+    device: &Handle,
+    feature: FeatureReport,
+) -> Result<Option<(BluetoothPairing, u8)>, ControlError> {
+    let mut buffer = vec![0u8; device.report_length(feature)];
+    buffer[0] = feature.report_id;
+    let length =
+        device
+            .get_feature_report(&mut buffer)
+            .map_err(|source| ControlError::ReportRead {
+                report_id: feature.report_id,
+                selector: feature.selector,
+                source,
+            })?;
+    let body = response_body(feature, &buffer[..length.min(buffer.len())]);
+    let [selector, slot, count, connected, ref rest @ ..] = *body else {
+        return Ok(None);
+    };
+    if selector != feature.selector || rest.len() < 7 {
+        return Ok(None);
+    }
+    let (address, rest) = rest.split_at(6);
+    let name = rest[1..].get(..usize::from(rest[0])).unwrap_or(&rest[1..]);
+    let address = address
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":");
+    Ok(Some((
+        BluetoothPairing {
+            slot,
+            address,
+            name: String::from_utf8_lossy(name).into_owned(),
+            connected: connected != 0,
+        },
+        count,
+    )))
+}
+
+pub(crate) fn anc_level(value: u16) -> u8 {
+    value.min(u16::from(u8::MAX)) as u8
+}
+
+/// What a headset does with outside sound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseMode {
+    Off,
+    /// Active noise cancellation, at its current level.
+    Anc,
+    /// Outside sound is passed through, e.g. to hear someone talking.
+    Ambient,
+}
+
+impl Device {
+    pub fn has_anc(&self) -> bool {
+        self.descriptor.anc.is_some()
+    }
+
+    /// How many noise cancellation levels the headset has; `None` without
+    /// noise cancellation.
+    pub fn anc_levels(&self) -> Option<u8> {
+        self.descriptor.anc.map(|anc| anc.levels)
+    }
+
+    pub fn set_anc(&self, enabled: bool) -> Result<(), ControlError> {
+        self.write(FeatureKind::Anc, u16::from(enabled))
+    }
+
+    /// See [`Device::read_sidetone_state`] for `Ok(None)`.
+    pub fn read_anc(&self) -> Result<Option<bool>, ControlError> {
+        let value = self.read(FeatureKind::Anc)?;
+        Ok(value.map(|value| value != 0))
+    }
+
+    /// Select noise cancellation level `level`, clamped to 1 to
+    /// [`Device::anc_levels`]. The level is kept while ANC is off.
+    pub fn set_anc_level(&self, level: u8) -> Result<(), ControlError> {
+        let levels = self.anc_levels().unwrap_or(1);
+        self.write(FeatureKind::AncLevel, u16::from(level.clamp(1, levels)))
+    }
+
+    /// See [`Device::read_sidetone_state`] for `Ok(None)`.
+    pub fn read_anc_level(&self) -> Result<Option<u8>, ControlError> {
+        let value = self.read(FeatureKind::AncLevel)?;
+        Ok(value.map(anc_level))
+    }
+
+    pub fn has_ambient(&self) -> bool {
+        self.descriptor
+            .anc
+            .is_some_and(|anc| anc.ambient_feature.is_some())
+    }
+
+    pub fn set_ambient(&self, enabled: bool) -> Result<(), ControlError> {
+        self.write(FeatureKind::Ambient, u16::from(enabled))
+    }
+
+    /// See [`Device::read_sidetone_state`] for `Ok(None)`.
+    pub fn read_ambient(&self) -> Result<Option<bool>, ControlError> {
+        let value = self.read(FeatureKind::Ambient)?;
+        Ok(value.map(|value| value != 0))
+    }
+
+    /// Switch between noise cancellation, the ambient mode and neither,
+    /// turning the other one off first, as one change (see
+    /// [`Device::set_features`]).
+    pub fn set_noise_mode(&self, mode: NoiseMode) -> Result<(), ControlError> {
+        let features = match mode {
+            NoiseMode::Off => [Feature::Ambient(false), Feature::Anc(false)],
+            NoiseMode::Anc => [Feature::Ambient(false), Feature::Anc(true)],
+            NoiseMode::Ambient => [Feature::Anc(false), Feature::Ambient(true)],
+        };
+        // Without an ambient mode there is nothing of it to turn off.
+        let features: Vec<Feature> = features
+            .into_iter()
+            .filter(|&feature| self.has_ambient() || feature != Feature::Ambient(false))
+            .collect();
+        self.set_features(&features).map(drop)
+    }
+
+    /// See [`Device::read_sidetone_state`] for `Ok(None)`.
+    pub fn read_noise_mode(&self) -> Result<Option<NoiseMode>, ControlError> {
+        let Some(anc) = self.read_anc()? else {
+            return Ok(None);
+        };
+        if anc {
+            return Ok(Some(NoiseMode::Anc));
+        }
+        if !self.has_ambient() {
+            return Ok(Some(NoiseMode::Off));
+        }
+        Ok(self.read_ambient()?.map(|ambient| {
+            if ambient {
+                NoiseMode::Ambient
+            } else {
+                NoiseMode::Off
+            }
+        }))
+    }
+}
+
+impl Device {
+    pub fn has_multipoint(&self) -> bool {
+        self.descriptor.multipoint.is_some()
+    }
+
+    pub fn set_multipoint(&self, enabled: bool) -> Result<(), ControlError> {
+        self.write(FeatureKind::Multipoint, u16::from(enabled))
+    }
+
+    /// Whether two Bluetooth sources may connect at once, see
+    /// [`Device::read_sidetone_state`] for `Ok(None)`.
+    pub fn read_multipoint(&self) -> Result<Option<bool>, ControlError> {
+        let value = self.read(FeatureKind::Multipoint)?;
+        Ok(value.map(|value| value != 0))
+    }
+
+    /// Whether the firmware can answer incoming Bluetooth calls by itself
+    /// when the headset is put on.
+    pub fn has_auto_answer(&self) -> bool {
+        self.descriptor.auto_answer_feature.is_some()
+    }
+
+    pub fn set_auto_answer(&self, enabled: bool) -> Result<(), ControlError> {
+        self.write(FeatureKind::AutoAnswer, u16::from(enabled))
+    }
+
+    /// See [`Device::read_sidetone_state`] for `Ok(None)`.
+    pub fn read_auto_answer(&self) -> Result<Option<bool>, ControlError> {
+        let value = self.read(FeatureKind::AutoAnswer)?;
+        Ok(value.map(|value| value != 0))
+    }
+
+    /// The Bluetooth sources the headset is paired with.
+    pub fn list_bluetooth_pairings(&self) -> Result<Vec<BluetoothPairing>, ControlError> {
+        let feature = validate_feature(self.id, Capability::Multipoint, pairings_feature(self.id))?;
+        metrics::timed(self.id, "list_bluetooth_pairings", || {
+            self.with_handle(FirmwareTarget::Headset, |device| {
+                let mut pairings = Vec::new();
+                let mut slot = 0;
+                loop {
+                    pairings_command(device, feature, PAIRINGS_SELECT, slot)?;
+                    match read_pairing(device, feature)? {
+                        Some((pairing, count)) if slot < count => pairings.push(pairing),
+                        _ => break,
+                    }
+                    slot += 1;
+                }
+                Ok(pairings)
+            })
+        })
+    }
+
+    /// Make the headset forget the source in `slot`, disconnecting it.
+    pub fn remove_bluetooth_pairing(&self, slot: u8) -> Result<(), ControlError> {
+        let feature = validate_feature(self.id, Capability::Multipoint, pairings_feature(self.id))?;
+        metrics::timed(self.id, "remove_bluetooth_pairing", || {
+            self.with_handle(FirmwareTarget::Headset, |device| {
+                if is_dry_run() {
+                    info!(slot, "dry run: not removing the pairing");
+                    return Ok(());
+                }
+                pairings_command(device, feature, PAIRINGS_REMOVE, slot)
+            })
+        })
+    }
+}
+
+/// A headset button whose function the firmware lets you change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Button {
+    /// The button on the ear cup that plays, pauses and answers calls.
+    MultiFunction,
+    /// The button that switches noise cancellation and ambient mode.
+    NoiseControl,
+}
+
+impl Button {
+    /// The serialised name, e.g. `multi_function`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Button::MultiFunction => "multi_function",
+            Button::NoiseControl => "noise_control",
+        }
+    }
+
+    /// The button's number in the remapping and button reports.
+    fn code(self) -> u8 {
+        match self {
+            Button::MultiFunction => 0x01,
+            Button::NoiseControl => 0x02,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Button> {
+        [Button::MultiFunction, Button::NoiseControl]
+            .into_iter()
+            .find(|button| button.code() == code)
+    }
+}
+
+/// What a remappable button does when pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ButtonAction {
+    /// What the button does out of the box.
+    Default,
+    PlayPause,
+    NextTrack,
+    PreviousTrack,
+    /// Asks the phone's or computer's voice assistant.
+    VoiceAssistant,
+    /// Steps through noise cancellation, ambient mode and off.
+    NoiseMode,
+    /// Nothing, e.g. so the button is not pressed by accident.
+    Disabled,
+}
+
+impl ButtonAction {
+    const ALL: [ButtonAction; 7] = [
+        ButtonAction::Default,
+        ButtonAction::PlayPause,
+        ButtonAction::NextTrack,
+        ButtonAction::PreviousTrack,
+        ButtonAction::VoiceAssistant,
+        ButtonAction::NoiseMode,
+        ButtonAction::Disabled,
+    ];
+
+    /// The serialised name, e.g. `play_pause`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ButtonAction::Default => "default",
+            ButtonAction::PlayPause => "play_pause",
+            ButtonAction::NextTrack => "next_track",
+            ButtonAction::PreviousTrack => "previous_track",
+            ButtonAction::VoiceAssistant => "voice_assistant",
+            ButtonAction::NoiseMode => "noise_mode",
+            ButtonAction::Disabled => "disabled",
+        }
+    }
+
+    /// The action's number in the remapping report.
+    fn code(self) -> u8 {
+        match self {
+            ButtonAction::Default => 0x00,
+            ButtonAction::PlayPause => 0x01,
+            ButtonAction::NextTrack => 0x02,
+            ButtonAction::PreviousTrack => 0x03,
+            ButtonAction::VoiceAssistant => 0x04,
+            ButtonAction::NoiseMode => 0x05,
+            ButtonAction::Disabled => 0xFF,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<ButtonAction> {
+        ButtonAction::ALL
+            .into_iter()
+            .find(|action| action.code() == code)
+    }
+}
+
+/// A button and the actions it can be given, for a remapping UI.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RemappableButton {
+    pub button: Button,
+    /// In the order a menu would list them, [`ButtonAction::Default`] first.
+    pub actions: &'static [ButtonAction],
+}
+
+fn buttons_feature(device_id: DeviceId) -> Option<FeatureReport> {
+    find_descriptor(device_id)
+        .buttons
+        .map(|buttons| buttons.feature)
+}
+
+/// Report ID and selector of the button remapping report, for the simulator.
+pub fn buttons_report(device_id: DeviceId) -> Option<(u8, u8)> {
+    buttons_feature(device_id).map(|feature| (feature.report_id, feature.selector))
+}
+
+/// Send a remapping command for `button`.
+fn button_command(
+    device: &Handle,
+    feature: FeatureReport,
+    command: u8,
+    button: Button,
+    action: ButtonAction,
+) -> Result<(), ControlError> {
+    let value = u16::from_le_bytes([command, button.code()]);
+    // The action follows the 16-bit value.
+    let length = device.report_length(feature).max(MIN_PAYLOAD_LENGTH + 1);
+    let mut payload = build_feature_payload(feature, value, length);
+    payload[MIN_PAYLOAD_LENGTH] = action.code();
+    device
+        .send_feature_report(&payload)
+        .map_err(|source| ControlError::ReportSend {
+            report_id: feature.report_id,
+            selector: feature.selector,
+            source,
+        })
+}
+
+/// Read the selected button's action; `None` when the response is not about
+/// `button` or names an action this version does not know.
+fn read_button_action(
+    device: &Handle,
+    feature: FeatureReport,
+    button: Button,
+) -> Result<Option<ButtonAction>, ControlError> {
+    let mut buffer = vec![0u8; device.report_length(feature)];
+    buffer[0] = feature.report_id;
+    let length =
+        device
+            .get_feature_report(&mut buffer)
+            .map_err(|source| ControlError::ReportRead {
+                report_id: feature.report_id,
+                selector: feature.selector,
+                source,
+            })?;
+    let body = response_body(feature, &buffer[..length.min(buffer.len())]);
+    let [selector, code, action, ..] = *body else {
+        return Ok(None);
+    };
+    if selector != feature.selector || code != button.code() {
+        return Ok(None);
+    }
+    Ok(ButtonAction::from_code(action))
+}
+
+impl Device {
+    pub fn has_button_remapping(&self) -> bool {
+        self.descriptor.buttons.is_some()
+    }
+
+    /// The buttons that can be remapped and the actions each accepts; empty
+    /// on models without remapping.
+    pub fn remappable_buttons(&self) -> &'static [RemappableButton] {
+        self.descriptor
+            .buttons
+            .map_or(&[], |buttons| buttons.buttons)
+    }
+
+    /// What each remappable button is set to. Buttons whose response cannot
+    /// be read as an action are left out.
+    pub fn read_button_mapping(&self) -> Result<BTreeMap<Button, ButtonAction>, ControlError> {
+        let feature = validate_feature(
+            self.id,
+            Capability::ButtonRemapping,
+            buttons_feature(self.id),
+        )?;
+        metrics::timed(self.id, "read_button_mapping", || {
+            self.with_handle(FirmwareTarget::Headset, |device| {
+                let mut mapping = BTreeMap::new();
+                for remappable in self.remappable_buttons() {
+                    let button = remappable.button;
+                    button_command(
+                        device,
+                        feature,
+                        BUTTONS_SELECT,
+                        button,
+                        ButtonAction::Default,
+                    )?;
+                    if let Some(action) = read_button_action(device, feature, button)? {
+                        mapping.insert(button, action);
+                    }
+                }
+                Ok(mapping)
+            })
+        })
+    }
+
+    /// Give each button in `mapping` its action. Every pair is checked
+    /// against [`Device::remappable_buttons`] before the first is written, so
+    /// an action the model does not offer changes nothing.
+    pub fn set_button_mapping(
+        &self,
+        mapping: &BTreeMap<Button, ButtonAction>,
+    ) -> Result<(), ControlError> {
+        let feature = validate_feature(
+            self.id,
+            Capability::ButtonRemapping,
+            buttons_feature(self.id),
+        )?;
+        for (&button, &action) in mapping {
+            let offered = self.remappable_buttons().iter().any(|remappable| {
+                remappable.button == button && remappable.actions.contains(&action)
+            });
+            if !offered {
+                return Err(ControlError::UnsupportedButtonAction { button, action });
+            }
+        }
+        metrics::timed(self.id, "set_button_mapping", || {
+            self.with_handle(FirmwareTarget::Headset, |device| {
+                for (&button, &action) in mapping {
+                    if is_dry_run() {
+                        info!(
+                            button = button.as_str(),
+                            action = action.as_str(),
+                            "dry run: not remapping the button"
+                        );
+                        continue;
+                    }
+                    button_command(device, feature, BUTTONS_ASSIGN, button, action)?;
+                }
+                Ok(())
+            })
+        })
+    }
+
+    /// Give one button another function, see [`Device::set_button_mapping`].
+    pub fn set_button_action(
+        &self,
+        button: Button,
+        action: ButtonAction,
+    ) -> Result<(), ControlError> {
+        self.set_button_mapping(&BTreeMap::from([(button, action)]))
+    }
+}
+
+/// What identifies one physical headset, for a device info panel and support.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HardwareInfo {
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    /// Unmasked; mask it before it leaves the machine.
+    pub serial_number: Option<String>,
+    /// Board revision, on models with a hardware report.
+    pub hardware_revision: Option<u8>,
+    /// Year and ISO week of manufacture, e.g. `2024-W14`.
+    pub manufactured: Option<String>,
+}
+
+/// Report ID and selector of the hardware report, for the simulator.
+pub fn hardware_report(device_id: DeviceId) -> Option<(u8, u8)> {
+    find_descriptor(device_id)
+        .hardware_feature
+        .map(|feature| (feature.report_id, feature.selector))
+}
+
+/// Report ID of the factory reset report, for the simulator.
+pub fn factory_reset_report(device_id: DeviceId) -> Option<u8> {
+    find_descriptor(device_id)
+        .factory_reset_feature
+        .map(|feature| feature.report_id)
+}
+
+/// Report ID of the identify report, for the simulator.
+pub fn identify_report(device_id: DeviceId) -> Option<u8> {
+    find_descriptor(device_id)
+        .identify_feature
+        .map(|feature| feature.report_id)
+}
+
+/// Times the mute LED blinks on models without an identify report.
+const IDENTIFY_BLINKS: u32 = 5;
+const IDENTIFY_BLINK: Duration = Duration::from_millis(300);
+
+impl Device {
+    /// The USB strings of the headset and, where the model has a hardware
+    /// report, its board revision and manufacturing date.
+    pub fn read_hardware_info(&self) -> Result<HardwareInfo, ControlError> {
+        let feature = self.descriptor.hardware_feature;
+        metrics::timed(self.id, "read_hardware_info", || {
+            self.with_handle(FirmwareTarget::Headset, |device| {
+                let (manufacturer, product, serial_number) = device.strings();
+                let mut info = HardwareInfo {
+                    manufacturer,
+                    product,
+                    serial_number,
+                    ..HardwareInfo::default()
+                };
+                let Some(feature) = feature else {
+                    return Ok(info);
+                };
+                let mut buffer = vec![0u8; device.report_length(feature)];
+                buffer[0] = feature.report_id;
+                let length = device.get_feature_report(&mut buffer).map_err(|source| {
+                    ControlError::ReportRead {
+                        report_id: feature.report_id,
+                        selector: feature.selector,
+                        source,
+                    }
+                })?;
+                let body = response_body(feature, &buffer[..length.min(buffer.len())]);
+                if let [selector, revision, year, week, ..] = *body {
+                    if selector == feature.selector {
+                        info.hardware_revision = Some(revision);
+                        info.manufactured = (1..=53)
+                            .contains(&week)
+                            .then(|| format!("{}-W{week:02}", 2000 + u16::from(year)));
+                    }
+                }
+                Ok(info)
+            })
+        })
+    }
+
+    /// Restore every on-device setting to its factory default. Nothing is
+    /// read back: the device may reboot while it resets.
+    pub fn factory_reset(&self) -> Result<(), ControlError> {
+        let feature = validate_feature(
+            self.id,
+            Capability::FactoryReset,
+            self.descriptor.factory_reset_feature,
+        )?;
+        self.trigger("factory_reset", feature)
+    }
+
+    /// Whether [`Device::identify`] works.
+    pub fn has_identify(&self) -> bool {
+        self.descriptor.identify_feature.is_some() || self.descriptor.mute_led_feature.is_some()
+    }
+
+    /// Make the headset show itself, so a list entry can be matched to the
+    /// headset on the desk. Models with an identify report blink or play
+    /// their pairing tone; others blink the mute LED a few times and leave
+    /// it as it was.
+    pub fn identify(&self) -> Result<(), ControlError> {
+        if let Some(feature) = self.descriptor.identify_feature {
+            return self.trigger("identify", feature);
+        }
+        validate_feature(
+            self.id,
+            Capability::Identify,
+            self.descriptor.mute_led_feature,
+        )?;
+        let lit = self
+            .read(FeatureKind::MuteLed)?
+            .is_some_and(|value| value != 0);
+        for _ in 0..IDENTIFY_BLINKS {
+            self.set_mute_led(!lit)?;
+            std::thread::sleep(IDENTIFY_BLINK);
+            self.set_mute_led(lit)?;
+            std::thread::sleep(IDENTIFY_BLINK);
+        }
+        Ok(())
+    }
+
+    /// Send 1 to a report that starts an action on the device, without
+    /// reading anything back.
+    fn trigger(&self, operation: &'static str, feature: FeatureReport) -> Result<(), ControlError> {
+        metrics::timed(self.id, operation, || {
+            self.with_handle(FirmwareTarget::Headset, |device| {
+                let payload = build_feature_payload(feature, 1, device.report_length(feature));
+                if is_dry_run() {
+                    info!(payload = %recorder::hex(&payload), "dry run: not sending");
+                    return Ok(());
+                }
+                device
+                    .send_feature_report(&payload)
+                    .map_err(|source| ControlError::ReportSend {
+                        report_id: feature.report_id,
+                        selector: feature.selector,
+                        source,
+                    })
+            })
+        })
+    }
+}
+
+/// A firmware revision such as `1.07`. Versions compare by major, then minor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl FirmwareVersion {
+    /// Decode a BCD revision like `bcdDevice`, `0x0107` being 1.07. Bytes
+    /// that are not valid BCD are taken as plain numbers.
+    pub fn from_bcd(value: u16) -> Self {
+        let decode = |byte: u8| {
+            if byte >> 4 <= 9 && byte & 0x0F <= 9 {
+                (byte >> 4) * 10 + (byte & 0x0F)
+            } else {
+                byte
+            }
+        };
+        let [minor, major] = value.to_le_bytes();
+        FirmwareVersion {
+            major: decode(major),
+            minor: decode(minor),
+        }
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:02}", self.major, self.minor)
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidVersion(pub String);
+
+impl fmt::Display for InvalidVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid firmware version '{}', expected e.g. 1.07",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidVersion {}
+
+/// Parses `1.07`, `1.7` and `v1.07`; `1.7` and `1.07` are the same version.
+impl std::str::FromStr for FirmwareVersion {
+    type Err = InvalidVersion;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidVersion(value.to_string());
+        let (major, minor) = value
+            .trim()
+            .trim_start_matches('v')
+            .split_once('.')
+            .ok_or_else(invalid)?;
+        Ok(FirmwareVersion {
+            major: major.parse().map_err(|_| invalid())?,
+            minor: minor.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Serialize for FirmwareVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for FirmwareVersion {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Device {
+    /// The firmware version of the headset or its receiver, from the
+    /// headset's version report when it has one and from the USB descriptor
+    /// otherwise. Report layouts can differ between revisions, so this is
+    /// what to check before relying on one. `Ok(None)` when the device does
+    /// not tell, as for simulated devices without a version report.
+    pub fn read_firmware_version(
+        &self,
+        target: FirmwareTarget,
+    ) -> Result<Option<FirmwareVersion>, ControlError> {
+        if target == FirmwareTarget::Headset && self.descriptor.firmware_feature.is_some() {
+            let value = self.read(FeatureKind::Firmware)?;
+            return Ok(value.map(FirmwareVersion::from_bcd));
+        }
+        metrics::timed(self.id, "read", || {
+            self.with_handle(target, |device| {
+                Ok(device.release_number().map(FirmwareVersion::from_bcd))
+            })
+        })
+    }
+}
+
+fn pairing_feature(device_id: DeviceId) -> Option<FeatureReport> {
+    find_descriptor(device_id)
+        .receiver
+        .and_then(|receiver| receiver.pairing_feature)
+}
+
+/// Report ID and selector of the receiver's pairing report, for the simulator.
+pub fn pairing_report(device_id: DeviceId) -> Option<(u8, u8)> {
+    pairing_feature(device_id).map(|feature| (feature.report_id, feature.selector))
+}
+
+fn link_feature(device_id: DeviceId) -> Option<FeatureReport> {
+    find_descriptor(device_id)
+        .receiver
+        .and_then(|receiver| receiver.link_feature)
+}
+
+/// Report ID and selector of the receiver's link report, for the simulator.
+pub fn link_report(device_id: DeviceId) -> Option<(u8, u8)> {
+    link_feature(device_id).map(|feature| (feature.report_id, feature.selector))
+}
+
+/// Report ID and selector of the headset's battery input report, for the
+/// simulator.
+pub fn battery_input_report(device_id: DeviceId) -> Option<(u8, u8)> {
+    find_descriptor(device_id)
+        .battery_input
+        .map(|feature| (feature.report_id, feature.selector))
+}
+
+/// Report ID and selector of the headset's button input report, for the
+/// simulator.
+pub fn button_input_report(device_id: DeviceId) -> Option<(u8, u8)> {
+    find_descriptor(device_id)
+        .button_input
+        .map(|feature| (feature.report_id, feature.selector))
+}
+
+/// Report ID and selector of the headset's volume dial input report, for
+/// the simulator.
+pub fn dial_input_report(device_id: DeviceId) -> Option<(u8, u8)> {
+    find_descriptor(device_id)
+        .dial_input
+        .map(|feature| (feature.report_id, feature.selector))
+}
+
+fn game_mode_feature(device_id: DeviceId) -> Option<FeatureReport> {
+    find_descriptor(device_id)
+        .receiver
+        .and_then(|receiver| receiver.game_mode_feature)
+}
+
+/// Report ID and selector of the receiver's game mode report, for the simulator.
+pub fn game_mode_report(device_id: DeviceId) -> Option<(u8, u8)> {
+    game_mode_feature(device_id).map(|feature| (feature.report_id, feature.selector))
+}
+
+impl Device {
+    /// Whether the receiver can be paired with the headset from the app, and
+    /// has to be after a flash.
+    pub fn needs_pairing(&self) -> bool {
+        pairing_feature(self.id).is_some()
+    }
+
+    /// Put the receiver into pairing mode, or take it out again. See
+    /// the app's pairing flow for the whole procedure.
+    pub fn set_pairing_mode(&self, enabled: bool) -> Result<(), ControlError> {
+        let feature = validate_feature(
+            self.id,
+            Capability::ReceiverPairing,
+            pairing_feature(self.id),
+        )?;
+        metrics::timed(self.id, "set_pairing_mode", || {
+            self.with_handle(FirmwareTarget::Dongle, |receiver| {
+                let payload = build_feature_payload(
+                    feature,
+                    u16::from(enabled),
+                    receiver.report_length(feature),
+                );
+                if is_dry_run() {
+                    info!(payload = %recorder::hex(&payload), "dry run: not sending");
+                    return Ok(());
+                }
+                receiver
+                    .send_feature_report(&payload)
+                    .map_err(|source| ControlError::ReportSend {
+                        report_id: feature.report_id,
+                        selector: feature.selector,
+                        source,
+                    })
+            })
+        })
+    }
+
+    /// Whether the receiver has found its headset.
+    pub fn is_paired(&self) -> Result<bool, ControlError> {
+        let feature = validate_feature(
+            self.id,
+            Capability::ReceiverPairing,
+            pairing_feature(self.id),
+        )?;
+        metrics::timed(self.id, "read_pairing", || {
+            self.with_handle(FirmwareTarget::Dongle, |receiver| {
+                Ok(read_value(receiver, feature)? == Some(0))
+            })
+        })
+    }
+
+    /// Whether the receiver tells when its headset is switched on or off.
+    pub fn has_link_state(&self) -> bool {
+        link_feature(self.id).is_some()
+    }
+
+    /// Whether the headset is switched on and linked to its receiver; `None`
+    /// when the receiver answered with something unreadable.
+    pub fn read_headset_link(&self) -> Result<Option<bool>, ControlError> {
+        let feature = validate_feature(self.id, Capability::HeadsetLink, link_feature(self.id))?;
+        metrics::timed(self.id, "read_link", || {
+            self.with_handle(FirmwareTarget::Dongle, |receiver| {
+                Ok(read_value(receiver, feature)?.map(|value| value != 0))
+            })
+        })
+    }
+
+    pub fn has_game_mode(&self) -> bool {
+        game_mode_feature(self.id).is_some()
+    }
+
+    /// Switch the receiver's low-latency game mode, which trades battery
+    /// life for latency.
+    pub fn set_game_mode(&self, enabled: bool) -> Result<(), ControlError> {
+        self.write(FeatureKind::GameMode, u16::from(enabled))
+    }
+
+    /// See [`Device::read_sidetone_state`] for `Ok(None)`.
+    pub fn read_game_mode(&self) -> Result<Option<bool>, ControlError> {
+        let value = self.read(FeatureKind::GameMode)?;
+        Ok(value.map(|value| value != 0))
+    }
+}
+
+/// A feature with its value, for frontends that handle every feature through
+/// [`Device::get_feature`] and [`Device::set_feature`] instead of one command
+/// each. Serialised as `{"feature": "volume", "value": 40}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "feature", content = "value", rename_all = "snake_case")]
+pub enum Feature {
+    Sidetone(bool),
+    /// In percent.
+    Volume(u8),
+    /// In percent; read-only.
+    Battery(u8),
+    MuteLed(bool),
+    /// Read-only.
+    Firmware(FirmwareVersion),
+    WirelessMode(WirelessMode),
+    Multipoint(bool),
+    OutputMute(bool),
+    VoicePrompts(bool),
+    /// In percent.
+    LedBrightness(u8),
+    NotificationBeeps(bool),
+    /// In percent, 0 for no beep.
+    MuteBeepVolume(u8),
+    /// Read-only.
+    Boom(BoomPosition),
+    Anc(bool),
+    /// From 1 to [`Device::anc_levels`].
+    AncLevel(u8),
+    Ambient(bool),
+    AutoAnswer(bool),
+    GameMode(bool),
+}
+
+impl Feature {
+    pub fn kind(self) -> FeatureKind {
+        match self {
+            Feature::Sidetone(_) => FeatureKind::Sidetone,
+            Feature::Volume(_) => FeatureKind::Volume,
+            Feature::Battery(_) => FeatureKind::Battery,
+            Feature::MuteLed(_) => FeatureKind::MuteLed,
+            Feature::Firmware(_) => FeatureKind::Firmware,
+            Feature::WirelessMode(_) => FeatureKind::WirelessMode,
+            Feature::Multipoint(_) => FeatureKind::Multipoint,
+            Feature::OutputMute(_) => FeatureKind::OutputMute,
+            Feature::VoicePrompts(_) => FeatureKind::VoicePrompts,
+            Feature::LedBrightness(_) => FeatureKind::LedBrightness,
+            Feature::NotificationBeeps(_) => FeatureKind::NotificationBeeps,
+            Feature::MuteBeepVolume(_) => FeatureKind::MuteBeepVolume,
+            Feature::Boom(_) => FeatureKind::Boom,
+            Feature::Anc(_) => FeatureKind::Anc,
+            Feature::AncLevel(_) => FeatureKind::AncLevel,
+            Feature::Ambient(_) => FeatureKind::Ambient,
+            Feature::AutoAnswer(_) => FeatureKind::AutoAnswer,
+            Feature::GameMode(_) => FeatureKind::GameMode,
+        }
+    }
+}
+
+impl Device {
+    /// Whether the device has `kind`. Every device tells its firmware
+    /// version, from the USB descriptor when it has no version report.
+    pub fn supports(&self, kind: FeatureKind) -> bool {
+        kind == FeatureKind::Firmware || self.feature(kind).is_ok()
+    }
+
+    /// Whether the model has `capability`; commands needing one it lacks
+    /// fail with [`ControlError::UnsupportedFeature`] before sending anything.
+    pub fn has(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::FactoryReset => self.descriptor.factory_reset_feature.is_some(),
+            Capability::Identify => self.has_identify(),
+            Capability::Receiver => self.descriptor.receiver.is_some(),
+            Capability::ReceiverPairing => self.needs_pairing(),
+            Capability::HeadsetLink => self.has_link_state(),
+            Capability::FirmwareUpdate => [FirmwareTarget::Headset, FirmwareTarget::Dongle]
+                .into_iter()
+                .any(|target| dfu_protocol(self.id, target).is_some()),
+            Capability::Sidetone => self.feature(FeatureKind::Sidetone).is_ok(),
+            Capability::Volume => self.feature(FeatureKind::Volume).is_ok(),
+            Capability::Battery => self.feature(FeatureKind::Battery).is_ok(),
+            Capability::MuteLed => self.feature(FeatureKind::MuteLed).is_ok(),
+            Capability::Firmware => self.feature(FeatureKind::Firmware).is_ok(),
+            Capability::WirelessMode => self.feature(FeatureKind::WirelessMode).is_ok(),
+            Capability::Multipoint => self.feature(FeatureKind::Multipoint).is_ok(),
+            Capability::OutputMute => self.feature(FeatureKind::OutputMute).is_ok(),
+            Capability::VoicePrompts => self.feature(FeatureKind::VoicePrompts).is_ok(),
+            Capability::LedBrightness => self.feature(FeatureKind::LedBrightness).is_ok(),
+            Capability::NotificationBeeps => self.feature(FeatureKind::NotificationBeeps).is_ok(),
+            Capability::MuteBeepVolume => self.feature(FeatureKind::MuteBeepVolume).is_ok(),
+            Capability::Boom => self.feature(FeatureKind::Boom).is_ok(),
+            Capability::Anc => self.feature(FeatureKind::Anc).is_ok(),
+            Capability::Ambient => self.feature(FeatureKind::Ambient).is_ok(),
+            Capability::AutoAnswer => self.feature(FeatureKind::AutoAnswer).is_ok(),
+            Capability::ButtonRemapping => self.has_button_remapping(),
+            Capability::ButtonEvents => self.pushes_buttons(),
+            Capability::DoNotDisturb => [
+                FeatureKind::VoicePrompts,
+                FeatureKind::LedBrightness,
+                FeatureKind::NotificationBeeps,
+            ]
+            .into_iter()
+            .any(|kind| self.feature(kind).is_ok()),
+            Capability::GameMode => self.feature(FeatureKind::GameMode).is_ok(),
+        }
+    }
+
+    /// Every [`Capability`] of the model, in [`Capability::ALL`] order.
+    pub fn capabilities(&self) -> Vec<Capability> {
+        Capability::ALL
+            .into_iter()
+            .filter(|&capability| self.has(capability))
+            .collect()
+    }
+
+    /// The features [`Device::get_feature`] can read, in [`FeatureKind::ALL`]
+    /// order.
+    pub fn supported_features(&self) -> Vec<FeatureKind> {
+        FeatureKind::ALL
+            .into_iter()
+            .filter(|&kind| self.supports(kind))
+            .collect()
+    }
+
+    /// Read `kind` from the device, see [`Device::read_sidetone_state`] for
+    /// `Ok(None)`.
+    pub fn get_feature(&self, kind: FeatureKind) -> Result<Option<Feature>, ControlError> {
+        Ok(match kind {
+            FeatureKind::Sidetone => self.read_sidetone_state()?.map(Feature::Sidetone),
+            FeatureKind::Volume => self.read_volume()?.map(Feature::Volume),
+            FeatureKind::Battery => self.read_battery()?.map(Feature::Battery),
+            FeatureKind::MuteLed => self
+                .read(FeatureKind::MuteLed)?
+                .map(|value| Feature::MuteLed(value != 0)),
+            FeatureKind::Firmware => self
+                .read_firmware_version(FirmwareTarget::Headset)?
+                .map(Feature::Firmware),
+            FeatureKind::WirelessMode => self.read_wireless_mode()?.map(Feature::WirelessMode),
+            FeatureKind::Multipoint => self.read_multipoint()?.map(Feature::Multipoint),
+            FeatureKind::OutputMute => self.read_output_mute()?.map(Feature::OutputMute),
+            FeatureKind::VoicePrompts => self.read_voice_prompts()?.map(Feature::VoicePrompts),
+            FeatureKind::LedBrightness => self.read_led_brightness()?.map(Feature::LedBrightness),
+            FeatureKind::NotificationBeeps => self
+                .read_notification_beeps()?
+                .map(Feature::NotificationBeeps),
+            FeatureKind::MuteBeepVolume => {
+                self.read_mute_beep_volume()?.map(Feature::MuteBeepVolume)
+            }
+            FeatureKind::Boom => self.read_boom_position()?.map(Feature::Boom),
+            FeatureKind::Anc => self.read_anc()?.map(Feature::Anc),
+            FeatureKind::AncLevel => self.read_anc_level()?.map(Feature::AncLevel),
+            FeatureKind::Ambient => self.read_ambient()?.map(Feature::Ambient),
+            FeatureKind::AutoAnswer => self.read_auto_answer()?.map(Feature::AutoAnswer),
+            FeatureKind::GameMode => self.read_game_mode()?.map(Feature::GameMode),
+        })
+    }
+
+    /// Write `feature` to the device, like its own setter does.
+    pub fn set_feature(&self, feature: Feature) -> Result<(), ControlError> {
+        match feature {
+            Feature::Sidetone(enabled) => self.set_sidetone(enabled),
+            Feature::Volume(percent) => self.set_volume(percent),
+            Feature::MuteLed(lit) => self.set_mute_led(lit),
+            Feature::WirelessMode(mode) => self.set_wireless_mode(mode),
+            Feature::Multipoint(enabled) => self.set_multipoint(enabled),
+            Feature::OutputMute(muted) => self.set_output_mute(muted),
+            Feature::VoicePrompts(enabled) => self.set_voice_prompts(enabled),
+            Feature::LedBrightness(percent) => self.set_led_brightness(percent),
+            Feature::NotificationBeeps(enabled) => self.set_notification_beeps(enabled),
+            Feature::MuteBeepVolume(percent) => self.set_mute_beep_volume(percent),
+            Feature::Anc(enabled) => self.set_anc(enabled),
+            Feature::AncLevel(level) => self.set_anc_level(level),
+            Feature::Ambient(enabled) => self.set_ambient(enabled),
+            Feature::AutoAnswer(enabled) => self.set_auto_answer(enabled),
+            Feature::GameMode(enabled) => self.set_game_mode(enabled),
+            Feature::Battery(_) | Feature::Firmware(_) | Feature::Boom(_) => {
+                Err(ControlError::ReadOnlyFeature {
+                    feature: feature.kind(),
+                })
+            }
+        }
+    }
+
+    /// Write `features` in order, as one change: when one fails, the ones
+    /// already written are set back to what they read before, last first,
+    /// and its error is returned. Nothing is written when the device lacks
+    /// one of them, one is read-only or the device does not report the
+    /// current value of one, which could not be set back. Returns what the
+    /// features were before.
+    pub fn set_features(&self, features: &[Feature]) -> Result<Vec<Feature>, ControlError> {
+        for feature in features {
+            if let Feature::Battery(_) | Feature::Firmware(_) | Feature::Boom(_) = feature {
+                return Err(ControlError::ReadOnlyFeature {
+                    feature: feature.kind(),
+                });
+            }
+            self.feature(feature.kind())?;
+        }
+        let mut previous = Vec::with_capacity(features.len());
+        for feature in features {
+            let kind = feature.kind();
+            let before = self.get_feature(kind)?.ok_or(ControlError::Unreadable {
+                device_id: self.id,
+                feature: kind,
+            })?;
+            previous.push(before);
+        }
+        for (written, &feature) in features.iter().enumerate() {
+            if let Err(err) = self.set_feature(feature) {
+                for &before in previous[..written].iter().rev() {
+                    if let Err(rollback) = self.set_feature(before) {
+                        warn!(
+                            device_id = %self.id,
+                            "failed to set {} back: {rollback}",
+                            before.kind().as_str()
+                        );
+                    }
+                }
+                return Err(err);
+            }
+        }
+        Ok(previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::TestHeadset;
+    use crate::{Model, REPORT_LENGTH};
+
+    const SIDETONE: FeatureReport = FeatureReport {
+        report_id: 0x20,
+        selector: 0x86,
+        length: REPORT_LENGTH,
+    };
+
+    #[test]
+    fn payload_starts_with_report_id_selector_and_value() {
+        let payload = build_feature_payload(SIDETONE, 0x1234, REPORT_LENGTH);
+        assert_eq!(payload.len(), REPORT_LENGTH);
+        assert_eq!(payload[..4], [0x20, 0x86, 0x34, 0x12]);
+        assert!(payload[4..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn payload_always_fits_the_value() {
+        assert_eq!(
+            *build_feature_payload(SIDETONE, 1, 0),
+            [0x20, 0x86, 0x01, 0x00]
+        );
+        // Longer than the inline buffer, as Windows may ask for.
+        let payload = build_feature_payload(SIDETONE, 1, 2 * INLINE_REPORT_LENGTH);
+        assert_eq!(payload.len(), 2 * INLINE_REPORT_LENGTH);
+        assert_eq!(payload[..4], [0x20, 0x86, 0x01, 0x00]);
+    }
+
+    #[test]
+    fn response_is_parsed_with_or_without_report_id() {
+        assert_eq!(parse_response(SIDETONE, &[0x20, 0x86, 0x01, 0x00]), Some(1));
+        assert_eq!(parse_response(SIDETONE, &[0x86, 0x34, 0x12]), Some(0x1234));
+        // A report ID equal to the selector is never taken for the report ID.
+        let report = FeatureReport {
+            report_id: 0x86,
+            ..SIDETONE
+        };
+        assert_eq!(parse_response(report, &[0x86, 0x01, 0x00]), Some(1));
+    }
+
+    #[test]
+    fn response_with_other_selector_or_truncated_is_not_parsed() {
+        assert_eq!(parse_response(SIDETONE, &[0x20, 0x87, 0x01, 0x00]), None);
+        assert_eq!(parse_response(SIDETONE, &[0x20, 0x86, 0x01]), None);
+        assert_eq!(parse_response(SIDETONE, &[]), None);
+    }
+
+    #[test]
+    fn write_status_follows_the_value() {
+        assert_eq!(
+            write_status(SIDETONE, &[0x20, 0x86, 0x01, 0x00, 0x02]),
+            Some(2)
+        );
+        assert_eq!(write_status(SIDETONE, &[0x86, 0x01, 0x00, 0x00]), Some(0));
+        assert_eq!(write_status(SIDETONE, &[0x20, 0x86, 0x01, 0x00]), None);
+        assert_eq!(
+            write_status(SIDETONE, &[0x20, 0x87, 0x01, 0x00, 0x00]),
+            None
+        );
+    }
+
+    #[test]
+    fn missing_feature_names_the_capability() {
+        let device_id = DeviceId::first(Model::CloudIiiWired);
+        let feature = validate_feature(device_id, Capability::Sidetone, Some(SIDETONE));
+        assert_eq!(feature.unwrap().report_id, SIDETONE.report_id);
+        let err = validate_feature(device_id, Capability::Anc, None).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ControlError::UnsupportedFeature {
+                    device_id: id,
+                    capability: Capability::Anc,
+                } if id == device_id
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn failed_write_sets_earlier_features_back() {
+        let device_id = DeviceId {
+            model: Model::SimulatedWireless,
+            unit: 20,
+        };
+        let headset = TestHeadset::install(device_id);
+        headset.set(FeatureKind::VoicePrompts, 1);
+        headset.set(FeatureKind::LedBrightness, 80);
+        headset.fail_writes(FeatureKind::LedBrightness);
+        let err = crate::devices()
+            .get(device_id)
+            .set_features(&[Feature::VoicePrompts(false), Feature::LedBrightness(0)])
+            .unwrap_err();
+        assert!(matches!(err, ControlError::ReportSend { .. }), "{err:?}");
+        assert_eq!(headset.value(FeatureKind::VoicePrompts), 1);
+        assert_eq!(headset.value(FeatureKind::LedBrightness), 80);
+    }
+}
@@ -0,0 +1,67 @@
+//! What the app adds around the protocol. The core knows nothing about
+//! settings, events or the platform's permission model, so an app installs a
+//! [`Host`] at startup to diagnose open failures, name competing controllers,
+//! correct battery readings and count HID traffic. Without one, open
+//! failures are [`OpenDiagnosis::Unknown`], readings are passed through and
+//! nothing is counted.
+
+use crate::{ControlError, DeviceId, OpenDiagnosis};
+use hidapi::{HidApi, HidError};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+pub trait Host: Send + Sync {
+    /// Why `HidApi::open` failed for the given VID/PID.
+    fn diagnose_open_failure(
+        &self,
+        _api: &HidApi,
+        _vendor_id: u16,
+        _product_id: u16,
+        _error: &HidError,
+    ) -> OpenDiagnosis {
+        OpenDiagnosis::Unknown
+    }
+
+    /// Another process that controls the device with this VID/PID, blamed
+    /// for a failed or reverted write.
+    fn find_controller(&self, _api: &HidApi, _vendor_id: u16, _product_id: u16) -> Option<String> {
+        None
+    }
+
+    /// A battery reading in percent as it should be reported, e.g. through a
+    /// calibration curve.
+    fn correct_battery(&self, _device_id: DeviceId, percent: u8) -> u8 {
+        percent
+    }
+
+    /// An operation (`"read"`, `"write"`) on `device_id` finished; `error`
+    /// is set when it failed.
+    fn operation_finished(
+        &self,
+        _device_id: DeviceId,
+        _operation: &'static str,
+        _elapsed: Duration,
+        _error: Option<&ControlError>,
+    ) {
+    }
+
+    /// One of the HID calls an operation is made of (`"open"`,
+    /// `"send_feature_report"`, `"get_feature_report"`) finished.
+    fn call_finished(&self, _call: &'static str, _elapsed: Duration, _ok: bool) {}
+}
+
+/// Used while no host is installed.
+struct NoHost;
+
+impl Host for NoHost {}
+
+static HOST: OnceLock<&'static dyn Host> = OnceLock::new();
+
+/// Use `host` for the rest of the process. Only the first call has an effect.
+pub fn install(host: &'static dyn Host) {
+    let _ = HOST.set(host);
+}
+
+pub(crate) fn get() -> &'static dyn Host {
+    HOST.get().copied().unwrap_or(&NoHost)
+}
@@ -0,0 +1,119 @@
+//! Translations of error messages, in Fluent files under `locales/`, read
+//! with `fluent-bundle`. [`set_locale`] picks the language errors are
+//! serialised in for the frontend; their `Display` stays English, so logs
+//! and the audit log read the same on every system.
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+use tracing::warn;
+use unic_langid::LanguageIdentifier;
+
+pub const DEFAULT_LOCALE: &str = "en";
+
+const RESOURCES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+];
+
+type Bundle = FluentBundle<FluentResource>;
+
+static LOCALE: Mutex<&str> = Mutex::new(DEFAULT_LOCALE);
+static BUNDLES: OnceLock<HashMap<&str, Bundle>> = OnceLock::new();
+
+/// The languages messages are translated to.
+pub fn locales() -> Vec<&'static str> {
+    RESOURCES.iter().map(|&(locale, _)| locale).collect()
+}
+
+/// Serialise errors in `locale`, a language tag such as `de` or `de-AT`.
+/// Returns `false`, keeping the current language, when it is not translated.
+pub fn set_locale(locale: &str) -> bool {
+    let language = locale
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let Some(&(language, _)) = RESOURCES.iter().find(|&&(other, _)| other == language) else {
+        return false;
+    };
+    *LOCALE.lock().unwrap() = language;
+    true
+}
+
+pub fn locale() -> &'static str {
+    *LOCALE.lock().unwrap()
+}
+
+/// The bundle of `locale`. Entries with syntax errors are left out and
+/// logged, so they fall back to English.
+fn bundle(locale: &str, resource: &str) -> Bundle {
+    let resource =
+        FluentResource::try_new(resource.to_string()).unwrap_or_else(|(resource, errors)| {
+            warn!("{locale}.ftl has syntax errors: {errors:?}");
+            resource
+        });
+    let language: LanguageIdentifier = locale.parse().expect("a valid language tag");
+    let mut bundle = Bundle::new_concurrent(vec![language]);
+    // Isolation marks around placeables would end up in logs and in text
+    // fields that cannot show them.
+    bundle.set_use_isolating(false);
+    if let Err(errors) = bundle.add_resource(resource) {
+        warn!("{locale}.ftl has duplicate entries: {errors:?}");
+    }
+    bundle
+}
+
+/// Message `id` in `locale`, falling back to English and then to the id
+/// itself, with each `{ $name }` replaced by its value in `args`.
+pub(crate) fn translate(locale: &str, id: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    let bundles = BUNDLES.get_or_init(|| {
+        RESOURCES
+            .iter()
+            .map(|&(locale, resource)| (locale, bundle(locale, resource)))
+            .collect()
+    });
+    let Some((bundle, pattern)) = [locale, DEFAULT_LOCALE].iter().find_map(|locale| {
+        let bundle = bundles.get(locale)?;
+        Some((bundle, bundle.get_message(id)?.value()?))
+    }) else {
+        return id.to_string();
+    };
+    let mut fluent_args = FluentArgs::new();
+    for &(name, value) in args {
+        fluent_args.set(name, value.to_string());
+    }
+    let mut errors = Vec::new();
+    let message = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+    if !errors.is_empty() {
+        warn!("message {id} in {locale} did not format cleanly: {errors:?}");
+    }
+    message.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resources_parse() {
+        for &(locale, resource) in RESOURCES {
+            if let Err((_, errors)) = FluentResource::try_new(resource.to_string()) {
+                panic!("{locale}.ftl: {errors:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn placeables_are_filled_in_without_isolation_marks() {
+        let message = translate(
+            "de",
+            "error-flashing",
+            &[("device_id", &"cloud_alpha_wireless")],
+        );
+        assert!(message.starts_with("cloud_alpha_wireless "), "{message}");
+        assert_eq!(translate("fr", "no-such-message", &[]), "no-such-message");
+    }
+}
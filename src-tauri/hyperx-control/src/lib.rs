@@ -0,0 +1,868 @@
+//! The HID protocol of HyperX headsets: which models exist, how their
+//! feature reports are laid out, and how they are enumerated, opened, read
+//! and written. It has no dependency on Tauri, so the app, its CLI and other
+//! frontends share it; anything app-specific comes in through
+//! [`host::Host`].
+
+mod device;
+mod dfu;
+mod diagnosis;
+mod features;
+pub mod host;
+pub mod i18n;
+mod metrics;
+pub mod mock;
+pub mod recorder;
+pub mod report_descriptor;
+
+#[cfg(windows)]
+pub use device::container_id;
+pub use device::{
+    connected_devices, devices, firmware_targets, firmware_usb_id, hid_backend, is_dry_run,
+    list_devices, present_devices, refresh_devices, set_dry_run, supported_devices, usb_id,
+    with_api, with_priority, Device, DeviceManager, DeviceState, FeatureCheck, FirmwareTarget,
+    HidBackend, Priority, SelfTestReport,
+};
+pub use dfu::{
+    dfu_checksum, dfu_read_response, dfu_status_response, is_dfu_report, supports_firmware_backup,
+    DfuCommand, DfuSession, DfuState, DfuStatus,
+};
+pub use diagnosis::OpenDiagnosis;
+pub use features::{
+    battery_input_report, button_input_report, buttons_report, dial_input_report,
+    factory_reset_report, feature_kind, game_mode_report, hardware_report, identify_report,
+    link_report, pairing_report, pairings_report, BluetoothPairing, BoomPosition, Button,
+    ButtonAction, ButtonPress, Capability, Feature, FeatureKind, FirmwareVersion, HardwareInfo,
+    InputListener, InputReport, InvalidVersion, NoiseMode, RemappableButton, WirelessMode,
+};
+
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+
+const REPORT_LENGTH: usize = 62;
+/// Report ID, selector and a 16-bit value.
+const MIN_PAYLOAD_LENGTH: usize = 4;
+
+/// Supported HyperX headset models.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Model {
+    CloudIiiWired,
+    /// Only exists while a simulator is installed as the [`mock`] backend.
+    SimulatedWireless,
+}
+
+/// Every model, including the simulated ones.
+const MODELS: &[Model] = &[Model::CloudIiiWired, Model::SimulatedWireless];
+
+impl Model {
+    /// The serialised name, e.g. `cloud_iii_wired`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Model::CloudIiiWired => "cloud_iii_wired",
+            Model::SimulatedWireless => "simulated_wireless",
+        }
+    }
+}
+
+/// One headset: its model, and which unit of that model when several are
+/// plugged in at once. Units are told apart by serial number, or by device
+/// path when there is none, and keep their number while the app runs, so
+/// each has its own state. Written as the model name for the first unit and
+/// with `-2`, `-3`, ... appended for further ones, e.g. `cloud_iii_wired-2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeviceId {
+    pub model: Model,
+    /// 0 for the first unit.
+    pub unit: u8,
+}
+
+impl DeviceId {
+    /// The first (usually only) unit of `model`.
+    pub const fn first(model: Model) -> Self {
+        DeviceId { model, unit: 0 }
+    }
+
+    /// Parse `value` as a unit of one of `models`.
+    fn parse(value: &str, models: impl IntoIterator<Item = Model>) -> Result<Self, UnknownDevice> {
+        let unknown = || UnknownDevice(value.to_string());
+        let (name, unit) = match value.rsplit_once('-') {
+            Some((name, number)) => {
+                let number: u16 = number.parse().map_err(|_| unknown())?;
+                let unit = u8::try_from(number.wrapping_sub(1))
+                    .ok()
+                    .filter(|&unit| unit > 0)
+                    .ok_or_else(unknown)?;
+                (name, unit)
+            }
+            None => (value, 0),
+        };
+        models
+            .into_iter()
+            .find(|model| model.as_str() == name)
+            .map(|model| DeviceId { model, unit })
+            .ok_or_else(unknown)
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.unit {
+            0 => f.write_str(self.model.as_str()),
+            unit => write!(f, "{}-{}", self.model.as_str(), u16::from(unit) + 1),
+        }
+    }
+}
+
+/// Only accepts the models of [`supported_devices`].
+impl std::str::FromStr for DeviceId {
+    type Err = UnknownDevice;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        DeviceId::parse(
+            value,
+            supported_devices().iter().map(|device| device.id.model),
+        )
+    }
+}
+
+impl Serialize for DeviceId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Accepts every model, so settings written with `--simulate` still load
+/// without it.
+impl<'de> Deserialize<'de> for DeviceId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        DeviceId::parse(&value, MODELS.iter().copied()).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownDevice(pub String);
+
+impl fmt::Display for UnknownDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown device '{}'", self.0)
+    }
+}
+
+impl std::error::Error for UnknownDevice {}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct DeviceMetadata {
+    pub id: DeviceId,
+    pub label: &'static str,
+}
+
+const CLOUD_III_WIRED_METADATA: DeviceMetadata = DeviceMetadata {
+    id: DeviceId::first(Model::CloudIiiWired),
+    label: "Cloud III (wired)",
+};
+
+const DEVICE_CATALOG: &[DeviceMetadata] = &[CLOUD_III_WIRED_METADATA];
+
+/// The catalog while a mock backend is installed.
+const SIMULATED_CATALOG: &[DeviceMetadata] = &[
+    CLOUD_III_WIRED_METADATA,
+    DeviceMetadata {
+        id: DeviceId::first(Model::SimulatedWireless),
+        label: "Simulated wireless headset",
+    },
+];
+
+#[derive(Debug, Clone, Copy)]
+struct FeatureReport {
+    report_id: u8,
+    selector: u8,
+    /// Fallback when the device's report descriptor cannot be read.
+    length: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DeviceDescriptor {
+    vendor_id: u16,
+    product_id: u16,
+    sidetone_feature: Option<FeatureReport>,
+    /// Headphone volume in percent, as set by the volume wheel.
+    volume_feature: Option<FeatureReport>,
+    /// Battery charge in percent, on wireless models.
+    battery_feature: Option<FeatureReport>,
+    /// Input report, laid out like a feature response, that the headset
+    /// sends on its own whenever its charge changes, so it need not be
+    /// polled. `length` is the buffer to read it into.
+    battery_input: Option<FeatureReport>,
+    /// Input report the headset sends when a button is pressed, whatever
+    /// the button is mapped to; its value is the button (low byte, see
+    /// [`Button::code`]) and the [`ButtonPress`] (high byte).
+    button_input: Option<FeatureReport>,
+    /// Input report the headset sends when its volume dial is turned, on
+    /// models where the dial is not a standard consumer control; its value
+    /// is the number of steps as a signed byte, positive for louder.
+    dial_input: Option<FeatureReport>,
+    /// The microphone mute LED, 1 for lit; separate from the mute state.
+    mute_led_feature: Option<FeatureReport>,
+    /// 1 while the headphones are muted by the headset itself, whatever the
+    /// OS plays; also set by the headset's own mute control, where it has
+    /// one.
+    output_mute_feature: Option<FeatureReport>,
+    /// 1 for spoken prompts such as "power on" and "battery low".
+    voice_prompts_feature: Option<FeatureReport>,
+    /// Brightness of the headset's LEDs in percent, 0 for off; the mute LED
+    /// still lights while muted.
+    led_brightness_feature: Option<FeatureReport>,
+    /// 1 for the tones the headset plays to notify, e.g. of a low battery or
+    /// an incoming call; mute and power tones are not affected.
+    notification_beeps_feature: Option<FeatureReport>,
+    /// Volume in percent of the beep confirming a microphone mute or unmute,
+    /// 0 for none.
+    mute_beep_feature: Option<FeatureReport>,
+    /// Position of the flip-to-mute microphone boom, see [`BoomPosition`];
+    /// read-only.
+    boom_feature: Option<FeatureReport>,
+    /// Firmware version, BCD-encoded like `bcdDevice`. Without it the USB
+    /// descriptor's `bcdDevice` is used.
+    firmware_feature: Option<FeatureReport>,
+    /// Writing 1 restores every on-device setting to its factory default.
+    factory_reset_feature: Option<FeatureReport>,
+    /// `[selector, board revision, year of manufacture - 2000, ISO week]`.
+    hardware_feature: Option<FeatureReport>,
+    /// Writing 1 makes the headset show itself for a few seconds, with its
+    /// LEDs or the pairing tone, whichever it has.
+    identify_feature: Option<FeatureReport>,
+    /// The active radio of dual-mode headsets, see [`WirelessMode`].
+    wireless_mode_feature: Option<FeatureReport>,
+    /// Bluetooth multipoint management.
+    multipoint: Option<MultipointProtocol>,
+    /// Active noise cancellation.
+    anc: Option<AncProtocol>,
+    /// 1 to answer an incoming Bluetooth call when the headset is put on;
+    /// only on models with Bluetooth.
+    auto_answer_feature: Option<FeatureReport>,
+    /// Giving buttons other functions.
+    buttons: Option<ButtonProtocol>,
+    /// Feature responses carry a status byte after the value: 0 when the
+    /// last write was applied, otherwise why the firmware refused it.
+    write_status: bool,
+    /// The bootloader protocol, see [`DfuSession`]; `None` while the model's
+    /// update traffic has not been captured.
+    dfu: Option<DfuProtocol>,
+    /// The USB receiver of wireless models.
+    receiver: Option<ReceiverDescriptor>,
+}
+
+/// A wireless headset's USB receiver (dongle), which runs its own firmware
+/// and is updated separately from the headset.
+#[derive(Debug, Clone, Copy)]
+struct ReceiverDescriptor {
+    product_id: u16,
+    dfu: Option<DfuProtocol>,
+    /// Writing 1 starts pairing with the headset and 0 stops it; reads 1
+    /// while pairing and 0 once paired. A freshly flashed or replacement
+    /// receiver does not know the headset yet.
+    pairing_feature: Option<FeatureReport>,
+    /// 1 while the headset is switched on and linked to the receiver, 0
+    /// while it is off or out of range. The receiver stays enumerated either
+    /// way.
+    link_feature: Option<FeatureReport>,
+    /// 1 for the low-latency game mode, which polls the headset more often
+    /// at the cost of battery life.
+    game_mode_feature: Option<FeatureReport>,
+}
+
+/// Reports of headsets that keep several Bluetooth sources paired.
+#[derive(Debug, Clone, Copy)]
+struct MultipointProtocol {
+    /// 1 lets two paired sources connect at once, 0 only one.
+    enabled_feature: FeatureReport,
+    /// Writing `[report ID, selector, command, slot]` selects the slot the
+    /// next read returns, or removes that pairing. A read returns `[report
+    /// ID, selector, slot, number of pairings, 1 when connected, address (6
+    /// bytes, most significant first), name length, name (UTF-8)]`. Pairings
+    /// move up to fill a removed slot.
+    pairings_feature: FeatureReport,
+}
+
+/// Reports of headsets with active noise cancellation.
+#[derive(Debug, Clone, Copy)]
+struct AncProtocol {
+    /// 1 while noise cancellation is on.
+    enabled_feature: FeatureReport,
+    /// How strongly it cancels, from 1 to `levels`; kept while it is off.
+    level_feature: FeatureReport,
+    levels: u8,
+    /// 1 while the ambient (transparency) mode passes outside sound
+    /// through, on headsets that have one; only one of it and noise
+    /// cancellation is on at a time.
+    ambient_feature: Option<FeatureReport>,
+}
+
+const PAIRINGS_SELECT: u8 = 0x00;
+const PAIRINGS_REMOVE: u8 = 0x01;
+
+/// Reports of headsets whose buttons can be given other functions.
+#[derive(Debug, Clone, Copy)]
+struct ButtonProtocol {
+    /// Writing `[report ID, selector, command, button, action]` assigns
+    /// `action` to `button`, or selects the button the next read returns as
+    /// `[report ID, selector, button, action]`; see [`Button::code`] and
+    /// [`ButtonAction::code`].
+    feature: FeatureReport,
+    buttons: &'static [RemappableButton],
+}
+
+const BUTTONS_SELECT: u8 = 0x00;
+const BUTTONS_ASSIGN: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy)]
+struct DfuProtocol {
+    report_id: u8,
+    /// The bootloader can read back the installed image.
+    readback: bool,
+}
+
+const CLOUD_III_WIRED: DeviceDescriptor = DeviceDescriptor {
+    vendor_id: 0x03F0,
+    product_id: 0x089D,
+    sidetone_feature: Some(FeatureReport {
+        report_id: 0x20,
+        selector: 0x86,
+        length: REPORT_LENGTH,
+    }),
+    // No HID volume report is known for this model.
+    volume_feature: None,
+    battery_feature: None,
+    battery_input: None,
+    // The in-line controls are standard consumer controls.
+    button_input: None,
+    dial_input: None,
+    // The mute LED follows the hardware mute switch and is not addressable.
+    mute_led_feature: None,
+    // The firmware has no output mute; NGenuity mutes the OS endpoint.
+    output_mute_feature: None,
+    // No prompts, LEDs apart from the mute LED, or notification tones.
+    voice_prompts_feature: None,
+    led_brightness_feature: None,
+    notification_beeps_feature: None,
+    // The mute switch beeps at a fixed volume.
+    mute_beep_feature: None,
+    // The microphone is detachable and mutes with a switch instead.
+    boom_feature: None,
+    // NGenuity reads the version from the USB descriptor.
+    firmware_feature: None,
+    // NGenuity offers no reset for this model.
+    factory_reset_feature: None,
+    // Only the USB strings are known for this model.
+    hardware_feature: None,
+    // NGenuity has no identify button for this model, and its mute LED
+    // cannot be blinked either.
+    identify_feature: None,
+    // Wired only.
+    wireless_mode_feature: None,
+    multipoint: None,
+    // No noise cancellation.
+    anc: None,
+    // Wired only.
+    auto_answer_feature: None,
+    // The buttons are wired to the in-line controls and cannot be remapped.
+    buttons: None,
+    // Captured responses end after the value.
+    write_status: false,
+    dfu: None,
+    receiver: None,
+};
+
+/// A made-up wireless model with every feature, so the simulator covers the
+/// whole command surface. The product id is not used by any HyperX device.
+const SIMULATED_WIRELESS: DeviceDescriptor = DeviceDescriptor {
+    vendor_id: 0x03F0,
+    product_id: 0xFFFF,
+    sidetone_feature: Some(FeatureReport {
+        report_id: 0x20,
+        selector: 0x86,
+        length: REPORT_LENGTH,
+    }),
+    volume_feature: Some(FeatureReport {
+        report_id: 0x21,
+        selector: 0x01,
+        length: REPORT_LENGTH,
+    }),
+    battery_feature: Some(FeatureReport {
+        report_id: 0x22,
+        selector: 0x02,
+        length: REPORT_LENGTH,
+    }),
+    battery_input: Some(FeatureReport {
+        report_id: 0x42,
+        selector: 0x02,
+        length: REPORT_LENGTH,
+    }),
+    button_input: Some(FeatureReport {
+        report_id: 0x43,
+        selector: 0x19,
+        length: REPORT_LENGTH,
+    }),
+    dial_input: Some(FeatureReport {
+        report_id: 0x44,
+        selector: 0x1A,
+        length: REPORT_LENGTH,
+    }),
+    mute_led_feature: Some(FeatureReport {
+        report_id: 0x23,
+        selector: 0x03,
+        length: REPORT_LENGTH,
+    }),
+    output_mute_feature: Some(FeatureReport {
+        report_id: 0x2B,
+        selector: 0x0E,
+        length: REPORT_LENGTH,
+    }),
+    voice_prompts_feature: Some(FeatureReport {
+        report_id: 0x2C,
+        selector: 0x0F,
+        length: REPORT_LENGTH,
+    }),
+    led_brightness_feature: Some(FeatureReport {
+        report_id: 0x2D,
+        selector: 0x10,
+        length: REPORT_LENGTH,
+    }),
+    notification_beeps_feature: Some(FeatureReport {
+        report_id: 0x2E,
+        selector: 0x11,
+        length: REPORT_LENGTH,
+    }),
+    mute_beep_feature: Some(FeatureReport {
+        report_id: 0x38,
+        selector: 0x16,
+        length: REPORT_LENGTH,
+    }),
+    boom_feature: Some(FeatureReport {
+        report_id: 0x39,
+        selector: 0x17,
+        length: REPORT_LENGTH,
+    }),
+    firmware_feature: Some(FeatureReport {
+        report_id: 0x24,
+        selector: 0x04,
+        length: REPORT_LENGTH,
+    }),
+    factory_reset_feature: Some(FeatureReport {
+        report_id: 0x25,
+        selector: 0x05,
+        length: REPORT_LENGTH,
+    }),
+    hardware_feature: Some(FeatureReport {
+        report_id: 0x26,
+        selector: 0x06,
+        length: REPORT_LENGTH,
+    }),
+    identify_feature: Some(FeatureReport {
+        report_id: 0x2A,
+        selector: 0x0D,
+        length: REPORT_LENGTH,
+    }),
+    wireless_mode_feature: Some(FeatureReport {
+        report_id: 0x27,
+        selector: 0x09,
+        length: REPORT_LENGTH,
+    }),
+    multipoint: Some(MultipointProtocol {
+        enabled_feature: FeatureReport {
+            report_id: 0x28,
+            selector: 0x0A,
+            length: REPORT_LENGTH,
+        },
+        pairings_feature: FeatureReport {
+            report_id: 0x29,
+            selector: 0x0B,
+            length: REPORT_LENGTH,
+        },
+    }),
+    anc: Some(AncProtocol {
+        enabled_feature: FeatureReport {
+            report_id: 0x2F,
+            selector: 0x12,
+            length: REPORT_LENGTH,
+        },
+        level_feature: FeatureReport {
+            report_id: 0x35,
+            selector: 0x13,
+            length: REPORT_LENGTH,
+        },
+        levels: 3,
+        ambient_feature: Some(FeatureReport {
+            report_id: 0x36,
+            selector: 0x14,
+            length: REPORT_LENGTH,
+        }),
+    }),
+    auto_answer_feature: Some(FeatureReport {
+        report_id: 0x37,
+        selector: 0x15,
+        length: REPORT_LENGTH,
+    }),
+    buttons: Some(ButtonProtocol {
+        feature: FeatureReport {
+            report_id: 0x3A,
+            selector: 0x18,
+            length: REPORT_LENGTH,
+        },
+        buttons: &[
+            RemappableButton {
+                button: Button::MultiFunction,
+                actions: &[
+                    ButtonAction::Default,
+                    ButtonAction::PlayPause,
+                    ButtonAction::NextTrack,
+                    ButtonAction::PreviousTrack,
+                    ButtonAction::VoiceAssistant,
+                    ButtonAction::NoiseMode,
+                    ButtonAction::Disabled,
+                ],
+            },
+            RemappableButton {
+                button: Button::NoiseControl,
+                actions: &[
+                    ButtonAction::Default,
+                    ButtonAction::PlayPause,
+                    ButtonAction::VoiceAssistant,
+                    ButtonAction::Disabled,
+                ],
+            },
+        ],
+    }),
+    write_status: true,
+    dfu: Some(DfuProtocol {
+        report_id: 0x30,
+        readback: true,
+    }),
+    receiver: Some(ReceiverDescriptor {
+        product_id: 0xFFFE,
+        dfu: Some(DfuProtocol {
+            report_id: 0x31,
+            readback: false,
+        }),
+        pairing_feature: Some(FeatureReport {
+            report_id: 0x32,
+            selector: 0x07,
+            length: REPORT_LENGTH,
+        }),
+        link_feature: Some(FeatureReport {
+            report_id: 0x33,
+            selector: 0x08,
+            length: REPORT_LENGTH,
+        }),
+        game_mode_feature: Some(FeatureReport {
+            report_id: 0x34,
+            selector: 0x0C,
+            length: REPORT_LENGTH,
+        }),
+    }),
+};
+
+fn find_descriptor(device_id: DeviceId) -> DeviceDescriptor {
+    match device_id.model {
+        Model::CloudIiiWired => CLOUD_III_WIRED,
+        Model::SimulatedWireless => SIMULATED_WIRELESS,
+    }
+}
+
+/// High-level errors returned to the frontend.
+#[derive(Debug)]
+pub enum ControlError {
+    HidInit {
+        source: hidapi::HidError,
+    },
+    DeviceOpen {
+        vendor_id: u16,
+        product_id: u16,
+        source: hidapi::HidError,
+        diagnosis: OpenDiagnosis,
+    },
+    ReportSend {
+        report_id: u8,
+        selector: u8,
+        source: hidapi::HidError,
+    },
+    ReportRead {
+        report_id: u8,
+        selector: u8,
+        source: hidapi::HidError,
+    },
+    UnsupportedFeature {
+        device_id: DeviceId,
+        capability: Capability,
+    },
+    /// A [`Feature`] the device only reports, such as its battery charge.
+    ReadOnlyFeature {
+        feature: FeatureKind,
+    },
+    /// The report was delivered, but the firmware refused the value with
+    /// `status`.
+    Rejected {
+        report_id: u8,
+        selector: u8,
+        status: u8,
+    },
+    /// The device accepted a write but reads back `actual` instead of
+    /// `expected`.
+    NotApplied {
+        report_id: u8,
+        selector: u8,
+        expected: u16,
+        actual: u16,
+    },
+    /// A write failed or was reverted while another controller is running.
+    DeviceBusy {
+        process: String,
+    },
+    /// A raw feature report that does not fit the device's report descriptor.
+    InvalidReport {
+        reason: String,
+    },
+    /// A firmware flash or backup holds the device, see [`DfuSession`].
+    Flashing {
+        device_id: DeviceId,
+    },
+    /// The model does not offer `action` for `button`, or has no such
+    /// button.
+    UnsupportedButtonAction {
+        button: Button,
+        action: ButtonAction,
+    },
+    /// The device did not report the current value of `feature`, which a
+    /// change through [`Device::set_features`] needs to be undone.
+    Unreadable {
+        device_id: DeviceId,
+        feature: FeatureKind,
+    },
+}
+
+impl fmt::Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message(i18n::DEFAULT_LOCALE))
+    }
+}
+
+impl std::error::Error for ControlError {}
+
+impl ControlError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ControlError::HidInit { .. } => "hid_init",
+            ControlError::DeviceOpen { .. } => "device_open",
+            ControlError::ReportSend { .. } => "report_send",
+            ControlError::ReportRead { .. } => "report_read",
+            ControlError::UnsupportedFeature { .. } => "unsupported_feature",
+            ControlError::ReadOnlyFeature { .. } => "read_only_feature",
+            ControlError::Rejected { .. } => "rejected",
+            ControlError::NotApplied { .. } => "not_applied",
+            ControlError::DeviceBusy { .. } => "device_busy",
+            ControlError::InvalidReport { .. } => "invalid_report",
+            ControlError::Flashing { .. } => "flashing",
+            ControlError::UnsupportedButtonAction { .. } => "unsupported_button_action",
+            ControlError::Unreadable { .. } => "unreadable",
+        }
+    }
+
+    /// The message in `locale`, see [`i18n`].
+    pub fn message(&self, locale: &str) -> String {
+        let translate = |id, args: &[(&str, &dyn fmt::Display)]| i18n::translate(locale, id, args);
+        let hex = |byte: &u8| format!("0x{byte:02X}");
+        match self {
+            ControlError::HidInit { source } => translate("error-hid-init", &[("source", source)]),
+            ControlError::DeviceOpen {
+                vendor_id,
+                product_id,
+                source,
+                diagnosis,
+            } => {
+                let reason = match diagnosis {
+                    OpenDiagnosis::Unknown => source.to_string(),
+                    diagnosis => diagnosis.message(locale),
+                };
+                translate(
+                    "error-device-open",
+                    &[
+                        ("vendor_id", &format!("0x{vendor_id:04X}")),
+                        ("product_id", &format!("0x{product_id:04X}")),
+                        ("reason", &reason),
+                    ],
+                )
+            }
+            ControlError::ReportSend {
+                report_id,
+                selector,
+                source,
+            } => translate(
+                "error-report-send",
+                &[
+                    ("report_id", &hex(report_id)),
+                    ("selector", &hex(selector)),
+                    ("source", source),
+                ],
+            ),
+            ControlError::ReportRead {
+                report_id,
+                selector,
+                source,
+            } => translate(
+                "error-report-read",
+                &[
+                    ("report_id", &hex(report_id)),
+                    ("selector", &hex(selector)),
+                    ("source", source),
+                ],
+            ),
+            ControlError::UnsupportedFeature {
+                device_id,
+                capability,
+            } => translate(
+                "error-unsupported-feature",
+                &[
+                    ("device_id", device_id),
+                    ("capability", &capability.as_str()),
+                ],
+            ),
+            ControlError::ReadOnlyFeature { feature } => {
+                translate("error-read-only-feature", &[("feature", &feature.as_str())])
+            }
+            ControlError::Rejected {
+                report_id,
+                selector,
+                status,
+            } => translate(
+                "error-rejected",
+                &[
+                    ("report_id", &hex(report_id)),
+                    ("selector", &hex(selector)),
+                    ("status", &hex(status)),
+                ],
+            ),
+            ControlError::NotApplied {
+                report_id,
+                selector,
+                expected,
+                actual,
+            } => translate(
+                "error-not-applied",
+                &[
+                    ("report_id", &hex(report_id)),
+                    ("selector", &hex(selector)),
+                    ("expected", expected),
+                    ("actual", actual),
+                ],
+            ),
+            ControlError::DeviceBusy { process } => {
+                translate("error-device-busy", &[("process", process)])
+            }
+            ControlError::InvalidReport { reason } => {
+                translate("error-invalid-report", &[("reason", reason)])
+            }
+            ControlError::Flashing { device_id } => {
+                translate("error-flashing", &[("device_id", device_id)])
+            }
+            ControlError::UnsupportedButtonAction { button, action } => translate(
+                "error-unsupported-button-action",
+                &[("button", &button.as_str()), ("action", &action.as_str())],
+            ),
+            ControlError::Unreadable { device_id, feature } => translate(
+                "error-unreadable",
+                &[("device_id", device_id), ("feature", &feature.as_str())],
+            ),
+        }
+    }
+
+    /// Suggested fix in `locale`, phrased for end users.
+    pub fn remedy(&self, locale: &str) -> Option<String> {
+        match self {
+            ControlError::DeviceOpen { diagnosis, .. } => Some(diagnosis.remedy(locale)),
+            ControlError::DeviceBusy { process } => Some(i18n::translate(
+                locale,
+                "remedy-device-busy",
+                &[("process", process)],
+            )),
+            _ => None,
+        }
+    }
+
+    /// Extra advice beyond the diagnosis remedy, e.g. pointing at the libusb
+    /// build when hidraw permissions cannot be fixed on this system.
+    pub fn hint(&self, locale: &str) -> Option<String> {
+        match self {
+            ControlError::DeviceOpen {
+                diagnosis:
+                    OpenDiagnosis::MissingUdevRule { .. }
+                    | OpenDiagnosis::NotInGroup { .. }
+                    | OpenDiagnosis::ReconnectRequired { .. },
+                ..
+            } if hid_backend() == HidBackend::Hidraw => {
+                Some(i18n::translate(locale, "hint-libusb", &[]))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Serialised as `{ kind, capability, message, diagnosis, remedy, hint }` so
+/// the frontend can show a fix next to the error instead of the raw hidapi
+/// string. The texts are in [`i18n::locale`].
+impl Serialize for ControlError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let diagnosis = match self {
+            ControlError::DeviceOpen { diagnosis, .. } => Some(diagnosis),
+            _ => None,
+        };
+        let capability = match self {
+            ControlError::UnsupportedFeature { capability, .. } => Some(capability),
+            _ => None,
+        };
+        let mut state = serializer.serialize_struct("ControlError", 6)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("capability", &capability)?;
+        let locale = i18n::locale();
+        state.serialize_field("message", &self.message(locale))?;
+        state.serialize_field("diagnosis", &diagnosis)?;
+        state.serialize_field("remedy", &self.remedy(locale))?;
+        state.serialize_field("hint", &self.hint(locale))?;
+        state.end()
+    }
+}
+
+/// Entry points for the fuzz targets in `fuzz/`. They use the same code as
+/// the device paths, but take raw report layouts instead of a device.
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing {
+    use crate::{features, FeatureReport};
+
+    pub fn build_feature_payload(
+        report_id: u8,
+        selector: u8,
+        value: u16,
+        length: usize,
+    ) -> Vec<u8> {
+        let report = FeatureReport {
+            report_id,
+            selector,
+            length,
+        };
+        features::build_feature_payload(report, value, length).to_vec()
+    }
+
+    pub fn parse_response(report_id: u8, selector: u8, response: &[u8]) -> Option<u16> {
+        let report = FeatureReport {
+            report_id,
+            selector,
+            length: response.len(),
+        };
+        features::parse_response(report, response)
+    }
+
+    pub fn percent(value: u16) -> u8 {
+        features::percent(value)
+    }
+}
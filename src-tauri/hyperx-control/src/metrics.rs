@@ -0,0 +1,38 @@
+//! Timing of HID traffic, handed to the [`crate::host::Host`] to count.
+
+use crate::{host, ControlError, DeviceId};
+use std::time::Instant;
+use tracing::{info_span, warn};
+
+/// Run one HID operation (`"read"`, `"write"`) inside a `hid` span, logging
+/// it when it fails.
+pub(crate) fn timed<T>(
+    device_id: DeviceId,
+    operation: &'static str,
+    run: impl FnOnce() -> Result<T, ControlError>,
+) -> Result<T, ControlError> {
+    let _span = info_span!("hid", device = %device_id, operation).entered();
+    let started = Instant::now();
+    let result = run();
+    if let Err(err) = &result {
+        warn!(kind = err.kind(), "{err}");
+    }
+    host::get().operation_finished(
+        device_id,
+        operation,
+        started.elapsed(),
+        result.as_ref().err(),
+    );
+    result
+}
+
+/// Time one of the HID calls an operation is made of.
+pub(crate) fn timed_call<T, E>(
+    call: &'static str,
+    run: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    let started = Instant::now();
+    let result = run();
+    host::get().call_finished(call, started.elapsed(), result.is_ok());
+    result
+}
@@ -0,0 +1,207 @@
+//! A stand-in for hidapi. When a backend is installed at startup (the
+//! simulator, or a replayed capture), every feature report goes to it
+//! instead of real hardware, so the whole command surface works without a
+//! headset.
+
+use crate::DeviceId;
+#[cfg(any(test, feature = "test-util"))]
+use crate::{features, find_descriptor, FeatureKind};
+use hidapi::HidError;
+#[cfg(any(test, feature = "test-util"))]
+use std::collections::{HashMap, HashSet};
+#[cfg(any(test, feature = "test-util"))]
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(any(test, feature = "test-util"))]
+use std::sync::Mutex;
+#[cfg(not(any(test, feature = "test-util")))]
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+pub trait MockBackend: Send + Sync {
+    /// Devices that currently count as plugged in.
+    fn connected(&self) -> Vec<DeviceId>;
+
+    fn send_feature_report(&self, device_id: DeviceId, data: &[u8]) -> Result<(), HidError>;
+
+    /// Fill `buffer`, whose first byte holds the requested report id, like
+    /// `hid_get_feature_report`; returns the number of bytes written.
+    fn get_feature_report(&self, device_id: DeviceId, buffer: &mut [u8])
+        -> Result<usize, HidError>;
+
+    /// Wait up to `timeout` for an input report the device sends on its own
+    /// and copy it into `buffer`, like `hid_read_timeout`; returns 0 when
+    /// none came. By default devices send none.
+    fn read_input_report(
+        &self,
+        _device_id: DeviceId,
+        _buffer: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize, HidError> {
+        thread::sleep(timeout);
+        Ok(0)
+    }
+}
+
+#[cfg(not(any(test, feature = "test-util")))]
+static BACKEND: OnceLock<&'static dyn MockBackend> = OnceLock::new();
+
+// Unit tests share one process, so a backend installed by one test must not
+// leak into tests that talk to real (or uhid) devices.
+#[cfg(any(test, feature = "test-util"))]
+thread_local! {
+    static TEST_BACKEND: std::cell::Cell<Option<&'static dyn MockBackend>> =
+        const { std::cell::Cell::new(None) };
+}
+
+/// Route all HID traffic to `backend` for the rest of the process. Only the
+/// first call has an effect. In tests it applies to the calling thread.
+pub fn install(backend: &'static dyn MockBackend) {
+    #[cfg(any(test, feature = "test-util"))]
+    TEST_BACKEND.set(Some(backend));
+    #[cfg(not(any(test, feature = "test-util")))]
+    let _ = BACKEND.set(backend);
+}
+
+pub fn backend() -> Option<&'static dyn MockBackend> {
+    #[cfg(any(test, feature = "test-util"))]
+    return TEST_BACKEND.get();
+    #[cfg(not(any(test, feature = "test-util")))]
+    BACKEND.get().copied()
+}
+
+pub fn is_active() -> bool {
+    backend().is_some()
+}
+
+/// A headset for tests: each of its features keeps the value last written to
+/// it, 0 at first, and every read is counted. Only the plain features are
+/// answered, not the bootloader, pairings or button remapping.
+#[cfg(any(test, feature = "test-util"))]
+pub struct TestHeadset {
+    device_id: DeviceId,
+    connected: AtomicBool,
+    /// By report ID.
+    values: Mutex<HashMap<u8, u16>>,
+    /// Report IDs whose writes fail to send.
+    failing: Mutex<HashSet<u8>>,
+    /// Report IDs whose writes the firmware refuses, with the status it
+    /// answers with.
+    rejecting: Mutex<HashMap<u8, u8>>,
+    reads: AtomicUsize,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl TestHeadset {
+    /// Install a plugged-in headset with `device_id` as the backend of the
+    /// calling thread.
+    pub fn install(device_id: DeviceId) -> &'static TestHeadset {
+        let headset = Box::leak(Box::new(TestHeadset {
+            device_id,
+            connected: AtomicBool::new(true),
+            values: Mutex::new(HashMap::new()),
+            failing: Mutex::new(HashSet::new()),
+            rejecting: Mutex::new(HashMap::new()),
+            reads: AtomicUsize::new(0),
+        }));
+        install(headset);
+        headset
+    }
+
+    fn report_id(&self, kind: FeatureKind) -> u8 {
+        features::features(find_descriptor(self.device_id))
+            .into_iter()
+            .find_map(|(other, feature)| feature.filter(|_| other == kind))
+            .unwrap_or_else(|| panic!("{} has no {} report", self.device_id, kind.as_str()))
+            .report_id
+    }
+
+    /// Set `kind` as if the user changed it on the headset.
+    pub fn set(&self, kind: FeatureKind, value: u16) {
+        let report_id = self.report_id(kind);
+        self.values.lock().unwrap().insert(report_id, value);
+    }
+
+    pub fn value(&self, kind: FeatureKind) -> u16 {
+        let report_id = self.report_id(kind);
+        self.values
+            .lock()
+            .unwrap()
+            .get(&report_id)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Make sending `kind` fail, as when the headset is unplugged mid-write.
+    pub fn fail_writes(&self, kind: FeatureKind) {
+        let report_id = self.report_id(kind);
+        self.failing.lock().unwrap().insert(report_id);
+    }
+
+    /// Keep `kind` as it is on writes, answering with `status` after the
+    /// value.
+    pub fn reject_writes(&self, kind: FeatureKind, status: u8) {
+        let report_id = self.report_id(kind);
+        self.rejecting.lock().unwrap().insert(report_id, status);
+    }
+
+    pub fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::SeqCst);
+    }
+
+    /// Feature reports read so far.
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockBackend for TestHeadset {
+    fn connected(&self) -> Vec<DeviceId> {
+        if self.connected.load(Ordering::SeqCst) {
+            vec![self.device_id]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn send_feature_report(&self, _device_id: DeviceId, data: &[u8]) -> Result<(), HidError> {
+        let &[report_id, _, low, high, ..] = data else {
+            return Err(HidError::HidApiError {
+                message: "test headset got a short report".into(),
+            });
+        };
+        if self.failing.lock().unwrap().contains(&report_id) {
+            return Err(HidError::HidApiError {
+                message: "test headset failed the write".into(),
+            });
+        }
+        if !self.rejecting.lock().unwrap().contains_key(&report_id) {
+            let value = u16::from_le_bytes([low, high]);
+            self.values.lock().unwrap().insert(report_id, value);
+        }
+        Ok(())
+    }
+
+    fn get_feature_report(
+        &self,
+        _device_id: DeviceId,
+        buffer: &mut [u8],
+    ) -> Result<usize, HidError> {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        let report_id = buffer.first().copied().unwrap_or(0);
+        let (_, selector) =
+            crate::feature_kind(self.device_id, report_id, None).ok_or_else(|| {
+                HidError::HidApiError {
+                    message: format!("test headset has no feature report 0x{report_id:02X}"),
+                }
+            })?;
+        let value = self.values.lock().unwrap().get(&report_id).copied();
+        let [low, high] = value.unwrap_or(0).to_le_bytes();
+        let status = self.rejecting.lock().unwrap().get(&report_id).copied();
+        let response = [report_id, selector, low, high, status.unwrap_or(0)];
+        let length = response.len().min(buffer.len());
+        buffer[..length].copy_from_slice(&response[..length]);
+        Ok(length)
+    }
+}
@@ -0,0 +1,194 @@
+//! Opt-in capture of every feature report exchanged with a headset, for bug
+//! reports. A recording is a JSON Lines file: a [`Header`] line followed by
+//! one [`Transaction`] per report, with payloads as hex. Device serial
+//! numbers are replaced by `*` wherever they show up, in payloads (ASCII or
+//! UTF-16) as well as in error messages.
+
+use crate::{DeviceId, HidBackend};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Below the config directory.
+pub const RECORDINGS_DIR: &str = "recordings";
+/// Bumped when the line format changes incompatibly.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    pub format: u32,
+    pub app_version: String,
+    pub os: String,
+    pub hid_backend: HidBackend,
+    /// Unix time in milliseconds.
+    pub started_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    SendFeatureReport,
+    GetFeatureReport,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
+    /// Milliseconds since the recording started.
+    pub time_ms: u64,
+    pub device_id: DeviceId,
+    pub operation: Operation,
+    /// The written report, or the buffer handed to the read with the report id in front.
+    pub sent: String,
+    /// Bytes the device returned, for reads that succeeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct Recording {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    started: Instant,
+    /// Serial numbers of the devices seen so far.
+    secrets: Vec<String>,
+}
+
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+fn write_line(writer: &mut BufWriter<File>, value: &impl Serialize) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, value)?;
+    writer.write_all(b"\n")?;
+    writer.flush()
+}
+
+/// Start recording to `path`, replacing a recording in progress.
+/// `app_version` goes into the header.
+pub fn start(path: &Path, app_version: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    let header = Header {
+        format: FORMAT_VERSION,
+        app_version: app_version.to_string(),
+        os: std::env::consts::OS.to_string(),
+        hid_backend: crate::hid_backend(),
+        started_at: unix_millis(),
+    };
+    write_line(&mut writer, &header)?;
+    *RECORDING.lock().unwrap() = Some(Recording {
+        path: path.to_path_buf(),
+        writer,
+        started: Instant::now(),
+        secrets: Vec::new(),
+    });
+    Ok(())
+}
+
+/// Start recording to a new timestamped file in [`RECORDINGS_DIR`].
+pub fn start_in(config_dir: &Path, app_version: &str) -> io::Result<PathBuf> {
+    let path = config_dir
+        .join(RECORDINGS_DIR)
+        .join(format!("hid-{}.jsonl", unix_millis() / 1000));
+    start(&path, app_version)?;
+    Ok(path)
+}
+
+/// Stop recording; returns the file that was written.
+pub fn stop() -> Option<PathBuf> {
+    RECORDING
+        .lock()
+        .unwrap()
+        .take()
+        .map(|recording| recording.path)
+}
+
+pub fn is_recording() -> bool {
+    RECORDING.lock().unwrap().is_some()
+}
+
+/// Remember a device serial number so it is redacted from later transactions.
+pub(crate) fn add_secret(serial: &str) {
+    let mut recording = RECORDING.lock().unwrap();
+    let Some(recording) = recording.as_mut() else {
+        return;
+    };
+    if !serial.is_empty() && !recording.secrets.iter().any(|known| known == serial) {
+        recording.secrets.push(serial.to_string());
+    }
+}
+
+pub fn hex(data: &[u8]) -> String {
+    data.iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn redact_bytes(data: &[u8], secrets: &[String]) -> Vec<u8> {
+    let mut data = data.to_vec();
+    for secret in secrets {
+        let ascii = secret.as_bytes().to_vec();
+        let utf16: Vec<u8> = secret.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        for needle in [ascii, utf16] {
+            let mut start = 0;
+            while let Some(offset) = data[start..]
+                .windows(needle.len())
+                .position(|window| window == needle)
+            {
+                let at = start + offset;
+                data[at..at + needle.len()].fill(b'*');
+                start = at + needle.len();
+            }
+        }
+    }
+    data
+}
+
+fn redact_text(text: &str, secrets: &[String]) -> String {
+    secrets.iter().fold(text.to_string(), |text, secret| {
+        text.replace(secret.as_str(), &"*".repeat(secret.len()))
+    })
+}
+
+/// Append one transaction if a recording is running. `received` is the part of
+/// the buffer a successful read filled in.
+pub(crate) fn record(
+    device_id: DeviceId,
+    operation: Operation,
+    sent: &[u8],
+    result: Result<Option<&[u8]>, &hidapi::HidError>,
+) {
+    let mut guard = RECORDING.lock().unwrap();
+    let Some(recording) = guard.as_mut() else {
+        return;
+    };
+    let secrets = &recording.secrets;
+    let (received, error) = match result {
+        Ok(received) => (received.map(|data| hex(&redact_bytes(data, secrets))), None),
+        Err(err) => (None, Some(redact_text(&err.to_string(), secrets))),
+    };
+    let transaction = Transaction {
+        time_ms: recording.started.elapsed().as_millis() as u64,
+        device_id,
+        operation,
+        sent: hex(&redact_bytes(sent, secrets)),
+        received,
+        error,
+    };
+    if let Err(err) = write_line(&mut recording.writer, &transaction) {
+        warn!("HID recording stopped: {err}");
+        *guard = None;
+    }
+}
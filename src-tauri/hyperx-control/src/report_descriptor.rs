@@ -0,0 +1,73 @@
+//! Just enough of a HID report descriptor parser to learn feature report sizes.
+
+use std::collections::HashMap;
+
+const TYPE_MAIN: u8 = 0;
+const TYPE_GLOBAL: u8 = 1;
+
+const MAIN_FEATURE: u8 = 0xB;
+const GLOBAL_REPORT_SIZE: u8 = 0x7;
+const GLOBAL_REPORT_ID: u8 = 0x8;
+const GLOBAL_REPORT_COUNT: u8 = 0x9;
+const GLOBAL_PUSH: u8 = 0xA;
+const GLOBAL_POP: u8 = 0xB;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Globals {
+    report_size: u32,
+    report_count: u32,
+    report_id: u8,
+}
+
+/// Size in bytes of each feature report, keyed by report ID and including the
+/// report ID byte when the descriptor uses IDs (ID `0` otherwise).
+pub fn feature_lengths(descriptor: &[u8]) -> HashMap<u8, usize> {
+    let mut bits: HashMap<u8, u32> = HashMap::new();
+    let mut globals = Globals::default();
+    let mut stack = Vec::new();
+    let mut uses_ids = false;
+
+    let mut rest = descriptor;
+    while let Some((&prefix, tail)) = rest.split_first() {
+        // Long items carry their size in the next byte and are never feature-related.
+        if prefix == 0xFE {
+            let size = tail.first().copied().unwrap_or(0) as usize;
+            rest = tail.get(2 + size..).unwrap_or_default();
+            continue;
+        }
+        let size = match prefix & 0b11 {
+            3 => 4,
+            size => size as usize,
+        };
+        let Some(data) = tail.get(..size) else {
+            break;
+        };
+        rest = &tail[size..];
+        let value = data
+            .iter()
+            .rev()
+            .fold(0u32, |value, &byte| (value << 8) | u32::from(byte));
+
+        match ((prefix >> 2) & 0b11, prefix >> 4) {
+            (TYPE_GLOBAL, GLOBAL_REPORT_SIZE) => globals.report_size = value,
+            (TYPE_GLOBAL, GLOBAL_REPORT_COUNT) => globals.report_count = value,
+            (TYPE_GLOBAL, GLOBAL_REPORT_ID) => {
+                globals.report_id = value as u8;
+                uses_ids = true;
+            }
+            (TYPE_GLOBAL, GLOBAL_PUSH) => stack.push(globals),
+            (TYPE_GLOBAL, GLOBAL_POP) => globals = stack.pop().unwrap_or_default(),
+            (TYPE_MAIN, MAIN_FEATURE) => {
+                let total = bits.entry(globals.report_id).or_default();
+                *total =
+                    total.saturating_add(globals.report_size.saturating_mul(globals.report_count));
+            }
+            _ => {}
+        }
+    }
+
+    let id_byte = usize::from(uses_ids);
+    bits.into_iter()
+        .map(|(id, bits)| (id, (bits as usize).div_ceil(8) + id_byte))
+        .collect()
+}
@@ -0,0 +1,151 @@
+//! User-triggerable actions shared by every input path (global shortcuts,
+//! Stream Deck buttons).
+
+use crate::audio::{self, AudioError};
+use crate::audit::{self, Source};
+use crate::hyperx::{self, ControlError, DeviceId, NoiseMode};
+use crate::profiles::{self, DeviceSettings};
+use crate::settings::SettingsStore;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+const LED_FLASHES: usize = 3;
+const LED_FLASH_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// Mute or unmute the headset microphone at the OS audio server.
+    ToggleMicMute { device_id: DeviceId },
+    /// Flip the hardware sidetone.
+    ToggleSidetone { device_id: DeviceId },
+    /// Blink the microphone mute LED, on headsets where it is addressable.
+    FlashMuteLed { device_id: DeviceId },
+    /// Step through noise cancellation, the ambient mode and off, skipping
+    /// the ambient mode on headsets without it.
+    CycleNoiseMode { device_id: DeviceId },
+}
+
+impl Action {
+    /// Name used in the audit log.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::ToggleMicMute { .. } => "toggle_mic_mute",
+            Action::ToggleSidetone { .. } => "toggle_sidetone",
+            Action::FlashMuteLed { .. } => "flash_mute_led",
+            Action::CycleNoiseMode { .. } => "cycle_noise_mode",
+        }
+    }
+
+    pub fn device_id(&self) -> DeviceId {
+        match *self {
+            Action::ToggleMicMute { device_id }
+            | Action::ToggleSidetone { device_id }
+            | Action::FlashMuteLed { device_id }
+            | Action::CycleNoiseMode { device_id } => device_id,
+        }
+    }
+
+    /// Human-readable label, e.g. shown by the desktop's shortcut settings.
+    pub fn describe(&self) -> String {
+        match self {
+            Action::ToggleMicMute { device_id } => {
+                format!("Toggle microphone mute ({device_id})")
+            }
+            Action::ToggleSidetone { device_id } => format!("Toggle sidetone ({device_id})"),
+            Action::FlashMuteLed { device_id } => format!("Flash mute LED ({device_id})"),
+            Action::CycleNoiseMode { device_id } => {
+                format!("Cycle noise cancellation ({device_id})")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ActionError {
+    Control(ControlError),
+    Audio(AudioError),
+    /// The device answered with a value we could not interpret.
+    UnknownState,
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionError::Control(err) => err.fmt(f),
+            ActionError::Audio(err) => err.fmt(f),
+            ActionError::UnknownState => write!(f, "device reported an unreadable state"),
+        }
+    }
+}
+
+impl std::error::Error for ActionError {}
+
+/// Perform `action` on behalf of `source`, logging it to the audit log.
+pub fn perform(
+    settings: &SettingsStore,
+    source: Source,
+    action: &Action,
+) -> Result<(), ActionError> {
+    let result = perform_unaudited(settings, action);
+    audit::record(
+        source,
+        Some(action.device_id()),
+        action.name(),
+        Value::Null,
+        result,
+    )
+}
+
+fn perform_unaudited(settings: &SettingsStore, action: &Action) -> Result<(), ActionError> {
+    match *action {
+        Action::ToggleMicMute { device_id } => {
+            audio::toggle_mic_mute(device_id).map_err(ActionError::Audio)
+        }
+        Action::ToggleSidetone { device_id } => {
+            let enabled = hyperx::devices()
+                .get(device_id)
+                .read_sidetone_state()
+                .map_err(ActionError::Control)?
+                .ok_or(ActionError::UnknownState)?;
+            let values = DeviceSettings {
+                sidetone: Some(!enabled),
+                ..DeviceSettings::default()
+            };
+            profiles::apply(settings, device_id, &values).map_err(ActionError::Control)
+        }
+        Action::FlashMuteLed { device_id } => flash_mute_led(device_id),
+        Action::CycleNoiseMode { device_id } => {
+            let device = hyperx::devices().get(device_id);
+            let mode = device
+                .read_noise_mode()
+                .map_err(ActionError::Control)?
+                .ok_or(ActionError::UnknownState)?;
+            let next = match mode {
+                NoiseMode::Off => NoiseMode::Anc,
+                NoiseMode::Anc if device.has_ambient() => NoiseMode::Ambient,
+                NoiseMode::Anc | NoiseMode::Ambient => NoiseMode::Off,
+            };
+            device.set_noise_mode(next).map_err(ActionError::Control)
+        }
+    }
+}
+
+/// Blink the mute LED a few times, then leave it showing the actual mute state.
+pub fn flash_mute_led(device_id: DeviceId) -> Result<(), ActionError> {
+    for index in 0..LED_FLASHES * 2 {
+        hyperx::devices()
+            .get(device_id)
+            .set_mute_led(index % 2 == 0)
+            .map_err(ActionError::Control)?;
+        thread::sleep(LED_FLASH_INTERVAL);
+    }
+    let muted = audio::mic_muted(device_id).unwrap_or(false);
+    hyperx::devices()
+        .get(device_id)
+        .set_mute_led(muted)
+        .map_err(ActionError::Control)
+}
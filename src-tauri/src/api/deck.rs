@@ -0,0 +1,74 @@
+//! `GET` routes under `/api/v2/deck` for Stream Deck buttons. Stream Deck
+//! web request plugins rarely support other methods or headers, so these
+//! take the token as `?token=` and act on `GET`. Each answers with the state
+//! after the action, for buttons that show it.
+
+use super::http::Response;
+use super::{control_error, with_device};
+use crate::actions::{self, Action, ActionError};
+use crate::audio;
+use crate::audit::{self, Source};
+use crate::hyperx;
+use crate::profiles::{self, ProfileError};
+use crate::settings::SettingsStore;
+use serde_json::json;
+
+fn action_error(err: ActionError) -> Response {
+    match err {
+        ActionError::Control(err) => control_error(err),
+        err => Response::error(500, err.to_string()),
+    }
+}
+
+/// Handle `route` (below `deck`), or `None` when it is not a deck route.
+pub fn handle(settings: &SettingsStore, route: &[&str]) -> Option<Result<Response, Response>> {
+    let result = match route {
+        [device, "toggle-mute"] => with_device(device, |device_id| {
+            actions::perform(
+                settings,
+                Source::StreamDeck,
+                &Action::ToggleMicMute { device_id },
+            )
+            .map_err(action_error)?;
+            let muted =
+                audio::mic_muted(device_id).map_err(|err| Response::error(500, err.to_string()))?;
+            Ok(Response::json(200, &json!({ "muted": muted })))
+        }),
+        [device, "toggle-sidetone"] => with_device(device, |device_id| {
+            actions::perform(
+                settings,
+                Source::StreamDeck,
+                &Action::ToggleSidetone { device_id },
+            )
+            .map_err(action_error)?;
+            let enabled = hyperx::devices()
+                .get(device_id)
+                .read_sidetone_state()
+                .map_err(control_error)?;
+            Ok(Response::json(200, &json!({ "enabled": enabled })))
+        }),
+        [device, "battery"] => with_device(device, |device_id| {
+            let percent = hyperx::devices()
+                .get(device_id)
+                .read_battery()
+                .map_err(control_error)?;
+            Ok(Response::json(200, &json!({ "percent": percent })))
+        }),
+        ["profiles", name, "apply"] => match audit::record(
+            Source::StreamDeck,
+            None,
+            "apply_profile",
+            json!(name),
+            profiles::apply_named(settings, name),
+        ) {
+            Ok(()) => Ok(Response::json(200, &json!({ "profile": name }))),
+            Err(ProfileError::NotFound { name }) => {
+                Err(Response::error(404, format!("no profile named '{name}'")))
+            }
+            Err(ProfileError::Control(err)) => Err(control_error(err)),
+            Err(err) => Err(Response::error(500, err.to_string())),
+        },
+        _ => return None,
+    };
+    Some(result)
+}
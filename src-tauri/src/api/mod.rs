@@ -0,0 +1,430 @@
+//! Optional localhost HTTP API mirroring the Tauri commands, so scripts and
+//! tools like AutoHotkey can drive the headset, plus a WebSocket stream of
+//! device events at `/api/v2/events`. `/api/version` tells clients which
+//! versions are served; other versions than `v2` get a
+//! [`VersionMismatch`](ipc::VersionMismatch). Every request must carry
+//! `Authorization: Bearer <token>` with the token from the settings, or
+//! `?token=<token>` where headers cannot be set (browser WebSockets). With
+//! `api.metrics` enabled, `/metrics` serves Prometheus metrics.
+
+mod deck;
+mod http;
+mod websocket;
+
+use crate::audio;
+use crate::audit::{self, Source};
+use crate::events::EventBus;
+use crate::groups::{self, GroupChange};
+use crate::hyperx::{self, ControlError, DeviceId, Feature};
+use crate::ipc;
+use crate::metrics;
+use crate::profiles::{self, DeviceSettings, ProfileError};
+use crate::settings::SettingsStore;
+use http::{Request, Response};
+use serde::Deserialize;
+use serde_json::json;
+use std::fmt::Write as _;
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use tracing::warn;
+
+/// Connections served at once, event streams included; further ones are
+/// turned away with 503 until one closes.
+const MAX_CONNECTIONS: usize = 32;
+
+/// A fresh 128-bit hex token from the OS RNG.
+pub fn generate_token() -> Result<String, getrandom::Error> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes)?;
+    Ok(bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    }))
+}
+
+/// Counts a connection as served until dropped.
+struct Connection(Arc<AtomicUsize>);
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Bind to the loopback interface and serve until the process exits. A
+/// missing token is generated and saved first.
+pub fn spawn(settings: Arc<SettingsStore>, events: Arc<EventBus>) {
+    let mut config = settings.get().api;
+    if config.token.is_none() {
+        let token = match generate_token() {
+            Ok(token) => token,
+            Err(err) => {
+                warn!("local API disabled: cannot generate a token: {err}");
+                return;
+            }
+        };
+        config.token = Some(token.clone());
+        if let Err(err) = settings.update(|settings| settings.api.token = Some(token)) {
+            warn!("local API disabled: {err}");
+            return;
+        }
+    }
+    let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, config.port)) {
+        Ok(listener) => listener,
+        Err(err) => {
+            warn!(
+                "local API disabled: cannot listen on port {}: {err}",
+                config.port
+            );
+            return;
+        }
+    };
+    if config.metrics {
+        metrics::spawn(events.clone());
+    }
+    let active = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                active.fetch_sub(1, Ordering::SeqCst);
+                let _ = stream.set_write_timeout(Some(http::TIMEOUT));
+                let response = Response::error(503, "too many connections");
+                let _ = http::write_response(&stream, &response);
+                continue;
+            }
+            let connection = Connection(active.clone());
+            let settings = settings.clone();
+            let events = events.clone();
+            thread::spawn(move || {
+                let _connection = connection;
+                serve(&settings, &events, stream)
+            });
+        }
+    });
+}
+
+fn serve(settings: &SettingsStore, events: &EventBus, stream: TcpStream) {
+    let response = match http::read_request(&stream) {
+        Ok(request)
+            if request.path == format!("/api/v{}/events", ipc::API_VERSION)
+                && websocket::is_upgrade(&request) =>
+        {
+            if authorized(settings, &request) {
+                // Event streams stay open while the client is quiet.
+                if stream.set_read_timeout(None).is_ok() {
+                    let _ = websocket::stream_events(stream, &request, events);
+                }
+                return;
+            }
+            Response::error(401, "missing or wrong bearer token")
+        }
+        Ok(request) => handle(settings, &request),
+        Err(err) => Response::error(400, err.to_string()),
+    };
+    let _ = http::write_response(&stream, &response);
+}
+
+fn authorized(settings: &SettingsStore, request: &Request) -> bool {
+    let Some(token) = settings.get().api.token else {
+        return false;
+    };
+    request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or(request.query.get("token").map(String::as_str))
+        .is_some_and(|given| given == token)
+}
+
+fn parse_body<T: for<'de> Deserialize<'de>>(request: &Request) -> Result<T, Response> {
+    serde_json::from_slice(&request.body).map_err(|err| Response::error(400, err.to_string()))
+}
+
+fn control_error(err: ControlError) -> Response {
+    let status = match err {
+        ControlError::UnsupportedFeature { .. }
+        | ControlError::ReadOnlyFeature { .. }
+        | ControlError::UnsupportedButtonAction { .. } => 400,
+        ControlError::DeviceBusy { .. } | ControlError::Flashing { .. } => 409,
+        ControlError::Rejected { .. } | ControlError::NotApplied { .. } => 422,
+        _ => 500,
+    };
+    Response::json(status, &err)
+}
+
+#[derive(Deserialize)]
+struct SidetoneBody {
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+struct MuteBody {
+    muted: bool,
+}
+
+fn handle(settings: &SettingsStore, request: &Request) -> Response {
+    if !authorized(settings, request) {
+        return Response::error(401, "missing or wrong bearer token");
+    }
+    let segments: Vec<&str> = request
+        .path
+        .trim_matches('/')
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    if segments == ["metrics"] && settings.get().api.metrics {
+        return match request.method.as_str() {
+            "GET" => Response::text(200, "text/plain; version=0.0.4", metrics::render()),
+            _ => Response::error(405, "method not allowed"),
+        };
+    }
+    let route = match segments.as_slice() {
+        ["api", "version"] => return Response::json(200, &ipc::info()),
+        ["api", version, route @ ..] => {
+            match version.strip_prefix('v').and_then(|v| v.parse().ok()) {
+                Some(ipc::API_VERSION) => route,
+                Some(version) => {
+                    return match ipc::check(version) {
+                        Err(mismatch) => Response::json(400, &mismatch),
+                        Ok(_) => Response::error(404, "unknown route"),
+                    };
+                }
+                None => return Response::error(404, "unknown route"),
+            }
+        }
+        _ => return Response::error(404, "unknown route"),
+    };
+    let result = match (request.method.as_str(), route) {
+        ("GET", ["devices"]) => Ok(Response::json(200, &hyperx::list_devices())),
+        ("GET", ["devices", device, "sidetone"]) => with_device(device, |device_id| {
+            hyperx::devices()
+                .get(device_id)
+                .read_sidetone_state()
+                .map(|enabled| Response::json(200, &serde_json::json!({ "enabled": enabled })))
+                .map_err(control_error)
+        }),
+        ("PUT", ["devices", device, "sidetone"]) => with_device(device, |device_id| {
+            let body: SidetoneBody = parse_body(request)?;
+            let values = DeviceSettings {
+                sidetone: Some(body.enabled),
+                ..DeviceSettings::default()
+            };
+            let result = profiles::apply(settings, device_id, &values);
+            audit::record(
+                Source::Api,
+                Some(device_id),
+                "set_sidetone",
+                json!(body.enabled),
+                result,
+            )
+            .map_err(control_error)?;
+            Ok(Response::no_content())
+        }),
+        ("PUT", ["devices", device, "mic-mute"]) => with_device(device, |device_id| {
+            let body: MuteBody = parse_body(request)?;
+            let result = audio::set_mic_mute(device_id, body.muted);
+            audit::record(
+                Source::Api,
+                Some(device_id),
+                "set_mic_mute",
+                json!(body.muted),
+                result,
+            )
+            .map_err(|err| Response::error(500, err.to_string()))?;
+            Ok(Response::no_content())
+        }),
+        ("POST", ["devices", device, "mic-mute", "toggle"]) => with_device(device, |device_id| {
+            let result = audio::toggle_mic_mute(device_id);
+            audit::record(
+                Source::Api,
+                Some(device_id),
+                "toggle_mic_mute",
+                json!(null),
+                result,
+            )
+            .map_err(|err| Response::error(500, err.to_string()))?;
+            Ok(Response::no_content())
+        }),
+        ("GET", ["devices", device, "battery"]) => with_device(device, |device_id| {
+            hyperx::devices()
+                .get(device_id)
+                .read_battery()
+                .map(|percent| Response::json(200, &serde_json::json!({ "percent": percent })))
+                .map_err(control_error)
+        }),
+        ("GET", ["devices", device, "state"]) => with_device(device, |device_id| {
+            let device = hyperx::devices().get(device_id);
+            let mut state = Vec::new();
+            for kind in device.supported_features() {
+                state.extend(device.get_feature(kind).map_err(control_error)?);
+            }
+            Ok(Response::json(200, &state))
+        }),
+        ("PUT", ["devices", device, "features"]) => with_device(device, |device_id| {
+            let features: Vec<Feature> = parse_body(request)?;
+            let result = hyperx::devices().get(device_id).set_features(&features);
+            let previous = audit::record(
+                Source::Api,
+                Some(device_id),
+                "set_features",
+                json!(features),
+                result,
+            )
+            .map_err(control_error)?;
+            let mut values = DeviceSettings::default();
+            for feature in &features {
+                match *feature {
+                    Feature::Sidetone(enabled) => values.sidetone = Some(enabled),
+                    Feature::GameMode(enabled) => values.game_mode = Some(enabled),
+                    _ => {}
+                }
+            }
+            profiles::record(settings, device_id, &values);
+            Ok(Response::json(200, &previous))
+        }),
+        ("GET", ["deck", deck_route @ ..]) => match deck::handle(settings, deck_route) {
+            Some(result) => result,
+            None => Err(Response::error(404, "unknown route")),
+        },
+        ("GET", ["profiles"]) => Ok(Response::json(200, &settings.get().profiles)),
+        ("POST", ["profiles", name, "apply"]) => match audit::record(
+            Source::Api,
+            None,
+            "apply_profile",
+            json!(name),
+            profiles::apply_named(settings, name),
+        ) {
+            Ok(()) => Ok(Response::no_content()),
+            Err(ProfileError::NotFound { name }) => {
+                Err(Response::error(404, format!("no profile named '{name}'")))
+            }
+            Err(ProfileError::Control(err)) => Err(control_error(err)),
+            Err(err) => Err(Response::error(500, err.to_string())),
+        },
+        ("GET", ["groups"]) => Ok(Response::json(200, &settings.get().groups)),
+        ("POST", ["groups", name, "apply"]) => {
+            parse_body(request).and_then(|change: GroupChange| {
+                groups::apply(settings, Source::Api, name, &change)
+                    .map(|outcomes| Response::json(200, &outcomes))
+                    .map_err(|err| Response::error(404, err.to_string()))
+            })
+        }
+        (
+            _,
+            ["devices"]
+            | ["devices", _, "sidetone" | "mic-mute" | "battery" | "state" | "features"]
+            | ["devices", _, "mic-mute", "toggle"]
+            | ["profiles"]
+            | ["profiles", _, "apply"]
+            | ["groups"]
+            | ["groups", _, "apply"],
+        ) => Err(Response::error(405, "method not allowed")),
+        _ => Err(Response::error(404, "unknown route")),
+    };
+    result.unwrap_or_else(|response| response)
+}
+
+fn with_device(
+    device: &str,
+    handler: impl FnOnce(DeviceId) -> Result<Response, Response>,
+) -> Result<Response, Response> {
+    let device_id = device
+        .parse()
+        .map_err(|err: hyperx::UnknownDevice| Response::error(404, err.to_string()))?;
+    handler(device_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hyperx::{FeatureKind, Model};
+    use crate::mock::TestHeadset;
+    use std::collections::HashMap;
+
+    const TOKEN: &str = "test-token";
+
+    fn settings(name: &str) -> SettingsStore {
+        let path =
+            std::env::temp_dir().join(format!("hyperx-pilot-{name}-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let settings = SettingsStore::load(path);
+        settings
+            .update(|settings| settings.api.token = Some(TOKEN.into()))
+            .unwrap();
+        settings
+    }
+
+    fn request(method: &str, path: &str, body: &str) -> Request {
+        Request {
+            method: method.into(),
+            path: path.into(),
+            query: HashMap::new(),
+            headers: HashMap::from([("authorization".into(), format!("Bearer {TOKEN}"))]),
+            body: body.into(),
+        }
+    }
+
+    #[test]
+    fn rejected_write_is_unprocessable() {
+        let device_id = DeviceId {
+            model: Model::SimulatedWireless,
+            unit: 10,
+        };
+        TestHeadset::install(device_id).reject_writes(FeatureKind::Sidetone, 0x02);
+        let path = format!("/api/v2/devices/{device_id}/sidetone");
+        let response = handle(
+            &settings("api-rejected"),
+            &request("PUT", &path, r#"{"enabled": true}"#),
+        );
+        assert_eq!(response.status, 422, "{}", response.body);
+        let mut written = Vec::new();
+        http::write_response(&mut written, &response).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(
+            written.starts_with("HTTP/1.1 422 Unprocessable Entity\r\n"),
+            "{written}"
+        );
+    }
+
+    #[test]
+    fn state_and_features_go_through_every_feature() {
+        let device_id = DeviceId {
+            model: Model::SimulatedWireless,
+            unit: 11,
+        };
+        let headset = TestHeadset::install(device_id);
+        headset.set(FeatureKind::Sidetone, 1);
+        headset.set(FeatureKind::VoicePrompts, 1);
+        let settings = settings("api-features");
+
+        let path = format!("/api/v2/devices/{device_id}/state");
+        let response = handle(&settings, &request("GET", &path, ""));
+        assert_eq!(response.status, 200, "{}", response.body);
+        let state: Vec<Feature> = serde_json::from_str(&response.body).unwrap();
+        assert!(state.contains(&Feature::Sidetone(true)), "{state:?}");
+        assert!(state.contains(&Feature::VoicePrompts(true)), "{state:?}");
+
+        let path = format!("/api/v2/devices/{device_id}/features");
+        let body = r#"[{"feature": "voice_prompts", "value": false}, {"feature": "sidetone", "value": false}]"#;
+        let response = handle(&settings, &request("PUT", &path, body));
+        assert_eq!(response.status, 200, "{}", response.body);
+        let previous: Vec<Feature> = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(
+            previous,
+            [Feature::VoicePrompts(true), Feature::Sidetone(true)]
+        );
+        assert_eq!(headset.value(FeatureKind::VoicePrompts), 0);
+        assert_eq!(headset.value(FeatureKind::Sidetone), 0);
+        assert_eq!(settings.get().last_known[&device_id].sidetone, Some(false));
+    }
+
+    #[test]
+    fn unknown_device_routes_are_not_found() {
+        let settings = settings("api-unknown");
+        let response = handle(&settings, &request("GET", "/api/v2/devices/x/foo", ""));
+        assert_eq!(response.status, 404, "{}", response.body);
+        let response = handle(&settings, &request("POST", "/api/v2/devices/x/state", ""));
+        assert_eq!(response.status, 405, "{}", response.body);
+    }
+}
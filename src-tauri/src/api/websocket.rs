@@ -0,0 +1,86 @@
+//! The `/api/v2/events` WebSocket: pushes JSON text frames and answers pings
+//! and closes.
+
+use super::http::Request;
+use crate::events::EventBus;
+use crate::websocket::{self, Role, OPCODE_CLOSE, OPCODE_PING, OPCODE_PONG, OPCODE_TEXT};
+use std::io::{self, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Idle connections get a ping this often, which also notices dead peers.
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+pub fn is_upgrade(request: &Request) -> bool {
+    request.method == "GET"
+        && request
+            .header("upgrade")
+            .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+/// Complete the handshake and send every published event as a JSON text
+/// frame until the client goes away.
+pub fn stream_events(stream: TcpStream, request: &Request, events: &EventBus) -> io::Result<()> {
+    let Some(key) = request.header("sec-websocket-key") else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing Sec-WebSocket-Key",
+        ));
+    };
+    let mut writer = &stream;
+    write!(
+        writer,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        websocket::accept_key(key)
+    )?;
+
+    let receiver = events.subscribe();
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    {
+        let writer = writer.clone();
+        let mut reader = stream;
+        thread::spawn(move || answer_control_frames(&mut reader, &writer));
+    }
+
+    loop {
+        let (opcode, payload) = match receiver.recv_timeout(PING_INTERVAL) {
+            Ok(event) => (OPCODE_TEXT, serde_json::to_vec(&event).unwrap_or_default()),
+            Err(RecvTimeoutError::Timeout) => (OPCODE_PING, Vec::new()),
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        websocket::write_frame(&mut *writer.lock().unwrap(), opcode, &payload, Role::Server)?;
+    }
+}
+
+/// Read client frames, replying to pings and closes; ends with the socket.
+fn answer_control_frames(reader: &mut TcpStream, writer: &Mutex<TcpStream>) {
+    while let Ok((opcode, payload)) = websocket::read_frame(reader) {
+        match opcode {
+            OPCODE_PING
+                if websocket::write_frame(
+                    &mut *writer.lock().unwrap(),
+                    OPCODE_PONG,
+                    &payload,
+                    Role::Server,
+                )
+                .is_err() =>
+            {
+                break;
+            }
+            OPCODE_CLOSE => {
+                let _ = websocket::write_frame(
+                    &mut *writer.lock().unwrap(),
+                    OPCODE_CLOSE,
+                    &payload,
+                    Role::Server,
+                );
+                break;
+            }
+            _ => {}
+        }
+    }
+    let _ = reader.shutdown(Shutdown::Both);
+}
@@ -0,0 +1,352 @@
+//! OS audio integration: makes a connected headset the default output/input
+//! and puts the previous defaults back when it goes away, and provides a
+//! software sidetone for headsets without one in hardware.
+
+#[cfg(target_os = "linux")]
+mod pulse;
+#[cfg(target_os = "linux")]
+use pulse as backend;
+
+#[cfg(windows)]
+mod wasapi;
+#[cfg(windows)]
+use wasapi as backend;
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod unsupported;
+#[cfg(not(any(target_os = "linux", windows)))]
+use unsupported as backend;
+
+use crate::crash;
+use crate::events::{DeviceEvent, EventBus};
+use crate::hyperx::{self, DeviceId};
+use crate::settings::SettingsStore;
+use crate::simulator;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+/// The audio interface usually enumerates a moment after the HID interface.
+const ENDPOINT_LOOKUP_ATTEMPTS: u32 = 10;
+const ENDPOINT_LOOKUP_DELAY: Duration = Duration::from_millis(500);
+const MUTE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// OS identifiers of a playback sink and a recording source.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Endpoints {
+    pub sink: Option<String>,
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointKind {
+    Sink,
+    Source,
+}
+
+/// A playback or recording device the user can pick for a headset.
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioEndpoint {
+    pub id: String,
+    pub name: String,
+    pub kind: EndpointKind,
+}
+
+#[derive(Debug)]
+pub enum AudioError {
+    #[cfg(not(any(target_os = "linux", windows)))]
+    Unsupported,
+    Spawn {
+        program: &'static str,
+        source: io::Error,
+    },
+    CommandFailed {
+        program: &'static str,
+        stderr: String,
+    },
+    Parse {
+        detail: String,
+    },
+    MissingEndpoints,
+    #[cfg(windows)]
+    Com {
+        source: windows::core::Error,
+    },
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(not(any(target_os = "linux", windows)))]
+            AudioError::Unsupported => {
+                write!(f, "this audio feature is not supported on this platform")
+            }
+            AudioError::Spawn { program, source } => write!(f, "failed to run {program}: {source}"),
+            AudioError::CommandFailed { program, stderr } => {
+                write!(f, "{program} failed: {}", stderr.trim())
+            }
+            AudioError::Parse { detail } => write!(f, "unexpected audio server output: {detail}"),
+            AudioError::MissingEndpoints => {
+                write!(
+                    f,
+                    "the headset's playback and recording devices were not found"
+                )
+            }
+            #[cfg(windows)]
+            AudioError::Com { source } => write!(f, "Core Audio call failed: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for AudioError {}
+
+/// Sink and source exposed by the headset's USB audio interface.
+pub fn headset_endpoints(device_id: DeviceId) -> Result<Endpoints, AudioError> {
+    if simulator::is_enabled() {
+        return Ok(Endpoints {
+            sink: Some(simulator::sink_name(device_id)),
+            source: Some(simulator::source_name(device_id)),
+        });
+    }
+    let (vendor_id, product_id) = hyperx::usb_id(device_id);
+    backend::usb_endpoints(vendor_id, product_id)
+}
+
+/// Endpoints belonging to a device: the ones the user associated with it,
+/// with gaps filled in by detection. Detected endpoints are remembered so the
+/// association can be reviewed and changed later.
+pub fn device_endpoints(
+    settings: &SettingsStore,
+    device_id: DeviceId,
+) -> Result<Endpoints, AudioError> {
+    let stored = settings
+        .get()
+        .audio
+        .endpoints
+        .get(&device_id)
+        .cloned()
+        .unwrap_or_default();
+    if stored.sink.is_some() && stored.source.is_some() {
+        return Ok(stored);
+    }
+    let detected = headset_endpoints(device_id)?;
+    let endpoints = Endpoints {
+        sink: stored.sink.clone().or(detected.sink),
+        source: stored.source.clone().or(detected.source),
+    };
+    if endpoints != stored {
+        let remembered = endpoints.clone();
+        let result = settings.update(|settings| {
+            settings.audio.endpoints.insert(device_id, remembered);
+        });
+        if let Err(err) = result {
+            warn!("failed to remember audio endpoints: {err}");
+        }
+    }
+    Ok(endpoints)
+}
+
+/// Active playback and recording devices, for choosing an association.
+pub fn list_endpoints() -> Result<Vec<AudioEndpoint>, AudioError> {
+    backend::list_endpoints()
+}
+
+pub fn default_endpoints() -> Result<Endpoints, AudioError> {
+    backend::default_endpoints()
+}
+
+/// Make the given endpoints the defaults; `None` entries are left alone.
+/// Does nothing in `--simulate` mode, whose endpoints do not exist.
+pub fn set_default_endpoints(endpoints: &Endpoints) -> Result<(), AudioError> {
+    if simulator::is_enabled() {
+        return Ok(());
+    }
+    backend::set_default_endpoints(endpoints)
+}
+
+fn headset_source(device_id: DeviceId) -> Result<String, AudioError> {
+    headset_endpoints(device_id)?
+        .source
+        .ok_or(AudioError::MissingEndpoints)
+}
+
+/// Mute state of a source, including the simulator's microphones.
+fn source_mute(source: &str) -> Result<bool, AudioError> {
+    match simulator::source_muted(source) {
+        Some(muted) => Ok(muted),
+        None => backend::source_mute(source),
+    }
+}
+
+fn set_source_mute(source: &str, muted: bool) -> Result<(), AudioError> {
+    if simulator::set_source_muted(source, muted) {
+        return Ok(());
+    }
+    backend::set_source_mute(source, muted)
+}
+
+/// Flip the OS-level mute of the headset microphone.
+pub fn toggle_mic_mute(device_id: DeviceId) -> Result<(), AudioError> {
+    let source = headset_source(device_id)?;
+    match simulator::source_muted(&source) {
+        Some(muted) => set_source_mute(&source, !muted),
+        None => backend::toggle_source_mute(&source),
+    }
+}
+
+pub fn set_mic_mute(device_id: DeviceId, muted: bool) -> Result<(), AudioError> {
+    set_source_mute(&headset_source(device_id)?, muted)
+}
+
+pub fn mic_muted(device_id: DeviceId) -> Result<bool, AudioError> {
+    source_mute(&headset_source(device_id)?)
+}
+
+/// Publish [`DeviceEvent::MicMuteChanged`] whenever the microphone of a
+/// connected headset changes its mute state. The OS mixer has no portable
+/// change notification, so the state is polled.
+pub fn spawn_mute_monitor(events: Arc<EventBus>) {
+    let receiver = events.subscribe();
+    crash::supervise("mute-monitor", move || {
+        // Source name and last seen state of every connected headset. After a
+        // restart the devices connected so far are picked up again.
+        let mut sources: HashMap<DeviceId, (String, Option<bool>)> = hyperx::connected_devices()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|device_id| Some((device_id, (headset_source(device_id).ok()?, None))))
+            .collect();
+        loop {
+            for event in receiver.try_iter() {
+                match event {
+                    DeviceEvent::Connected { device_id } => {
+                        if let Ok(source) = wait_for_source(device_id) {
+                            sources.insert(device_id, (source, None));
+                        }
+                    }
+                    DeviceEvent::Disconnected { device_id } => {
+                        sources.remove(&device_id);
+                    }
+                    _ => {}
+                }
+            }
+            for (&device_id, (source, last)) in &mut sources {
+                let Ok(muted) = source_mute(source) else {
+                    continue;
+                };
+                if last.is_some_and(|last| last != muted) {
+                    events.publish(DeviceEvent::MicMuteChanged { device_id, muted });
+                }
+                *last = Some(muted);
+            }
+            thread::sleep(MUTE_POLL_INTERVAL);
+        }
+    });
+}
+
+fn wait_for_source(device_id: DeviceId) -> Result<String, AudioError> {
+    for _ in 0..ENDPOINT_LOOKUP_ATTEMPTS {
+        if let Ok(source) = headset_source(device_id) {
+            return Ok(source);
+        }
+        thread::sleep(ENDPOINT_LOOKUP_DELAY);
+    }
+    headset_source(device_id)
+}
+
+/// Remembers per device which defaults were active before it took over.
+#[derive(Default)]
+pub struct DefaultSwitcher {
+    previous: Mutex<HashMap<DeviceId, Endpoints>>,
+}
+
+impl DefaultSwitcher {
+    pub fn on_connected(
+        &self,
+        settings: &SettingsStore,
+        device_id: DeviceId,
+    ) -> Result<(), AudioError> {
+        let headset = wait_for_endpoints(settings, device_id)?;
+        let previous = default_endpoints()?;
+        if previous == headset {
+            return Ok(());
+        }
+        set_default_endpoints(&headset)?;
+        self.previous.lock().unwrap().insert(device_id, previous);
+        Ok(())
+    }
+
+    pub fn on_disconnected(&self, device_id: DeviceId) -> Result<(), AudioError> {
+        let Some(previous) = self.previous.lock().unwrap().remove(&device_id) else {
+            return Ok(());
+        };
+        set_default_endpoints(&previous)
+    }
+}
+
+fn wait_for_endpoints(
+    settings: &SettingsStore,
+    device_id: DeviceId,
+) -> Result<Endpoints, AudioError> {
+    let mut endpoints = Endpoints::default();
+    for _ in 0..ENDPOINT_LOOKUP_ATTEMPTS {
+        endpoints = device_endpoints(settings, device_id)?;
+        if endpoints.sink.is_some() && endpoints.source.is_some() {
+            break;
+        }
+        thread::sleep(ENDPOINT_LOOKUP_DELAY);
+    }
+    Ok(endpoints)
+}
+
+/// Mic-to-headphone loopbacks, at most one per device, torn down on
+/// disconnect and on exit.
+#[derive(Default)]
+pub struct SoftwareSidetone {
+    active: Mutex<HashMap<DeviceId, backend::Loopback>>,
+}
+
+impl SoftwareSidetone {
+    /// Start the loopback (or adjust it if already running) at `gain`, clamped to `0.0..=1.0`.
+    pub fn enable(&self, device_id: DeviceId, gain: f32) -> Result<(), AudioError> {
+        let gain = gain.clamp(0.0, 1.0);
+        let mut active = self.active.lock().unwrap();
+        if let Some(loopback) = active.get(&device_id) {
+            return backend::set_loopback_gain(loopback, gain);
+        }
+        let loopback = backend::load_loopback(&headset_endpoints(device_id)?)?;
+        if let Err(err) = backend::set_loopback_gain(&loopback, gain) {
+            let _ = backend::unload_loopback(loopback);
+            return Err(err);
+        }
+        active.insert(device_id, loopback);
+        Ok(())
+    }
+
+    pub fn disable(&self, device_id: DeviceId) -> Result<(), AudioError> {
+        match self.active.lock().unwrap().remove(&device_id) {
+            Some(loopback) => backend::unload_loopback(loopback),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_active(&self, device_id: DeviceId) -> bool {
+        self.active.lock().unwrap().contains_key(&device_id)
+    }
+
+    pub fn disable_all(&self) {
+        for (_, loopback) in self.active.lock().unwrap().drain() {
+            let _ = backend::unload_loopback(loopback);
+        }
+    }
+}
+
+pub fn unload_stale_loopbacks() -> Result<(), AudioError> {
+    backend::unload_stale_loopbacks()
+}
@@ -0,0 +1,219 @@
+//! PulseAudio backend driven through `pactl`, which also talks to PipeWire
+//! via pipewire-pulse.
+
+use super::{AudioEndpoint, AudioError, EndpointKind, Endpoints};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::process::Command;
+
+const PACTL: &str = "pactl";
+
+/// Tags our loopback streams so leftovers from a crashed session can be found.
+const LOOPBACK_MARKER: &str = "media.name=HyperXPilotSidetone";
+const LOOPBACK_LATENCY_MSEC: u32 = 20;
+
+#[derive(Debug, Deserialize)]
+struct Node {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    properties: HashMap<String, serde_json::Value>,
+}
+
+impl Node {
+    fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).and_then(|value| value.as_str())
+    }
+
+    /// PipeWire reports ids as `0x03f0`, PulseAudio's udev module as `03f0`.
+    fn usb_id(&self, key: &str) -> Option<u16> {
+        let raw = self.property(key)?;
+        let hex = raw.strip_prefix("0x").unwrap_or(raw);
+        u16::from_str_radix(hex, 16).ok()
+    }
+
+    fn matches(&self, vendor_id: u16, product_id: u16) -> bool {
+        self.usb_id("device.vendor.id") == Some(vendor_id)
+            && self.usb_id("device.product.id") == Some(product_id)
+            && self.property("device.class") != Some("monitor")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Module {
+    index: u32,
+    name: String,
+    #[serde(default)]
+    argument: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SinkInput {
+    index: u32,
+    #[serde(default)]
+    owner_module: serde_json::Value,
+}
+
+impl SinkInput {
+    /// pactl prints the owning module as a string, so accept both encodings.
+    fn owned_by(&self, module: u32) -> bool {
+        match &self.owner_module {
+            serde_json::Value::Number(number) => number.as_u64() == Some(u64::from(module)),
+            serde_json::Value::String(text) => text.parse().ok() == Some(module),
+            _ => false,
+        }
+    }
+}
+
+/// A loaded `module-loopback` instance routing the headset mic to its output.
+#[derive(Debug)]
+pub struct Loopback {
+    module: u32,
+}
+
+fn pactl(args: &[&str]) -> Result<String, AudioError> {
+    let output = Command::new(PACTL)
+        .args(args)
+        .output()
+        .map_err(|source| AudioError::Spawn {
+            program: PACTL,
+            source,
+        })?;
+    if !output.status.success() {
+        return Err(AudioError::CommandFailed {
+            program: PACTL,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn list<T: serde::de::DeserializeOwned>(kind: &str) -> Result<Vec<T>, AudioError> {
+    let json = pactl(&["--format=json", "list", kind])?;
+    serde_json::from_str(&json).map_err(|err| AudioError::Parse {
+        detail: err.to_string(),
+    })
+}
+
+fn find(kind: &str, vendor_id: u16, product_id: u16) -> Result<Option<String>, AudioError> {
+    Ok(list::<Node>(kind)?
+        .into_iter()
+        .find(|node| node.matches(vendor_id, product_id))
+        .map(|node| node.name))
+}
+
+pub fn usb_endpoints(vendor_id: u16, product_id: u16) -> Result<Endpoints, AudioError> {
+    Ok(Endpoints {
+        sink: find("sinks", vendor_id, product_id)?,
+        source: find("sources", vendor_id, product_id)?,
+    })
+}
+
+pub fn list_endpoints() -> Result<Vec<AudioEndpoint>, AudioError> {
+    let mut endpoints = Vec::new();
+    for (list_kind, kind) in [
+        ("sinks", EndpointKind::Sink),
+        ("sources", EndpointKind::Source),
+    ] {
+        let nodes = list::<Node>(list_kind)?
+            .into_iter()
+            .filter(|node| node.property("device.class") != Some("monitor"));
+        endpoints.extend(nodes.map(|node| AudioEndpoint {
+            id: node.name,
+            name: node.description,
+            kind,
+        }));
+    }
+    Ok(endpoints)
+}
+
+pub fn default_endpoints() -> Result<Endpoints, AudioError> {
+    Ok(Endpoints {
+        sink: Some(pactl(&["get-default-sink"])?),
+        source: Some(pactl(&["get-default-source"])?),
+    })
+}
+
+pub fn set_default_endpoints(endpoints: &Endpoints) -> Result<(), AudioError> {
+    if let Some(sink) = &endpoints.sink {
+        pactl(&["set-default-sink", sink])?;
+    }
+    if let Some(source) = &endpoints.source {
+        pactl(&["set-default-source", source])?;
+    }
+    Ok(())
+}
+
+pub fn toggle_source_mute(source: &str) -> Result<(), AudioError> {
+    pactl(&["set-source-mute", source, "toggle"])?;
+    Ok(())
+}
+
+pub fn source_mute(source: &str) -> Result<bool, AudioError> {
+    let output = pactl(&["get-source-mute", source])?;
+    match output.strip_prefix("Mute:").map(str::trim) {
+        Some("yes") => Ok(true),
+        Some("no") => Ok(false),
+        _ => Err(AudioError::Parse {
+            detail: format!("mute state '{output}'"),
+        }),
+    }
+}
+
+pub fn set_source_mute(source: &str, muted: bool) -> Result<(), AudioError> {
+    pactl(&["set-source-mute", source, if muted { "1" } else { "0" }])?;
+    Ok(())
+}
+
+pub fn load_loopback(endpoints: &Endpoints) -> Result<Loopback, AudioError> {
+    let (Some(sink), Some(source)) = (&endpoints.sink, &endpoints.source) else {
+        return Err(AudioError::MissingEndpoints);
+    };
+    let index = pactl(&[
+        "load-module",
+        "module-loopback",
+        &format!("source={source}"),
+        &format!("sink={sink}"),
+        &format!("latency_msec={LOOPBACK_LATENCY_MSEC}"),
+        "source_dont_move=true",
+        "sink_dont_move=true",
+        &format!("sink_input_properties={LOOPBACK_MARKER}"),
+    ])?;
+    let module = index.parse().map_err(|_| AudioError::Parse {
+        detail: format!("module index '{index}'"),
+    })?;
+    Ok(Loopback { module })
+}
+
+/// Set the loopback stream volume; `gain` is a linear factor in `0.0..=1.0`.
+pub fn set_loopback_gain(loopback: &Loopback, gain: f32) -> Result<(), AudioError> {
+    let input = list::<SinkInput>("sink-inputs")?
+        .into_iter()
+        .find(|input| input.owned_by(loopback.module))
+        .ok_or_else(|| AudioError::Parse {
+            detail: format!("no stream for loopback module {}", loopback.module),
+        })?;
+    let percent = format!("{}%", (gain * 100.0).round() as u32);
+    pactl(&["set-sink-input-volume", &input.index.to_string(), &percent])?;
+    Ok(())
+}
+
+pub fn unload_loopback(loopback: Loopback) -> Result<(), AudioError> {
+    pactl(&["unload-module", &loopback.module.to_string()])?;
+    Ok(())
+}
+
+/// Unload loopbacks a previous, uncleanly terminated session left behind.
+pub fn unload_stale_loopbacks() -> Result<(), AudioError> {
+    for module in list::<Module>("modules")? {
+        let ours = module
+            .argument
+            .as_deref()
+            .is_some_and(|argument| argument.contains(LOOPBACK_MARKER));
+        if module.name == "module-loopback" && ours {
+            pactl(&["unload-module", &module.index.to_string()])?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use super::{AudioEndpoint, AudioError, Endpoints};
+
+pub fn usb_endpoints(_vendor_id: u16, _product_id: u16) -> Result<Endpoints, AudioError> {
+    Err(AudioError::Unsupported)
+}
+
+pub fn list_endpoints() -> Result<Vec<AudioEndpoint>, AudioError> {
+    Err(AudioError::Unsupported)
+}
+
+pub fn default_endpoints() -> Result<Endpoints, AudioError> {
+    Err(AudioError::Unsupported)
+}
+
+pub fn set_default_endpoints(_endpoints: &Endpoints) -> Result<(), AudioError> {
+    Err(AudioError::Unsupported)
+}
+
+pub fn toggle_source_mute(_source: &str) -> Result<(), AudioError> {
+    Err(AudioError::Unsupported)
+}
+
+pub fn source_mute(_source: &str) -> Result<bool, AudioError> {
+    Err(AudioError::Unsupported)
+}
+
+pub fn set_source_mute(_source: &str, _muted: bool) -> Result<(), AudioError> {
+    Err(AudioError::Unsupported)
+}
+
+#[derive(Debug)]
+pub struct Loopback;
+
+pub fn load_loopback(_endpoints: &Endpoints) -> Result<Loopback, AudioError> {
+    Err(AudioError::Unsupported)
+}
+
+pub fn set_loopback_gain(_loopback: &Loopback, _gain: f32) -> Result<(), AudioError> {
+    Err(AudioError::Unsupported)
+}
+
+pub fn unload_loopback(_loopback: Loopback) -> Result<(), AudioError> {
+    Err(AudioError::Unsupported)
+}
+
+pub fn unload_stale_loopbacks() -> Result<(), AudioError> {
+    Ok(())
+}
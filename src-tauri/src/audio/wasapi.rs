@@ -0,0 +1,415 @@
+//! Core Audio backend. Endpoints are identified by their IMMDevice id and
+//! matched to a headset through the PnP container ID they share with its HID
+//! interfaces.
+
+use super::{AudioEndpoint, AudioError, EndpointKind, Endpoints};
+use crate::platform::windows::devices;
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::warn;
+use windows::core::{interface, IUnknown, IUnknown_Vtbl, GUID, HRESULT, PCWSTR, PWSTR};
+use windows::Win32::Devices::FunctionDiscovery::PKEY_Device_FriendlyName;
+use windows::Win32::Devices::Properties::{DEVPKEY_Device_ContainerId, DEVPROPKEY};
+use windows::Win32::Foundation::E_UNEXPECTED;
+use windows::Win32::Media::Audio::Endpoints::IAudioEndpointVolume;
+use windows::Win32::Media::Audio::{
+    eCapture, eCommunications, eConsole, eMultimedia, eRender, EDataFlow, ERole,
+    IAudioCaptureClient, IAudioClient, IAudioRenderClient, IAudioStreamVolume, IMMDevice,
+    IMMDeviceEnumerator, MMDeviceEnumerator, AUDCLNT_BUFFERFLAGS_SILENT, AUDCLNT_SHAREMODE_SHARED,
+    AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM, AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY,
+    DEVICE_STATE_ACTIVE,
+};
+use windows::Win32::System::Com::StructuredStorage::{PropVariantToGUID, PropVariantToStringAlloc};
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CoTaskMemFree, CLSCTX_ALL, COINIT_MULTITHREADED, STGM_READ,
+};
+
+/// `CPolicyConfigClient`, the undocumented coclass behind the Sound control
+/// panel's "Set Default" button.
+const POLICY_CONFIG_CLIENT: GUID = GUID::from_u128(0x870af99c_171d_4f9e_af0d_e63df40c2bc9);
+
+/// Only `SetDefaultEndpoint` is called; the other slots keep the vtable layout.
+#[interface("f8679f50-850a-41cf-9c72-430f290290c8")]
+unsafe trait IPolicyConfig: IUnknown {
+    fn GetMixFormat(&self, device: PCWSTR, format: *mut *mut c_void) -> HRESULT;
+    fn GetDeviceFormat(&self, device: PCWSTR, default: i32, format: *mut *mut c_void) -> HRESULT;
+    fn ResetDeviceFormat(&self, device: PCWSTR) -> HRESULT;
+    fn SetDeviceFormat(&self, device: PCWSTR, endpoint: *mut c_void, mix: *mut c_void) -> HRESULT;
+    fn GetProcessingPeriod(
+        &self,
+        device: PCWSTR,
+        default: i32,
+        default_period: *mut i64,
+        minimum_period: *mut i64,
+    ) -> HRESULT;
+    fn SetProcessingPeriod(&self, device: PCWSTR, period: *mut i64) -> HRESULT;
+    fn GetShareMode(&self, device: PCWSTR, mode: *mut c_void) -> HRESULT;
+    fn SetShareMode(&self, device: PCWSTR, mode: *mut c_void) -> HRESULT;
+    fn GetPropertyValue(
+        &self,
+        device: PCWSTR,
+        fx_store: i32,
+        key: *const c_void,
+        value: *mut c_void,
+    ) -> HRESULT;
+    fn SetPropertyValue(
+        &self,
+        device: PCWSTR,
+        fx_store: i32,
+        key: *const c_void,
+        value: *mut c_void,
+    ) -> HRESULT;
+    fn SetDefaultEndpoint(&self, device: PCWSTR, role: ERole) -> HRESULT;
+    fn SetEndpointVisibility(&self, device: PCWSTR, visible: i32) -> HRESULT;
+}
+
+impl From<windows::core::Error> for AudioError {
+    fn from(source: windows::core::Error) -> Self {
+        AudioError::Com { source }
+    }
+}
+
+fn enumerator() -> Result<IMMDeviceEnumerator, AudioError> {
+    // SAFETY: joining the MTA is harmless when the thread already has an
+    // apartment; the error in that case is ignored on purpose.
+    unsafe {
+        let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+        Ok(CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?)
+    }
+}
+
+fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(Some(0)).collect()
+}
+
+/// Take ownership of a COM-allocated string.
+unsafe fn take_string(value: PWSTR) -> String {
+    let text = value.to_string().unwrap_or_default();
+    CoTaskMemFree(Some(value.0 as *const c_void));
+    text
+}
+
+unsafe fn device_id(device: &IMMDevice) -> Result<String, AudioError> {
+    Ok(take_string(device.GetId()?))
+}
+
+unsafe fn device_name(device: &IMMDevice) -> Result<String, AudioError> {
+    let store = device.OpenPropertyStore(STGM_READ)?;
+    let value = store.GetValue(&PKEY_Device_FriendlyName)?;
+    Ok(take_string(PropVariantToStringAlloc(&value)?))
+}
+
+unsafe fn device_container(device: &IMMDevice) -> Result<u128, AudioError> {
+    let store = device.OpenPropertyStore(STGM_READ)?;
+    // PROPERTYKEY and DEVPROPKEY share their layout.
+    let value = store.GetValue(&DEVPKEY_Device_ContainerId as *const DEVPROPKEY as *const _)?;
+    Ok(PropVariantToGUID(&value)?.to_u128())
+}
+
+unsafe fn active_devices(
+    enumerator: &IMMDeviceEnumerator,
+    flow: EDataFlow,
+) -> Result<Vec<IMMDevice>, AudioError> {
+    let collection = enumerator.EnumAudioEndpoints(flow, DEVICE_STATE_ACTIVE)?;
+    (0..collection.GetCount()?)
+        .map(|index| Ok(collection.Item(index)?))
+        .collect()
+}
+
+pub fn usb_endpoints(vendor_id: u16, product_id: u16) -> Result<Endpoints, AudioError> {
+    let containers = devices::usb_containers(vendor_id, product_id);
+    let enumerator = enumerator()?;
+    let find = |flow| -> Result<Option<String>, AudioError> {
+        // SAFETY: COM calls on interfaces owned by this function.
+        unsafe {
+            for device in active_devices(&enumerator, flow)? {
+                if device_container(&device).is_ok_and(|id| containers.contains(&id)) {
+                    return Ok(Some(device_id(&device)?));
+                }
+            }
+        }
+        Ok(None)
+    };
+    Ok(Endpoints {
+        sink: find(eRender)?,
+        source: find(eCapture)?,
+    })
+}
+
+pub fn list_endpoints() -> Result<Vec<AudioEndpoint>, AudioError> {
+    let enumerator = enumerator()?;
+    let mut endpoints = Vec::new();
+    for (flow, kind) in [
+        (eRender, EndpointKind::Sink),
+        (eCapture, EndpointKind::Source),
+    ] {
+        // SAFETY: COM calls on interfaces owned by this function.
+        unsafe {
+            for device in active_devices(&enumerator, flow)? {
+                endpoints.push(AudioEndpoint {
+                    id: device_id(&device)?,
+                    name: device_name(&device).unwrap_or_default(),
+                    kind,
+                });
+            }
+        }
+    }
+    Ok(endpoints)
+}
+
+pub fn default_endpoints() -> Result<Endpoints, AudioError> {
+    let enumerator = enumerator()?;
+    // SAFETY: COM calls on interfaces owned by this function.
+    let default = |flow| unsafe {
+        let device = enumerator.GetDefaultAudioEndpoint(flow, eConsole)?;
+        device_id(&device)
+    };
+    Ok(Endpoints {
+        sink: Some(default(eRender)?),
+        source: Some(default(eCapture)?),
+    })
+}
+
+pub fn set_default_endpoints(endpoints: &Endpoints) -> Result<(), AudioError> {
+    let _ = enumerator()?;
+    // SAFETY: creating the policy client and calling it with NUL-terminated ids.
+    unsafe {
+        let policy: IPolicyConfig = CoCreateInstance(&POLICY_CONFIG_CLIENT, None, CLSCTX_ALL)?;
+        for id in [&endpoints.sink, &endpoints.source].into_iter().flatten() {
+            let id = wide(id);
+            for role in [eConsole, eMultimedia, eCommunications] {
+                policy.SetDefaultEndpoint(PCWSTR(id.as_ptr()), role).ok()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn endpoint_volume(id: &str) -> Result<IAudioEndpointVolume, AudioError> {
+    let enumerator = enumerator()?;
+    let id = wide(id);
+    // SAFETY: the id is NUL-terminated; the device interface is owned here.
+    unsafe {
+        let device = enumerator.GetDevice(PCWSTR(id.as_ptr()))?;
+        Ok(device.Activate(CLSCTX_ALL, None)?)
+    }
+}
+
+pub fn toggle_source_mute(source: &str) -> Result<(), AudioError> {
+    let volume = endpoint_volume(source)?;
+    // SAFETY: `volume` is a live interface pointer.
+    unsafe {
+        let muted = volume.GetMute()?.as_bool();
+        volume.SetMute(!muted, std::ptr::null())?;
+    }
+    Ok(())
+}
+
+pub fn source_mute(source: &str) -> Result<bool, AudioError> {
+    let volume = endpoint_volume(source)?;
+    // SAFETY: `volume` is a live interface pointer.
+    Ok(unsafe { volume.GetMute()? }.as_bool())
+}
+
+pub fn set_source_mute(source: &str, muted: bool) -> Result<(), AudioError> {
+    let volume = endpoint_volume(source)?;
+    // SAFETY: `volume` is a live interface pointer.
+    unsafe { volume.SetMute(muted, std::ptr::null())? };
+    Ok(())
+}
+
+/// How much audio the render buffer holds; captured audio that does not fit
+/// is dropped, which keeps the delay short.
+const RENDER_BUFFER: i64 = 50 * HNS_PER_MS;
+const CAPTURE_BUFFER: i64 = 20 * HNS_PER_MS;
+/// WASAPI durations are in 100 ns units.
+const HNS_PER_MS: i64 = 10_000;
+/// How often the loopback thread moves captured audio to the headphones.
+const LOOPBACK_POLL: Duration = Duration::from_millis(5);
+
+/// Mic-to-headphone loopback: a thread copying what the headset's capture
+/// endpoint delivers to its render endpoint, both in shared mode at the
+/// render endpoint's mix format, with the capture side converted to it.
+#[derive(Debug)]
+pub struct Loopback {
+    stop: Arc<AtomicBool>,
+    /// `f32` bits of the volume the thread applies to its render stream.
+    gain: Arc<AtomicU32>,
+    thread: JoinHandle<()>,
+}
+
+pub fn load_loopback(endpoints: &Endpoints) -> Result<Loopback, AudioError> {
+    let (Some(sink), Some(source)) = (endpoints.sink.clone(), endpoints.source.clone()) else {
+        return Err(AudioError::MissingEndpoints);
+    };
+    let stop = Arc::new(AtomicBool::new(false));
+    let gain = Arc::new(AtomicU32::new(1.0_f32.to_bits()));
+    let (started, result) = mpsc::sync_channel(1);
+    let thread = {
+        let stop = stop.clone();
+        let gain = gain.clone();
+        thread::spawn(move || {
+            // SAFETY: the streams are created and used on this thread only.
+            let streams = unsafe { LoopbackStreams::open(&source, &sink) };
+            let streams = match streams {
+                Ok(streams) => {
+                    let _ = started.send(Ok(()));
+                    streams
+                }
+                Err(err) => {
+                    let _ = started.send(Err(err));
+                    return;
+                }
+            };
+            // SAFETY: as above.
+            if let Err(err) = unsafe { streams.run(&stop, &gain) } {
+                warn!("software sidetone stopped: {err}");
+            }
+        })
+    };
+    match result.recv() {
+        Ok(Ok(())) => Ok(Loopback { stop, gain, thread }),
+        Ok(Err(err)) => Err(err),
+        // The thread panicked before it could answer.
+        Err(_) => Err(windows::core::Error::from(E_UNEXPECTED).into()),
+    }
+}
+
+pub fn set_loopback_gain(loopback: &Loopback, gain: f32) -> Result<(), AudioError> {
+    loopback.gain.store(gain.to_bits(), Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn unload_loopback(loopback: Loopback) -> Result<(), AudioError> {
+    loopback.stop.store(true, Ordering::Relaxed);
+    let _ = loopback.thread.join();
+    Ok(())
+}
+
+struct LoopbackStreams {
+    capture: IAudioClient,
+    render: IAudioClient,
+    capture_client: IAudioCaptureClient,
+    render_client: IAudioRenderClient,
+    volume: IAudioStreamVolume,
+    render_frames: u32,
+    block_align: usize,
+}
+
+impl LoopbackStreams {
+    /// # Safety
+    ///
+    /// The streams must only be used on the calling thread.
+    unsafe fn open(source: &str, sink: &str) -> Result<Self, AudioError> {
+        let enumerator = enumerator()?;
+        let activate = |id: &str| -> Result<IAudioClient, AudioError> {
+            let id = wide(id);
+            let device = enumerator.GetDevice(PCWSTR(id.as_ptr()))?;
+            Ok(device.Activate(CLSCTX_ALL, None)?)
+        };
+        let capture = activate(source)?;
+        let render = activate(sink)?;
+
+        let format = render.GetMixFormat()?;
+        let block_align = usize::from((*format).nBlockAlign);
+        let initialized = render
+            .Initialize(AUDCLNT_SHAREMODE_SHARED, 0, RENDER_BUFFER, 0, format, None)
+            .and_then(|()| {
+                capture.Initialize(
+                    AUDCLNT_SHAREMODE_SHARED,
+                    AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM | AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY,
+                    CAPTURE_BUFFER,
+                    0,
+                    format,
+                    None,
+                )
+            });
+        CoTaskMemFree(Some(format as *const c_void));
+        initialized?;
+
+        let streams = LoopbackStreams {
+            capture_client: capture.GetService()?,
+            render_client: render.GetService()?,
+            volume: render.GetService()?,
+            render_frames: render.GetBufferSize()?,
+            block_align,
+            capture,
+            render,
+        };
+        streams.render.Start()?;
+        streams.capture.Start()?;
+        Ok(streams)
+    }
+
+    /// Copy captured packets to the render buffer until `stop` is set.
+    ///
+    /// # Safety
+    ///
+    /// Must run on the thread that opened the streams.
+    unsafe fn run(&self, stop: &AtomicBool, gain: &AtomicU32) -> Result<(), AudioError> {
+        let mut applied = None;
+        let result = loop {
+            if stop.load(Ordering::Relaxed) {
+                break Ok(());
+            }
+            let bits = gain.load(Ordering::Relaxed);
+            if applied != Some(bits) {
+                if let Err(err) = self.set_volume(f32::from_bits(bits)) {
+                    break Err(err);
+                }
+                applied = Some(bits);
+            }
+            if let Err(err) = self.forward() {
+                break Err(err);
+            }
+            thread::sleep(LOOPBACK_POLL);
+        };
+        let _ = self.capture.Stop();
+        let _ = self.render.Stop();
+        result
+    }
+
+    unsafe fn set_volume(&self, gain: f32) -> Result<(), AudioError> {
+        for channel in 0..self.volume.GetChannelCount()? {
+            self.volume.SetChannelVolume(channel, gain)?;
+        }
+        Ok(())
+    }
+
+    /// Move every waiting capture packet into the render buffer, dropping
+    /// what does not fit.
+    unsafe fn forward(&self) -> Result<(), AudioError> {
+        while self.capture_client.GetNextPacketSize()? > 0 {
+            let mut data = ptr::null_mut();
+            let mut frames = 0;
+            let mut flags = 0;
+            self.capture_client
+                .GetBuffer(&mut data, &mut frames, &mut flags, None, None)?;
+            let free = self.render_frames - self.render.GetCurrentPadding()?;
+            let writable = frames.min(free);
+            if writable > 0 {
+                let target = self.render_client.GetBuffer(writable)?;
+                let silent = flags & AUDCLNT_BUFFERFLAGS_SILENT.0 as u32 != 0;
+                if !silent {
+                    ptr::copy_nonoverlapping(data, target, writable as usize * self.block_align);
+                }
+                let render_flags = if silent {
+                    AUDCLNT_BUFFERFLAGS_SILENT.0 as u32
+                } else {
+                    0
+                };
+                self.render_client.ReleaseBuffer(writable, render_flags)?;
+            }
+            self.capture_client.ReleaseBuffer(frames)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn unload_stale_loopbacks() -> Result<(), AudioError> {
+    Ok(())
+}
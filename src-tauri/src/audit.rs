@@ -0,0 +1,173 @@
+//! Append-only log of every state-changing command, with where it came from,
+//! so a sidetone that changed by itself can be traced to the rule, shortcut
+//! or API client that changed it. Entries are JSON Lines in `audit.jsonl` in
+//! the config directory; past 1 MiB the file moves to
+//! `audit.1.jsonl`, replacing the previous one.
+
+use crate::hyperx::DeviceId;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+pub const AUDIT_FILE: &str = "audit.jsonl";
+const PREVIOUS_FILE: &str = "audit.1.jsonl";
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+/// Entries returned when the query sets no limit.
+pub const DEFAULT_LIMIT: usize = 200;
+
+/// Who issued a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// The app's window.
+    App,
+    Cli,
+    /// The local REST API.
+    Api,
+    StreamDeck,
+    DBus,
+    Mqtt,
+    Shortcut,
+    /// A headset button bound to an app-side action, see [`crate::macros`].
+    Macro,
+    Rule,
+    Notification,
+    /// Last-known settings re-applied on connect.
+    Restore,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Unix time in milliseconds.
+    pub time: u64,
+    pub source: Source,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<DeviceId>,
+    pub command: String,
+    /// The requested value, e.g. `true` for enabling sidetone.
+    #[serde(default)]
+    pub value: Value,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct Log {
+    directory: PathBuf,
+    file: File,
+    written: u64,
+}
+
+static LOG: Mutex<Option<Log>> = Mutex::new(None);
+
+/// Start logging to [`AUDIT_FILE`] in `config_dir`.
+pub fn open(config_dir: &Path) {
+    let open = || -> io::Result<Log> {
+        fs::create_dir_all(config_dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(config_dir.join(AUDIT_FILE))?;
+        let written = file.metadata()?.len();
+        Ok(Log {
+            directory: config_dir.to_path_buf(),
+            file,
+            written,
+        })
+    };
+    match open() {
+        Ok(log) => *LOG.lock().unwrap() = Some(log),
+        Err(err) => warn!("audit log disabled: {err}"),
+    }
+}
+
+impl Log {
+    fn append(&mut self, entry: &Entry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        if self.written + line.len() as u64 > MAX_FILE_BYTES {
+            fs::rename(
+                self.directory.join(AUDIT_FILE),
+                self.directory.join(PREVIOUS_FILE),
+            )?;
+            self.file = File::create(self.directory.join(AUDIT_FILE))?;
+            self.written = 0;
+        }
+        self.file.write_all(&line)?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// Log `command` and its outcome, passing `result` through.
+pub fn record<T, E: fmt::Display>(
+    source: Source,
+    device_id: Option<DeviceId>,
+    command: &str,
+    value: Value,
+    result: Result<T, E>,
+) -> Result<T, E> {
+    let mut log = LOG.lock().unwrap();
+    let Some(writer) = log.as_mut() else {
+        return result;
+    };
+    let entry = Entry {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64),
+        source,
+        device_id,
+        command: command.to_string(),
+        value,
+        ok: result.is_ok(),
+        error: result.as_ref().err().map(ToString::to_string),
+    };
+    if let Err(err) = writer.append(&entry) {
+        warn!("audit log disabled: {err}");
+        *log = None;
+    }
+    result
+}
+
+/// The latest `limit` entries, newest first, optionally only for one device.
+pub fn query(device_id: Option<DeviceId>, limit: usize) -> io::Result<Vec<Entry>> {
+    let Some(directory) = LOG
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|log| log.directory.clone())
+    else {
+        return Ok(Vec::new());
+    };
+    let mut latest = VecDeque::with_capacity(limit);
+    for name in [PREVIOUS_FILE, AUDIT_FILE] {
+        let file = match File::open(directory.join(name)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        for line in BufReader::new(file).lines() {
+            // A line cut short by a crash is skipped rather than failing the query.
+            let Ok(entry) = serde_json::from_str::<Entry>(&line?) else {
+                continue;
+            };
+            if device_id.is_some() && entry.device_id != device_id {
+                continue;
+            }
+            if latest.len() == limit {
+                latest.pop_front();
+            }
+            if limit > 0 {
+                latest.push_back(entry);
+            }
+        }
+    }
+    Ok(latest.into_iter().rev().collect())
+}
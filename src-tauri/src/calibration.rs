@@ -0,0 +1,343 @@
+//! Battery calibration for models whose battery reports are coarse or skewed.
+//! A calibration follows one charge/discharge cycle through the hotplug
+//! watcher's battery events: the headset is charged until it reads full, taken
+//! off the charger and used until it switches off. Assuming it drains at a
+//! steady rate, the time at which a reported value first appeared says how
+//! much charge was really left; a value first reported halfway through the
+//! discharge means 50%. The resulting curve is stored in the settings and
+//! corrects every later reading of that device, see [`correct`]. While a
+//! device is being calibrated its readings are reported uncorrected.
+
+use crate::events::{DeviceEvent, EventBus};
+use crate::hyperx::{self, Capability, ControlError, DeviceId};
+use crate::settings::{SettingsError, SettingsStore};
+use crate::watcher::LOW_BATTERY_PERCENT;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// Shorter discharges are too coarse to learn from.
+const MIN_DISCHARGE: Duration = Duration::from_secs(10 * 60);
+/// Rises up to this much while discharging are taken as jitter, not charging.
+const JITTER_PERCENT: u8 = 2;
+
+/// One point of a [`BatteryCurve`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CurvePoint {
+    pub reported: u8,
+    pub actual: u8,
+}
+
+/// Maps reported to actual charge, interpolating between points.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatteryCurve {
+    /// Ascending by `reported`; 0% is implied.
+    pub points: Vec<CurvePoint>,
+    /// Unix time in milliseconds.
+    pub calibrated_at: u64,
+}
+
+impl BatteryCurve {
+    pub fn correct(&self, reported: u8) -> u8 {
+        let mut lower = CurvePoint {
+            reported: 0,
+            actual: 0,
+        };
+        for &point in &self.points {
+            if reported <= point.reported {
+                if point.reported == lower.reported {
+                    return point.actual;
+                }
+                let span = i32::from(point.reported - lower.reported);
+                let offset = i32::from(reported - lower.reported);
+                let rise = i32::from(point.actual) - i32::from(lower.actual);
+                return (i32::from(lower.actual) + rise * offset / span) as u8;
+            }
+            lower = point;
+        }
+        lower.actual
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "phase", rename_all = "snake_case")]
+pub enum Phase {
+    /// Waiting for the headset to read 100%.
+    Charging,
+    /// Recording readings until the headset switches off.
+    Discharging,
+    Done,
+    Failed {
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CalibrationStatus {
+    pub device_id: DeviceId,
+    #[serde(flatten)]
+    pub phase: Phase,
+    /// Unix time in milliseconds.
+    pub started_at: u64,
+    pub last_reading: Option<u8>,
+    /// Distinct values recorded while discharging.
+    pub readings: usize,
+}
+
+struct Run {
+    status: CalibrationStatus,
+    /// When the headset read full.
+    discharge_start: Option<Instant>,
+    /// Each newly reached low and how long after full it first appeared.
+    samples: Vec<(Duration, u8)>,
+}
+
+impl Run {
+    fn is_active(&self) -> bool {
+        matches!(self.status.phase, Phase::Charging | Phase::Discharging)
+    }
+}
+
+static CURVES: Mutex<Option<HashMap<DeviceId, BatteryCurve>>> = Mutex::new(None);
+static RUNS: Mutex<Option<HashMap<DeviceId, Run>>> = Mutex::new(None);
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// `reported` through the stored curve of `device_id`, if it has one and is
+/// not being calibrated.
+pub fn correct(device_id: DeviceId, reported: u8) -> u8 {
+    let calibrating = RUNS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|runs| runs.get(&device_id))
+        .is_some_and(Run::is_active);
+    if calibrating {
+        return reported;
+    }
+    CURVES
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|curves| curves.get(&device_id))
+        .map_or(reported, |curve| curve.correct(reported))
+}
+
+/// Use the curves in `settings`; needed again after the settings are replaced.
+pub fn load(settings: &SettingsStore) {
+    *CURVES.lock().unwrap() = Some(settings.get().battery.curves);
+}
+
+/// Load the stored curves and follow battery events for running calibrations.
+pub fn spawn(settings: Arc<SettingsStore>, events: &EventBus) {
+    load(&settings);
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        // The watcher reports every device as disconnected while the session
+        // is inactive; that is not the headset switching off.
+        let mut session_active = true;
+        for event in receiver {
+            match event {
+                DeviceEvent::SessionChanged { active } => session_active = active,
+                DeviceEvent::BatteryChanged { device_id, percent } => {
+                    update(&settings, device_id, Some(percent));
+                }
+                DeviceEvent::Disconnected { device_id } if session_active => {
+                    update(&settings, device_id, None);
+                }
+                // The receiver of a wireless model stays plugged in.
+                DeviceEvent::HeadsetPoweredOff { device_id } => {
+                    update(&settings, device_id, None);
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+/// Start calibrating `device_id`, replacing any earlier run.
+pub fn start(device_id: DeviceId) -> Result<CalibrationStatus, ControlError> {
+    if !hyperx::devices().get(device_id).has_battery() {
+        return Err(ControlError::UnsupportedFeature {
+            device_id,
+            capability: Capability::Battery,
+        });
+    }
+    let run = Run {
+        status: CalibrationStatus {
+            device_id,
+            phase: Phase::Charging,
+            started_at: now_millis(),
+            last_reading: None,
+            readings: 0,
+        },
+        discharge_start: None,
+        samples: Vec::new(),
+    };
+    // Registered first, so the reading below comes back uncorrected.
+    RUNS.lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(device_id, run);
+    let reading = hyperx::devices().get(device_id).read_battery();
+
+    let mut runs = RUNS.lock().unwrap();
+    let runs = runs.get_or_insert_with(HashMap::new);
+    let reading = match reading {
+        Ok(reading) => reading,
+        Err(err) => {
+            runs.remove(&device_id);
+            return Err(err);
+        }
+    };
+    let run = runs.get_mut(&device_id).expect("registered above");
+    if let Some(percent) = reading {
+        // A charging run does not end here.
+        record(run, percent);
+    }
+    info!(
+        device_id = %device_id,
+        "battery calibration started"
+    );
+    Ok(run.status.clone())
+}
+
+/// Stop a running calibration; the stored curve is kept.
+pub fn cancel(device_id: DeviceId) {
+    if let Some(runs) = RUNS.lock().unwrap().as_mut() {
+        runs.remove(&device_id);
+    }
+}
+
+/// The running or last finished calibration of `device_id`.
+pub fn status(device_id: DeviceId) -> Option<CalibrationStatus> {
+    RUNS.lock()
+        .unwrap()
+        .as_ref()?
+        .get(&device_id)
+        .map(|run| run.status.clone())
+}
+
+/// Forget the curve of `device_id`, so its readings are shown as reported.
+pub fn reset(settings: &SettingsStore, device_id: DeviceId) -> Result<(), SettingsError> {
+    settings.update(|settings| {
+        settings.battery.curves.remove(&device_id);
+    })?;
+    load(settings);
+    Ok(())
+}
+
+/// A new reading of `device_id`, or `None` when it disconnected.
+fn update(settings: &SettingsStore, device_id: DeviceId, reading: Option<u8>) {
+    let mut runs = RUNS.lock().unwrap();
+    let Some(run) = runs
+        .as_mut()
+        .and_then(|runs| runs.get_mut(&device_id))
+        .filter(|run| run.is_active())
+    else {
+        return;
+    };
+    let curve = match reading {
+        Some(percent) => record(run, percent),
+        None if run.status.phase == Phase::Discharging => finish(run),
+        None => None,
+    };
+    drop(runs);
+    if let Some(curve) = curve {
+        save(settings, device_id, curve);
+    }
+}
+
+/// Returns the curve once the discharge ends.
+fn record(run: &mut Run, percent: u8) -> Option<BatteryCurve> {
+    let previous = run.status.last_reading.replace(percent);
+    match run.status.phase {
+        Phase::Charging if percent >= 100 => {
+            run.status.phase = Phase::Discharging;
+            run.discharge_start = Some(Instant::now());
+            run.samples.push((Duration::ZERO, percent));
+            run.status.readings = 1;
+            None
+        }
+        Phase::Discharging => {
+            let lowest = run.samples.last().map_or(100, |&(_, lowest)| lowest);
+            if percent < lowest {
+                let elapsed = run
+                    .discharge_start
+                    .map_or(Duration::ZERO, |start| start.elapsed());
+                run.samples.push((elapsed, percent));
+                run.status.readings = run.samples.len();
+                if percent == 0 {
+                    return finish(run);
+                }
+            } else if percent > lowest.saturating_add(JITTER_PERCENT) {
+                // Back on the charger; the last reading is where it ended.
+                run.status.last_reading = previous;
+                return finish(run);
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// End the discharge, building the curve if the run covered enough of it.
+fn finish(run: &mut Run) -> Option<BatteryCurve> {
+    let total = run
+        .discharge_start
+        .map_or(Duration::ZERO, |start| start.elapsed());
+    let lowest = run.samples.last().map_or(100, |&(_, lowest)| lowest);
+    let failure = if total < MIN_DISCHARGE {
+        Some(format!(
+            "the discharge ended after {} minutes; use the headset until it switches off",
+            total.as_secs() / 60
+        ))
+    } else if lowest > LOW_BATTERY_PERCENT {
+        Some(format!(
+            "the discharge ended at {lowest}%; use the headset until it switches off before charging it"
+        ))
+    } else {
+        None
+    };
+    if let Some(reason) = failure {
+        run.status.phase = Phase::Failed { reason };
+        return None;
+    }
+    run.status.phase = Phase::Done;
+    let total = total.as_secs_f64();
+    let mut points: Vec<CurvePoint> = run
+        .samples
+        .iter()
+        .map(|&(elapsed, reported)| CurvePoint {
+            reported,
+            actual: (100.0 * (1.0 - elapsed.as_secs_f64() / total)).round() as u8,
+        })
+        .collect();
+    points.reverse();
+    Some(BatteryCurve {
+        points,
+        calibrated_at: now_millis(),
+    })
+}
+
+fn save(settings: &SettingsStore, device_id: DeviceId, curve: BatteryCurve) {
+    let points = curve.points.len();
+    if let Err(err) = settings.update(|settings| {
+        settings.battery.curves.insert(device_id, curve);
+    }) {
+        warn!("failed to save the battery calibration: {err}");
+        return;
+    }
+    load(settings);
+    info!(
+        device_id = %device_id,
+        points, "battery calibration finished"
+    );
+}
@@ -0,0 +1,434 @@
+//! A command line compatible with headsetcontrol, so status bar modules,
+//! scripts and desktop extensions written for it work unchanged. It runs
+//! when the first argument is a headsetcontrol flag, or when the binary is
+//! started through a symlink named `headsetcontrol`.
+//!
+//! Supported: `-b`/`--battery`, `-s`/`--sidetone <0-128>` (any level above 0
+//! turns sidetone on), `--connected`, `-c`/`--short-output` and
+//! `-o`/`--output <standard|short|json|yaml|env>`; `headsetcontrol` is
+//! accepted as an alias of `standard`.
+//!
+//! When the app or the daemon runs, `--sidetone` goes through it, so the
+//! new state ends up in its settings rather than in a file it overwrites on
+//! its next save.
+
+use crate::audit::{self, Source};
+use crate::daemon;
+use crate::hyperx::{self, DeviceId};
+use crate::instance::{self, InstanceError, Request};
+use crate::profiles::{self, DeviceSettings};
+use crate::services::SETTINGS_FILE;
+use crate::settings::SettingsStore;
+use serde::Serialize;
+use std::fmt::Write;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+const FLAGS: &[&str] = &[
+    "-b",
+    "--battery",
+    "-s",
+    "--sidetone",
+    "-c",
+    "--short-output",
+    "-o",
+    "--output",
+    "--connected",
+    "-?",
+    "-h",
+    "--help",
+];
+
+/// Reported as headsetcontrol's version so version checks in scripts pass.
+const COMPATIBLE_VERSION: &str = "3.0.0";
+
+const USAGE: &str = "Usage: hyperx-pilot [options]
+
+headsetcontrol-compatible options:
+  -b, --battery            print the battery level
+  -s, --sidetone LEVEL     set sidetone, 0 turns it off and 1-128 on
+      --connected          exit with 0 if a headset is connected, 1 otherwise
+  -c, --short-output       same as --output short
+  -o, --output FORMAT      standard, short, json, yaml or env
+  -?, --help               show this help
+";
+
+/// Whether `args` (including the program name) ask for the CLI.
+pub fn is_invocation(args: &[String]) -> bool {
+    let invoked_as_headsetcontrol = args
+        .first()
+        .and_then(|program| Path::new(program).file_stem())
+        .is_some_and(|stem| stem == "headsetcontrol");
+    invoked_as_headsetcontrol
+        || args
+            .get(1)
+            .is_some_and(|first| FLAGS.contains(&first.as_str()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Standard,
+    Short,
+    Json,
+    Yaml,
+    Env,
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    battery: bool,
+    sidetone: Option<u8>,
+    connected: bool,
+    help: bool,
+    format: Option<Format>,
+}
+
+fn parse(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-b" | "--battery" => options.battery = true,
+            "-s" | "--sidetone" => {
+                let level = args
+                    .next()
+                    .and_then(|value| value.parse::<u8>().ok())
+                    .filter(|&level| level <= 128)
+                    .ok_or("--sidetone needs a level between 0 and 128")?;
+                options.sidetone = Some(level);
+            }
+            "--connected" => options.connected = true,
+            "-c" | "--short-output" => options.format = Some(Format::Short),
+            "-o" | "--output" => {
+                options.format = Some(match args.next().map(String::as_str) {
+                    Some("standard" | "headsetcontrol") => Format::Standard,
+                    Some("short") => Format::Short,
+                    Some("json") => Format::Json,
+                    Some("yaml") => Format::Yaml,
+                    Some("env") => Format::Env,
+                    _ => return Err("--output needs standard, short, json, yaml or env".into()),
+                })
+            }
+            "-?" | "-h" | "--help" => options.help = true,
+            other => return Err(format!("unsupported option '{other}'")),
+        }
+    }
+    Ok(options)
+}
+
+/// Where `--sidetone` is written.
+enum Writer {
+    /// The running instance, which remembers it in its settings.
+    Instance(PathBuf),
+    /// The device, remembered in the settings file, which stays locked
+    /// against an instance starting meanwhile.
+    Local {
+        store: Box<SettingsStore>,
+        _lock: File,
+    },
+    /// Only the device, without a configuration directory.
+    Device,
+}
+
+impl Writer {
+    fn open(config_dir: Option<PathBuf>) -> Result<Self, InstanceError> {
+        let Some(dir) = config_dir else {
+            return Ok(Writer::Device);
+        };
+        match instance::lock(&dir) {
+            Ok(lock) => {
+                audit::open(&dir);
+                Ok(Writer::Local {
+                    store: Box::new(SettingsStore::load(dir.join(SETTINGS_FILE))),
+                    _lock: lock,
+                })
+            }
+            Err(InstanceError::Running { .. }) => Ok(Writer::Instance(dir)),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn set_sidetone(&self, device_id: DeviceId, enabled: bool) -> Result<(), String> {
+        let result = match self {
+            Writer::Instance(dir) => {
+                return instance::send(dir, Request::SetSidetone { device_id, enabled })
+                    .map_err(|err| err.to_string());
+            }
+            Writer::Local { store, .. } => {
+                let values = DeviceSettings {
+                    sidetone: Some(enabled),
+                    ..DeviceSettings::default()
+                };
+                profiles::apply(store, device_id, &values)
+            }
+            Writer::Device => hyperx::devices().get(device_id).set_sidetone(enabled),
+        };
+        audit::record(
+            Source::Cli,
+            Some(device_id),
+            "set_sidetone",
+            serde_json::json!(enabled),
+            result,
+        )
+        .map_err(|err| err.to_string())
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Battery {
+    status: &'static str,
+    level: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct ActionError {
+    action: &'static str,
+    message: String,
+}
+
+/// One device in headsetcontrol's JSON output shape.
+#[derive(Debug, Serialize)]
+struct DeviceReport {
+    status: &'static str,
+    device: String,
+    vendor: &'static str,
+    product: &'static str,
+    id_vendor: String,
+    id_product: String,
+    capabilities: Vec<&'static str>,
+    capabilities_str: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    battery: Option<Battery>,
+    errors: Vec<ActionError>,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    name: &'static str,
+    version: &'static str,
+    device_count: usize,
+    devices: Vec<DeviceReport>,
+}
+
+/// Run the CLI with the arguments after the program name; returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    #[cfg(windows)]
+    attach_console();
+
+    let options = match parse(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{message}\n\n{USAGE}");
+            return 1;
+        }
+    };
+    if options.help {
+        print!("{USAGE}");
+        return 0;
+    }
+    let devices = match hyperx::connected_devices() {
+        Ok(devices) => devices,
+        Err(err) => {
+            eprintln!("{err}");
+            return 1;
+        }
+    };
+    if options.connected {
+        return i32::from(devices.is_empty());
+    }
+
+    let writer = match options.sidetone {
+        Some(_) => match Writer::open(daemon::config_dir()) {
+            Ok(writer) => Some(writer),
+            Err(err) => {
+                eprintln!("{err}");
+                return 1;
+            }
+        },
+        None => None,
+    };
+    let reports: Vec<DeviceReport> = devices
+        .iter()
+        .map(|&device_id| device_report(writer.as_ref(), device_id, &options))
+        .collect();
+    let failed = devices.is_empty() || reports.iter().any(|report| !report.errors.is_empty());
+
+    let report = Report {
+        name: "HeadsetControl",
+        version: COMPATIBLE_VERSION,
+        device_count: reports.len(),
+        devices: reports,
+    };
+    match options.format.unwrap_or(Format::Standard) {
+        Format::Standard => print!("{}", render_standard(&report)),
+        Format::Short => print!("{}", render_short(&report)),
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&report).unwrap_or_default()
+        ),
+        Format::Yaml => print!("{}", render_yaml(&report)),
+        Format::Env => print!("{}", render_env(&report)),
+    }
+    i32::from(failed)
+}
+
+fn device_report(writer: Option<&Writer>, device_id: DeviceId, options: &Options) -> DeviceReport {
+    let metadata = hyperx::supported_devices()
+        .iter()
+        .find(|device| device.id == device_id)
+        .expect("connected devices come from the catalog");
+    let (vendor_id, product_id) = hyperx::usb_id(device_id);
+    let mut capabilities = Vec::new();
+    let mut capabilities_str = Vec::new();
+    if hyperx::devices().get(device_id).has_hardware_sidetone() {
+        capabilities.push("CAP_SIDETONE");
+        capabilities_str.push("sidetone");
+    }
+    if hyperx::devices().get(device_id).has_battery() {
+        capabilities.push("CAP_BATTERY_STATUS");
+        capabilities_str.push("battery");
+    }
+
+    let mut errors = Vec::new();
+    if let (Some(level), Some(writer)) = (options.sidetone, writer) {
+        if let Err(message) = writer.set_sidetone(device_id, level > 0) {
+            errors.push(ActionError {
+                action: "sidetone",
+                message,
+            });
+        }
+    }
+    let battery = options
+        .battery
+        .then(|| match hyperx::devices().get(device_id).read_battery() {
+            Ok(Some(percent)) => Battery {
+                status: "BATTERY_AVAILABLE",
+                level: i32::from(percent),
+            },
+            Ok(None) | Err(_) => Battery {
+                status: "BATTERY_UNAVAILABLE",
+                level: -1,
+            },
+        });
+
+    DeviceReport {
+        status: if errors.is_empty() {
+            "success"
+        } else {
+            "partial"
+        },
+        device: format!("HyperX {}", metadata.label),
+        vendor: "HyperX",
+        product: metadata.label,
+        id_vendor: format!("0x{vendor_id:04x}"),
+        id_product: format!("0x{product_id:04x}"),
+        capabilities,
+        capabilities_str,
+        battery,
+        errors,
+    }
+}
+
+fn render_standard(report: &Report) -> String {
+    let mut out = String::new();
+    if report.devices.is_empty() {
+        out.push_str("No supported device found\n");
+    }
+    for device in &report.devices {
+        let _ = writeln!(out, "Found {}!", device.device);
+        if let Some(battery) = &device.battery {
+            out.push_str("\nBattery:\n");
+            let _ = writeln!(out, "\tStatus: {}", battery.status);
+            if battery.level >= 0 {
+                let _ = writeln!(out, "\tLevel: {}%", battery.level);
+            }
+        }
+        for error in &device.errors {
+            let _ = writeln!(out, "\nFailed to set {}: {}", error.action, error.message);
+        }
+    }
+    out
+}
+
+/// Only values, one per line, for status bars; `-2` when the battery level
+/// is unavailable, as headsetcontrol does.
+fn render_short(report: &Report) -> String {
+    let mut out = String::new();
+    for device in &report.devices {
+        if let Some(battery) = &device.battery {
+            let level = if battery.level >= 0 {
+                battery.level
+            } else {
+                -2
+            };
+            let _ = writeln!(out, "{level}");
+        }
+    }
+    out
+}
+
+fn render_yaml(report: &Report) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "name: \"{}\"", report.name);
+    let _ = writeln!(out, "version: \"{}\"", report.version);
+    let _ = writeln!(out, "device_count: {}", report.device_count);
+    out.push_str("devices:\n");
+    for device in &report.devices {
+        let _ = writeln!(out, "  - status: \"{}\"", device.status);
+        let _ = writeln!(out, "    device: \"{}\"", device.device);
+        let _ = writeln!(out, "    vendor: \"{}\"", device.vendor);
+        let _ = writeln!(out, "    product: \"{}\"", device.product);
+        let _ = writeln!(out, "    id_vendor: \"{}\"", device.id_vendor);
+        let _ = writeln!(out, "    id_product: \"{}\"", device.id_product);
+        out.push_str("    capabilities:\n");
+        for capability in &device.capabilities_str {
+            let _ = writeln!(out, "      - {capability}");
+        }
+        if let Some(battery) = &device.battery {
+            out.push_str("    battery:\n");
+            let _ = writeln!(out, "      status: \"{}\"", battery.status);
+            let _ = writeln!(out, "      level: {}", battery.level);
+        }
+        if !device.errors.is_empty() {
+            out.push_str("    errors:\n");
+            for error in &device.errors {
+                let _ = writeln!(out, "      {}: {:?}", error.action, error.message);
+            }
+        }
+    }
+    out
+}
+
+fn render_env(report: &Report) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "HEADSETCONTROL_NAME=\"{}\"", report.name);
+    let _ = writeln!(out, "HEADSETCONTROL_VERSION=\"{}\"", report.version);
+    let _ = writeln!(out, "DEVICE_COUNT={}", report.device_count);
+    for (index, device) in report.devices.iter().enumerate() {
+        let prefix = format!("DEVICE_{index}");
+        let _ = writeln!(out, "{prefix}=\"{}\"", device.device);
+        let _ = writeln!(
+            out,
+            "{prefix}_CAPABILITIES=\"{}\"",
+            device.capabilities.join(" ")
+        );
+        if let Some(battery) = &device.battery {
+            let _ = writeln!(out, "{prefix}_BATTERY_STATUS=\"{}\"", battery.status);
+            let _ = writeln!(out, "{prefix}_BATTERY_LEVEL={}", battery.level);
+        }
+        for error in &device.errors {
+            let action = error.action.to_uppercase();
+            let _ = writeln!(out, "{prefix}_ERROR_{action}={:?}", error.message);
+        }
+    }
+    out
+}
+
+/// Release builds use the GUI subsystem and start without a console; attach
+/// to the calling terminal's so the output is visible.
+#[cfg(windows)]
+fn attach_console() {
+    use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+    let _ = unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+}
@@ -0,0 +1,146 @@
+//! Finds other software that drives the headset at the same time, so a failed
+//! or silently reverted write can name the culprit.
+
+use hidapi::HidApi;
+
+/// Process names (case-insensitive, without `.exe`) of known headset
+/// controllers and the name to show for them.
+const KNOWN_CONTROLLERS: &[(&str, &str)] = &[
+    ("ngenuity", "HyperX NGENUITY"),
+    ("ngenuity2", "HyperX NGENUITY"),
+    ("openrgb", "OpenRGB"),
+    ("signalrgb", "SignalRGB"),
+    ("headsetcontrol", "HeadsetControl"),
+];
+
+fn known_label(process: &str) -> Option<&'static str> {
+    let process = process.to_lowercase();
+    let process = process.strip_suffix(".exe").unwrap_or(&process);
+    KNOWN_CONTROLLERS
+        .iter()
+        .find(|(name, _)| *name == process)
+        .map(|&(_, label)| label)
+}
+
+/// Name of another process that controls the device with this VID/PID. On
+/// Linux with hidraw the node's holders are checked, which also catches
+/// unknown tools; elsewhere running processes are matched by name.
+pub fn find_controller(api: &HidApi, vendor_id: u16, product_id: u16) -> Option<String> {
+    #[cfg(target_os = "linux")]
+    for info in api.device_list() {
+        if info.vendor_id() != vendor_id || info.product_id() != product_id {
+            continue;
+        }
+        if let Some(process) = info.path().to_str().ok().and_then(linux::node_holder) {
+            return Some(known_label(&process).map_or(process, str::to_string));
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (api, vendor_id, product_id);
+
+    running_processes()
+        .iter()
+        .find_map(|process| known_label(process))
+        .map(str::to_string)
+}
+
+#[cfg(target_os = "linux")]
+fn running_processes() -> Vec<String> {
+    linux::running_processes()
+}
+
+#[cfg(windows)]
+fn running_processes() -> Vec<String> {
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+        TH32CS_SNAPPROCESS,
+    };
+
+    let mut processes = Vec::new();
+    // SAFETY: the snapshot handle is closed below and the entry carries its size.
+    unsafe {
+        let Ok(snapshot) = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) else {
+            return processes;
+        };
+        let mut entry = PROCESSENTRY32W {
+            dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+            ..Default::default()
+        };
+        let mut more = Process32FirstW(snapshot, &mut entry).is_ok();
+        while more {
+            let name = &entry.szExeFile;
+            let length = name
+                .iter()
+                .position(|&unit| unit == 0)
+                .unwrap_or(name.len());
+            processes.push(String::from_utf16_lossy(&name[..length]));
+            more = Process32NextW(snapshot, &mut entry).is_ok();
+        }
+        let _ = CloseHandle(snapshot);
+    }
+    processes
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn running_processes() -> Vec<String> {
+    let output = std::process::Command::new("ps")
+        .args(["-axco", "comm="])
+        .output();
+    match output {
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| line.trim().to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::fs;
+    use std::path::Path;
+    use std::process;
+
+    fn process_dirs() -> impl Iterator<Item = (u32, std::path::PathBuf)> {
+        fs::read_dir("/proc")
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let pid = entry.file_name().to_str()?.parse().ok()?;
+                Some((pid, entry.path()))
+            })
+    }
+
+    fn comm(dir: &Path) -> Option<String> {
+        fs::read_to_string(dir.join("comm"))
+            .ok()
+            .map(|name| name.trim().to_string())
+    }
+
+    /// Another process with `node` open. Processes of other users are not
+    /// readable and therefore not found.
+    pub(super) fn node_holder(node: &str) -> Option<String> {
+        if !node.starts_with("/dev/") {
+            return None;
+        }
+        let own_pid = process::id();
+        process_dirs()
+            .filter(|&(pid, _)| pid != own_pid)
+            .find(|(_, dir)| {
+                fs::read_dir(dir.join("fd"))
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .any(|fd| {
+                        fs::read_link(fd.path()).is_ok_and(|target| target == Path::new(node))
+                    })
+            })
+            .and_then(|(_, dir)| comm(&dir))
+    }
+
+    pub(super) fn running_processes() -> Vec<String> {
+        process_dirs().filter_map(|(_, dir)| comm(&dir)).collect()
+    }
+}
@@ -0,0 +1,144 @@
+//! Panic capture. Every panic is logged and written to a crash report in the
+//! `crashes` folder of the config directory, and sent to [`subscribe`]
+//! receivers (the frontend gets it as a [`CRASH_EVENT`]). Polling threads started with
+//! [`supervise`] are restarted after a panic, so one bad read does not
+//! silently end hotplug detection for the rest of the session.
+
+use serde::Serialize;
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{error, warn};
+
+/// Below the config directory.
+pub const CRASH_DIR: &str = "crashes";
+/// Name of the Tauri event carrying each [`CrashReport`] to the frontend.
+pub const CRASH_EVENT: &str = "background-crash";
+/// Older reports are deleted beyond this many.
+const KEPT_REPORTS: usize = 20;
+const MAX_RESTARTS: u32 = 5;
+/// Doubled after every restart.
+const RESTART_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrashReport {
+    /// Unix time in milliseconds.
+    pub time: u64,
+    pub thread: String,
+    pub message: String,
+    /// `file:line:column` of the panic.
+    pub location: Option<String>,
+    pub app_version: &'static str,
+    pub os: &'static str,
+    pub backtrace: String,
+    /// Where the report was written, when that worked.
+    pub path: Option<PathBuf>,
+}
+
+static DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+static SUBSCRIBERS: Mutex<Vec<Sender<CrashReport>>> = Mutex::new(Vec::new());
+
+/// Install the panic hook, writing reports to [`CRASH_DIR`] below `config_dir`.
+pub fn install(config_dir: Option<&Path>) {
+    if let Some(dir) = config_dir {
+        let _ = DIRECTORY.set(dir.join(CRASH_DIR));
+    }
+    panic::set_hook(Box::new(report));
+}
+
+/// Every crash report from now on.
+pub fn subscribe() -> Receiver<CrashReport> {
+    let (sender, receiver) = mpsc::channel();
+    SUBSCRIBERS.lock().unwrap().push(sender);
+    receiver
+}
+
+fn report(info: &PanicHookInfo<'_>) {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panic with a non-string payload".to_string());
+    let thread = thread::current().name().unwrap_or("unnamed").to_string();
+    let location = info.location().map(ToString::to_string);
+    error!(
+        "thread '{thread}' panicked at {}: {message}",
+        location.as_deref().unwrap_or("an unknown location")
+    );
+
+    let mut report = CrashReport {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64),
+        thread,
+        message,
+        location,
+        app_version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        backtrace: Backtrace::force_capture().to_string(),
+        path: None,
+    };
+    if let Some(directory) = DIRECTORY.get() {
+        match write(directory, &report) {
+            Ok(path) => report.path = Some(path),
+            Err(err) => error!("failed to write crash report: {err}"),
+        }
+    }
+    SUBSCRIBERS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .retain(|subscriber| subscriber.send(report.clone()).is_ok());
+}
+
+fn write(directory: &Path, report: &CrashReport) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(directory)?;
+    let path = directory.join(format!("crash-{}.json", report.time));
+    fs::write(&path, serde_json::to_vec_pretty(report)?)?;
+
+    let mut reports: Vec<PathBuf> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    // The names sort by time.
+    reports.sort();
+    let excess = reports.len().saturating_sub(KEPT_REPORTS);
+    for old in &reports[..excess] {
+        let _ = fs::remove_file(old);
+    }
+    Ok(path)
+}
+
+/// Run `body` on a thread called `name`, running it again after a panic (up to
+/// [`MAX_RESTARTS`] times, waiting longer each time). Returning ends the thread.
+pub fn supervise(name: &str, mut body: impl FnMut() + Send + 'static) {
+    let thread_name = name.to_string();
+    let spawned = thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            let mut delay = RESTART_DELAY;
+            for restarts in 0.. {
+                if panic::catch_unwind(AssertUnwindSafe(&mut body)).is_ok() {
+                    return;
+                }
+                if restarts == MAX_RESTARTS {
+                    error!("{thread_name} stopped after {MAX_RESTARTS} restarts");
+                    return;
+                }
+                warn!("restarting {thread_name} in {}s", delay.as_secs());
+                thread::sleep(delay);
+                delay *= 2;
+            }
+        });
+    if let Err(err) = spawned {
+        error!("failed to start {name}: {err}");
+    }
+}
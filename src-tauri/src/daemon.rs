@@ -0,0 +1,38 @@
+//! Headless mode (`--headless`): the background services without a window,
+//! meant to be started by the session's service manager.
+
+use crate::instance::{self, Mode};
+use crate::services::Services;
+use std::path::PathBuf;
+use std::thread;
+
+/// Must match `identifier` in `tauri.conf.json` so both modes share settings.
+pub const APP_IDENTIFIER: &str = "ch.datascale.hyperx-pilot";
+
+/// Same location Tauri's `app_config_dir` resolves to.
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_IDENTIFIER))
+}
+
+pub fn run() {
+    let Some(config_dir) = config_dir() else {
+        eprintln!("cannot determine the configuration directory");
+        return;
+    };
+    let instance = match instance::acquire(&config_dir, Mode::Headless) {
+        Ok(instance) => instance,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+    let services = Services::start(&config_dir, instance, || {
+        Err("HyperX Pilot runs in the background without a window".into())
+    });
+    services.start_watcher();
+    // Everything runs on background threads; termination is left to the
+    // service manager, and stale loopbacks are cleaned up on next start.
+    loop {
+        thread::park();
+    }
+}
@@ -0,0 +1,105 @@
+//! `dump_device_info`: everything hidapi knows about a headset's HID
+//! interfaces, including the raw report descriptors, plus the headset's
+//! hardware details from [`hyperx::Device::read_hardware_info`]. This is what is
+//! needed to add support for a variant from a bug report.
+
+use crate::hyperx::{self, ControlError, DeviceId};
+use crate::recorder;
+use crate::report_descriptor;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceInfoDump {
+    pub device_id: DeviceId,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// One entry per top-level collection, as enumerated by hidapi; several
+    /// may share an interface.
+    pub interfaces: Vec<InterfaceInfo>,
+    /// The serial number is masked; `None` when the device could not be read.
+    pub hardware: Option<hyperx::HardwareInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InterfaceInfo {
+    pub path: String,
+    /// `-1` when the backend does not know it.
+    pub interface_number: i32,
+    pub usage_page: u16,
+    pub usage: u16,
+    pub bus_type: String,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    /// Masked with `*`, like in HID recordings.
+    pub serial_number: Option<String>,
+    /// `bcdDevice`, e.g. `0x0107` for release 1.07.
+    pub release_number: u16,
+    /// Hex, `None` when the interface could not be opened.
+    pub report_descriptor: Option<String>,
+    /// Size of each feature report in bytes, keyed by report ID as `0x20`.
+    pub feature_reports: BTreeMap<String, usize>,
+    /// Why the report descriptor could not be read.
+    pub error: Option<String>,
+}
+
+fn mask(serial: &str) -> String {
+    "*".repeat(serial.chars().count())
+}
+
+pub fn dump_device_info(device_id: DeviceId) -> Result<DeviceInfoDump, ControlError> {
+    let (vendor_id, product_id) = hyperx::usb_id(device_id);
+    hyperx::refresh_devices()?;
+    let interfaces = hyperx::with_api(|api| {
+        api.device_list()
+            .filter(|info| info.vendor_id() == vendor_id && info.product_id() == product_id)
+            .map(|info| {
+                let mut interface = InterfaceInfo {
+                    path: info.path().to_string_lossy().into_owned(),
+                    interface_number: info.interface_number(),
+                    usage_page: info.usage_page(),
+                    usage: info.usage(),
+                    bus_type: format!("{:?}", info.bus_type()),
+                    manufacturer: info.manufacturer_string().map(str::to_string),
+                    product: info.product_string().map(str::to_string),
+                    serial_number: info.serial_number().map(mask),
+                    release_number: info.release_number(),
+                    report_descriptor: None,
+                    feature_reports: BTreeMap::new(),
+                    error: None,
+                };
+                let mut descriptor = vec![0u8; hidapi::MAX_REPORT_DESCRIPTOR_SIZE];
+                let size = api
+                    .open_path(info.path())
+                    .and_then(|device| device.get_report_descriptor(&mut descriptor));
+                match size {
+                    Ok(size) => {
+                        let descriptor = &descriptor[..size];
+                        interface.report_descriptor = Some(recorder::hex(descriptor));
+                        interface.feature_reports = report_descriptor::feature_lengths(descriptor)
+                            .into_iter()
+                            .map(|(id, length)| (format!("0x{id:02X}"), length))
+                            .collect();
+                    }
+                    Err(err) => interface.error = Some(err.to_string()),
+                }
+                interface
+            })
+            .collect::<Vec<_>>()
+    })?;
+    let hardware = hyperx::devices()
+        .get(device_id)
+        .read_hardware_info()
+        .ok()
+        .map(|hardware| hyperx::HardwareInfo {
+            serial_number: hardware.serial_number.as_deref().map(mask),
+            ..hardware
+        });
+    Ok(DeviceInfoDump {
+        device_id,
+        vendor_id,
+        product_id,
+        interfaces,
+        hardware,
+    })
+}
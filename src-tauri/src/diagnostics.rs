@@ -0,0 +1,147 @@
+use crate::conflicts;
+pub use crate::hyperx::OpenDiagnosis;
+use hidapi::{HidApi, HidError};
+
+/// Work out why `HidApi::open` failed for the given VID/PID.
+pub fn diagnose_open_failure(
+    api: &HidApi,
+    vendor_id: u16,
+    product_id: u16,
+    error: &HidError,
+) -> OpenDiagnosis {
+    let mut diagnosis = classify(api, vendor_id, product_id, error);
+    if let OpenDiagnosis::DeviceBusy { process } = &mut diagnosis {
+        *process = conflicts::find_controller(api, vendor_id, product_id);
+    }
+    diagnosis
+}
+
+fn classify(api: &HidApi, vendor_id: u16, product_id: u16, error: &HidError) -> OpenDiagnosis {
+    // Inside a sandbox without device access nothing enumerates, so check it
+    // before concluding the headset is unplugged.
+    #[cfg(target_os = "linux")]
+    if let Some(diagnosis) = linux::diagnose_sandbox() {
+        return diagnosis;
+    }
+
+    let Some(info) = api
+        .device_list()
+        .find(|info| info.vendor_id() == vendor_id && info.product_id() == product_id)
+    else {
+        #[cfg(windows)]
+        {
+            use crate::platform::windows::devices;
+            let hardware_ids = devices::usb_hardware_ids(vendor_id, product_id);
+            if !hardware_ids.is_empty() {
+                return OpenDiagnosis::DriverMissing { hardware_ids };
+            }
+        }
+        return OpenDiagnosis::NotConnected;
+    };
+
+    let message = error.to_string().to_lowercase();
+    if message.contains("busy")
+        || message.contains("exclusive")
+        || message.contains("sharing violation")
+    {
+        return OpenDiagnosis::DeviceBusy { process: None };
+    }
+    #[cfg(target_os = "macos")]
+    {
+        use crate::platform::macos::{self, AccessState};
+        let refused = message.contains("not permitted") || message.contains("e00002e2");
+        if refused || macos::input_monitoring_access() == AccessState::Denied {
+            return OpenDiagnosis::InputMonitoringDenied;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Ok(node) = info.path().to_str() {
+        if node.starts_with("/dev/") {
+            return linux::diagnose_node(node);
+        }
+        // libusb paths look like `1-4:1.3`; libusb only reports the errno text.
+        if message.contains("access denied") || message.contains("insufficient permissions") {
+            return linux::diagnose_permissions(node);
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = info;
+
+    OpenDiagnosis::Unknown
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::OpenDiagnosis;
+    use crate::platform::linux::{sandbox, udev};
+    use std::fs::{self, OpenOptions};
+    use std::io::ErrorKind;
+    use std::os::unix::fs::MetadataExt;
+
+    const EBUSY: i32 = 16;
+
+    pub(super) fn diagnose_sandbox() -> Option<OpenDiagnosis> {
+        let status = sandbox::detect();
+        Some(OpenDiagnosis::SandboxRestricted {
+            sandbox: status.kind.label().to_string(),
+            grant_command: status.grant_command?,
+        })
+    }
+
+    pub(super) fn diagnose_node(node: &str) -> OpenDiagnosis {
+        let error = match OpenOptions::new().read(true).write(true).open(node) {
+            Ok(_) => return OpenDiagnosis::Unknown,
+            Err(error) => error,
+        };
+        if error.raw_os_error() == Some(EBUSY) {
+            return OpenDiagnosis::DeviceBusy { process: None };
+        }
+        if error.kind() != ErrorKind::PermissionDenied {
+            return OpenDiagnosis::Unknown;
+        }
+
+        let node = node.to_string();
+        let gid = fs::metadata(&node).map(|meta| meta.gid()).unwrap_or(0);
+        if gid != 0 && !user_gids().contains(&gid) {
+            let group = group_name(gid).unwrap_or_else(|| gid.to_string());
+            return OpenDiagnosis::NotInGroup { node, group };
+        }
+        diagnose_permissions(&node)
+    }
+
+    /// Permission failure that group membership cannot explain: either the
+    /// rules are missing or the device predates them.
+    pub(super) fn diagnose_permissions(node: &str) -> OpenDiagnosis {
+        let node = node.to_string();
+        if udev::rules_installed() {
+            OpenDiagnosis::ReconnectRequired { node }
+        } else {
+            OpenDiagnosis::MissingUdevRule { node }
+        }
+    }
+
+    /// Primary and supplementary group ids of this process, from `/proc/self/status`.
+    fn user_gids() -> Vec<u32> {
+        let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+        status
+            .lines()
+            .filter_map(|line| {
+                line.strip_prefix("Groups:")
+                    .or_else(|| line.strip_prefix("Gid:"))
+            })
+            .flat_map(str::split_whitespace)
+            .filter_map(|gid| gid.parse().ok())
+            .collect()
+    }
+
+    fn group_name(gid: u32) -> Option<String> {
+        let groups = fs::read_to_string("/etc/group").ok()?;
+        groups.lines().find_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse::<u32>().ok()?;
+            (id == gid).then(|| name.to_string())
+        })
+    }
+}
@@ -0,0 +1,185 @@
+//! Discord's local RPC transport: the `discord-ipc-N` Unix socket or named
+//! pipe, carrying frames of a little-endian `u32` opcode, a `u32` length and
+//! a JSON body. Reads never block so one thread can also forward our own
+//! mute changes; Windows pipes cannot be read and written concurrently.
+
+use serde_json::Value;
+use std::io::{self, Read, Write};
+
+pub const OP_HANDSHAKE: u32 = 0;
+pub const OP_FRAME: u32 = 1;
+pub const OP_CLOSE: u32 = 2;
+pub const OP_PING: u32 = 3;
+pub const OP_PONG: u32 = 4;
+
+/// Discord takes the first free of `discord-ipc-0` to `discord-ipc-9`.
+const PIPE_SLOTS: u32 = 10;
+const MAX_FRAME: usize = 1024 * 1024;
+
+pub struct Connection {
+    pipe: Pipe,
+    buffer: Vec<u8>,
+}
+
+impl Connection {
+    pub fn open() -> io::Result<Connection> {
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "Discord is not running");
+        for slot in 0..PIPE_SLOTS {
+            match Pipe::open(slot) {
+                Ok(pipe) => {
+                    return Ok(Connection {
+                        pipe,
+                        buffer: Vec::new(),
+                    })
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => last_error = err,
+            }
+        }
+        Err(last_error)
+    }
+
+    pub fn send(&mut self, op: u32, body: &Value) -> io::Result<()> {
+        let body = body.to_string();
+        let mut frame = op.to_le_bytes().to_vec();
+        frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        frame.extend_from_slice(body.as_bytes());
+        self.pipe.write_all(&frame)?;
+        self.pipe.flush()
+    }
+
+    /// The next complete frame, or `None` when none has fully arrived yet.
+    pub fn poll(&mut self) -> io::Result<Option<(u32, Value)>> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            let read = self.pipe.read_available(&mut chunk)?;
+            if read == 0 {
+                break;
+            }
+            self.buffer.extend_from_slice(&chunk[..read]);
+        }
+        if self.buffer.len() < 8 {
+            return Ok(None);
+        }
+        let op = u32::from_le_bytes(self.buffer[0..4].try_into().unwrap());
+        let length = u32::from_le_bytes(self.buffer[4..8].try_into().unwrap()) as usize;
+        if length > MAX_FRAME {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "frame too large",
+            ));
+        }
+        if self.buffer.len() < 8 + length {
+            return Ok(None);
+        }
+        let body: Vec<u8> = self.buffer.drain(..8 + length).skip(8).collect();
+        let body = serde_json::from_slice(&body)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(Some((op, body)))
+    }
+}
+
+fn closed() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::ConnectionAborted,
+        "Discord closed the connection",
+    )
+}
+
+#[cfg(unix)]
+struct Pipe(std::os::unix::net::UnixStream);
+
+#[cfg(unix)]
+impl Pipe {
+    /// Discord creates the socket in its runtime directory, which for the
+    /// Flatpak and Snap builds is an app-specific subdirectory.
+    fn open(slot: u32) -> io::Result<Pipe> {
+        use std::env;
+        use std::os::unix::net::UnixStream;
+        use std::path::PathBuf;
+
+        let base = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+            .iter()
+            .find_map(env::var_os)
+            .map_or_else(|| PathBuf::from("/tmp"), PathBuf::from);
+        let name = format!("discord-ipc-{slot}");
+        let mut last_error = io::Error::from(io::ErrorKind::NotFound);
+        for dir in ["", "app/com.discordapp.Discord", "snap.discord"] {
+            match UnixStream::connect(base.join(dir).join(&name)) {
+                Ok(stream) => {
+                    stream.set_nonblocking(true)?;
+                    return Ok(Pipe(stream));
+                }
+                Err(err) => last_error = err,
+            }
+        }
+        Err(last_error)
+    }
+
+    fn read_available(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buffer) {
+            Ok(0) => Err(closed()),
+            Ok(read) => Ok(read),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(0),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        // The socket is non-blocking for reads; a full send buffer only
+        // happens if Discord hangs, in which case giving up is fine.
+        self.0.write_all(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(windows)]
+struct Pipe(std::fs::File);
+
+#[cfg(windows)]
+impl Pipe {
+    fn open(slot: u32) -> io::Result<Pipe> {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(format!(r"\\.\pipe\discord-ipc-{slot}"))
+            .map(Pipe)
+    }
+
+    /// Only read what `PeekNamedPipe` reports as waiting, so a read never
+    /// blocks the writes that share the handle.
+    fn read_available(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        use std::os::windows::io::AsRawHandle;
+        use windows::Win32::Foundation::HANDLE;
+        use windows::Win32::System::Pipes::PeekNamedPipe;
+
+        let mut available = 0u32;
+        unsafe {
+            PeekNamedPipe(
+                HANDLE(self.0.as_raw_handle()),
+                None,
+                0,
+                None,
+                Some(&mut available),
+                None,
+            )
+        }
+        .map_err(|_| closed())?;
+        if available == 0 {
+            return Ok(0);
+        }
+        let wanted = buffer.len().min(available as usize);
+        self.0.read(&mut buffer[..wanted])
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.0.write_all(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
@@ -0,0 +1,234 @@
+//! Optional sync of the microphone mute with Discord through its local RPC
+//! server. Muting the headset mutes Discord, and mutes made in Discord light
+//! the mute LED on headsets that have an addressable one.
+//!
+//! Discord only grants RPC voice access to registered applications, so this
+//! needs the user's own application id and an OAuth2 access token with the
+//! `rpc` and `rpc.voice.write` scopes in the settings.
+
+mod ipc;
+
+use crate::events::{DeviceEvent, EventBus};
+use crate::hyperx;
+use crate::settings::{DiscordSettings, SettingsStore};
+use ipc::{Connection, OP_CLOSE, OP_FRAME, OP_HANDSHAKE, OP_PING, OP_PONG};
+use serde_json::{json, Value};
+use std::fmt;
+use std::io;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+const RPC_VERSION: u64 = 1;
+/// How long events may wait in the pipe while we wait for our own events.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+const RECONNECT_DELAY: Duration = Duration::from_secs(15);
+
+#[derive(Debug)]
+pub enum DiscordError {
+    Io {
+        source: io::Error,
+    },
+    NotConfigured,
+    /// Discord answered a command with an error, e.g. an expired token.
+    Rejected {
+        command: String,
+        message: String,
+    },
+    Timeout {
+        command: String,
+    },
+}
+
+impl fmt::Display for DiscordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiscordError::Io { source } => write!(f, "{source}"),
+            DiscordError::NotConfigured => {
+                write!(f, "discord.client_id and discord.access_token must be set")
+            }
+            DiscordError::Rejected { command, message } => {
+                write!(f, "Discord rejected {command}: {message}")
+            }
+            DiscordError::Timeout { command } => write!(f, "Discord did not answer {command}"),
+        }
+    }
+}
+
+impl std::error::Error for DiscordError {}
+
+impl From<io::Error> for DiscordError {
+    fn from(source: io::Error) -> Self {
+        DiscordError::Io { source }
+    }
+}
+
+/// Stay connected to Discord while the process runs. Discord is often not
+/// running, so a failure is only logged once until the next success.
+pub fn spawn(settings: Arc<SettingsStore>, events: &EventBus) {
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        let mut logged_failure = false;
+        loop {
+            receiver.try_iter().for_each(drop);
+            let config = settings.get().discord;
+            match Session::open(&config) {
+                Ok(mut session) => {
+                    logged_failure = false;
+                    if let Err(err) = session.run(&receiver) {
+                        warn!("Discord connection lost: {err}");
+                    }
+                }
+                Err(err) if !logged_failure => {
+                    logged_failure = true;
+                    warn!("cannot connect to Discord: {err}");
+                }
+                Err(_) => {}
+            }
+            thread::sleep(RECONNECT_DELAY);
+        }
+    });
+}
+
+struct Session {
+    connection: Connection,
+    next_nonce: u64,
+    /// Discord's mute state as last reported or set by us.
+    discord_muted: Option<bool>,
+}
+
+impl Session {
+    fn open(config: &DiscordSettings) -> Result<Session, DiscordError> {
+        let (Some(client_id), Some(access_token)) = (&config.client_id, &config.access_token)
+        else {
+            return Err(DiscordError::NotConfigured);
+        };
+        let mut session = Session {
+            connection: Connection::open()?,
+            next_nonce: 0,
+            discord_muted: None,
+        };
+        session.connection.send(
+            OP_HANDSHAKE,
+            &json!({ "v": RPC_VERSION, "client_id": client_id }),
+        )?;
+        session.wait_for(|frame| frame["evt"] == "READY", "the handshake")?;
+        session.command(
+            "AUTHENTICATE",
+            json!({ "access_token": access_token }),
+            None,
+        )?;
+        session.command("SUBSCRIBE", json!({}), Some("VOICE_SETTINGS_UPDATE"))?;
+        let settings = session.command("GET_VOICE_SETTINGS", json!({}), None)?;
+        session.on_discord_mute(&settings);
+        Ok(session)
+    }
+
+    /// Send a command and wait for its response, handling unrelated frames
+    /// on the way.
+    fn command(
+        &mut self,
+        command: &str,
+        args: Value,
+        event: Option<&str>,
+    ) -> Result<Value, DiscordError> {
+        self.next_nonce += 1;
+        let nonce = self.next_nonce.to_string();
+        let mut message = json!({ "cmd": command, "args": args, "nonce": nonce });
+        if let Some(event) = event {
+            message["evt"] = json!(event);
+        }
+        self.connection.send(OP_FRAME, &message)?;
+        let response = self.wait_for(|frame| frame["nonce"] == nonce.as_str(), command)?;
+        if response["evt"] == "ERROR" {
+            return Err(DiscordError::Rejected {
+                command: command.to_string(),
+                message: response["data"]["message"]
+                    .as_str()
+                    .unwrap_or("unknown error")
+                    .to_string(),
+            });
+        }
+        Ok(response["data"].clone())
+    }
+
+    fn wait_for(
+        &mut self,
+        matches: impl Fn(&Value) -> bool,
+        what: &str,
+    ) -> Result<Value, DiscordError> {
+        let deadline = Instant::now() + RESPONSE_TIMEOUT;
+        while Instant::now() < deadline {
+            match self.next_frame()? {
+                Some(frame) if matches(&frame) => return Ok(frame),
+                Some(frame) => self.handle(&frame),
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        }
+        Err(DiscordError::Timeout {
+            command: what.to_string(),
+        })
+    }
+
+    /// The next `OP_FRAME` body, answering pings and failing on close.
+    fn next_frame(&mut self) -> io::Result<Option<Value>> {
+        while let Some((op, body)) = self.connection.poll()? {
+            match op {
+                OP_FRAME => return Ok(Some(body)),
+                OP_PING => self.connection.send(OP_PONG, &body)?,
+                OP_CLOSE => {
+                    let message = body["message"].as_str().unwrap_or("closed");
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionAborted,
+                        format!("Discord closed the connection: {message}"),
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    fn handle(&mut self, frame: &Value) {
+        if frame["evt"] == "VOICE_SETTINGS_UPDATE" {
+            self.on_discord_mute(&frame["data"]);
+        }
+    }
+
+    fn on_discord_mute(&mut self, voice_settings: &Value) {
+        let Some(muted) = voice_settings["mute"].as_bool() else {
+            return;
+        };
+        if self.discord_muted == Some(muted) {
+            return;
+        }
+        self.discord_muted = Some(muted);
+        for device in hyperx::list_devices() {
+            if hyperx::devices().get(device.id).has_mute_led() {
+                let _ = hyperx::devices().get(device.id).set_mute_led(muted);
+            }
+        }
+    }
+
+    fn run(&mut self, receiver: &Receiver<DeviceEvent>) -> Result<(), DiscordError> {
+        loop {
+            while let Some(frame) = self.next_frame()? {
+                self.handle(&frame);
+            }
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                // Skip echoes of mutes that came from Discord in the first place.
+                Ok(DeviceEvent::MicMuteChanged { muted, .. })
+                    if self.discord_muted != Some(muted) =>
+                {
+                    self.discord_muted = Some(muted);
+                    self.command("SET_VOICE_SETTINGS", json!({ "mute": muted }), None)?;
+                }
+                Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+    }
+}
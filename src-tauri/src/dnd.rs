@@ -0,0 +1,118 @@
+//! "Do not disturb": one switch for what a headset does on its own to get
+//! attention. Turning it on disables voice prompts, turns the LEDs off and
+//! mutes notification beeps, whichever of them the model has, in that order
+//! and as one change through [`Device::set_features`], so a failure partway
+//! leaves the headset as it was. The values from before are kept until it is
+//! turned off again; they are not saved, so after a restart turning it off
+//! restores the defaults instead.
+//!
+//! [`Device::set_features`]: crate::hyperx::Device::set_features
+
+use crate::hyperx::{self, Capability, ControlError, DeviceId, Feature};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+const QUIET: [Feature; 3] = [
+    Feature::VoicePrompts(false),
+    Feature::LedBrightness(0),
+    Feature::NotificationBeeps(false),
+];
+
+/// What turning it off restores when the values from before are unknown.
+const DEFAULTS: [Feature; 3] = [
+    Feature::VoicePrompts(true),
+    Feature::LedBrightness(100),
+    Feature::NotificationBeeps(true),
+];
+
+/// The values of each device with "do not disturb" on, from before it was
+/// turned on.
+static BEFORE: Mutex<BTreeMap<DeviceId, Vec<Feature>>> = Mutex::new(BTreeMap::new());
+
+pub fn set(device_id: DeviceId, enabled: bool) -> Result<(), ControlError> {
+    let device = hyperx::devices().get(device_id);
+    if !device.has(Capability::DoNotDisturb) {
+        return Err(ControlError::UnsupportedFeature {
+            device_id,
+            capability: Capability::DoNotDisturb,
+        });
+    }
+    let supported = |features: &[Feature]| -> Vec<Feature> {
+        features
+            .iter()
+            .copied()
+            .filter(|feature| device.supports(feature.kind()))
+            .collect()
+    };
+    let mut before = BEFORE.lock().unwrap();
+    if enabled {
+        // Already on: the values from before stay the ones to restore.
+        if before.contains_key(&device_id) {
+            return Ok(());
+        }
+        let previous = device.set_features(&supported(&QUIET))?;
+        before.insert(device_id, previous);
+    } else {
+        let restore = before
+            .remove(&device_id)
+            .unwrap_or_else(|| supported(&DEFAULTS));
+        if let Err(err) = device.set_features(&restore) {
+            before.insert(device_id, restore);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Whether "do not disturb" was turned on for the device since startup and
+/// not turned off again.
+pub fn is_enabled(device_id: DeviceId) -> bool {
+    BEFORE.lock().unwrap().contains_key(&device_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hyperx::{FeatureKind, Model};
+    use crate::mock::TestHeadset;
+
+    fn headset(unit: u8) -> (DeviceId, &'static TestHeadset) {
+        let device_id = DeviceId {
+            model: Model::SimulatedWireless,
+            unit,
+        };
+        let headset = TestHeadset::install(device_id);
+        headset.set(FeatureKind::VoicePrompts, 1);
+        headset.set(FeatureKind::LedBrightness, 80);
+        headset.set(FeatureKind::NotificationBeeps, 1);
+        (device_id, headset)
+    }
+
+    #[test]
+    fn failed_enable_leaves_it_off() {
+        let (device_id, headset) = headset(12);
+        headset.fail_writes(FeatureKind::LedBrightness);
+        assert!(set(device_id, true).is_err());
+        assert!(!is_enabled(device_id));
+        assert_eq!(headset.value(FeatureKind::VoicePrompts), 1);
+    }
+
+    #[test]
+    fn failed_disable_keeps_the_values_from_before() {
+        let (device_id, headset) = headset(13);
+        set(device_id, true).unwrap();
+        assert_eq!(headset.value(FeatureKind::LedBrightness), 0);
+        headset.fail_writes(FeatureKind::LedBrightness);
+        assert!(set(device_id, false).is_err());
+        assert!(is_enabled(device_id));
+        assert_eq!(
+            BEFORE.lock().unwrap()[&device_id],
+            [
+                Feature::VoicePrompts(true),
+                Feature::LedBrightness(80),
+                Feature::NotificationBeeps(true),
+            ]
+        );
+        assert_eq!(headset.value(FeatureKind::VoicePrompts), 0);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::hyperx::{BoomPosition, Button, ButtonPress, DeviceId, Feature};
+use serde::Serialize;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+/// Name of the Tauri event carrying [`DeviceEvent`]s to the frontend.
+pub const DEVICE_EVENT: &str = "device-event";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DeviceEvent {
+    Connected {
+        device_id: DeviceId,
+    },
+    Disconnected {
+        device_id: DeviceId,
+    },
+    /// The headset of a wireless model was switched on, or came back in
+    /// range, while its receiver stayed plugged in.
+    HeadsetPoweredOn {
+        device_id: DeviceId,
+    },
+    /// The headset was switched off or went out of range; its receiver is
+    /// still connected.
+    HeadsetPoweredOff {
+        device_id: DeviceId,
+    },
+    /// A new battery reading differs from the previous one.
+    BatteryChanged {
+        device_id: DeviceId,
+        percent: u8,
+    },
+    /// The charge dropped below [`crate::watcher::LOW_BATTERY_PERCENT`].
+    BatteryLow {
+        device_id: DeviceId,
+        percent: u8,
+    },
+    /// The headset microphone was muted or unmuted, by us or anyone else.
+    MicMuteChanged {
+        device_id: DeviceId,
+        muted: bool,
+    },
+    /// The microphone boom of a flip-to-mute headset was flipped up or down,
+    /// or was read for the first time after the headset connected.
+    BoomMoved {
+        device_id: DeviceId,
+        position: BoomPosition,
+    },
+    /// A button was pressed on a headset that reports its presses, whatever
+    /// the firmware does with it; see [`crate::macros`].
+    ButtonPressed {
+        device_id: DeviceId,
+        button: Button,
+        press: ButtonPress,
+    },
+    /// The volume dial of a headset that reports its turns was turned by
+    /// `steps`, positive for louder; see [`crate::media_keys`].
+    DialTurned {
+        device_id: DeviceId,
+        steps: i8,
+    },
+    /// A polled value differs from the previous poll, see
+    /// [`crate::state_poll`]. Serialised with the feature's own fields, e.g.
+    /// `{"type": "feature_changed", "device_id": "...", "feature": "sidetone", "value": true}`.
+    FeatureChanged {
+        device_id: DeviceId,
+        #[serde(flatten)]
+        feature: Feature,
+    },
+    /// The system woke from sleep; the watcher re-reports present devices.
+    Resumed,
+    /// The user's session was locked or switched away from (`active: false`),
+    /// or became the foreground session again.
+    SessionChanged {
+        active: bool,
+    },
+}
+
+/// Fan-out of device events to every interested background task.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Sender<DeviceEvent>>>,
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> Receiver<DeviceEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Deliver `event` to all live subscribers, forgetting the ones that hung up.
+    pub fn publish(&self, event: DeviceEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
@@ -0,0 +1,128 @@
+//! Named groups of devices, so one command changes all of them, e.g. muting
+//! every headset at once. Each member is changed and audited on its own; one
+//! failing does not stop the others, and the result says how each one went.
+
+use crate::audio;
+use crate::audit::{self, Source};
+use crate::hyperx::DeviceId;
+use crate::profiles::{self, DeviceSettings};
+use crate::settings::SettingsStore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceGroup {
+    pub name: String,
+    pub devices: Vec<DeviceId>,
+}
+
+/// What to apply to every member of a group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GroupChange {
+    Sidetone {
+        enabled: bool,
+    },
+    /// Mute or unmute the microphones at the OS audio server.
+    MicMute {
+        muted: bool,
+    },
+    /// The device settings of a saved profile, whichever device it was saved
+    /// for.
+    Profile {
+        name: String,
+    },
+}
+
+/// How the change went on one member.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceOutcome {
+    pub device_id: DeviceId,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum GroupError {
+    NotFound { name: String },
+    ProfileNotFound { name: String },
+}
+
+impl fmt::Display for GroupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupError::NotFound { name } => write!(f, "no device group named '{name}'"),
+            GroupError::ProfileNotFound { name } => write!(f, "no profile named '{name}'"),
+        }
+    }
+}
+
+impl std::error::Error for GroupError {}
+
+/// A [`GroupChange`] with its profile looked up.
+enum Write {
+    Settings(DeviceSettings),
+    MicMute(bool),
+}
+
+/// Apply `change` to every member of the group `name` on behalf of `source`.
+pub fn apply(
+    settings: &SettingsStore,
+    source: Source,
+    name: &str,
+    change: &GroupChange,
+) -> Result<Vec<DeviceOutcome>, GroupError> {
+    let current = settings.get();
+    let group = current
+        .groups
+        .iter()
+        .find(|group| group.name == name)
+        .ok_or_else(|| GroupError::NotFound {
+            name: name.to_string(),
+        })?;
+    let write = match change {
+        GroupChange::Sidetone { enabled } => Write::Settings(DeviceSettings {
+            sidetone: Some(*enabled),
+            ..DeviceSettings::default()
+        }),
+        GroupChange::MicMute { muted } => Write::MicMute(*muted),
+        GroupChange::Profile { name } => Write::Settings(
+            current
+                .profiles
+                .iter()
+                .find(|profile| &profile.name == name)
+                .map(|profile| profile.settings.clone())
+                .ok_or_else(|| GroupError::ProfileNotFound { name: name.clone() })?,
+        ),
+    };
+
+    let outcomes = group
+        .devices
+        .iter()
+        .map(|&device_id| {
+            let result = match &write {
+                Write::Settings(values) => audit::record(
+                    source,
+                    Some(device_id),
+                    "apply_device_settings",
+                    json!(values),
+                    profiles::apply(settings, device_id, values).map_err(|err| err.to_string()),
+                ),
+                Write::MicMute(muted) => audit::record(
+                    source,
+                    Some(device_id),
+                    "set_mic_mute",
+                    json!(muted),
+                    audio::set_mic_mute(device_id, *muted).map_err(|err| err.to_string()),
+                ),
+            };
+            DeviceOutcome {
+                device_id,
+                ok: result.is_ok(),
+                error: result.err(),
+            }
+        })
+        .collect();
+    Ok(outcomes)
+}
@@ -0,0 +1,117 @@
+//! Recent activity: the last [`CAPACITY`] device events and failed HID
+//! operations, kept in memory from startup so the window can show what
+//! happened while it was closed.
+
+use crate::events::{DeviceEvent, EventBus};
+use crate::hyperx::DeviceId;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const CAPACITY: usize = 500;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Activity {
+    Event {
+        event: DeviceEvent,
+    },
+    Error {
+        device_id: DeviceId,
+        operation: &'static str,
+        /// [`crate::hyperx::ControlError::kind`].
+        error: &'static str,
+        message: String,
+    },
+}
+
+impl Activity {
+    fn device_id(&self) -> Option<DeviceId> {
+        match self {
+            Activity::Event { event } => match *event {
+                DeviceEvent::Connected { device_id }
+                | DeviceEvent::Disconnected { device_id }
+                | DeviceEvent::BatteryChanged { device_id, .. }
+                | DeviceEvent::BatteryLow { device_id, .. }
+                | DeviceEvent::MicMuteChanged { device_id, .. }
+                | DeviceEvent::FeatureChanged { device_id, .. }
+                | DeviceEvent::BoomMoved { device_id, .. }
+                | DeviceEvent::ButtonPressed { device_id, .. }
+                | DeviceEvent::DialTurned { device_id, .. }
+                | DeviceEvent::HeadsetPoweredOn { device_id }
+                | DeviceEvent::HeadsetPoweredOff { device_id } => Some(device_id),
+                DeviceEvent::Resumed | DeviceEvent::SessionChanged { .. } => None,
+            },
+            Activity::Error { device_id, .. } => Some(*device_id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Entry {
+    /// Unix time in milliseconds.
+    pub time: u64,
+    #[serde(flatten)]
+    pub activity: Activity,
+}
+
+static HISTORY: Mutex<VecDeque<Entry>> = Mutex::new(VecDeque::new());
+
+fn push(activity: Activity) {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64);
+    let mut history = HISTORY.lock().unwrap();
+    if history.len() == CAPACITY {
+        history.pop_front();
+    }
+    history.push_back(Entry { time, activity });
+}
+
+/// Record every event published on `events` from now on.
+pub fn spawn(events: &EventBus) {
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        for event in receiver {
+            push(Activity::Event { event });
+        }
+    });
+}
+
+pub(crate) fn record_error(
+    device_id: DeviceId,
+    operation: &'static str,
+    error: &'static str,
+    message: String,
+) {
+    push(Activity::Error {
+        device_id,
+        operation,
+        error,
+        message,
+    });
+}
+
+/// Up to `limit` entries newer than `since` (Unix milliseconds), newest first.
+/// Events that concern no particular device (resume, session changes) are
+/// kept when filtering by device.
+pub fn query(device_id: Option<DeviceId>, since: Option<u64>, limit: usize) -> Vec<Entry> {
+    HISTORY
+        .lock()
+        .unwrap()
+        .iter()
+        .rev()
+        .take_while(|entry| since.is_none_or(|since| entry.time > since))
+        .filter(|entry| {
+            device_id.is_none()
+                || entry
+                    .activity
+                    .device_id()
+                    .is_none_or(|id| Some(id) == device_id)
+        })
+        .take(limit)
+        .cloned()
+        .collect()
+}
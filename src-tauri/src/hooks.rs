@@ -0,0 +1,158 @@
+//! User-defined shell commands run on device events. Event data is passed
+//! in environment variables:
+//! - `HYPERX_PILOT_EVENT`: the trigger type, e.g. `battery_below`
+//! - `HYPERX_PILOT_DEVICE`: the device id, e.g. `cloud_iii_wired`
+//! - `HYPERX_PILOT_BATTERY`: the charge in percent (battery triggers)
+//! - `HYPERX_PILOT_MUTED`: `1` or `0` (mute triggers)
+
+use crate::events::{DeviceEvent, EventBus};
+use crate::hyperx::DeviceId;
+use crate::settings::SettingsStore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use tracing::warn;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HookTrigger {
+    Connected,
+    Disconnected,
+    /// The charge fell below `percent`; fires once per discharge.
+    BatteryBelow {
+        percent: u8,
+    },
+    MicMuted,
+    MicUnmuted,
+}
+
+impl HookTrigger {
+    fn name(&self) -> &'static str {
+        match self {
+            HookTrigger::Connected => "connected",
+            HookTrigger::Disconnected => "disconnected",
+            HookTrigger::BatteryBelow { .. } => "battery_below",
+            HookTrigger::MicMuted => "mic_muted",
+            HookTrigger::MicUnmuted => "mic_unmuted",
+        }
+    }
+}
+
+/// A command and the event it runs on. Commands go through `sh -c`, or
+/// `cmd /C` on Windows, and are not waited for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hook {
+    pub on: HookTrigger,
+    pub command: String,
+}
+
+/// Run matching hooks for every event. Hooks are read from the settings on
+/// each event, so edits apply without a restart.
+pub fn spawn(settings: Arc<SettingsStore>, events: &EventBus) {
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        let mut last_battery: HashMap<DeviceId, u8> = HashMap::new();
+        for event in receiver {
+            let hooks = settings.get().hooks;
+            if hooks.is_empty() {
+                continue;
+            }
+            let (device_id, mut environment) = match event {
+                DeviceEvent::Connected { device_id } | DeviceEvent::Disconnected { device_id } => {
+                    (device_id, Vec::new())
+                }
+                DeviceEvent::BatteryChanged { device_id, percent } => (
+                    device_id,
+                    vec![("HYPERX_PILOT_BATTERY", percent.to_string())],
+                ),
+                DeviceEvent::MicMuteChanged { device_id, muted } => (
+                    device_id,
+                    vec![("HYPERX_PILOT_MUTED", u8::from(muted).to_string())],
+                ),
+                DeviceEvent::BatteryLow { .. }
+                | DeviceEvent::HeadsetPoweredOn { .. }
+                | DeviceEvent::HeadsetPoweredOff { .. }
+                | DeviceEvent::FeatureChanged { .. }
+                | DeviceEvent::BoomMoved { .. }
+                | DeviceEvent::ButtonPressed { .. }
+                | DeviceEvent::DialTurned { .. }
+                | DeviceEvent::Resumed
+                | DeviceEvent::SessionChanged { .. } => continue,
+            };
+            environment.push(("HYPERX_PILOT_DEVICE", device_id.to_string()));
+
+            for hook in &hooks {
+                let fires = match (&hook.on, &event) {
+                    (HookTrigger::Connected, DeviceEvent::Connected { .. })
+                    | (HookTrigger::Disconnected, DeviceEvent::Disconnected { .. }) => true,
+                    (
+                        HookTrigger::BatteryBelow { percent: threshold },
+                        DeviceEvent::BatteryChanged { percent, .. },
+                    ) => {
+                        percent < threshold
+                            && last_battery
+                                .get(&device_id)
+                                .is_none_or(|last| last >= threshold)
+                    }
+                    (HookTrigger::MicMuted, DeviceEvent::MicMuteChanged { muted, .. }) => *muted,
+                    (HookTrigger::MicUnmuted, DeviceEvent::MicMuteChanged { muted, .. }) => !*muted,
+                    _ => false,
+                };
+                if fires {
+                    let mut environment = environment.clone();
+                    environment.push(("HYPERX_PILOT_EVENT", hook.on.name().to_string()));
+                    run_command(&hook.command, &environment);
+                }
+            }
+
+            match event {
+                DeviceEvent::BatteryChanged { device_id, percent } => {
+                    last_battery.insert(device_id, percent);
+                }
+                DeviceEvent::Disconnected { device_id } => {
+                    last_battery.remove(&device_id);
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+/// Start `line` in the shell with `environment` added, without waiting for it.
+pub fn run_command(line: &str, environment: &[(&str, String)]) {
+    let mut command = shell(line);
+    command
+        .envs(environment.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null());
+    match command.spawn() {
+        // Reap the child so it does not linger as a zombie.
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(err) => warn!("failed to run '{line}': {err}"),
+    }
+}
+
+#[cfg(not(windows))]
+fn shell(line: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(line);
+    command
+}
+
+/// `cmd` parses its command line itself, so the line is passed verbatim; the
+/// console window it would open is suppressed.
+#[cfg(windows)]
+fn shell(line: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    let mut command = Command::new("cmd");
+    command
+        .arg("/C")
+        .raw_arg(line)
+        .creation_flags(CREATE_NO_WINDOW);
+    command
+}
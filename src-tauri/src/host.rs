@@ -0,0 +1,50 @@
+//! The app's side of [`hyperx::host::Host`]: open failures are diagnosed
+//! with the platform checks in [`diagnostics`], competing controllers come
+//! from [`conflicts`], battery readings go through [`calibration`] and HID
+//! traffic is counted in [`metrics`].
+
+use crate::hyperx::{self, ControlError, DeviceId, OpenDiagnosis};
+use crate::{calibration, conflicts, diagnostics, metrics};
+use hidapi::{HidApi, HidError};
+use std::time::Duration;
+
+struct AppHost;
+
+impl hyperx::host::Host for AppHost {
+    fn diagnose_open_failure(
+        &self,
+        api: &HidApi,
+        vendor_id: u16,
+        product_id: u16,
+        error: &HidError,
+    ) -> OpenDiagnosis {
+        diagnostics::diagnose_open_failure(api, vendor_id, product_id, error)
+    }
+
+    fn find_controller(&self, api: &HidApi, vendor_id: u16, product_id: u16) -> Option<String> {
+        conflicts::find_controller(api, vendor_id, product_id)
+    }
+
+    fn correct_battery(&self, device_id: DeviceId, percent: u8) -> u8 {
+        calibration::correct(device_id, percent)
+    }
+
+    fn operation_finished(
+        &self,
+        device_id: DeviceId,
+        operation: &'static str,
+        elapsed: Duration,
+        error: Option<&ControlError>,
+    ) {
+        metrics::observe_operation(device_id, operation, elapsed, error);
+    }
+
+    fn call_finished(&self, call: &'static str, elapsed: Duration, ok: bool) {
+        metrics::observe_call(call, elapsed, ok);
+    }
+}
+
+/// Install the app's host; call before the first HID operation.
+pub fn install() {
+    hyperx::host::install(&AppHost);
+}
@@ -0,0 +1,298 @@
+//! One running instance per user. The GUI and the headless daemon would
+//! otherwise both claim the D-Bus name, grab the global shortcuts, bind the
+//! API port and overwrite each other's settings. The first to start holds
+//! `instance.lock` in the config directory for as long as it runs, and
+//! answers later processes on a loopback port whose number and secret it
+//! writes to `instance.json` next to it:
+//!
+//! - a second window asks the running one to come to the front and exits;
+//! - a second `--headless` daemon, or a window while the daemon runs, exits
+//!   with an error;
+//! - the CLI makes its changes through the running instance, so they end up
+//!   in that instance's settings instead of a file it later overwrites.
+
+use crate::api;
+use crate::audit::{self, Source};
+use crate::hyperx::DeviceId;
+use crate::profiles::{self, DeviceSettings};
+use crate::settings::{self, SettingsStore};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+const LOCK_FILE: &str = "instance.lock";
+const INFO_FILE: &str = "instance.json";
+/// How long either side waits for the other; a request may write to a
+/// headset that is slow to answer.
+const TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REQUEST: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    Window,
+    Headless,
+}
+
+/// What `instance.json` tells other processes.
+#[derive(Serialize, Deserialize)]
+struct Info {
+    pid: u32,
+    mode: Mode,
+    port: u16,
+    token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    /// Bring the window to the front.
+    Show,
+    /// The CLI's `--sidetone`, kept in the instance's last-known settings.
+    SetSidetone { device_id: DeviceId, enabled: bool },
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    token: String,
+    request: Request,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Reply {
+    error: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum InstanceError {
+    /// Another process is the running instance.
+    Running {
+        pid: Option<u32>,
+        mode: Option<Mode>,
+    },
+    NotRunning,
+    Io(io::Error),
+    /// The running instance refused or failed the request.
+    Failed(String),
+}
+
+impl fmt::Display for InstanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstanceError::Running { pid, mode } => {
+                let what = match mode {
+                    Some(Mode::Headless) => "in the background",
+                    _ => "already",
+                };
+                write!(f, "HyperX Pilot runs {what}")?;
+                if let Some(pid) = pid {
+                    write!(f, " (process {pid})")?;
+                }
+                Ok(())
+            }
+            InstanceError::NotRunning => write!(f, "HyperX Pilot is not running"),
+            InstanceError::Io(err) => write!(f, "cannot reach the running HyperX Pilot: {err}"),
+            InstanceError::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for InstanceError {}
+
+impl From<io::Error> for InstanceError {
+    fn from(err: io::Error) -> Self {
+        InstanceError::Io(err)
+    }
+}
+
+/// The lock of the running instance, held until the process exits.
+pub struct Instance {
+    _lock: File,
+    listener: TcpListener,
+    token: String,
+}
+
+/// Take the lock on `config_dir`'s settings without becoming the running
+/// instance, e.g. while the CLI writes them; fails with
+/// [`InstanceError::Running`] when another process holds it.
+pub fn lock(config_dir: &Path) -> Result<File, InstanceError> {
+    fs::create_dir_all(config_dir)?;
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(config_dir.join(LOCK_FILE))?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => {
+            let info = read_info(config_dir);
+            Err(InstanceError::Running {
+                pid: info.as_ref().map(|info| info.pid),
+                mode: info.map(|info| info.mode),
+            })
+        }
+        Err(TryLockError::Error(err)) => Err(err.into()),
+    }
+}
+
+fn read_info(config_dir: &Path) -> Option<Info> {
+    let contents = fs::read(config_dir.join(INFO_FILE)).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+/// Become the running instance for `config_dir`.
+pub fn acquire(config_dir: &Path, mode: Mode) -> Result<Instance, InstanceError> {
+    let lock = lock(config_dir)?;
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let token = api::generate_token().map_err(|err| io::Error::other(err.to_string()))?;
+    let info = Info {
+        pid: process::id(),
+        mode,
+        port: listener.local_addr()?.port(),
+        token: token.clone(),
+    };
+    let encoded = serde_json::to_vec(&info).map_err(io::Error::other)?;
+    settings::write_private(&config_dir.join(INFO_FILE), &encoded)?;
+    Ok(Instance {
+        _lock: lock,
+        listener,
+        token,
+    })
+}
+
+/// Have the running instance for `config_dir` carry out `request`.
+pub fn send(config_dir: &Path, request: Request) -> Result<(), InstanceError> {
+    // A free lock means `instance.json` is left over from an instance that
+    // exited.
+    drop(lock(config_dir).err().ok_or(InstanceError::NotRunning)?);
+    let info = read_info(config_dir)
+        .ok_or_else(|| InstanceError::Failed("the running instance is still starting".into()))?;
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, info.port));
+    let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let envelope = Envelope {
+        token: info.token,
+        request,
+    };
+    let mut line = serde_json::to_vec(&envelope).map_err(io::Error::other)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    let reply: Reply = serde_json::from_str(&reply).map_err(io::Error::other)?;
+    match reply.error {
+        None => Ok(()),
+        Some(message) => Err(InstanceError::Failed(message)),
+    }
+}
+
+impl Instance {
+    /// Answer requests of other processes until the process exits. `show`
+    /// brings the window to the front, or says why there is none.
+    pub fn serve(
+        self,
+        settings: Arc<SettingsStore>,
+        show: impl Fn() -> Result<(), String> + Send + 'static,
+    ) {
+        thread::spawn(move || {
+            for stream in self.listener.incoming().flatten() {
+                if let Err(err) = self.answer(&settings, &show, stream) {
+                    warn!("request from another HyperX Pilot process failed: {err}");
+                }
+            }
+        });
+    }
+
+    fn answer(
+        &self,
+        settings: &SettingsStore,
+        show: &impl Fn() -> Result<(), String>,
+        mut stream: TcpStream,
+    ) -> io::Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut line = String::new();
+        BufReader::new((&stream).take(MAX_REQUEST)).read_line(&mut line)?;
+        let result = match serde_json::from_str::<Envelope>(&line) {
+            Ok(envelope) if envelope.token == self.token => run(settings, show, envelope.request),
+            Ok(_) => Err("wrong instance token".to_string()),
+            Err(err) => Err(format!("invalid request: {err}")),
+        };
+        let reply = Reply {
+            error: result.err(),
+        };
+        let mut line = serde_json::to_vec(&reply).map_err(io::Error::other)?;
+        line.push(b'\n');
+        stream.write_all(&line)
+    }
+}
+
+fn run(
+    settings: &SettingsStore,
+    show: &impl Fn() -> Result<(), String>,
+    request: Request,
+) -> Result<(), String> {
+    match request {
+        Request::Show => show(),
+        Request::SetSidetone { device_id, enabled } => {
+            let values = DeviceSettings {
+                sidetone: Some(enabled),
+                ..DeviceSettings::default()
+            };
+            audit::record(
+                Source::Cli,
+                Some(device_id),
+                "set_sidetone",
+                json!(enabled),
+                profiles::apply(settings, device_id, &values),
+            )
+            .map_err(|err| err.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("hyperx-pilot-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn second_instance_is_refused_and_reaches_the_first() {
+        let dir = config_dir("instance");
+        assert!(matches!(
+            send(&dir, Request::Show),
+            Err(InstanceError::NotRunning)
+        ));
+
+        let instance = acquire(&dir, Mode::Window).unwrap();
+        assert!(matches!(
+            acquire(&dir, Mode::Headless),
+            Err(InstanceError::Running {
+                mode: Some(Mode::Window),
+                ..
+            })
+        ));
+        let settings = Arc::new(SettingsStore::load(dir.join("settings.json")));
+        instance.serve(settings, || Err("no window".into()));
+        match send(&dir, Request::Show) {
+            Err(InstanceError::Failed(message)) => assert_eq!(message, "no window"),
+            other => panic!("unexpected reply {other:?}"),
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
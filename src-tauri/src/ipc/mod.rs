@@ -0,0 +1,79 @@
+//! Version of the interface the frontend and REST API clients talk to. One
+//! number covers the payloads of every command, event and route: it is bumped
+//! whenever one changes incompatibly (a field removed, renamed or retyped, a
+//! command dropped), not for additions older clients can ignore. A client
+//! states the version it was written against in a handshake and, when the
+//! backend no longer speaks it, gets a [`VersionMismatch`] it can show instead
+//! of failing on the first payload it cannot parse.
+//!
+//! The command payloads of the current version are the structs in [`v2`].
+//! Only the handshake takes its `client_version` as a plain argument, so
+//! clients of every version can make it.
+
+pub mod v2;
+
+use serde::Serialize;
+use std::fmt;
+
+/// Version 2 passes every command its arguments as one `payload`.
+pub const API_VERSION: u32 = 2;
+
+/// Oldest client version the backend still answers correctly.
+pub const MIN_CLIENT_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiInfo {
+    pub api_version: u32,
+    pub min_client_version: u32,
+    pub app_version: &'static str,
+}
+
+pub fn info() -> ApiInfo {
+    ApiInfo {
+        api_version: API_VERSION,
+        min_client_version: MIN_CLIENT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION"),
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename = "version_mismatch")]
+pub struct VersionMismatch {
+    pub client_version: u32,
+    pub api_version: u32,
+    pub min_client_version: u32,
+    pub message: String,
+}
+
+impl fmt::Display for VersionMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for VersionMismatch {}
+
+/// [`info`] when a client written against `client_version` is served
+/// correctly.
+pub fn check(client_version: u32) -> Result<ApiInfo, VersionMismatch> {
+    if (MIN_CLIENT_VERSION..=API_VERSION).contains(&client_version) {
+        return Ok(info());
+    }
+    let message = if client_version > API_VERSION {
+        format!(
+            "the client needs API version {client_version}, but this backend only speaks \
+             up to {API_VERSION}; update HyperX Pilot"
+        )
+    } else {
+        format!(
+            "API version {client_version} is no longer supported (oldest: \
+             {MIN_CLIENT_VERSION}); update the client"
+        )
+    };
+    Err(VersionMismatch {
+        client_version,
+        api_version: API_VERSION,
+        min_client_version: MIN_CLIENT_VERSION,
+        message,
+    })
+}
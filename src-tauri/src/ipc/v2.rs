@@ -0,0 +1,232 @@
+//! Command payloads of API version 2. Every command that takes arguments
+//! takes one of these as its `payload`, e.g.
+//! `invoke("set_sidetone", { payload: { device_id, enabled } })`; missing
+//! `Option` fields are `null`. Changing a field here incompatibly means a new
+//! module and a bump of [`API_VERSION`](super::API_VERSION); adding one does
+//! not.
+
+use crate::audio::Endpoints;
+use crate::groups::{DeviceGroup, GroupChange};
+use crate::hyperx::{
+    Button, ButtonAction, DeviceId, Feature, FeatureKind, FirmwareTarget, FirmwareVersion,
+    NoiseMode, WirelessMode,
+};
+use crate::operations::OperationId;
+use crate::profiles::Profile;
+use crate::settings::Settings;
+use serde::Deserialize;
+
+/// Commands that only need to know the device.
+#[derive(Debug, Deserialize)]
+pub struct Device {
+    pub device_id: DeviceId,
+}
+
+/// Turn a setting of the device on or off.
+#[derive(Debug, Deserialize)]
+pub struct Toggle {
+    pub device_id: DeviceId,
+    pub enabled: bool,
+}
+
+/// A setting of the device in percent.
+#[derive(Debug, Deserialize)]
+pub struct Percent {
+    pub device_id: DeviceId,
+    pub percent: u8,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SoftwareSidetone {
+    pub device_id: DeviceId,
+    pub enabled: bool,
+    pub gain: f32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AudioAssociation {
+    pub device_id: DeviceId,
+    pub endpoints: Option<Endpoints>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateSettings {
+    pub settings: Settings,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveProfile {
+    pub profile: Profile,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveGroup {
+    pub group: DeviceGroup,
+}
+
+/// A profile or group.
+#[derive(Debug, Deserialize)]
+pub struct Named {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApplyToGroup {
+    pub name: String,
+    pub change: GroupChange,
+}
+
+/// One firmware of the device; the headset's when `target` is missing.
+#[derive(Debug, Deserialize)]
+pub struct Firmware {
+    pub device_id: DeviceId,
+    pub target: Option<FirmwareTarget>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FlashFirmware {
+    pub device_id: DeviceId,
+    pub target: Option<FirmwareTarget>,
+    pub version: FirmwareVersion,
+    pub allow_downgrade: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PinFirmware {
+    pub device_id: DeviceId,
+    pub target: Option<FirmwareTarget>,
+    pub version: Option<FirmwareVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FactoryReset {
+    pub device_id: DeviceId,
+    pub confirm: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetLocale {
+    pub locale: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetFeature {
+    pub device_id: DeviceId,
+    pub kind: FeatureKind,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetFeature {
+    pub device_id: DeviceId,
+    pub feature: Feature,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetWirelessMode {
+    pub device_id: DeviceId,
+    pub mode: WirelessMode,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetOutputMute {
+    pub device_id: DeviceId,
+    pub muted: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetAncLevel {
+    pub device_id: DeviceId,
+    pub level: u8,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetNoiseMode {
+    pub device_id: DeviceId,
+    pub mode: NoiseMode,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ButtonActions {
+    pub device_id: DeviceId,
+    pub button: Button,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetButtonAction {
+    pub device_id: DeviceId,
+    pub button: Button,
+    pub action: ButtonAction,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveBluetoothPairing {
+    pub device_id: DeviceId,
+    pub slot: u8,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CancelOperation {
+    pub operation_id: OperationId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportDiagnostics {
+    pub include_capture: bool,
+}
+
+/// `data` is the report as hex.
+#[derive(Debug, Deserialize)]
+pub struct SendRawFeatureReport {
+    pub device_id: DeviceId,
+    pub data: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetRawFeatureReport {
+    pub device_id: DeviceId,
+    pub report_id: u8,
+    pub length: Option<usize>,
+}
+
+/// Events of one device, or of all when `device_id` is missing.
+#[derive(Debug, Deserialize)]
+pub struct EventHistory {
+    pub device_id: Option<DeviceId>,
+    pub since: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TailLog {
+    pub lines: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetLogFilter {
+    pub filter: String,
+}
+
+/// `preview` is the body `preview_device_report` returned, as shown to the
+/// user.
+#[derive(Debug, Deserialize)]
+pub struct SubmitDeviceReport {
+    pub preview: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetDryRun {
+    pub enabled: bool,
+}
+
+/// Entries of one device, or of all when `device_id` is missing.
+#[derive(Debug, Deserialize)]
+pub struct AuditLog {
+    pub device_id: Option<DeviceId>,
+    pub limit: Option<usize>,
+}
+
+#[cfg(windows)]
+#[derive(Debug, Deserialize)]
+pub struct InstallStartupTask {
+    pub delay_secs: Option<u32>,
+}
@@ -1,34 +1,1543 @@
-mod hyperx;
+mod actions;
+mod api;
+mod audio;
+mod audit;
+mod calibration;
+mod cli;
+mod conflicts;
+mod crash;
+mod curl;
+mod daemon;
+mod device_info;
+mod diagnostics;
+mod discord;
+mod dnd;
+mod events;
+mod groups;
+mod history;
+mod hooks;
+mod host;
+mod instance;
+mod ipc;
+mod logging;
+mod macros;
+mod media_keys;
+mod metrics;
+mod mqtt;
+mod obs;
+mod operations;
+mod pairing;
+mod platform;
+mod power;
+mod profiles;
+mod raw_reports;
+mod replay;
+mod rules;
+mod services;
+mod session;
+mod settings;
+mod simulator;
+mod state_poll;
+mod support_bundle;
+mod telemetry;
+#[cfg(test)]
+mod tests;
+mod tray;
+mod updater;
+mod watcher;
+mod websocket;
+
+/// Parsers and builders exposed to the fuzz targets in `fuzz/`.
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing {
+    pub use crate::hyperx::fuzzing::*;
+    pub use crate::report_descriptor::feature_lengths;
+}
+
+use hyperx_control as hyperx;
+use hyperx_control::{mock, recorder, report_descriptor};
+
+use audio::{AudioEndpoint, Endpoints, SoftwareSidetone};
+use audit::Source;
+use events::EventBus;
+use groups::DeviceOutcome;
+use ipc::v2;
+use operations::OperationKind;
+use profiles::DeviceSettings;
+use serde_json::json;
+use services::Services;
+use settings::{FirmwarePin, Settings, SettingsStore};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::env;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use tauri::{AppHandle, Emitter, Manager, RunEvent, State};
 
 use hyperx::{
-    read_sidetone_state as hyperx_read_sidetone_state, set_sidetone as hyperx_set_sidetone,
-    DeviceId, DeviceMetadata,
+    BoomPosition, Button, ButtonAction, ControlError, DeviceMetadata, Feature, FeatureKind,
+    HidBackend, NoiseMode, RemappableButton, WirelessMode,
 };
 
+/// The supported models plus every connected unit. Like every command that
+/// talks to the devices, it runs off the main thread: the first one creates
+/// the HID context and enumerates, which must not hold up the window.
+#[tauri::command]
+async fn list_hyperx_devices() -> Vec<DeviceMetadata> {
+    hyperx::list_devices()
+}
+
+/// Enumerate HID devices again, e.g. after a `device-event` about a hotplug,
+/// and return the updated device list.
+#[tauri::command]
+async fn refresh_devices() -> Result<Vec<DeviceMetadata>, ControlError> {
+    hyperx::refresh_devices()?;
+    Ok(hyperx::list_devices())
+}
+
+#[tauri::command]
+async fn set_sidetone(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::Toggle,
+) -> Result<(), ControlError> {
+    let v2::Toggle { device_id, enabled } = payload;
+    let values = DeviceSettings {
+        sidetone: Some(enabled),
+        ..DeviceSettings::default()
+    };
+    let result = profiles::apply(&store, device_id, &values);
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_sidetone",
+        json!(enabled),
+        result,
+    )
+}
+
+#[tauri::command]
+async fn get_sidetone_state(payload: v2::Device) -> Result<Option<bool>, ControlError> {
+    hyperx::devices()
+        .get(payload.device_id)
+        .read_sidetone_state()
+}
+
+#[tauri::command]
+fn has_hardware_sidetone(payload: v2::Device) -> bool {
+    hyperx::devices()
+        .get(payload.device_id)
+        .has_hardware_sidetone()
+}
+
+#[tauri::command]
+async fn set_software_sidetone(
+    sidetone: State<'_, Arc<SoftwareSidetone>>,
+    payload: v2::SoftwareSidetone,
+) -> Result<(), String> {
+    let v2::SoftwareSidetone {
+        device_id,
+        enabled,
+        gain,
+    } = payload;
+    let result = if enabled {
+        sidetone.enable(device_id, gain)
+    } else {
+        sidetone.disable(device_id)
+    };
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_software_sidetone",
+        json!({ "enabled": enabled, "gain": gain }),
+        result,
+    )
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn get_software_sidetone(sidetone: State<'_, Arc<SoftwareSidetone>>, payload: v2::Device) -> bool {
+    sidetone.is_active(payload.device_id)
+}
+
+#[tauri::command]
+async fn list_audio_endpoints() -> Result<Vec<AudioEndpoint>, String> {
+    audio::list_endpoints().map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn get_audio_association(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::Device,
+) -> Result<Endpoints, String> {
+    audio::device_endpoints(&store, payload.device_id).map_err(|err| err.to_string())
+}
+
+/// Associate endpoints with a device; `None` forgets the association so the
+/// endpoints are detected again.
+#[tauri::command]
+fn set_audio_association(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::AudioAssociation,
+) -> Result<(), String> {
+    let v2::AudioAssociation {
+        device_id,
+        endpoints,
+    } = payload;
+    let value = json!(endpoints);
+    let result = store.update(|settings| match endpoints {
+        Some(endpoints) => {
+            settings.audio.endpoints.insert(device_id, endpoints);
+        }
+        None => {
+            settings.audio.endpoints.remove(&device_id);
+        }
+    });
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_audio_association",
+        value,
+        result,
+    )
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn get_settings(store: State<'_, Arc<SettingsStore>>) -> Settings {
+    store.get()
+}
+
+#[tauri::command]
+fn update_settings(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::UpdateSettings,
+) -> Result<(), String> {
+    // Settings hold tokens and passwords, so only the change is logged.
+    let result = store.replace(payload.settings);
+    calibration::load(&store);
+    audit::record(Source::App, None, "update_settings", json!(null), result)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn save_profile(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::SaveProfile,
+) -> Result<(), String> {
+    let v2::SaveProfile { profile } = payload;
+    let value = json!(profile);
+    let device_id = profile.device_id;
+    let result = store.update(|settings| {
+        settings
+            .profiles
+            .retain(|existing| existing.name != profile.name);
+        settings.profiles.push(profile);
+    });
+    audit::record(Source::App, Some(device_id), "save_profile", value, result)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn delete_profile(store: State<'_, Arc<SettingsStore>>, payload: v2::Named) -> Result<(), String> {
+    let v2::Named { name } = payload;
+    let result = store.update(|settings| settings.profiles.retain(|profile| profile.name != name));
+    audit::record(Source::App, None, "delete_profile", json!(name), result)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn apply_profile(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::Named,
+) -> Result<(), String> {
+    let v2::Named { name } = payload;
+    let result = profiles::apply_named(&store, &name);
+    audit::record(Source::App, None, "apply_profile", json!(name), result)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn save_group(store: State<'_, Arc<SettingsStore>>, payload: v2::SaveGroup) -> Result<(), String> {
+    let v2::SaveGroup { group } = payload;
+    let value = json!(group);
+    let result = store.update(|settings| {
+        settings
+            .groups
+            .retain(|existing| existing.name != group.name);
+        settings.groups.push(group);
+    });
+    audit::record(Source::App, None, "save_group", value, result).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn delete_group(store: State<'_, Arc<SettingsStore>>, payload: v2::Named) -> Result<(), String> {
+    let v2::Named { name } = payload;
+    let result = store.update(|settings| settings.groups.retain(|group| group.name != name));
+    audit::record(Source::App, None, "delete_group", json!(name), result)
+        .map_err(|err| err.to_string())
+}
+
+/// Each member is audited on its own.
+#[tauri::command]
+async fn apply_to_group(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::ApplyToGroup,
+) -> Result<Vec<DeviceOutcome>, String> {
+    let v2::ApplyToGroup { name, change } = payload;
+    groups::apply(&store, Source::App, &name, &change).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn get_hid_backend() -> HidBackend {
+    hyperx::hid_backend()
+}
+
+/// HID interfaces, strings and report descriptors of a device, for support requests.
+#[tauri::command]
+async fn dump_device_info(
+    payload: v2::Device,
+) -> Result<device_info::DeviceInfoDump, ControlError> {
+    device_info::dump_device_info(payload.device_id)
+}
+
+/// Manufacturer, product, serial number, board revision and manufacturing
+/// date, as far as the model reports them, for the device info panel.
+#[tauri::command]
+async fn get_hardware_info(payload: v2::Device) -> Result<hyperx::HardwareInfo, ControlError> {
+    hyperx::devices()
+        .get(payload.device_id)
+        .read_hardware_info()
+}
+
+/// The firmware version as `1.07`; `null` when the device does not report one.
+/// `target` picks the headset (default) or its receiver.
+#[tauri::command]
+async fn get_firmware_version(
+    payload: v2::Firmware,
+) -> Result<Option<hyperx::FirmwareVersion>, ControlError> {
+    let v2::Firmware { device_id, target } = payload;
+    hyperx::devices()
+        .get(device_id)
+        .read_firmware_version(target.unwrap_or_default())
+}
+
+/// Restore every on-device setting to its factory default, on models that
+/// support it. Refused unless `confirm` is set, since it cannot be undone.
+/// The device's last-known settings are forgotten too, so they are not
+/// restored on the next connect.
+#[tauri::command]
+async fn factory_reset(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::FactoryReset,
+) -> Result<(), String> {
+    let v2::FactoryReset { device_id, confirm } = payload;
+    if !confirm {
+        return Err("a factory reset cannot be undone; pass confirm to reset".to_string());
+    }
+    let result = hyperx::devices()
+        .get(device_id)
+        .factory_reset()
+        .map_err(|err| err.to_string());
+    let result = result.and_then(|()| {
+        store
+            .update(|settings| {
+                settings.last_known.remove(&device_id);
+            })
+            .map_err(|err| err.to_string())
+    });
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "factory_reset",
+        json!(null),
+        result,
+    )
+}
+
+#[tauri::command]
+fn has_identify(payload: v2::Device) -> bool {
+    hyperx::devices().get(payload.device_id).has_identify()
+}
+
+/// Blink the device or play its pairing tone, to tell which list entry is
+/// which headset.
+#[tauri::command]
+async fn identify(payload: v2::Device) -> Result<(), ControlError> {
+    hyperx::devices().get(payload.device_id).identify()
+}
+
+/// What the app last wrote to or read from the device, without asking it
+/// again.
+#[tauri::command]
+fn get_device_state(payload: v2::Device) -> hyperx::DeviceState {
+    hyperx::devices().get(payload.device_id).state()
+}
+
+/// Check that the frontend, written against API `client_version`, can talk
+/// to this backend; call before any other command.
+#[tauri::command]
+fn handshake(client_version: u32) -> Result<ipc::ApiInfo, ipc::VersionMismatch> {
+    ipc::check(client_version)
+}
+
+/// Language of the messages in errors returned from now on, e.g. `"de"`.
+#[tauri::command]
+fn set_locale(payload: v2::SetLocale) -> Result<(), String> {
+    let v2::SetLocale { locale } = payload;
+    if hyperx::i18n::set_locale(&locale) {
+        Ok(())
+    } else {
+        Err(format!(
+            "no translations for '{locale}'; available: {}",
+            hyperx::i18n::locales().join(", ")
+        ))
+    }
+}
+
+/// What the device can do, so the frontend shows only the controls that work.
 #[tauri::command]
-fn list_hyperx_devices() -> Vec<DeviceMetadata> {
-    hyperx::supported_devices().to_vec()
+fn get_capabilities(payload: v2::Device) -> Vec<hyperx::Capability> {
+    hyperx::devices().get(payload.device_id).capabilities()
 }
 
+/// The features `get_feature` and `set_feature` work with on this device.
 #[tauri::command]
-fn set_sidetone(device_id: DeviceId, enabled: bool) -> Result<(), String> {
-    hyperx_set_sidetone(device_id, enabled).map_err(|err| err.to_string())
+fn supported_features(payload: v2::Device) -> Vec<FeatureKind> {
+    hyperx::devices()
+        .get(payload.device_id)
+        .supported_features()
 }
 
+/// Read any feature, e.g. `"volume"`, instead of through its own command.
 #[tauri::command]
-fn get_sidetone_state(device_id: DeviceId) -> Result<Option<bool>, String> {
-    hyperx_read_sidetone_state(device_id).map_err(|err| err.to_string())
+async fn get_feature(payload: v2::GetFeature) -> Result<Option<Feature>, ControlError> {
+    let v2::GetFeature { device_id, kind } = payload;
+    hyperx::devices().get(device_id).get_feature(kind)
+}
+
+/// Write any feature, e.g. `{"feature": "volume", "value": 40}`. Sidetone and
+/// game mode are remembered as through their own commands.
+#[tauri::command]
+async fn set_feature(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::SetFeature,
+) -> Result<(), ControlError> {
+    let v2::SetFeature { device_id, feature } = payload;
+    let remembered = match feature {
+        Feature::Sidetone(enabled) => Some(DeviceSettings {
+            sidetone: Some(enabled),
+            ..DeviceSettings::default()
+        }),
+        Feature::GameMode(enabled) => Some(DeviceSettings {
+            game_mode: Some(enabled),
+            ..DeviceSettings::default()
+        }),
+        _ => None,
+    };
+    let result = match remembered {
+        Some(values) => profiles::apply(&store, device_id, &values),
+        None => hyperx::devices().get(device_id).set_feature(feature),
+    };
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_feature",
+        json!(feature),
+        result,
+    )
+}
+
+/// Read every advertised feature of a device and time it, for support triage.
+#[tauri::command]
+async fn run_self_test(payload: v2::Device) -> Result<hyperx::SelfTestReport, ControlError> {
+    let v2::Device { device_id } = payload;
+    let operation = operations::begin(OperationKind::SelfTest, device_id);
+    hyperx::devices()
+        .get(device_id)
+        .run_self_test(|| operation.token().is_cancelled())
+}
+
+/// The anonymous device report exactly as `submit_device_report` would send it.
+#[tauri::command]
+async fn preview_device_report() -> Result<String, String> {
+    telemetry::preview().map_err(|err| err.to_string())
+}
+
+/// Send the previewed device report; needs `telemetry.enabled`. Fails
+/// when the report is no longer the one previewed. Returns the body sent.
+#[tauri::command]
+async fn submit_device_report(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::SubmitDeviceReport,
+) -> Result<String, String> {
+    let v2::SubmitDeviceReport { preview } = payload;
+    telemetry::submit(&store, preview).map_err(|err| err.to_string())
+}
+
+/// Whether newer firmware is published for the device and, on wireless
+/// models, its receiver; one entry each. Does not flash anything.
+#[tauri::command]
+async fn check_firmware_update(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::Device,
+) -> Result<Vec<updater::UpdateCheck>, String> {
+    updater::check(&store, payload.device_id).map_err(|err| err.to_string())
+}
+
+/// Flash firmware `version` from the manifest to the headset (default) or its
+/// receiver, emitting a `firmware-progress` event after every step.
+#[tauri::command]
+async fn flash_firmware(
+    app: AppHandle,
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::FlashFirmware,
+) -> Result<(), String> {
+    let v2::FlashFirmware {
+        device_id,
+        target,
+        version,
+        allow_downgrade,
+    } = payload;
+    let target = target.unwrap_or_default();
+    let allow_downgrade = allow_downgrade.unwrap_or(false);
+    let config_dir = app.path().app_config_dir().map_err(|err| err.to_string())?;
+    let result = updater::dfu::flash(
+        &store,
+        &config_dir,
+        device_id,
+        target,
+        version,
+        allow_downgrade,
+        |progress| {
+            let _ = app.emit(operations::PROGRESS_EVENT, progress.summary());
+            let _ = app.emit(updater::dfu::PROGRESS_EVENT, progress);
+        },
+    );
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "flash_firmware",
+        json!({ "target": target, "version": version, "allow_downgrade": allow_downgrade }),
+        result,
+    )
+    .map_err(|err| err.to_string())
+}
+
+/// Save the installed firmware image, where the bootloader can read it back;
+/// returns the file's path.
+#[tauri::command]
+async fn backup_firmware(app: AppHandle, payload: v2::Firmware) -> Result<String, String> {
+    let v2::Firmware { device_id, target } = payload;
+    let config_dir = app.path().app_config_dir().map_err(|err| err.to_string())?;
+    let path = updater::backup::backup(&config_dir, device_id, target.unwrap_or_default())
+        .map_err(|err| err.to_string())?;
+    Ok(path.display().to_string())
+}
+
+/// Keep firmware `version` on the headset (default) or its receiver, so
+/// `check_firmware_update` stops offering newer ones; `None` unpins.
+#[tauri::command]
+fn pin_firmware(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::PinFirmware,
+) -> Result<(), String> {
+    let v2::PinFirmware {
+        device_id,
+        target,
+        version,
+    } = payload;
+    let target = target.unwrap_or_default();
+    let result = store.update(|settings| {
+        let pinned = &mut settings.firmware.pinned;
+        pinned.retain(|pin| pin.device_id != device_id || pin.target != target);
+        if let Some(version) = version {
+            pinned.push(FirmwarePin {
+                device_id,
+                target,
+                version,
+            });
+        }
+    });
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "pin_firmware",
+        json!({ "target": target, "version": version }),
+        result,
+    )
+    .map_err(|err| err.to_string())
+}
+
+/// Pair a (replacement) USB receiver with its headset, emitting a
+/// `pairing-progress` event after every step with what the user should do.
+#[tauri::command]
+async fn pair_receiver(app: AppHandle, payload: v2::Device) -> Result<(), String> {
+    let v2::Device { device_id } = payload;
+    let operation = operations::begin(OperationKind::Pairing, device_id);
+    let result = pairing::pair(device_id, pairing::TIMEOUT, &operation, |progress| {
+        let _ = app.emit(operations::PROGRESS_EVENT, progress.summary());
+        let _ = app.emit(pairing::PROGRESS_EVENT, progress);
+    });
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "pair_receiver",
+        json!(null),
+        result,
+    )
+    .map_err(|err| err.to_string())
+}
+
+/// Whether to offer the receiver/Bluetooth switch.
+#[tauri::command]
+fn has_wireless_modes(payload: v2::Device) -> bool {
+    hyperx::devices()
+        .get(payload.device_id)
+        .has_wireless_modes()
+}
+
+#[tauri::command]
+async fn get_wireless_mode(payload: v2::Device) -> Result<Option<WirelessMode>, ControlError> {
+    hyperx::devices()
+        .get(payload.device_id)
+        .read_wireless_mode()
+}
+
+#[tauri::command]
+async fn set_wireless_mode(payload: v2::SetWirelessMode) -> Result<(), ControlError> {
+    let v2::SetWirelessMode { device_id, mode } = payload;
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_wireless_mode",
+        json!(mode),
+        hyperx::devices().get(device_id).set_wireless_mode(mode),
+    )
+}
+
+#[tauri::command]
+fn has_game_mode(payload: v2::Device) -> bool {
+    hyperx::devices().get(payload.device_id).has_game_mode()
+}
+
+#[tauri::command]
+async fn get_game_mode(payload: v2::Device) -> Result<Option<bool>, ControlError> {
+    hyperx::devices().get(payload.device_id).read_game_mode()
+}
+
+/// Remembered like sidetone, so it is restored on reconnect.
+#[tauri::command]
+async fn set_game_mode(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::Toggle,
+) -> Result<(), ControlError> {
+    let v2::Toggle { device_id, enabled } = payload;
+    let values = DeviceSettings {
+        game_mode: Some(enabled),
+        ..DeviceSettings::default()
+    };
+    let result = profiles::apply(&store, device_id, &values);
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_game_mode",
+        json!(enabled),
+        result,
+    )
+}
+
+#[tauri::command]
+fn has_multipoint(payload: v2::Device) -> bool {
+    hyperx::devices().get(payload.device_id).has_multipoint()
+}
+
+#[tauri::command]
+async fn get_multipoint(payload: v2::Device) -> Result<Option<bool>, ControlError> {
+    hyperx::devices().get(payload.device_id).read_multipoint()
+}
+
+#[tauri::command]
+async fn set_multipoint(payload: v2::Toggle) -> Result<(), ControlError> {
+    let v2::Toggle { device_id, enabled } = payload;
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_multipoint",
+        json!(enabled),
+        hyperx::devices().get(device_id).set_multipoint(enabled),
+    )
+}
+
+#[tauri::command]
+fn has_output_mute(payload: v2::Device) -> bool {
+    hyperx::devices().get(payload.device_id).has_output_mute()
+}
+
+/// Whether the headphones are muted at the headset, which the OS does not
+/// know about.
+#[tauri::command]
+async fn get_output_mute(payload: v2::Device) -> Result<Option<bool>, ControlError> {
+    hyperx::devices().get(payload.device_id).read_output_mute()
+}
+
+#[tauri::command]
+async fn set_output_mute(payload: v2::SetOutputMute) -> Result<(), ControlError> {
+    let v2::SetOutputMute { device_id, muted } = payload;
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_output_mute",
+        json!(muted),
+        hyperx::devices().get(device_id).set_output_mute(muted),
+    )
+}
+
+#[tauri::command]
+async fn get_voice_prompts(payload: v2::Device) -> Result<Option<bool>, ControlError> {
+    hyperx::devices()
+        .get(payload.device_id)
+        .read_voice_prompts()
+}
+
+#[tauri::command]
+async fn set_voice_prompts(payload: v2::Toggle) -> Result<(), ControlError> {
+    let v2::Toggle { device_id, enabled } = payload;
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_voice_prompts",
+        json!(enabled),
+        hyperx::devices().get(device_id).set_voice_prompts(enabled),
+    )
+}
+
+#[tauri::command]
+async fn get_led_brightness(payload: v2::Device) -> Result<Option<u8>, ControlError> {
+    hyperx::devices()
+        .get(payload.device_id)
+        .read_led_brightness()
+}
+
+/// `percent` is clamped to 100; 0 turns the LEDs off.
+#[tauri::command]
+async fn set_led_brightness(payload: v2::Percent) -> Result<(), ControlError> {
+    let v2::Percent { device_id, percent } = payload;
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_led_brightness",
+        json!(percent),
+        hyperx::devices().get(device_id).set_led_brightness(percent),
+    )
+}
+
+#[tauri::command]
+async fn get_notification_beeps(payload: v2::Device) -> Result<Option<bool>, ControlError> {
+    hyperx::devices()
+        .get(payload.device_id)
+        .read_notification_beeps()
+}
+
+#[tauri::command]
+async fn set_notification_beeps(payload: v2::Toggle) -> Result<(), ControlError> {
+    let v2::Toggle { device_id, enabled } = payload;
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_notification_beeps",
+        json!(enabled),
+        hyperx::devices()
+            .get(device_id)
+            .set_notification_beeps(enabled),
+    )
+}
+
+/// The number of noise cancellation levels, or `null` when the headset has
+/// no ANC, so the frontend can size its level selector.
+#[tauri::command]
+fn get_anc_levels(payload: v2::Device) -> Option<u8> {
+    hyperx::devices().get(payload.device_id).anc_levels()
+}
+
+#[tauri::command]
+async fn get_anc(payload: v2::Device) -> Result<Option<bool>, ControlError> {
+    hyperx::devices().get(payload.device_id).read_anc()
+}
+
+#[tauri::command]
+async fn set_anc(payload: v2::Toggle) -> Result<(), ControlError> {
+    let v2::Toggle { device_id, enabled } = payload;
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_anc",
+        json!(enabled),
+        hyperx::devices().get(device_id).set_anc(enabled),
+    )
+}
+
+#[tauri::command]
+async fn get_anc_level(payload: v2::Device) -> Result<Option<u8>, ControlError> {
+    hyperx::devices().get(payload.device_id).read_anc_level()
+}
+
+/// `level` is clamped to 1 to `get_anc_levels`.
+#[tauri::command]
+async fn set_anc_level(payload: v2::SetAncLevel) -> Result<(), ControlError> {
+    let v2::SetAncLevel { device_id, level } = payload;
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_anc_level",
+        json!(level),
+        hyperx::devices().get(device_id).set_anc_level(level),
+    )
+}
+
+/// `anc`, `ambient` or `off`; `null` when the headset's answer was unreadable.
+#[tauri::command]
+async fn get_noise_mode(payload: v2::Device) -> Result<Option<NoiseMode>, ControlError> {
+    hyperx::devices().get(payload.device_id).read_noise_mode()
+}
+
+/// Switch between noise cancellation, the ambient (transparency) mode and
+/// neither; `ambient` needs a headset with an ambient mode.
+#[tauri::command]
+async fn set_noise_mode(payload: v2::SetNoiseMode) -> Result<(), ControlError> {
+    let v2::SetNoiseMode { device_id, mode } = payload;
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_noise_mode",
+        json!(mode),
+        hyperx::devices().get(device_id).set_noise_mode(mode),
+    )
+}
+
+#[tauri::command]
+async fn get_mute_beep_volume(payload: v2::Device) -> Result<Option<u8>, ControlError> {
+    hyperx::devices()
+        .get(payload.device_id)
+        .read_mute_beep_volume()
+}
+
+/// Volume of the beep played on mute and unmute; `percent` is clamped to
+/// 100, and 0 turns the beep off.
+#[tauri::command]
+async fn set_mute_beep_volume(payload: v2::Percent) -> Result<(), ControlError> {
+    let v2::Percent { device_id, percent } = payload;
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_mute_beep_volume",
+        json!(percent),
+        hyperx::devices()
+            .get(device_id)
+            .set_mute_beep_volume(percent),
+    )
+}
+
+/// Whether the microphone boom is flipped up (muted) or down; headsets
+/// report changes on their own as `boom_moved` events.
+#[tauri::command]
+async fn get_boom_position(payload: v2::Device) -> Result<Option<BoomPosition>, ControlError> {
+    hyperx::devices()
+        .get(payload.device_id)
+        .read_boom_position()
+}
+
+/// The buttons the headset lets you remap, each with the actions it can be
+/// given; empty for models without remapping.
+#[tauri::command]
+fn list_remappable_buttons(payload: v2::Device) -> Vec<RemappableButton> {
+    hyperx::devices()
+        .get(payload.device_id)
+        .remappable_buttons()
+        .to_vec()
+}
+
+/// The actions `button` can be given, see [`list_remappable_buttons`].
+#[tauri::command]
+fn list_button_actions(payload: v2::ButtonActions) -> Vec<ButtonAction> {
+    let v2::ButtonActions { device_id, button } = payload;
+    hyperx::devices()
+        .get(device_id)
+        .remappable_buttons()
+        .iter()
+        .find(|remappable| remappable.button == button)
+        .map(|remappable| remappable.actions.to_vec())
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+async fn get_button_mapping(
+    payload: v2::Device,
+) -> Result<BTreeMap<Button, ButtonAction>, ControlError> {
+    hyperx::devices()
+        .get(payload.device_id)
+        .read_button_mapping()
+}
+
+/// Remap `button`, remembering it like sidetone so it is written again when
+/// the headset reconnects.
+#[tauri::command]
+async fn set_button_action(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::SetButtonAction,
+) -> Result<(), ControlError> {
+    let v2::SetButtonAction {
+        device_id,
+        button,
+        action,
+    } = payload;
+    let values = DeviceSettings {
+        buttons: BTreeMap::from([(button, action)]),
+        ..DeviceSettings::default()
+    };
+    let result = profiles::apply(&store, device_id, &values);
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_button_action",
+        json!({ "button": button, "action": action }),
+        result,
+    )
+}
+
+/// Turn voice prompts, LEDs and notification beeps off together, or back
+/// to what they were; see [`dnd`].
+#[tauri::command]
+async fn set_dnd(payload: v2::Toggle) -> Result<(), ControlError> {
+    let v2::Toggle { device_id, enabled } = payload;
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_dnd",
+        json!(enabled),
+        dnd::set(device_id, enabled),
+    )
+}
+
+#[tauri::command]
+fn get_dnd(payload: v2::Device) -> bool {
+    dnd::is_enabled(payload.device_id)
+}
+
+/// Whether incoming Bluetooth calls are answered when the headset is put on.
+#[tauri::command]
+async fn get_auto_answer(payload: v2::Device) -> Result<Option<bool>, ControlError> {
+    hyperx::devices().get(payload.device_id).read_auto_answer()
+}
+
+#[tauri::command]
+async fn set_auto_answer(payload: v2::Toggle) -> Result<(), ControlError> {
+    let v2::Toggle { device_id, enabled } = payload;
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "set_auto_answer",
+        json!(enabled),
+        hyperx::devices().get(device_id).set_auto_answer(enabled),
+    )
+}
+
+#[tauri::command]
+async fn list_bluetooth_pairings(
+    payload: v2::Device,
+) -> Result<Vec<hyperx::BluetoothPairing>, ControlError> {
+    hyperx::devices()
+        .get(payload.device_id)
+        .list_bluetooth_pairings()
+}
+
+#[tauri::command]
+async fn remove_bluetooth_pairing(payload: v2::RemoveBluetoothPairing) -> Result<(), ControlError> {
+    let v2::RemoveBluetoothPairing { device_id, slot } = payload;
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "remove_bluetooth_pairing",
+        json!(slot),
+        hyperx::devices()
+            .get(device_id)
+            .remove_bluetooth_pairing(slot),
+    )
+}
+
+/// `None` when the receiver's answer was unreadable.
+#[tauri::command]
+async fn get_headset_link(payload: v2::Device) -> Result<Option<bool>, ControlError> {
+    hyperx::devices().get(payload.device_id).read_headset_link()
+}
+
+#[tauri::command]
+fn cancel_pairing() {
+    operations::cancel_all(OperationKind::Pairing);
+}
+
+/// Cancel a running `flash_firmware` after the current chunk.
+#[tauri::command]
+fn abort_firmware_flash() {
+    operations::cancel_all(OperationKind::FirmwareFlash);
+}
+
+/// Running flashes, pairings and self-tests, with the IDs their progress
+/// events carry.
+#[tauri::command]
+fn list_operations() -> Vec<operations::OperationInfo> {
+    operations::list()
+}
+
+/// Stop operation `operation_id` at its next safe point; `false` when it is
+/// no longer running.
+#[tauri::command]
+fn cancel_operation(payload: v2::CancelOperation) -> bool {
+    operations::cancel(payload.operation_id)
+}
+
+/// Zip versions, devices, recent errors and logs (and optionally the latest
+/// HID recording) for a bug report; returns the file's path.
+#[tauri::command]
+async fn export_diagnostics(
+    app: AppHandle,
+    payload: v2::ExportDiagnostics,
+) -> Result<String, String> {
+    let config_dir = app.path().app_config_dir().map_err(|err| err.to_string())?;
+    let path = support_bundle::export(&config_dir, payload.include_capture)
+        .map_err(|err| err.to_string())?;
+    Ok(path.display().to_string())
+}
+
+/// Start capturing HID traffic for a bug report; returns the file it goes to.
+#[tauri::command]
+fn start_hid_recording(app: AppHandle) -> Result<String, String> {
+    let config_dir = app.path().app_config_dir().map_err(|err| err.to_string())?;
+    let path = recorder::start_in(&config_dir, env!("CARGO_PKG_VERSION"))
+        .map_err(|err| err.to_string())?;
+    Ok(path.display().to_string())
+}
+
+#[tauri::command]
+fn stop_hid_recording() -> Option<String> {
+    recorder::stop().map(|path| path.display().to_string())
+}
+
+#[tauri::command]
+fn is_hid_recording() -> bool {
+    recorder::is_recording()
+}
+
+/// Send a raw feature report given as hex; needs `developer.raw_reports`.
+#[tauri::command]
+async fn send_raw_feature_report(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::SendRawFeatureReport,
+) -> Result<raw_reports::RawExchange, String> {
+    let v2::SendRawFeatureReport { device_id, data } = payload;
+    let result = raw_reports::send(&store, device_id, &data);
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "send_raw_feature_report",
+        json!(data),
+        result,
+    )
+    .map_err(|err| err.to_string())
+}
+
+/// Read a raw feature report; needs `developer.raw_reports`.
+#[tauri::command]
+async fn get_raw_feature_report(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::GetRawFeatureReport,
+) -> Result<raw_reports::RawExchange, String> {
+    let v2::GetRawFeatureReport {
+        device_id,
+        report_id,
+        length,
+    } = payload;
+    raw_reports::get(&store, device_id, report_id, length).map_err(|err| err.to_string())
+}
+
+/// Recent device events and HID errors, newest first, including those from
+/// while the window was closed. `since` is a Unix time in milliseconds.
+#[tauri::command]
+fn get_event_history(payload: v2::EventHistory) -> Vec<history::Entry> {
+    let v2::EventHistory {
+        device_id,
+        since,
+        limit,
+    } = payload;
+    history::query(device_id, since, limit.unwrap_or(history::CAPACITY))
+}
+
+/// The last `lines` log lines (default 200); newer ones arrive as
+/// [`logging::LOG_LINE_EVENT`] events.
+#[tauri::command]
+async fn tail_log(app: AppHandle, payload: v2::TailLog) -> Result<Vec<String>, String> {
+    let config_dir = app.path().app_config_dir().map_err(|err| err.to_string())?;
+    logging::tail(&config_dir, payload.lines.unwrap_or(200)).map_err(|err| err.to_string())
+}
+
+/// p50/p95 latency and error rate of each kind of HID operation and call.
+#[tauri::command]
+fn get_latency_stats() -> Vec<metrics::LatencyStats> {
+    metrics::latency_stats()
+}
+
+/// Error counts and the last error of one device, for a health badge.
+#[tauri::command]
+fn get_device_health(payload: v2::Device) -> metrics::DeviceHealth {
+    metrics::device_health(payload.device_id)
+}
+
+/// Start a guided battery calibration: charge the headset to full, then use
+/// it until it switches off. Poll `get_battery_calibration` for progress.
+#[tauri::command]
+async fn start_battery_calibration(
+    payload: v2::Device,
+) -> Result<calibration::CalibrationStatus, ControlError> {
+    let v2::Device { device_id } = payload;
+    let result = calibration::start(device_id);
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "start_battery_calibration",
+        json!(null),
+        result,
+    )
+}
+
+#[tauri::command]
+fn cancel_battery_calibration(payload: v2::Device) {
+    calibration::cancel(payload.device_id);
+}
+
+/// The running or last finished calibration; `null` if none ran since startup.
+#[tauri::command]
+fn get_battery_calibration(payload: v2::Device) -> Option<calibration::CalibrationStatus> {
+    calibration::status(payload.device_id)
+}
+
+/// Drop the learned curve so battery readings are shown as reported.
+#[tauri::command]
+fn reset_battery_calibration(
+    store: State<'_, Arc<SettingsStore>>,
+    payload: v2::Device,
+) -> Result<(), String> {
+    let v2::Device { device_id } = payload;
+    let result = calibration::reset(&store, device_id);
+    audit::record(
+        Source::App,
+        Some(device_id),
+        "reset_battery_calibration",
+        json!(null),
+        result,
+    )
+    .map_err(|err| err.to_string())
+}
+
+/// The active log filter, e.g. `warn,hyperx_pilot_lib=info`.
+#[tauri::command]
+fn get_log_filter() -> String {
+    logging::filter()
+}
+
+/// Change the log filter without restarting; `hyperx_pilot_lib=trace` logs
+/// every HID payload.
+#[tauri::command]
+fn set_log_filter(app: AppHandle, payload: v2::SetLogFilter) -> Result<(), String> {
+    logging::set_filter(&payload.filter).map_err(|err| err.to_string())?;
+    if let Some(menu) = app.try_state::<tray::LogLevelMenu>() {
+        menu.sync();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn set_dry_run(payload: v2::SetDryRun) {
+    let v2::SetDryRun { enabled } = payload;
+    hyperx::set_dry_run(enabled);
+    let _ = audit::record(
+        Source::App,
+        None,
+        "set_dry_run",
+        json!(enabled),
+        Ok::<_, Infallible>(()),
+    );
+}
+
+/// State-changing commands from every source (app, API, rules, shortcuts,
+/// ...), newest first; `device_id` limits them to one device.
+#[tauri::command]
+async fn get_audit_log(payload: v2::AuditLog) -> Result<Vec<audit::Entry>, String> {
+    let v2::AuditLog { device_id, limit } = payload;
+    audit::query(device_id, limit.unwrap_or(audit::DEFAULT_LIMIT)).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn get_dry_run() -> bool {
+    hyperx::is_dry_run()
+}
+
+#[cfg(target_os = "macos")]
+#[tauri::command]
+fn check_macos_permissions() -> platform::macos::PermissionReport {
+    platform::macos::permission_report()
+}
+
+#[cfg(target_os = "macos")]
+#[tauri::command]
+async fn request_input_monitoring() -> bool {
+    platform::macos::request_input_monitoring()
+}
+
+#[cfg(target_os = "linux")]
+#[tauri::command]
+fn get_sandbox_status() -> platform::linux::sandbox::SandboxStatus {
+    platform::linux::sandbox::detect()
+}
+
+#[cfg(target_os = "linux")]
+#[tauri::command]
+fn get_udev_rules() -> platform::linux::udev::UdevRules {
+    platform::linux::udev::rules_preview()
+}
+
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn install_udev_rules() -> Result<(), String> {
+    platform::linux::udev::install_rules().map_err(|err| err.to_string())
+}
+
+#[cfg(target_os = "linux")]
+#[tauri::command]
+fn get_systemd_service_status() -> platform::linux::systemd::ServiceStatus {
+    platform::linux::systemd::status()
+}
+
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn install_systemd_service() -> Result<(), String> {
+    platform::linux::systemd::install().map_err(|err| err.to_string())
+}
+
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn uninstall_systemd_service() -> Result<(), String> {
+    platform::linux::systemd::uninstall().map_err(|err| err.to_string())
+}
+
+#[cfg(windows)]
+#[tauri::command]
+async fn list_device_containers(
+) -> Result<Vec<platform::windows::devices::LogicalDevice>, ControlError> {
+    platform::windows::devices::logical_devices()
+}
+
+#[cfg(windows)]
+#[tauri::command]
+fn get_startup_task_status() -> platform::windows::startup::StartupStatus {
+    platform::windows::startup::status()
+}
+
+/// Register the login task; `delay_secs` defaults to
+/// [`platform::windows::startup::DEFAULT_DELAY_SECS`].
+#[cfg(windows)]
+#[tauri::command]
+async fn install_startup_task(payload: v2::InstallStartupTask) -> Result<(), String> {
+    let delay_secs = payload
+        .delay_secs
+        .unwrap_or(platform::windows::startup::DEFAULT_DELAY_SECS);
+    platform::windows::startup::install(delay_secs).map_err(|err| err.to_string())
+}
+
+#[cfg(windows)]
+#[tauri::command]
+async fn uninstall_startup_task() -> Result<(), String> {
+    platform::windows::startup::uninstall().map_err(|err| err.to_string())
+}
+
+/// Bring the window to the front for a second start of the app.
+fn show_window(app: &AppHandle) -> Result<(), String> {
+    let window = app
+        .get_webview_window("main")
+        .ok_or("the window is closed")?;
+    window
+        .unminimize()
+        .and_then(|()| window.show())
+        .and_then(|()| window.set_focus())
+        .map_err(|err| err.to_string())
+}
+
+/// Tell the webview about panics in background threads.
+fn forward_crashes_to_frontend(app: AppHandle) {
+    let receiver = crash::subscribe();
+    thread::spawn(move || {
+        for report in receiver {
+            let _ = app.emit(crash::CRASH_EVENT, &report);
+        }
+    });
+}
+
+/// Relay new log lines to the webview's log viewer.
+fn forward_log_to_frontend(app: AppHandle) {
+    let receiver = logging::follow();
+    thread::spawn(move || {
+        for line in receiver {
+            let _ = app.emit(logging::LOG_LINE_EVENT, &line);
+        }
+    });
+}
+
+/// Relay device events to the webview.
+fn forward_events_to_frontend(app: AppHandle, events: &EventBus) {
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        for event in receiver {
+            let _ = app.emit(events::DEVICE_EVENT, &event);
+        }
+    });
+}
+
+/// Remove the switch `name` from `args`; returns whether it was given.
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let Some(index) = args.iter().skip(1).position(|arg| arg == name) else {
+        return false;
+    };
+    args.remove(index + 1);
+    true
+}
+
+/// Remove `name` and the path following it from `args`, returning the path.
+/// Exits when the path is missing.
+fn take_path_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index = args.iter().skip(1).position(|arg| arg == name)? + 1;
+    if index + 1 >= args.len() {
+        eprintln!("{name} needs a file");
+        std::process::exit(2);
+    }
+    let path = args.remove(index + 1);
+    args.remove(index);
+    Some(path)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    host::install();
+    let mut args: Vec<String> = env::args().collect();
+    if take_flag(&mut args, "--simulate") {
+        simulator::enable();
+    }
+    if take_flag(&mut args, "--dry-run") {
+        hyperx::set_dry_run(true);
+    }
+    if let Some(path) = take_path_option(&mut args, "--replay") {
+        match replay::Capture::load(Path::new(&path)) {
+            Ok(capture) => {
+                eprintln!(
+                    "replaying {} transactions recorded by version {} on {}",
+                    capture.transactions.len(),
+                    capture.header.app_version,
+                    capture.header.os
+                );
+                replay::enable(&capture);
+            }
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(path) = take_path_option(&mut args, "--record") {
+        if let Err(err) = recorder::start(Path::new(&path), env!("CARGO_PKG_VERSION")) {
+            eprintln!("cannot record to {path}: {err}");
+            std::process::exit(1);
+        }
+    }
+    if cli::is_invocation(&args) {
+        logging::init(None);
+        std::process::exit(cli::run(&args[1..]));
+    }
+    logging::init(daemon::config_dir().as_deref());
+    crash::install(daemon::config_dir().as_deref());
+    if args.iter().skip(1).any(|arg| arg == "--headless") {
+        daemon::run();
+        return;
+    }
+    let Some(config_dir) = daemon::config_dir() else {
+        eprintln!("cannot determine the configuration directory");
+        std::process::exit(1);
+    };
+    let instance = match instance::acquire(&config_dir, instance::Mode::Window) {
+        Ok(instance) => instance,
+        Err(err @ instance::InstanceError::Running { .. }) => {
+            match instance::send(&config_dir, instance::Request::Show) {
+                Ok(()) => std::process::exit(0),
+                Err(show_err) => {
+                    eprintln!("{err}: {show_err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .setup(move |app| {
+            let handle = app.handle().clone();
+            let services = Services::start(&config_dir, instance, move || show_window(&handle));
+            forward_events_to_frontend(app.handle().clone(), &services.events);
+            forward_log_to_frontend(app.handle().clone());
+            forward_crashes_to_frontend(app.handle().clone());
+            services.start_watcher();
+            tray::build(app.handle())?;
+
+            app.manage(services.settings.clone());
+            app.manage(services.events.clone());
+            app.manage(services.sidetone.clone());
+            app.manage(services);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             list_hyperx_devices,
+            refresh_devices,
             set_sidetone,
-            get_sidetone_state
+            get_sidetone_state,
+            has_hardware_sidetone,
+            set_software_sidetone,
+            get_software_sidetone,
+            get_hid_backend,
+            dump_device_info,
+            get_hardware_info,
+            get_firmware_version,
+            check_firmware_update,
+            flash_firmware,
+            abort_firmware_flash,
+            list_operations,
+            cancel_operation,
+            pin_firmware,
+            pair_receiver,
+            cancel_pairing,
+            get_headset_link,
+            has_wireless_modes,
+            get_wireless_mode,
+            set_wireless_mode,
+            has_game_mode,
+            get_game_mode,
+            set_game_mode,
+            has_multipoint,
+            get_multipoint,
+            set_multipoint,
+            get_auto_answer,
+            set_auto_answer,
+            has_output_mute,
+            get_output_mute,
+            set_output_mute,
+            get_voice_prompts,
+            set_voice_prompts,
+            get_led_brightness,
+            set_led_brightness,
+            get_notification_beeps,
+            set_notification_beeps,
+            get_mute_beep_volume,
+            set_mute_beep_volume,
+            get_boom_position,
+            list_remappable_buttons,
+            list_button_actions,
+            get_button_mapping,
+            set_button_action,
+            set_dnd,
+            get_dnd,
+            get_anc_levels,
+            get_anc,
+            set_anc,
+            get_anc_level,
+            set_anc_level,
+            get_noise_mode,
+            set_noise_mode,
+            list_bluetooth_pairings,
+            remove_bluetooth_pairing,
+            backup_firmware,
+            run_self_test,
+            factory_reset,
+            has_identify,
+            identify,
+            get_device_state,
+            handshake,
+            set_locale,
+            get_capabilities,
+            supported_features,
+            get_feature,
+            set_feature,
+            preview_device_report,
+            submit_device_report,
+            export_diagnostics,
+            start_hid_recording,
+            stop_hid_recording,
+            is_hid_recording,
+            set_dry_run,
+            send_raw_feature_report,
+            get_raw_feature_report,
+            get_dry_run,
+            get_latency_stats,
+            get_device_health,
+            start_battery_calibration,
+            cancel_battery_calibration,
+            get_battery_calibration,
+            reset_battery_calibration,
+            get_audit_log,
+            get_event_history,
+            tail_log,
+            get_log_filter,
+            set_log_filter,
+            list_audio_endpoints,
+            get_audio_association,
+            set_audio_association,
+            get_settings,
+            update_settings,
+            save_profile,
+            delete_profile,
+            apply_profile,
+            save_group,
+            delete_group,
+            apply_to_group,
+            #[cfg(target_os = "macos")]
+            check_macos_permissions,
+            #[cfg(target_os = "macos")]
+            request_input_monitoring,
+            #[cfg(target_os = "linux")]
+            get_sandbox_status,
+            #[cfg(target_os = "linux")]
+            get_udev_rules,
+            #[cfg(target_os = "linux")]
+            install_udev_rules,
+            #[cfg(target_os = "linux")]
+            get_systemd_service_status,
+            #[cfg(target_os = "linux")]
+            install_systemd_service,
+            #[cfg(target_os = "linux")]
+            uninstall_systemd_service,
+            #[cfg(windows)]
+            list_device_containers,
+            #[cfg(windows)]
+            get_startup_task_status,
+            #[cfg(windows)]
+            install_startup_task,
+            #[cfg(windows)]
+            uninstall_startup_task
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                app.state::<Services>().shutdown();
+            }
+        });
 }
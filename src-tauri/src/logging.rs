@@ -0,0 +1,439 @@
+//! `tracing` output for the GUI and headless modes: every event goes to
+//! stderr and to `logs/hyperx-pilot.log` in the config directory, which is
+//! rotated by size. Events carry the spans they happened in, so a failed HID
+//! operation is logged with its device and operation.
+//!
+//! Filters use a small subset of `RUST_LOG` syntax: a default level and
+//! per-target levels, e.g. `warn,hyperx_pilot_lib=debug`. The initial filter
+//! can be set with `HYPERX_PILOT_LOG` and changed at runtime with
+//! [`set_filter`].
+//!
+//! The in-app log viewer reads the file with [`tail`] and gets new lines from
+//! [`follow`], so the webview needs no filesystem access.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing::subscriber::{Interest, Subscriber};
+use tracing::{info, Event, Metadata};
+
+/// Below the config directory.
+pub const LOG_DIR: &str = "logs";
+const LOG_FILE: &str = "hyperx-pilot.log";
+/// The file is rotated once it grows past this size.
+const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+/// Rotated files kept next to the current one.
+const ROTATED_FILES: usize = 3;
+/// Most lines [`tail`] returns.
+const MAX_TAIL_LINES: usize = 5000;
+
+/// Name of the Tauri event carrying each new log line to the frontend.
+pub const LOG_LINE_EVENT: &str = "log-line";
+
+pub const DEFAULT_FILTER: &str = "warn,hyperx_pilot_lib=info";
+/// Logs every HID payload, see [`crate::hyperx`].
+pub const TRACE_FILTER: &str = "warn,hyperx_pilot_lib=trace";
+pub const FILTER_ENV: &str = "HYPERX_PILOT_LOG";
+
+#[derive(Debug)]
+pub struct InvalidFilter(pub String);
+
+impl fmt::Display for InvalidFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid log filter directive '{}'", self.0)
+    }
+}
+
+impl std::error::Error for InvalidFilter {}
+
+/// A default level plus levels for target prefixes; the longest matching
+/// prefix wins.
+#[derive(Debug, Clone)]
+struct Filter {
+    directives: String,
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    fn parse(directives: &str) -> Result<Self, InvalidFilter> {
+        let mut filter = Filter {
+            directives: directives.trim().to_string(),
+            default: LevelFilter::ERROR,
+            targets: Vec::new(),
+        };
+        for directive in directives.split(',').map(str::trim) {
+            if directive.is_empty() {
+                continue;
+            }
+            let invalid = || InvalidFilter(directive.to_string());
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    let level = level.parse().map_err(|_| invalid())?;
+                    filter.targets.push((target.to_string(), level));
+                }
+                None => filter.default = directive.parse().map_err(|_| invalid())?,
+            }
+        }
+        filter
+            .targets
+            .sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        Ok(filter)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        let level = self
+            .targets
+            .iter()
+            .find(|(target, _)| metadata.target().starts_with(target.as_str()))
+            .map_or(self.default, |(_, level)| *level);
+        *metadata.level() <= level
+    }
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+}
+
+impl LogFile {
+    fn open(directory: &Path) -> io::Result<Self> {
+        fs::create_dir_all(directory)?;
+        let path = directory.join(LOG_FILE);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(LogFile {
+            path,
+            file,
+            written,
+        })
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        self.path.with_extension(format!("log.{index}"))
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.written + line.len() as u64 > MAX_FILE_BYTES {
+            for index in (1..ROTATED_FILES).rev() {
+                let _ = fs::rename(self.rotated(index), self.rotated(index + 1));
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+            self.file = File::create(&self.path)?;
+            self.written = 0;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+}
+
+struct SpanData {
+    name: &'static str,
+    fields: String,
+    parent: Option<Id>,
+    references: usize,
+}
+
+/// Read on every callsite, so changes apply immediately.
+static FILTER: Mutex<Filter> = Mutex::new(Filter {
+    directives: String::new(),
+    default: LevelFilter::ERROR,
+    targets: Vec::new(),
+});
+
+/// Receivers of new lines, see [`follow`].
+static FOLLOWERS: Mutex<Vec<Sender<String>>> = Mutex::new(Vec::new());
+
+struct LogSubscriber {
+    file: Mutex<Option<LogFile>>,
+    spans: Mutex<HashMap<u64, SpanData>>,
+    next_id: AtomicU64,
+}
+
+thread_local! {
+    /// Spans entered on this thread, innermost last.
+    static ENTERED: RefCell<Vec<Id>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Collects an event's or span's fields as `name=value` pairs, keeping the
+/// message apart.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    pairs: String,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            if !self.pairs.is_empty() {
+                self.pairs.push(' ');
+            }
+            let _ = write!(self.pairs, "{}={value:?}", field.name());
+        }
+    }
+}
+
+impl LogSubscriber {
+    /// `outer{fields}:inner{fields}` for the span `id` and its parents.
+    fn span_context(&self, id: Option<Id>) -> String {
+        let spans = self.spans.lock().unwrap();
+        let mut chain = Vec::new();
+        let mut current = id;
+        while let Some(span) = current.and_then(|id| spans.get(&id.into_u64())) {
+            chain.push(if span.fields.is_empty() {
+                span.name.to_string()
+            } else {
+                format!("{}{{{}}}", span.name, span.fields)
+            });
+            current = span.parent.clone();
+        }
+        chain.reverse();
+        chain.join(":")
+    }
+
+    fn current_span() -> Option<Id> {
+        ENTERED.with(|entered| entered.borrow().last().cloned())
+    }
+}
+
+impl Subscriber for LogSubscriber {
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        // The filter can change at runtime, so ask `enabled` every time.
+        Interest::sometimes()
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        None
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        FILTER.lock().unwrap().enabled(metadata)
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let id = Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let mut fields = Fields::default();
+        attributes.record(&mut fields);
+        let parent = if attributes.is_contextual() {
+            LogSubscriber::current_span()
+        } else {
+            attributes.parent().cloned()
+        };
+        let span = SpanData {
+            name: attributes.metadata().name(),
+            fields: fields.pairs,
+            parent,
+            references: 1,
+        };
+        self.spans.lock().unwrap().insert(id.into_u64(), span);
+        id
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut fields = Fields::default();
+        values.record(&mut fields);
+        if let Some(span) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            if !span.fields.is_empty() && !fields.pairs.is_empty() {
+                span.fields.push(' ');
+            }
+            span.fields.push_str(&fields.pairs);
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let parent = if event.is_contextual() {
+            LogSubscriber::current_span()
+        } else {
+            event.parent().cloned()
+        };
+        let metadata = event.metadata();
+        let mut line = format!(
+            "{} {:>5} {}: ",
+            timestamp(),
+            metadata.level(),
+            metadata.target()
+        );
+        let context = self.span_context(parent);
+        if !context.is_empty() {
+            let _ = write!(line, "{context}: ");
+        }
+        line.push_str(&fields.message);
+        if !fields.pairs.is_empty() {
+            let _ = write!(line, " {}", fields.pairs);
+        }
+        line.push('\n');
+
+        let _ = io::stderr().write_all(line.as_bytes());
+        let mut file = self.file.lock().unwrap();
+        if let Some(log) = file.as_mut() {
+            if log.write_line(&line).is_err() {
+                // Keep logging to stderr rather than failing on every event.
+                *file = None;
+            }
+        }
+        drop(file);
+        let line = line.trim_end();
+        FOLLOWERS
+            .lock()
+            .unwrap()
+            .retain(|follower| follower.send(line.to_string()).is_ok());
+    }
+
+    fn enter(&self, span: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(span.clone()));
+    }
+
+    fn exit(&self, span: &Id) {
+        ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(index) = entered.iter().rposition(|id| id == span) {
+                entered.remove(index);
+            }
+        });
+    }
+
+    fn clone_span(&self, id: &Id) -> Id {
+        if let Some(span) = self.spans.lock().unwrap().get_mut(&id.into_u64()) {
+            span.references += 1;
+        }
+        id.clone()
+    }
+
+    fn try_close(&self, id: Id) -> bool {
+        let mut spans = self.spans.lock().unwrap();
+        let Some(span) = spans.get_mut(&id.into_u64()) else {
+            return false;
+        };
+        span.references -= 1;
+        if span.references > 0 {
+            return false;
+        }
+        spans.remove(&id.into_u64());
+        true
+    }
+}
+
+/// UTC time as `2024-05-01T12:34:56.789Z`.
+fn timestamp() -> String {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = elapsed.as_secs() as i64;
+    let (days, time) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+/// Install the global subscriber, logging to [`LOG_DIR`] below `config_dir`.
+/// Without a usable log directory, events still go to stderr.
+pub fn init(config_dir: Option<&Path>) {
+    let filter = std::env::var(FILTER_ENV)
+        .ok()
+        .and_then(|directives| match Filter::parse(&directives) {
+            Ok(filter) => Some(filter),
+            Err(err) => {
+                eprintln!("{FILTER_ENV}: {err}");
+                None
+            }
+        })
+        .unwrap_or_else(|| Filter::parse(DEFAULT_FILTER).expect("default filter is valid"));
+    let file = config_dir.and_then(|dir| match LogFile::open(&dir.join(LOG_DIR)) {
+        Ok(file) => Some(file),
+        Err(err) => {
+            eprintln!("not logging to a file: {err}");
+            None
+        }
+    });
+    *FILTER.lock().unwrap() = filter;
+    let subscriber = LogSubscriber {
+        file: Mutex::new(file),
+        spans: Mutex::new(HashMap::new()),
+        next_id: AtomicU64::new(1),
+    };
+    let _ = tracing::subscriber::set_global_default(subscriber);
+}
+
+/// The active filter directives.
+pub fn filter() -> String {
+    FILTER.lock().unwrap().directives.clone()
+}
+
+/// Replace the filter without restarting, e.g. with [`TRACE_FILTER`] while
+/// reproducing a problem.
+pub fn set_filter(directives: &str) -> Result<(), InvalidFilter> {
+    let filter = Filter::parse(directives)?;
+    *FILTER.lock().unwrap() = filter;
+    info!("log filter set to '{}'", directives.trim());
+    Ok(())
+}
+
+/// Every line logged from now on, without the trailing newline.
+pub fn follow() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    FOLLOWERS.lock().unwrap().push(sender);
+    receiver
+}
+
+/// The last `lines` lines of the log file in `config_dir`, oldest first,
+/// reaching into the previous file when the current one is shorter.
+pub fn tail(config_dir: &Path, lines: usize) -> io::Result<Vec<String>> {
+    let lines = lines.min(MAX_TAIL_LINES);
+    let current = config_dir.join(LOG_DIR).join(LOG_FILE);
+    let previous = current.with_extension("log.1");
+    let mut tail = Vec::new();
+    for path in [current, previous] {
+        let contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        let contents = String::from_utf8_lossy(&contents);
+        let missing = lines - tail.len();
+        let older: Vec<&str> = contents.lines().rev().take(missing).collect();
+        tail.extend(older.into_iter().map(str::to_string));
+        if tail.len() == lines {
+            break;
+        }
+    }
+    tail.reverse();
+    Ok(tail)
+}
@@ -0,0 +1,136 @@
+//! Software macros: app-side actions bound to headset button presses, run
+//! on top of whatever the firmware does with the button. Headsets that report
+//! their presses publish [`DeviceEvent::ButtonPressed`] from the hotplug
+//! watcher's input listener, and every press runs the entries of `macros` in
+//! the settings bound to that headset, button and kind of press. The settings
+//! are read on every press, so edits apply without a restart.
+
+use crate::actions::{self, Action, ActionError};
+use crate::audit::{self, Source};
+use crate::events::{DeviceEvent, EventBus};
+use crate::hooks;
+use crate::hyperx::{Button, ButtonPress, DeviceId};
+use crate::media_keys::{self, MediaKey, MediaKeyError};
+use crate::profiles::{self, ProfileError};
+use crate::settings::SettingsStore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+use std::sync::Arc;
+use std::thread;
+use tracing::warn;
+
+/// What to do when `button` on `device_id` is pressed the `press` way.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ButtonMacro {
+    pub device_id: DeviceId,
+    pub button: Button,
+    pub press: ButtonPress,
+    pub action: MacroAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MacroAction {
+    ApplyProfile {
+        name: String,
+    },
+    /// A shell command, run like a hook with `HYPERX_PILOT_EVENT` set to
+    /// `button_pressed` and `HYPERX_PILOT_BUTTON` and `HYPERX_PILOT_PRESS`
+    /// to the press, e.g. `multi_function` and `double`.
+    RunHook {
+        command: String,
+    },
+    /// Flip the hardware sidetone of the headset the button is on.
+    ToggleSidetone,
+    MediaKey {
+        key: MediaKey,
+    },
+}
+
+#[derive(Debug)]
+pub enum MacroError {
+    Profile(ProfileError),
+    Action(ActionError),
+    MediaKey(MediaKeyError),
+}
+
+impl fmt::Display for MacroError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MacroError::Profile(err) => err.fmt(f),
+            MacroError::Action(err) => err.fmt(f),
+            MacroError::MediaKey(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MacroError {}
+
+pub fn spawn(settings: Arc<SettingsStore>, events: &EventBus) {
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        for event in receiver {
+            let DeviceEvent::ButtonPressed {
+                device_id,
+                button,
+                press,
+            } = event
+            else {
+                continue;
+            };
+            let macros = settings.get().macros;
+            let bound = macros.iter().filter(|bound| {
+                bound.device_id == device_id && bound.button == button && bound.press == press
+            });
+            for bound in bound {
+                if let Err(err) = run(&settings, device_id, button, press, &bound.action) {
+                    warn!(
+                        "macro for a {} press of the {} button of {device_id} failed: {err}",
+                        press.as_str(),
+                        button.as_str()
+                    );
+                }
+            }
+        }
+    });
+}
+
+fn run(
+    settings: &SettingsStore,
+    device_id: DeviceId,
+    button: Button,
+    press: ButtonPress,
+    action: &MacroAction,
+) -> Result<(), MacroError> {
+    match action {
+        MacroAction::ApplyProfile { name } => {
+            let result = profiles::apply_named(settings, name);
+            audit::record(
+                Source::Macro,
+                Some(device_id),
+                "apply_profile",
+                json!(name),
+                result,
+            )
+            .map_err(MacroError::Profile)
+        }
+        MacroAction::RunHook { command } => {
+            let environment = [
+                ("HYPERX_PILOT_EVENT", "button_pressed".to_string()),
+                ("HYPERX_PILOT_DEVICE", device_id.to_string()),
+                ("HYPERX_PILOT_BUTTON", button.as_str().to_string()),
+                ("HYPERX_PILOT_PRESS", press.as_str().to_string()),
+            ];
+            hooks::run_command(command, &environment);
+            Ok(())
+        }
+        MacroAction::ToggleSidetone => actions::perform(
+            settings,
+            Source::Macro,
+            &Action::ToggleSidetone { device_id },
+        )
+        .map_err(MacroError::Action),
+        MacroAction::MediaKey { key } => media_keys::press(*key).map_err(MacroError::MediaKey),
+    }
+}
@@ -0,0 +1,150 @@
+//! Synthetic media keys, pressed as if on a keyboard so whichever player the
+//! desktop routes media keys to reacts. On Linux they come from a virtual
+//! keyboard on `/dev/uinput`, or through XTEST on X11 sessions where the app
+//! may not write there; other platforms fail with
+//! [`MediaKeyError::Unsupported`].
+//!
+//! With `media_keys.enabled` in the settings, the controls of headsets that
+//! report them to the app rather than as standard consumer controls press
+//! media keys: a single press of the multi-function button plays or pauses,
+//! a double press skips to the next track and a long press to the previous
+//! one, and every step of the volume dial raises or lowers the volume.
+//! Presses a macro is bound to run only the macro. The settings are read on
+//! every event, so turning it on or off applies without a restart.
+
+use crate::events::{DeviceEvent, EventBus};
+use crate::hyperx::{Button, ButtonPress};
+use crate::settings::SettingsStore;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use std::thread;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKey {
+    PlayPause,
+    NextTrack,
+    PreviousTrack,
+    VolumeUp,
+    VolumeDown,
+}
+
+impl MediaKey {
+    pub const ALL: [MediaKey; 5] = [
+        MediaKey::PlayPause,
+        MediaKey::NextTrack,
+        MediaKey::PreviousTrack,
+        MediaKey::VolumeUp,
+        MediaKey::VolumeDown,
+    ];
+
+    /// The serialised name, e.g. `play_pause`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MediaKey::PlayPause => "play_pause",
+            MediaKey::NextTrack => "next_track",
+            MediaKey::PreviousTrack => "previous_track",
+            MediaKey::VolumeUp => "volume_up",
+            MediaKey::VolumeDown => "volume_down",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MediaKeyError {
+    #[cfg(target_os = "linux")]
+    X11 { detail: String },
+    /// The keyboard map has no key for it.
+    #[cfg(target_os = "linux")]
+    NoKeycode { key: MediaKey },
+    #[cfg(not(target_os = "linux"))]
+    Unsupported,
+}
+
+impl fmt::Display for MediaKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(target_os = "linux")]
+            MediaKeyError::X11 { detail } => write!(f, "X11 key press failed: {detail}"),
+            #[cfg(target_os = "linux")]
+            MediaKeyError::NoKeycode { key } => {
+                write!(f, "the keyboard map has no {} key", key.as_str())
+            }
+            #[cfg(not(target_os = "linux"))]
+            MediaKeyError::Unsupported => {
+                write!(f, "media keys cannot be sent on this platform")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MediaKeyError {}
+
+/// Press and release `key`.
+pub fn press(key: MediaKey) -> Result<(), MediaKeyError> {
+    #[cfg(target_os = "linux")]
+    {
+        crate::platform::linux::media_keys::press(key)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = key;
+        Err(MediaKeyError::Unsupported)
+    }
+}
+
+pub fn spawn(settings: Arc<SettingsStore>, events: &EventBus) {
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        for event in receiver {
+            let (key, times) = match event {
+                DeviceEvent::ButtonPressed {
+                    device_id,
+                    button,
+                    press,
+                } => {
+                    let settings = settings.get();
+                    let bound = settings.macros.iter().any(|bound| {
+                        bound.device_id == device_id
+                            && bound.button == button
+                            && bound.press == press
+                    });
+                    match button_key(button, press) {
+                        Some(key) if settings.media_keys.enabled && !bound => (key, 1),
+                        _ => continue,
+                    }
+                }
+                DeviceEvent::DialTurned { steps, .. } => {
+                    if !settings.get().media_keys.enabled {
+                        continue;
+                    }
+                    let key = if steps > 0 {
+                        MediaKey::VolumeUp
+                    } else {
+                        MediaKey::VolumeDown
+                    };
+                    (key, steps.unsigned_abs())
+                }
+                _ => continue,
+            };
+            for _ in 0..times {
+                if let Err(err) = press(key) {
+                    warn!("pressing the {} media key failed: {err}", key.as_str());
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// The media key a press of a headset button stands for.
+fn button_key(button: Button, press: ButtonPress) -> Option<MediaKey> {
+    match (button, press) {
+        (Button::MultiFunction, ButtonPress::Single) => Some(MediaKey::PlayPause),
+        (Button::MultiFunction, ButtonPress::Double) => Some(MediaKey::NextTrack),
+        (Button::MultiFunction, ButtonPress::Long) => Some(MediaKey::PreviousTrack),
+        (Button::NoiseControl, _) => None,
+    }
+}
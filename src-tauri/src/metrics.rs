@@ -0,0 +1,320 @@
+//! Counters behind the optional Prometheus endpoint (`/metrics` on the local
+//! API). HID traffic is always counted; the connection gauge only tracks
+//! devices once [`spawn`] runs.
+
+use crate::events::{DeviceEvent, EventBus};
+use crate::history;
+use crate::hyperx::{self, ControlError, DeviceId, Priority};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 9] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+/// Failed HID operations kept for diagnostics bundles.
+const RECENT_ERRORS: usize = 20;
+/// Latest durations per call that percentiles are computed from.
+const LATENCY_SAMPLES: usize = 512;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentError {
+    /// Unix time in milliseconds.
+    pub time: u64,
+    pub device_id: DeviceId,
+    pub operation: &'static str,
+    pub kind: &'static str,
+    pub message: String,
+}
+
+/// Latency and error rate of one kind of HID call, see [`latency_stats`].
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyStats {
+    /// An operation (`"read"`, `"write"`) or one of the calls it is made of
+    /// (`"open"`, `"send_feature_report"`, `"get_feature_report"`).
+    pub call: &'static str,
+    pub count: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+    /// Over the last 512 calls.
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+}
+
+/// Error counts and the last error of one device, see [`device_health`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeviceHealth {
+    pub operations: u64,
+    pub errors: u64,
+    /// Failures since the last successful operation; non-zero means the
+    /// device is failing right now.
+    pub consecutive_errors: u64,
+    pub last_error: Option<RecentError>,
+}
+
+#[derive(Default)]
+struct CallStats {
+    count: u64,
+    errors: u64,
+    recent: VecDeque<Duration>,
+}
+
+impl CallStats {
+    fn observe(&mut self, elapsed: Duration, ok: bool) {
+        self.count += 1;
+        self.errors += u64::from(!ok);
+        if self.recent.len() == LATENCY_SAMPLES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(elapsed);
+    }
+
+    fn summary(&self, call: &'static str) -> LatencyStats {
+        let mut sorted: Vec<Duration> = self.recent.iter().copied().collect();
+        sorted.sort_unstable();
+        // Nearest-rank percentile.
+        let percentile = |p: usize| {
+            let rank = (sorted.len() * p).div_ceil(100).max(1);
+            sorted
+                .get(rank - 1)
+                .map_or(0.0, |duration| duration.as_secs_f64() * 1000.0)
+        };
+        LatencyStats {
+            call,
+            count: self.count,
+            errors: self.errors,
+            error_rate: if self.count == 0 {
+                0.0
+            } else {
+                self.errors as f64 / self.count as f64
+            },
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    connected: BTreeSet<DeviceId>,
+    /// Keyed by device, operation and [`ControlError`] kind.
+    hid_errors: BTreeMap<(DeviceId, &'static str, &'static str), u64>,
+    latencies: BTreeMap<&'static str, Histogram>,
+    calls: BTreeMap<&'static str, CallStats>,
+    recent_errors: VecDeque<RecentError>,
+    health: BTreeMap<DeviceId, DeviceHealth>,
+}
+
+impl Registry {
+    fn observe(&mut self, call: &'static str, elapsed: Duration, ok: bool) {
+        self.latencies.entry(call).or_default().observe(elapsed);
+        self.calls.entry(call).or_default().observe(elapsed, ok);
+    }
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    connected: BTreeSet::new(),
+    hid_errors: BTreeMap::new(),
+    latencies: BTreeMap::new(),
+    calls: BTreeMap::new(),
+    recent_errors: VecDeque::new(),
+    health: BTreeMap::new(),
+});
+
+/// Count one finished HID operation (`"read"`, `"write"`), and the error
+/// when it failed.
+pub fn observe_operation(
+    device_id: DeviceId,
+    operation: &'static str,
+    elapsed: Duration,
+    error: Option<&ControlError>,
+) {
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.observe(operation, elapsed, error.is_none());
+    let health = registry.health.entry(device_id).or_default();
+    health.operations += 1;
+    let Some(err) = error else {
+        health.consecutive_errors = 0;
+        return;
+    };
+    history::record_error(device_id, operation, err.kind(), err.to_string());
+    let error = RecentError {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64),
+        device_id,
+        operation,
+        kind: err.kind(),
+        message: err.to_string(),
+    };
+    health.errors += 1;
+    health.consecutive_errors += 1;
+    health.last_error = Some(error.clone());
+    *registry
+        .hid_errors
+        .entry((device_id, operation, err.kind()))
+        .or_default() += 1;
+    if registry.recent_errors.len() == RECENT_ERRORS {
+        registry.recent_errors.pop_front();
+    }
+    registry.recent_errors.push_back(error);
+}
+
+/// Count one of the HID calls an operation is made of.
+pub fn observe_call(call: &'static str, elapsed: Duration, ok: bool) {
+    REGISTRY.lock().unwrap().observe(call, elapsed, ok);
+}
+
+/// Latency percentiles and error rates per HID operation and call.
+pub fn latency_stats() -> Vec<LatencyStats> {
+    REGISTRY
+        .lock()
+        .unwrap()
+        .calls
+        .iter()
+        .map(|(call, stats)| stats.summary(call))
+        .collect()
+}
+
+/// The last failed HID operations, oldest first.
+pub fn recent_errors() -> Vec<RecentError> {
+    REGISTRY
+        .lock()
+        .unwrap()
+        .recent_errors
+        .iter()
+        .cloned()
+        .collect()
+}
+
+/// Operation and error counts of `device_id` since startup.
+pub fn device_health(device_id: DeviceId) -> DeviceHealth {
+    REGISTRY
+        .lock()
+        .unwrap()
+        .health
+        .get(&device_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Track which devices are connected for the connection gauge.
+pub fn spawn(events: Arc<EventBus>) {
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        for event in receiver {
+            let mut registry = REGISTRY.lock().unwrap();
+            match event {
+                DeviceEvent::Connected { device_id } => {
+                    registry.connected.insert(device_id);
+                }
+                DeviceEvent::Disconnected { device_id } => {
+                    registry.connected.remove(&device_id);
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+/// The current values in the Prometheus text exposition format. Battery
+/// levels are read from the devices at scrape time.
+pub fn render() -> String {
+    let connected = REGISTRY.lock().unwrap().connected.clone();
+    let batteries: Vec<(DeviceId, u8)> = hyperx::with_priority(Priority::Background, || {
+        connected
+            .iter()
+            .filter(|&&device_id| hyperx::devices().get(device_id).has_battery())
+            .filter_map(|&device_id| {
+                Some((
+                    device_id,
+                    hyperx::devices().get(device_id).read_battery().ok()??,
+                ))
+            })
+            .collect()
+    });
+
+    let registry = REGISTRY.lock().unwrap();
+    let mut out = String::new();
+    out.push_str("# HELP hyperx_pilot_device_connected Whether the headset is connected.\n");
+    out.push_str("# TYPE hyperx_pilot_device_connected gauge\n");
+    for device in hyperx::list_devices() {
+        let value = u8::from(registry.connected.contains(&device.id));
+        let _ = writeln!(
+            out,
+            "hyperx_pilot_device_connected{{device=\"{}\"}} {value}",
+            device.id
+        );
+    }
+
+    out.push_str("# HELP hyperx_pilot_battery_percent Remaining battery charge.\n");
+    out.push_str("# TYPE hyperx_pilot_battery_percent gauge\n");
+    for (device_id, percent) in batteries {
+        let _ = writeln!(
+            out,
+            "hyperx_pilot_battery_percent{{device=\"{}\"}} {percent}",
+            device_id
+        );
+    }
+
+    out.push_str("# HELP hyperx_pilot_hid_errors_total Failed HID operations.\n");
+    out.push_str("# TYPE hyperx_pilot_hid_errors_total counter\n");
+    for ((device_id, operation, kind), count) in &registry.hid_errors {
+        let _ = writeln!(
+            out,
+            "hyperx_pilot_hid_errors_total{{device=\"{}\",operation=\"{operation}\",kind=\"{kind}\"}} {count}",
+            device_id
+        );
+    }
+
+    out.push_str(
+        "# HELP hyperx_pilot_hid_duration_seconds Latency of HID operations and their calls.\n",
+    );
+    out.push_str("# TYPE hyperx_pilot_hid_duration_seconds histogram\n");
+    for (operation, histogram) in &registry.latencies {
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+            let _ = writeln!(
+                out,
+                "hyperx_pilot_hid_duration_seconds_bucket{{operation=\"{operation}\",le=\"{bound}\"}} {count}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "hyperx_pilot_hid_duration_seconds_bucket{{operation=\"{operation}\",le=\"+Inf\"}} {}",
+            histogram.count
+        );
+        let _ = writeln!(
+            out,
+            "hyperx_pilot_hid_duration_seconds_sum{{operation=\"{operation}\"}} {}",
+            histogram.sum
+        );
+        let _ = writeln!(
+            out,
+            "hyperx_pilot_hid_duration_seconds_count{{operation=\"{operation}\"}} {}",
+            histogram.count
+        );
+    }
+    out
+}
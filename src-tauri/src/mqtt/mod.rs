@@ -0,0 +1,367 @@
+//! Optional MQTT client that turns each headset into a Home Assistant device
+//! through the MQTT discovery convention: connection and battery sensors and
+//! a microphone mute switch. State topics are retained, and the broker marks
+//! everything unavailable through the last will when the app goes away.
+//!
+//! Topics below `mqtt.base_topic` (default `hyperx-pilot`):
+//! - `status`: `online`/`offline`
+//! - `<device>/connected`, `<device>/mic_mute`: `ON`/`OFF`
+//! - `<device>/battery`: percent
+//! - `<device>/mic_mute/set`: commands, `ON`/`OFF`
+
+mod packet;
+
+use crate::audio;
+use crate::audit::{self, Source};
+use crate::events::{DeviceEvent, EventBus};
+use crate::hyperx::{self, DeviceId, DeviceMetadata, Priority};
+use crate::settings::{MqttSettings, SettingsStore};
+use packet::{Connect, Incoming};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+const KEEP_ALIVE_SECS: u16 = 60;
+/// Pings go out, and batteries are polled, when nothing happened for this long.
+const IDLE_INTERVAL: Duration = Duration::from_secs(KEEP_ALIVE_SECS as u64 / 2);
+const CONNACK_TIMEOUT: Duration = Duration::from_secs(10);
+const RECONNECT_DELAY: Duration = Duration::from_secs(15);
+
+#[derive(Debug)]
+pub enum MqttError {
+    Io { source: io::Error },
+    Refused { return_code: u8 },
+    NoConnAck,
+}
+
+impl fmt::Display for MqttError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MqttError::Io { source } => write!(f, "{source}"),
+            MqttError::Refused { return_code } => {
+                let reason = match return_code {
+                    1 => "unsupported protocol version",
+                    2 => "client id rejected",
+                    3 => "server unavailable",
+                    4 => "bad user name or password",
+                    5 => "not authorized",
+                    _ => "unknown reason",
+                };
+                write!(f, "broker refused the connection: {reason}")
+            }
+            MqttError::NoConnAck => write!(f, "broker did not acknowledge the connection"),
+        }
+    }
+}
+
+impl std::error::Error for MqttError {}
+
+impl From<io::Error> for MqttError {
+    fn from(source: io::Error) -> Self {
+        MqttError::Io { source }
+    }
+}
+
+/// What Home Assistant is told about a device; `None` until first known.
+#[derive(Debug, Clone, Copy, Default)]
+struct DeviceState {
+    connected: bool,
+    battery: Option<u8>,
+    muted: Option<bool>,
+}
+
+/// Keep a broker connection up until the process exits, reconnecting after
+/// failures. Events missed in between are folded into the state that is
+/// published on reconnect.
+pub fn spawn(settings: Arc<SettingsStore>, events: &EventBus) {
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        let mut states: HashMap<DeviceId, DeviceState> = HashMap::new();
+        loop {
+            for event in receiver.try_iter() {
+                apply_event(&mut states, &event);
+            }
+            let config = settings.get().mqtt;
+            match Session::open(&config) {
+                Ok(session) => {
+                    if let Err(err) = session.run(&receiver, &mut states) {
+                        warn!("MQTT connection lost: {err}");
+                    }
+                }
+                Err(err) => warn!(
+                    "MQTT connection to {}:{} failed: {err}",
+                    config.host, config.port
+                ),
+            }
+            thread::sleep(RECONNECT_DELAY);
+        }
+    });
+}
+
+/// Fold `event` into `states`, returning the device whose state changed.
+fn apply_event(
+    states: &mut HashMap<DeviceId, DeviceState>,
+    event: &DeviceEvent,
+) -> Option<DeviceId> {
+    match *event {
+        DeviceEvent::Connected { device_id } => {
+            states.entry(device_id).or_default().connected = true;
+            Some(device_id)
+        }
+        DeviceEvent::Disconnected { device_id } => {
+            states.insert(device_id, DeviceState::default());
+            Some(device_id)
+        }
+        DeviceEvent::BatteryChanged { device_id, percent }
+        | DeviceEvent::BatteryLow { device_id, percent } => {
+            states.entry(device_id).or_default().battery = Some(percent);
+            Some(device_id)
+        }
+        DeviceEvent::MicMuteChanged { device_id, muted } => {
+            states.entry(device_id).or_default().muted = Some(muted);
+            Some(device_id)
+        }
+        DeviceEvent::HeadsetPoweredOn { .. }
+        | DeviceEvent::HeadsetPoweredOff { .. }
+        | DeviceEvent::FeatureChanged { .. }
+        | DeviceEvent::BoomMoved { .. }
+        | DeviceEvent::ButtonPressed { .. }
+        | DeviceEvent::DialTurned { .. }
+        | DeviceEvent::Resumed
+        | DeviceEvent::SessionChanged { .. } => None,
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "ON"
+    } else {
+        "OFF"
+    }
+}
+
+struct Session {
+    stream: TcpStream,
+    config: MqttSettings,
+}
+
+impl Session {
+    fn open(config: &MqttSettings) -> Result<Session, MqttError> {
+        let stream = TcpStream::connect((config.host.as_str(), config.port))?;
+        let status = format!("{}/status", config.base_topic);
+        packet::connect(
+            &mut &stream,
+            &Connect {
+                client_id: &config.client_id,
+                keep_alive_secs: KEEP_ALIVE_SECS,
+                username: config.username.as_deref(),
+                password: config.password.as_deref(),
+                will: (&status, "offline"),
+            },
+        )?;
+        stream.set_read_timeout(Some(CONNACK_TIMEOUT))?;
+        match packet::read(&mut &stream)? {
+            Incoming::ConnAck { return_code: 0 } => {}
+            Incoming::ConnAck { return_code } => return Err(MqttError::Refused { return_code }),
+            _ => return Err(MqttError::NoConnAck),
+        }
+        stream.set_read_timeout(None)?;
+        Ok(Session {
+            stream,
+            config: config.clone(),
+        })
+    }
+
+    fn topic(&self, device_id: DeviceId, name: &str) -> String {
+        format!("{}/{}/{name}", self.config.base_topic, device_id)
+    }
+
+    fn publish(&self, topic: &str, payload: &str) -> io::Result<()> {
+        packet::publish(&mut &self.stream, topic, payload.as_bytes(), true)
+    }
+
+    fn run(
+        self,
+        receiver: &Receiver<DeviceEvent>,
+        states: &mut HashMap<DeviceId, DeviceState>,
+    ) -> Result<(), MqttError> {
+        self.publish(&format!("{}/status", self.config.base_topic), "online")?;
+        for device in hyperx::list_devices() {
+            self.announce(&device)?;
+            self.publish_state(
+                device.id,
+                states.get(&device.id).copied().unwrap_or_default(),
+            )?;
+        }
+        let commands = format!("{}/+/mic_mute/set", self.config.base_topic);
+        packet::subscribe(&mut &self.stream, 1, &commands)?;
+        self.spawn_command_reader()?;
+
+        loop {
+            let device_id = match receiver.recv_timeout(IDLE_INTERVAL) {
+                Ok(event) => apply_event(states, &event),
+                Err(RecvTimeoutError::Timeout) => {
+                    packet::ping(&mut &self.stream)?;
+                    self.refresh(states)?;
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    let _ = packet::disconnect(&mut &self.stream);
+                    return Ok(());
+                }
+            };
+            if let Some(device_id) = device_id {
+                let state = states.entry(device_id).or_default();
+                if state.connected && state.muted.is_none() {
+                    state.muted = audio::mic_muted(device_id).ok();
+                }
+                self.publish_state(device_id, *state)?;
+            }
+        }
+    }
+
+    /// Poll what has no change event: battery levels, and mute states that
+    /// could not be read yet when the device connected.
+    fn refresh(&self, states: &mut HashMap<DeviceId, DeviceState>) -> io::Result<()> {
+        for (&device_id, state) in states.iter_mut() {
+            if !state.connected {
+                continue;
+            }
+            let before = (state.battery, state.muted);
+            if hyperx::devices().get(device_id).has_battery() {
+                let battery = hyperx::with_priority(Priority::Background, || {
+                    hyperx::devices().get(device_id).read_battery()
+                });
+                if let Ok(Some(percent)) = battery {
+                    state.battery = Some(percent);
+                }
+            }
+            if state.muted.is_none() {
+                state.muted = audio::mic_muted(device_id).ok();
+            }
+            if before != (state.battery, state.muted) {
+                self.publish_state(device_id, *state)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn publish_state(&self, device_id: DeviceId, state: DeviceState) -> io::Result<()> {
+        self.publish(&self.topic(device_id, "connected"), on_off(state.connected))?;
+        if let Some(percent) = state.battery {
+            self.publish(&self.topic(device_id, "battery"), &percent.to_string())?;
+        }
+        if let Some(muted) = state.muted {
+            self.publish(&self.topic(device_id, "mic_mute"), on_off(muted))?;
+        }
+        Ok(())
+    }
+
+    /// Publish the retained discovery configs for one device's entities.
+    fn announce(&self, device: &DeviceMetadata) -> io::Result<()> {
+        let node = format!("hyperx_pilot_{}", device.id);
+        let shared = json!({
+            "availability_topic": format!("{}/status", self.config.base_topic),
+            "device": {
+                "identifiers": [node],
+                "name": format!("HyperX {}", device.label),
+                "manufacturer": "HyperX",
+                "model": device.label,
+            },
+        });
+        let mut entities = vec![
+            (
+                "binary_sensor",
+                "connected",
+                json!({
+                    "name": "Connected",
+                    "device_class": "connectivity",
+                    "state_topic": self.topic(device.id, "connected"),
+                }),
+            ),
+            (
+                "switch",
+                "mic_mute",
+                json!({
+                    "name": "Microphone muted",
+                    "icon": "mdi:microphone-off",
+                    "state_topic": self.topic(device.id, "mic_mute"),
+                    "command_topic": self.topic(device.id, "mic_mute/set"),
+                }),
+            ),
+        ];
+        if hyperx::devices().get(device.id).has_battery() {
+            entities.push((
+                "sensor",
+                "battery",
+                json!({
+                    "name": "Battery",
+                    "device_class": "battery",
+                    "state_class": "measurement",
+                    "unit_of_measurement": "%",
+                    "state_topic": self.topic(device.id, "battery"),
+                }),
+            ));
+        }
+        for (component, object, mut config) in entities {
+            config["unique_id"] = json!(format!("{node}_{object}"));
+            for (key, value) in shared.as_object().into_iter().flatten() {
+                config[key] = value.clone();
+            }
+            let topic = format!(
+                "{}/{component}/{node}/{object}/config",
+                self.config.discovery_prefix
+            );
+            self.publish(&topic, &config.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Handle command topics on their own thread. When the connection drops
+    /// the socket is shut down, so the publishing side fails and reconnects.
+    fn spawn_command_reader(&self) -> io::Result<()> {
+        let mut stream = self.stream.try_clone()?;
+        let prefix = format!("{}/", self.config.base_topic);
+        thread::spawn(move || {
+            while let Ok(incoming) = packet::read(&mut stream) {
+                let Incoming::Publish { topic, payload } = incoming else {
+                    continue;
+                };
+                let Some(device) = topic
+                    .strip_prefix(&prefix)
+                    .and_then(|rest| rest.strip_suffix("/mic_mute/set"))
+                else {
+                    continue;
+                };
+                let Ok(device_id) = device.parse::<DeviceId>() else {
+                    continue;
+                };
+                let muted = match payload.as_slice() {
+                    b"ON" => true,
+                    b"OFF" => false,
+                    _ => continue,
+                };
+                let result = audio::set_mic_mute(device_id, muted);
+                let result = audit::record(
+                    Source::Mqtt,
+                    Some(device_id),
+                    "set_mic_mute",
+                    json!(muted),
+                    result,
+                );
+                if let Err(err) = result {
+                    warn!("MQTT mute command for {device} failed: {err}");
+                }
+            }
+            let _ = stream.shutdown(Shutdown::Both);
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+//! Encoding and decoding of the MQTT 3.1.1 control packets the client uses.
+//! Everything is QoS 0, so no packet needs acknowledging.
+
+use std::io::{self, Read, Write};
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: u8 = 0xC0;
+const DISCONNECT: u8 = 0xE0;
+
+const FLAG_CLEAN_SESSION: u8 = 0x02;
+const FLAG_WILL: u8 = 0x04;
+const FLAG_WILL_RETAIN: u8 = 0x20;
+const FLAG_PASSWORD: u8 = 0x40;
+const FLAG_USERNAME: u8 = 0x80;
+const MAX_INCOMING: usize = 256 * 1024;
+
+pub struct Connect<'a> {
+    pub client_id: &'a str,
+    pub keep_alive_secs: u16,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a str>,
+    /// Retained message the broker publishes when the connection drops.
+    pub will: (&'a str, &'a str),
+}
+
+pub enum Incoming {
+    ConnAck { return_code: u8 },
+    Publish { topic: String, payload: Vec<u8> },
+    Other,
+}
+
+fn invalid(detail: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, detail.to_string())
+}
+
+fn put_string(buffer: &mut Vec<u8>, value: &[u8]) {
+    buffer.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(value);
+}
+
+fn write_packet(writer: &mut impl Write, header: u8, body: &[u8]) -> io::Result<()> {
+    let mut packet = vec![header];
+    let mut remaining = body.len();
+    loop {
+        let mut byte = (remaining % 128) as u8;
+        remaining /= 128;
+        if remaining > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if remaining == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    writer.write_all(&packet)?;
+    writer.flush()
+}
+
+pub fn connect(writer: &mut impl Write, connect: &Connect<'_>) -> io::Result<()> {
+    let mut flags = FLAG_CLEAN_SESSION | FLAG_WILL | FLAG_WILL_RETAIN;
+    if connect.username.is_some() {
+        flags |= FLAG_USERNAME;
+    }
+    if connect.password.is_some() {
+        flags |= FLAG_PASSWORD;
+    }
+    let mut body = Vec::new();
+    put_string(&mut body, b"MQTT");
+    body.push(4);
+    body.push(flags);
+    body.extend_from_slice(&connect.keep_alive_secs.to_be_bytes());
+    put_string(&mut body, connect.client_id.as_bytes());
+    put_string(&mut body, connect.will.0.as_bytes());
+    put_string(&mut body, connect.will.1.as_bytes());
+    for value in [connect.username, connect.password].into_iter().flatten() {
+        put_string(&mut body, value.as_bytes());
+    }
+    write_packet(writer, CONNECT, &body)
+}
+
+pub fn publish(
+    writer: &mut impl Write,
+    topic: &str,
+    payload: &[u8],
+    retain: bool,
+) -> io::Result<()> {
+    let mut body = Vec::new();
+    put_string(&mut body, topic.as_bytes());
+    body.extend_from_slice(payload);
+    write_packet(writer, PUBLISH | u8::from(retain), &body)
+}
+
+pub fn subscribe(writer: &mut impl Write, packet_id: u16, filter: &str) -> io::Result<()> {
+    let mut body = packet_id.to_be_bytes().to_vec();
+    put_string(&mut body, filter.as_bytes());
+    body.push(0);
+    write_packet(writer, SUBSCRIBE, &body)
+}
+
+pub fn ping(writer: &mut impl Write) -> io::Result<()> {
+    write_packet(writer, PINGREQ, &[])
+}
+
+pub fn disconnect(writer: &mut impl Write) -> io::Result<()> {
+    write_packet(writer, DISCONNECT, &[])
+}
+
+pub fn read(reader: &mut impl Read) -> io::Result<Incoming> {
+    let mut header = [0u8; 1];
+    reader.read_exact(&mut header)?;
+    let mut length = 0usize;
+    for shift in 0..4 {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        length |= usize::from(byte[0] & 0x7F) << (7 * shift);
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    if length > MAX_INCOMING {
+        return Err(invalid("packet too large"));
+    }
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body)?;
+
+    match header[0] & 0xF0 {
+        CONNACK if body.len() == 2 => Ok(Incoming::ConnAck {
+            return_code: body[1],
+        }),
+        PUBLISH => {
+            let qos = (header[0] >> 1) & 0x03;
+            let Some(topic_length) = body.get(..2) else {
+                return Err(invalid("truncated publish"));
+            };
+            let topic_end = 2 + usize::from(u16::from_be_bytes([topic_length[0], topic_length[1]]));
+            // QoS 1 and 2 carry a packet id before the payload.
+            let payload_start = topic_end + if qos > 0 { 2 } else { 0 };
+            if payload_start > body.len() {
+                return Err(invalid("truncated publish"));
+            }
+            Ok(Incoming::Publish {
+                topic: String::from_utf8_lossy(&body[2..topic_end]).into_owned(),
+                payload: body[payload_start..].to_vec(),
+            })
+        }
+        _ => Ok(Incoming::Other),
+    }
+}
@@ -0,0 +1,314 @@
+//! Optional client for the OBS WebSocket protocol (v5, built into OBS 28+).
+//! Headset microphone mutes are mirrored onto the OBS inputs configured in
+//! `obs.sources`, and the headsets' mute LEDs flash when a recording starts.
+//! On flip-to-mute headsets a raised boom mutes the OBS inputs as well, even
+//! while the OS mixer still reports the microphone as live.
+
+use crate::actions;
+use crate::audio;
+use crate::events::{DeviceEvent, EventBus};
+use crate::hyperx::{self, BoomPosition, DeviceId};
+use crate::settings::{ObsSettings, SettingsStore};
+use crate::websocket::{self, Role, OPCODE_CLOSE, OPCODE_PING, OPCODE_PONG, OPCODE_TEXT};
+use base64::Engine;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, BufReader, Read};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+const RPC_VERSION: u64 = 1;
+/// The `Scenes` and `Outputs` event categories.
+const EVENT_SUBSCRIPTIONS: u64 = (1 << 2) | (1 << 6);
+const OP_HELLO: u64 = 0;
+const OP_IDENTIFY: u64 = 1;
+const OP_IDENTIFIED: u64 = 2;
+const OP_EVENT: u64 = 5;
+const OP_REQUEST: u64 = 6;
+const OP_REQUEST_RESPONSE: u64 = 7;
+const SCENE_REQUEST_ID: &str = "current-scene";
+
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(15);
+
+#[derive(Debug)]
+pub enum ObsError {
+    Io { source: io::Error },
+    Protocol { detail: String },
+    PasswordRequired,
+}
+
+impl fmt::Display for ObsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObsError::Io { source } => write!(f, "{source}"),
+            ObsError::Protocol { detail } => write!(f, "unexpected OBS message: {detail}"),
+            ObsError::PasswordRequired => {
+                write!(f, "OBS requires a password; set obs.password")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ObsError {}
+
+impl From<io::Error> for ObsError {
+    fn from(source: io::Error) -> Self {
+        ObsError::Io { source }
+    }
+}
+
+/// Keep a connection to OBS while the process runs. OBS is often not open,
+/// so a failure is only logged once until the next successful connection.
+pub fn spawn(settings: Arc<SettingsStore>, events: &EventBus) {
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        let mut logged_failure = false;
+        loop {
+            // Mutes from while OBS was away are stale by now.
+            receiver.try_iter().for_each(drop);
+            let config = settings.get().obs;
+            match Session::open(&config) {
+                Ok((session, reader)) => {
+                    logged_failure = false;
+                    if let Err(err) = session.run(reader, &config, &receiver) {
+                        warn!("OBS connection lost: {err}");
+                    }
+                }
+                Err(err) if !logged_failure => {
+                    logged_failure = true;
+                    warn!(
+                        "cannot connect to OBS at {}:{}: {err}",
+                        config.host, config.port
+                    );
+                }
+                Err(_) => {}
+            }
+            thread::sleep(RECONNECT_DELAY);
+        }
+    });
+}
+
+/// `base64(sha256(base64(sha256(password + salt)) + challenge))`.
+fn authentication(password: &str, salt: &str, challenge: &str) -> String {
+    let encode =
+        |data: &[u8]| base64::engine::general_purpose::STANDARD.encode(Sha256::digest(data));
+    let secret = encode(format!("{password}{salt}").as_bytes());
+    encode(format!("{secret}{challenge}").as_bytes())
+}
+
+struct Session {
+    writer: Arc<Mutex<TcpStream>>,
+    next_request: AtomicU64,
+}
+
+impl Session {
+    /// Connect and identify; the reader is for everything OBS sends afterwards.
+    fn open(config: &ObsSettings) -> Result<(Session, BufReader<TcpStream>), ObsError> {
+        let (stream, mut reader) =
+            websocket::connect(&config.host, config.port, "/", Some("obswebsocket.json"))?;
+        let session = Session {
+            writer: Arc::new(Mutex::new(stream)),
+            next_request: AtomicU64::new(0),
+        };
+
+        let hello = session.expect(&mut reader, OP_HELLO)?;
+        let mut identify = json!({
+            "rpcVersion": RPC_VERSION,
+            "eventSubscriptions": EVENT_SUBSCRIPTIONS,
+        });
+        if let Some(auth) = hello.get("authentication") {
+            let password = config
+                .password
+                .as_deref()
+                .ok_or(ObsError::PasswordRequired)?;
+            let field = |name: &str| auth[name].as_str().unwrap_or_default().to_string();
+            identify["authentication"] = json!(authentication(
+                password,
+                &field("salt"),
+                &field("challenge")
+            ));
+        }
+        session.send(OP_IDENTIFY, identify)?;
+        // OBS closes the connection instead of answering a wrong password.
+        session.expect(&mut reader, OP_IDENTIFIED)?;
+        Ok((session, reader))
+    }
+
+    fn send(&self, op: u64, data: Value) -> io::Result<()> {
+        let message = json!({ "op": op, "d": data }).to_string();
+        websocket::write_frame(
+            &mut *self.writer.lock().unwrap(),
+            OPCODE_TEXT,
+            message.as_bytes(),
+            Role::Client,
+        )
+    }
+
+    fn request(&self, request_type: &str, request_id: Option<&str>, data: Value) -> io::Result<()> {
+        let request_id = request_id.map_or_else(
+            || {
+                self.next_request
+                    .fetch_add(1, Ordering::Relaxed)
+                    .to_string()
+            },
+            str::to_string,
+        );
+        self.send(
+            OP_REQUEST,
+            json!({ "requestType": request_type, "requestId": request_id, "requestData": data }),
+        )
+    }
+
+    fn expect(&self, reader: &mut impl Read, op: u64) -> Result<Value, ObsError> {
+        let (received, data) = read_message(reader, &self.writer)?;
+        if received != op {
+            return Err(ObsError::Protocol {
+                detail: format!("expected op {op}, got {received}"),
+            });
+        }
+        Ok(data)
+    }
+
+    fn run(
+        &self,
+        reader: BufReader<TcpStream>,
+        config: &ObsSettings,
+        receiver: &Receiver<DeviceEvent>,
+    ) -> Result<(), ObsError> {
+        let scene = Arc::new(Mutex::new(None::<String>));
+        {
+            let writer = self.writer.clone();
+            let scene = scene.clone();
+            let flash = config.flash_led_on_record;
+            thread::spawn(move || read_updates(reader, &writer, &scene, flash));
+        }
+        self.request("GetCurrentProgramScene", Some(SCENE_REQUEST_ID), json!({}))?;
+
+        let mut booms_up: HashSet<DeviceId> = HashSet::new();
+        loop {
+            let (device_id, muted) = match receiver.recv_timeout(PING_INTERVAL) {
+                Ok(DeviceEvent::MicMuteChanged { device_id, muted }) => {
+                    (device_id, muted || booms_up.contains(&device_id))
+                }
+                Ok(DeviceEvent::BoomMoved {
+                    device_id,
+                    position,
+                }) => {
+                    let up = position == BoomPosition::Up;
+                    if up {
+                        booms_up.insert(device_id);
+                    } else {
+                        booms_up.remove(&device_id);
+                    }
+                    (
+                        device_id,
+                        up || audio::mic_muted(device_id).unwrap_or(false),
+                    )
+                }
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => {
+                    websocket::write_frame(
+                        &mut *self.writer.lock().unwrap(),
+                        OPCODE_PING,
+                        &[],
+                        Role::Client,
+                    )?;
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            };
+            let current = scene.lock().unwrap().clone();
+            for source in &config.sources {
+                let in_scene = source.scene.is_none() || source.scene == current;
+                if source.device_id == device_id && in_scene {
+                    self.request(
+                        "SetInputMute",
+                        None,
+                        json!({ "inputName": source.input, "inputMuted": muted }),
+                    )?;
+                }
+            }
+        }
+    }
+}
+
+/// Read the next text message as `(op, d)`, answering pings on the way.
+fn read_message(reader: &mut impl Read, writer: &Mutex<TcpStream>) -> io::Result<(u64, Value)> {
+    loop {
+        let (opcode, payload) = websocket::read_frame(reader)?;
+        match opcode {
+            OPCODE_TEXT => {
+                let message: Value = serde_json::from_slice(&payload)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                let op = message["op"].as_u64().unwrap_or(u64::MAX);
+                return Ok((op, message["d"].clone()));
+            }
+            OPCODE_PING => websocket::write_frame(
+                &mut *writer.lock().unwrap(),
+                OPCODE_PONG,
+                &payload,
+                Role::Client,
+            )?,
+            OPCODE_CLOSE => {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "OBS closed the connection",
+                ))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Track the program scene and react to recordings starting until the
+/// connection drops, then shut the socket so the sending side notices too.
+fn read_updates(
+    mut reader: BufReader<TcpStream>,
+    writer: &Mutex<TcpStream>,
+    scene: &Mutex<Option<String>>,
+    flash_on_record: bool,
+) {
+    while let Ok((op, data)) = read_message(&mut reader, writer) {
+        match op {
+            OP_REQUEST_RESPONSE if data["requestId"] == SCENE_REQUEST_ID => {
+                let name = &data["responseData"]["currentProgramSceneName"];
+                *scene.lock().unwrap() = name.as_str().map(str::to_string);
+            }
+            OP_EVENT => {
+                let payload = &data["eventData"];
+                match data["eventType"].as_str() {
+                    Some("CurrentProgramSceneChanged") => {
+                        *scene.lock().unwrap() = payload["sceneName"].as_str().map(str::to_string);
+                    }
+                    Some("RecordStateChanged")
+                        if flash_on_record
+                            && payload["outputState"] == "OBS_WEBSOCKET_OUTPUT_STARTED" =>
+                    {
+                        flash_mute_leds();
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    let _ = writer.lock().unwrap().shutdown(Shutdown::Both);
+}
+
+/// Flash the mute LED of every headset that has one. Disconnected headsets
+/// simply fail and are skipped.
+fn flash_mute_leds() {
+    for device in hyperx::list_devices() {
+        if hyperx::devices().get(device.id).has_mute_led() {
+            let _ = actions::flash_mute_led(device.id);
+        }
+    }
+}
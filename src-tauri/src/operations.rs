@@ -0,0 +1,147 @@
+//! Long-running operations and their cancellation. Firmware flashes,
+//! receiver pairings and self-tests are registered here while they run,
+//! under an [`OperationId`] that their progress events carry and [`list`]
+//! returns, so the frontend can stop one with `cancel_operation`. An
+//! operation only looks at its [`CancelToken`] where stopping leaves the
+//! device usable: a flash after the current chunk, cancelling the upload so
+//! the headset keeps its old image; a pairing at its next poll, taking the
+//! receiver out of pairing mode again; a self-test before its next feature.
+//!
+//! Besides their own events, flashes and pairings report every step as a
+//! [`Progress`] in one shape, so one progress bar can follow any operation.
+
+use crate::hyperx::DeviceId;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Name of the Tauri event carrying each [`Progress`] to the frontend.
+pub const PROGRESS_EVENT: &str = "operation-progress";
+
+pub type OperationId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    FirmwareFlash,
+    Pairing,
+    SelfTest,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationInfo {
+    pub id: OperationId,
+    pub kind: OperationKind,
+    pub device_id: DeviceId,
+}
+
+/// One step of an operation.
+#[derive(Debug, Clone, Serialize)]
+pub struct Progress {
+    pub operation_id: OperationId,
+    pub kind: OperationKind,
+    pub device_id: DeviceId,
+    /// The operation's own name for the step, e.g. `writing`.
+    pub step: &'static str,
+    /// How far the step is, when that can be measured.
+    pub percent: Option<u8>,
+    /// What is happening or what the user should do, in English.
+    pub message: Option<&'static str>,
+}
+
+/// `done` of `total` in percent; `None` while the total is unknown.
+pub fn percent(done: usize, total: usize) -> Option<u8> {
+    (total > 0).then(|| (done.min(total) * 100 / total) as u8)
+}
+
+/// Set once the operation should stop at its next safe point.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+struct Running {
+    info: OperationInfo,
+    token: CancelToken,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static RUNNING: Mutex<Vec<Running>> = Mutex::new(Vec::new());
+
+/// A registered operation; dropping it, however the operation ends,
+/// unregisters it.
+pub struct Operation {
+    id: OperationId,
+    token: CancelToken,
+}
+
+impl Operation {
+    pub fn id(&self) -> OperationId {
+        self.id
+    }
+
+    pub fn token(&self) -> &CancelToken {
+        &self.token
+    }
+}
+
+impl Drop for Operation {
+    fn drop(&mut self) {
+        RUNNING
+            .lock()
+            .unwrap()
+            .retain(|running| running.info.id != self.id);
+    }
+}
+
+/// Register a `kind` operation on `device_id` that is about to start.
+pub fn begin(kind: OperationKind, device_id: DeviceId) -> Operation {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let token = CancelToken::default();
+    RUNNING.lock().unwrap().push(Running {
+        info: OperationInfo {
+            id,
+            kind,
+            device_id,
+        },
+        token: token.clone(),
+    });
+    Operation { id, token }
+}
+
+/// The running operations, oldest first.
+pub fn list() -> Vec<OperationInfo> {
+    RUNNING
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|running| running.info.clone())
+        .collect()
+}
+
+/// Ask operation `id` to stop; `false` when it is not running (any more).
+pub fn cancel(id: OperationId) -> bool {
+    let running = RUNNING.lock().unwrap();
+    let Some(running) = running.iter().find(|running| running.info.id == id) else {
+        return false;
+    };
+    running.token.cancel();
+    true
+}
+
+/// Ask every running operation of `kind` to stop.
+pub fn cancel_all(kind: OperationKind) {
+    for running in RUNNING.lock().unwrap().iter() {
+        if running.info.kind == kind {
+            running.token.cancel();
+        }
+    }
+}
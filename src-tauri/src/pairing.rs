@@ -0,0 +1,187 @@
+//! Pairing a wireless headset with its USB receiver, e.g. a replacement
+//! receiver or one that was just flashed. The receiver is put into pairing
+//! mode, the user is asked to put the headset into pairing mode too, and the
+//! receiver is polled until it reports the link. Every step is reported, so
+//! the frontend can show what to do next. A cancelled pairing stops at its
+//! next poll, see [`crate::operations`].
+
+use crate::hyperx::{self, Capability, ControlError, DeviceId};
+use crate::operations::{self, CancelToken, Operation, OperationId, OperationKind};
+use serde::Serialize;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Name of the Tauri event carrying each [`Progress`] to the frontend.
+pub const PROGRESS_EVENT: &str = "pairing-progress";
+/// Covers a receiver rebooting after a flash and the user finding the button.
+pub const TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const HEADSET_INSTRUCTION: &str =
+    "Turn the headset off, then hold its power button until the LED flashes quickly.";
+
+static PAIRING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug)]
+pub enum PairingError {
+    Control(ControlError),
+    /// Another pairing is running.
+    Busy,
+    Cancelled,
+    TimedOut,
+}
+
+impl fmt::Display for PairingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PairingError::Control(err) => err.fmt(f),
+            PairingError::Busy => write!(f, "a pairing is already running"),
+            PairingError::Cancelled => write!(f, "pairing was cancelled"),
+            PairingError::TimedOut => write!(
+                f,
+                "the receiver did not find the headset; make sure it is charged and in pairing mode, then try again"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PairingError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    /// The receiver is being put into pairing mode.
+    PreparingReceiver,
+    /// The receiver is listening; the user has to act on the headset.
+    WaitingForHeadset,
+    Paired,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Progress {
+    pub operation_id: OperationId,
+    pub device_id: DeviceId,
+    pub step: Step,
+    /// What the user should do now.
+    pub instruction: Option<&'static str>,
+    /// Seconds left before pairing gives up.
+    pub remaining_secs: u64,
+}
+
+impl Step {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Step::PreparingReceiver => "preparing_receiver",
+            Step::WaitingForHeadset => "waiting_for_headset",
+            Step::Paired => "paired",
+        }
+    }
+}
+
+impl Progress {
+    /// This step as an [`operations::Progress`]; waiting for the headset
+    /// has no measurable progress.
+    pub fn summary(&self) -> operations::Progress {
+        operations::Progress {
+            operation_id: self.operation_id,
+            kind: OperationKind::Pairing,
+            device_id: self.device_id,
+            step: self.step.as_str(),
+            percent: (self.step == Step::Paired).then_some(100),
+            message: self.instruction,
+        }
+    }
+}
+
+/// Clears [`PAIRING`] however the pairing ends.
+struct PairingGuard;
+
+impl Drop for PairingGuard {
+    fn drop(&mut self) {
+        PAIRING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Pair the receiver of `device_id` with its headset as part of `operation`,
+/// reporting every step to `progress`. Receiver errors are retried until
+/// `timeout`, since a freshly flashed receiver is still rebooting.
+pub fn pair(
+    device_id: DeviceId,
+    timeout: Duration,
+    operation: &Operation,
+    mut progress: impl FnMut(Progress),
+) -> Result<(), PairingError> {
+    let device = hyperx::devices().get(device_id);
+    if !device.needs_pairing() {
+        return Err(PairingError::Control(ControlError::UnsupportedFeature {
+            device_id,
+            capability: Capability::ReceiverPairing,
+        }));
+    }
+    if PAIRING.swap(true, Ordering::SeqCst) {
+        return Err(PairingError::Busy);
+    }
+    let _guard = PairingGuard;
+    let cancel = operation.token();
+
+    let deadline = Instant::now() + timeout;
+    let mut report = |step, instruction| {
+        progress(Progress {
+            operation_id: operation.id(),
+            device_id,
+            step,
+            instruction,
+            remaining_secs: deadline.saturating_duration_since(Instant::now()).as_secs(),
+        })
+    };
+    // Errors only mean "not yet" until time runs out.
+    let retry = |result: Result<bool, ControlError>| match result {
+        Ok(done) => Ok(done),
+        Err(_) if Instant::now() < deadline => Ok(false),
+        Err(err) => Err(PairingError::Control(err)),
+    };
+
+    report(Step::PreparingReceiver, None);
+    while !retry(device.set_pairing_mode(true).map(|()| true))? {
+        wait(deadline, cancel)?;
+    }
+    report(Step::WaitingForHeadset, Some(HEADSET_INSTRUCTION));
+    loop {
+        match retry(device.is_paired()) {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(err) => {
+                stop(device_id);
+                return Err(err);
+            }
+        }
+        if let Err(err) = wait(deadline, cancel) {
+            stop(device_id);
+            return Err(err);
+        }
+        report(Step::WaitingForHeadset, Some(HEADSET_INSTRUCTION));
+    }
+    info!(device_id = %device_id, "receiver paired");
+    report(Step::Paired, None);
+    Ok(())
+}
+
+/// Sleep until the next poll, unless the pairing was cancelled or timed out.
+fn wait(deadline: Instant, cancel: &CancelToken) -> Result<(), PairingError> {
+    if cancel.is_cancelled() {
+        return Err(PairingError::Cancelled);
+    }
+    if Instant::now() >= deadline {
+        return Err(PairingError::TimedOut);
+    }
+    thread::sleep(POLL_INTERVAL);
+    Ok(())
+}
+
+fn stop(device_id: DeviceId) {
+    if let Err(err) = hyperx::devices().get(device_id).set_pairing_mode(false) {
+        warn!("failed to take the receiver out of pairing mode: {err}");
+    }
+}
@@ -0,0 +1,188 @@
+//! Session bus service so scripts, status bars and window managers can drive
+//! the headset without the GUI, e.g.
+//! `busctl --user call org.datascaled.HyperxPilot /org/datascaled/HyperxPilot
+//! org.datascaled.HyperxPilot1 SetSidetone sb cloud_iii_wired true`.
+
+use crate::audio;
+use crate::audit::{self, Source};
+use crate::events::{DeviceEvent, EventBus};
+use crate::hyperx::{self, DeviceId};
+use crate::profiles::{self, DeviceSettings};
+use crate::settings::SettingsStore;
+use serde_json::json;
+use std::sync::Arc;
+use std::thread;
+use tracing::warn;
+use zbus::blocking::connection;
+use zbus::fdo;
+use zbus::object_server::SignalEmitter;
+
+pub const BUS_NAME: &str = "org.datascaled.HyperxPilot";
+pub const OBJECT_PATH: &str = "/org/datascaled/HyperxPilot";
+
+struct ControlService {
+    settings: Arc<SettingsStore>,
+}
+
+fn parse_device(device_id: &str) -> fdo::Result<DeviceId> {
+    device_id
+        .parse()
+        .map_err(|err: hyperx::UnknownDevice| fdo::Error::InvalidArgs(err.to_string()))
+}
+
+fn failed(err: impl std::fmt::Display) -> fdo::Error {
+    fdo::Error::Failed(err.to_string())
+}
+
+#[zbus::interface(name = "org.datascaled.HyperxPilot1")]
+impl ControlService {
+    fn list_devices(&self) -> Vec<String> {
+        hyperx::list_devices()
+            .iter()
+            .map(|device| device.id.to_string())
+            .collect()
+    }
+
+    fn get_sidetone(&self, device_id: &str) -> fdo::Result<bool> {
+        hyperx::devices()
+            .get(parse_device(device_id)?)
+            .read_sidetone_state()
+            .map_err(failed)?
+            .ok_or_else(|| failed("device reported an unreadable sidetone state"))
+    }
+
+    fn set_sidetone(&self, device_id: &str, enabled: bool) -> fdo::Result<()> {
+        let values = DeviceSettings {
+            sidetone: Some(enabled),
+            ..DeviceSettings::default()
+        };
+        let device_id = parse_device(device_id)?;
+        let result = profiles::apply(&self.settings, device_id, &values);
+        audit::record(
+            Source::DBus,
+            Some(device_id),
+            "set_sidetone",
+            json!(enabled),
+            result,
+        )
+        .map_err(failed)
+    }
+
+    fn set_mic_mute(&self, device_id: &str, muted: bool) -> fdo::Result<()> {
+        let device_id = parse_device(device_id)?;
+        let result = audio::set_mic_mute(device_id, muted);
+        audit::record(
+            Source::DBus,
+            Some(device_id),
+            "set_mic_mute",
+            json!(muted),
+            result,
+        )
+        .map_err(failed)
+    }
+
+    fn toggle_mic_mute(&self, device_id: &str) -> fdo::Result<()> {
+        let device_id = parse_device(device_id)?;
+        let result = audio::toggle_mic_mute(device_id);
+        audit::record(
+            Source::DBus,
+            Some(device_id),
+            "toggle_mic_mute",
+            json!(null),
+            result,
+        )
+        .map_err(failed)
+    }
+
+    fn list_profiles(&self) -> Vec<String> {
+        self.settings
+            .get()
+            .profiles
+            .into_iter()
+            .map(|profile| profile.name)
+            .collect()
+    }
+
+    fn apply_profile(&self, name: &str) -> fdo::Result<()> {
+        let result = profiles::apply_named(&self.settings, name);
+        audit::record(Source::DBus, None, "apply_profile", json!(name), result).map_err(failed)
+    }
+
+    #[zbus(signal)]
+    async fn device_connected(emitter: &SignalEmitter<'_>, device_id: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn device_disconnected(emitter: &SignalEmitter<'_>, device_id: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn mic_mute_changed(
+        emitter: &SignalEmitter<'_>,
+        device_id: &str,
+        muted: bool,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn battery_low(
+        emitter: &SignalEmitter<'_>,
+        device_id: &str,
+        percent: u8,
+    ) -> zbus::Result<()>;
+}
+
+/// Claim [`BUS_NAME`] on the session bus and relay device events as signals.
+pub fn spawn(settings: Arc<SettingsStore>, events: &EventBus) {
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        let connection = match connection::Builder::session()
+            .and_then(|builder| builder.name(BUS_NAME))
+            .and_then(|builder| builder.serve_at(OBJECT_PATH, ControlService { settings }))
+            .and_then(|builder| builder.build())
+        {
+            Ok(connection) => connection,
+            Err(err) => {
+                warn!("D-Bus service disabled: {err}");
+                return;
+            }
+        };
+        let service = match connection
+            .object_server()
+            .interface::<_, ControlService>(OBJECT_PATH)
+        {
+            Ok(service) => service,
+            Err(err) => {
+                warn!("D-Bus service disabled: {err}");
+                return;
+            }
+        };
+
+        for event in receiver {
+            let emitter = service.signal_emitter();
+            let result = match event {
+                DeviceEvent::Connected { device_id } => zbus::block_on(
+                    ControlService::device_connected(emitter, &device_id.to_string()),
+                ),
+                DeviceEvent::Disconnected { device_id } => zbus::block_on(
+                    ControlService::device_disconnected(emitter, &device_id.to_string()),
+                ),
+                DeviceEvent::BatteryLow { device_id, percent } => zbus::block_on(
+                    ControlService::battery_low(emitter, &device_id.to_string(), percent),
+                ),
+                DeviceEvent::MicMuteChanged { device_id, muted } => zbus::block_on(
+                    ControlService::mic_mute_changed(emitter, &device_id.to_string(), muted),
+                ),
+                DeviceEvent::BatteryChanged { .. }
+                | DeviceEvent::HeadsetPoweredOn { .. }
+                | DeviceEvent::HeadsetPoweredOff { .. }
+                | DeviceEvent::FeatureChanged { .. }
+                | DeviceEvent::BoomMoved { .. }
+                | DeviceEvent::ButtonPressed { .. }
+                | DeviceEvent::DialTurned { .. }
+                | DeviceEvent::Resumed
+                | DeviceEvent::SessionChanged { .. } => continue,
+            };
+            if let Err(err) = result {
+                warn!("failed to emit D-Bus signal: {err}");
+            }
+        }
+    });
+}
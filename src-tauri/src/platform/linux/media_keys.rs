@@ -0,0 +1,170 @@
+//! Media keys through a virtual keyboard on `/dev/uinput`, which the kernel
+//! delivers like any other keyboard's keys and so works under X11 and Wayland
+//! alike. Creating it needs write access to `/dev/uinput`; without that the
+//! keys go through the XTEST extension instead, which injects them into the X
+//! server as if they came from a keyboard.
+
+use crate::media_keys::{MediaKey, MediaKeyError};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::mem;
+use std::os::fd::AsRawFd;
+use std::os::raw::{c_char, c_int, c_ulong};
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tracing::debug;
+use x11_dl::keysym;
+use x11_dl::xlib::{self, Xlib};
+// x11-dl names the XTEST bindings after another extension.
+use x11_dl::xtest::Xf86vmode as XTest;
+
+const UINPUT: &str = "/dev/uinput";
+const KEYBOARD_NAME: &str = "HyperX Pilot media keys";
+
+// From linux/uinput.h and linux/input-event-codes.h, which libc does not
+// carry.
+const UI_SET_EVBIT: libc::Ioctl = 0x4004_5564;
+const UI_SET_KEYBIT: libc::Ioctl = 0x4004_5565;
+const UI_DEV_SETUP: libc::Ioctl = 0x405C_5503;
+const UI_DEV_CREATE: libc::Ioctl = 0x5501;
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const SYN_REPORT: u16 = 0;
+const BUS_VIRTUAL: u16 = 0x06;
+
+/// How long a new virtual keyboard takes to be picked up by the desktop;
+/// keys pressed before that are lost.
+const KEYBOARD_SETTLE: Duration = Duration::from_millis(200);
+
+/// The virtual keyboard, created on the first press and kept for the next
+/// ones.
+static KEYBOARD: Mutex<Option<File>> = Mutex::new(None);
+
+pub fn press(key: MediaKey) -> Result<(), MediaKeyError> {
+    match press_uinput(key) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            debug!(
+                "pressing {} through {UINPUT} failed, using XTEST: {err}",
+                key.as_str()
+            );
+            press_xtest(key)
+        }
+    }
+}
+
+fn key_code(key: MediaKey) -> u16 {
+    match key {
+        MediaKey::PlayPause => 164,
+        MediaKey::NextTrack => 163,
+        MediaKey::PreviousTrack => 165,
+        MediaKey::VolumeUp => 115,
+        MediaKey::VolumeDown => 114,
+    }
+}
+
+fn press_uinput(key: MediaKey) -> io::Result<()> {
+    let mut keyboard = KEYBOARD.lock().unwrap();
+    let file = match keyboard.as_mut() {
+        Some(file) => file,
+        None => keyboard.insert(create_keyboard()?),
+    };
+    let code = key_code(key);
+    let result = [
+        (EV_KEY, code, 1),
+        (EV_SYN, SYN_REPORT, 0),
+        (EV_KEY, code, 0),
+        (EV_SYN, SYN_REPORT, 0),
+    ]
+    .into_iter()
+    .try_for_each(|(kind, code, value)| write_event(file, kind, code, value));
+    // Created again on the next press, e.g. after the device was removed.
+    if result.is_err() {
+        *keyboard = None;
+    }
+    result
+}
+
+fn create_keyboard() -> io::Result<File> {
+    let file = OpenOptions::new().write(true).open(UINPUT)?;
+    let fd = file.as_raw_fd();
+    let check = |result: c_int| {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    };
+    // SAFETY: the requests are the uinput ones with the argument types
+    // linux/uinput.h declares for them, on a descriptor kept open by `file`.
+    unsafe {
+        check(libc::ioctl(fd, UI_SET_EVBIT, c_ulong::from(EV_KEY)))?;
+        for key in MediaKey::ALL {
+            check(libc::ioctl(fd, UI_SET_KEYBIT, c_ulong::from(key_code(key))))?;
+        }
+        let mut setup: libc::uinput_setup = mem::zeroed();
+        setup.id.bustype = BUS_VIRTUAL;
+        // The name stays null-terminated, the array is longer.
+        for (slot, byte) in setup.name.iter_mut().zip(KEYBOARD_NAME.bytes()) {
+            *slot = byte as c_char;
+        }
+        check(libc::ioctl(fd, UI_DEV_SETUP, &setup))?;
+        check(libc::ioctl(fd, UI_DEV_CREATE))?;
+    }
+    thread::sleep(KEYBOARD_SETTLE);
+    Ok(file)
+}
+
+fn write_event(file: &mut File, kind: u16, code: u16, value: i32) -> io::Result<()> {
+    // SAFETY: input_event is plain data, for which all zeroes is valid; the
+    // kernel fills in the time.
+    let mut event: libc::input_event = unsafe { mem::zeroed() };
+    event.type_ = kind;
+    event.code = code;
+    event.value = value;
+    // SAFETY: the slice covers exactly `event`, which outlives it.
+    let bytes = unsafe {
+        slice::from_raw_parts(
+            (&event as *const libc::input_event).cast::<u8>(),
+            mem::size_of::<libc::input_event>(),
+        )
+    };
+    file.write_all(bytes)
+}
+
+fn press_xtest(key: MediaKey) -> Result<(), MediaKeyError> {
+    let x11 = |detail: String| MediaKeyError::X11 { detail };
+    let xlib = Xlib::open().map_err(|err| x11(err.to_string()))?;
+    let xtest = XTest::open().map_err(|err| x11(err.to_string()))?;
+    let keysym = match key {
+        MediaKey::PlayPause => keysym::XF86XK_AudioPlay,
+        MediaKey::NextTrack => keysym::XF86XK_AudioNext,
+        MediaKey::PreviousTrack => keysym::XF86XK_AudioPrev,
+        MediaKey::VolumeUp => keysym::XF86XK_AudioRaiseVolume,
+        MediaKey::VolumeDown => keysym::XF86XK_AudioLowerVolume,
+    };
+
+    // SAFETY: all calls go through the dynamically loaded libX11 and libXtst
+    // with a display pointer checked for null, which is closed before
+    // returning.
+    unsafe {
+        let display = (xlib.XOpenDisplay)(ptr::null());
+        if display.is_null() {
+            return Err(x11("cannot open display".into()));
+        }
+        let keycode = (xlib.XKeysymToKeycode)(display, c_ulong::from(keysym));
+        let result = if keycode == 0 {
+            Err(MediaKeyError::NoKeycode { key })
+        } else {
+            (xtest.XTestFakeKeyEvent)(display, keycode.into(), xlib::True, 0);
+            (xtest.XTestFakeKeyEvent)(display, keycode.into(), xlib::False, 0);
+            (xlib.XFlush)(display);
+            Ok(())
+        };
+        (xlib.XCloseDisplay)(display);
+        result
+    }
+}
@@ -0,0 +1,6 @@
+pub mod dbus;
+pub mod media_keys;
+pub mod sandbox;
+pub mod shortcuts;
+pub mod systemd;
+pub mod udev;
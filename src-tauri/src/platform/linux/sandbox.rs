@@ -0,0 +1,98 @@
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::process::Command;
+
+const FLATPAK_INFO: &str = "/.flatpak-info";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SandboxKind {
+    None,
+    Flatpak,
+    Snap,
+}
+
+impl SandboxKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            SandboxKind::None => "none",
+            SandboxKind::Flatpak => "Flatpak",
+            SandboxKind::Snap => "Snap",
+        }
+    }
+}
+
+/// Whether the app runs confined and, if so, whether HID nodes are reachable.
+#[derive(Debug, Clone, Serialize)]
+pub struct SandboxStatus {
+    pub kind: SandboxKind,
+    pub app_id: Option<String>,
+    pub device_access: bool,
+    /// Command to run on the host that grants the missing device access.
+    pub grant_command: Option<String>,
+}
+
+pub fn detect() -> SandboxStatus {
+    if let Ok(info) = fs::read_to_string(FLATPAK_INFO) {
+        return flatpak_status(&info);
+    }
+    if let Ok(snap_name) = env::var("SNAP_NAME") {
+        return snap_status(snap_name);
+    }
+    SandboxStatus {
+        kind: SandboxKind::None,
+        app_id: None,
+        device_access: true,
+        grant_command: None,
+    }
+}
+
+/// Flatpak only exposes `/dev/hidraw*` with `--device=all`; the granted
+/// devices are listed in the `[Context]` section of `/.flatpak-info`.
+fn flatpak_status(info: &str) -> SandboxStatus {
+    let mut section = "";
+    let mut app_id = None;
+    let mut device_access = false;
+    for line in info.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name;
+            continue;
+        }
+        match (section, line.split_once('=')) {
+            ("Application", Some(("name", value))) => app_id = Some(value.to_string()),
+            ("Context", Some(("devices", value))) => {
+                device_access = value.split(';').any(|device| device == "all");
+            }
+            _ => {}
+        }
+    }
+
+    let grant_command = (!device_access).then(|| {
+        format!(
+            "flatpak override --user --device=all {}",
+            app_id.as_deref().unwrap_or("<app-id>")
+        )
+    });
+    SandboxStatus {
+        kind: SandboxKind::Flatpak,
+        app_id,
+        device_access,
+        grant_command,
+    }
+}
+
+/// Snaps need the `raw-usb` interface connected to reach hidraw and usb nodes.
+fn snap_status(snap_name: String) -> SandboxStatus {
+    let device_access = Command::new("snapctl")
+        .args(["is-connected", "raw-usb"])
+        .status()
+        .is_ok_and(|status| status.success());
+    let grant_command = (!device_access).then(|| format!("sudo snap connect {snap_name}:raw-usb"));
+    SandboxStatus {
+        kind: SandboxKind::Snap,
+        app_id: Some(snap_name),
+        device_access,
+        grant_command,
+    }
+}
@@ -0,0 +1,78 @@
+//! Global hotkeys on Linux: the XDG GlobalShortcuts portal under Wayland,
+//! where clients cannot grab keys themselves, and X11 key grabs otherwise.
+
+mod portal;
+mod x11;
+
+use crate::settings::ShortcutBinding;
+use std::env;
+use std::fmt;
+use std::thread;
+use tracing::warn;
+
+#[derive(Debug)]
+pub enum ShortcutError {
+    Portal(zbus::Error),
+    X11 {
+        detail: String,
+    },
+    InvalidAccelerator {
+        accelerator: String,
+    },
+    /// Another client grabbed the key combination first.
+    Taken {
+        accelerator: String,
+    },
+}
+
+impl fmt::Display for ShortcutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShortcutError::Portal(err) => write!(f, "GlobalShortcuts portal failed: {err}"),
+            ShortcutError::X11 { detail } => write!(f, "X11 key grab failed: {detail}"),
+            ShortcutError::InvalidAccelerator { accelerator } => {
+                write!(f, "invalid shortcut '{accelerator}'")
+            }
+            ShortcutError::Taken { accelerator } => {
+                write!(f, "shortcut '{accelerator}' is used by another application")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShortcutError {}
+
+impl From<zbus::Error> for ShortcutError {
+    fn from(err: zbus::Error) -> Self {
+        ShortcutError::Portal(err)
+    }
+}
+
+fn is_wayland() -> bool {
+    env::var_os("WAYLAND_DISPLAY").is_some()
+        || env::var("XDG_SESSION_TYPE").is_ok_and(|kind| kind == "wayland")
+}
+
+/// Register `bindings` and call `on_activate` with the index of every
+/// triggered binding. Runs on its own thread for the lifetime of the app.
+pub fn spawn<F>(bindings: Vec<ShortcutBinding>, on_activate: F)
+where
+    F: Fn(usize) + Send + 'static,
+{
+    if bindings.is_empty() {
+        return;
+    }
+    thread::spawn(move || {
+        let result = if is_wayland() {
+            portal::listen(&bindings, &on_activate).or_else(|err| {
+                warn!("{err}; falling back to X11 key grabs");
+                x11::listen(&bindings, &on_activate)
+            })
+        } else {
+            x11::listen(&bindings, &on_activate)
+        };
+        if let Err(err) = result {
+            warn!("global shortcuts disabled: {err}");
+        }
+    });
+}
@@ -0,0 +1,116 @@
+use super::ShortcutError;
+use crate::settings::ShortcutBinding;
+use std::collections::HashMap;
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::{DynamicType, ObjectPath, OwnedObjectPath, OwnedValue, Value};
+
+const DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PATH: &str = "/org/freedesktop/portal/desktop";
+const INTERFACE: &str = "org.freedesktop.portal.GlobalShortcuts";
+const TOKEN_PREFIX: &str = "hyperx_pilot";
+
+type Results = HashMap<String, OwnedValue>;
+
+/// Call a portal method that answers through a `Request` object and wait for
+/// its `Response`. The signal is subscribed before the call to avoid a race.
+fn request<B>(
+    connection: &Connection,
+    portal: &Proxy<'_>,
+    method: &str,
+    token: &str,
+    body: &B,
+) -> Result<Results, ShortcutError>
+where
+    B: serde::Serialize + DynamicType,
+{
+    let sender = connection
+        .unique_name()
+        .map(|name| name.trim_start_matches(':').replace('.', "_"))
+        .unwrap_or_default();
+    let path = format!("{PATH}/request/{sender}/{token}");
+    let request = Proxy::new(
+        connection,
+        DESTINATION,
+        path.as_str(),
+        "org.freedesktop.portal.Request",
+    )?;
+    let mut responses = request.receive_signal("Response")?;
+
+    portal.call_method(method, body)?;
+
+    let response = responses
+        .next()
+        .ok_or_else(|| zbus::Error::Failure(format!("{method} got no response")))?;
+    let (code, results): (u32, Results) = response.body().deserialize()?;
+    if code != 0 {
+        return Err(zbus::Error::Failure(format!("{method} was denied (code {code})")).into());
+    }
+    Ok(results)
+}
+
+pub(super) fn listen(
+    bindings: &[ShortcutBinding],
+    on_activate: &dyn Fn(usize),
+) -> Result<(), ShortcutError> {
+    let connection = Connection::session()?;
+    let portal = Proxy::new(&connection, DESTINATION, PATH, INTERFACE)?;
+
+    let session_token = format!("{TOKEN_PREFIX}_session");
+    let create_token = format!("{TOKEN_PREFIX}_create");
+    let options = HashMap::from([
+        ("handle_token", Value::from(create_token.as_str())),
+        ("session_handle_token", Value::from(session_token.as_str())),
+    ]);
+    let results = request(
+        &connection,
+        &portal,
+        "CreateSession",
+        &create_token,
+        &(options,),
+    )?;
+    let session = results
+        .get("session_handle")
+        .and_then(|handle| handle.downcast_ref::<&str>().ok())
+        .and_then(|handle| OwnedObjectPath::try_from(handle).ok())
+        .ok_or_else(|| zbus::Error::Failure("CreateSession returned no session".into()))?;
+
+    let shortcuts: Vec<(String, HashMap<&str, Value<'_>>)> = bindings
+        .iter()
+        .enumerate()
+        .map(|(index, binding)| {
+            let properties = HashMap::from([
+                ("description", Value::from(binding.action.describe())),
+                (
+                    "preferred_trigger",
+                    Value::from(binding.accelerator.as_str()),
+                ),
+            ]);
+            (index.to_string(), properties)
+        })
+        .collect();
+    let bind_token = format!("{TOKEN_PREFIX}_bind");
+    let options = HashMap::from([("handle_token", Value::from(bind_token.as_str()))]);
+    request(
+        &connection,
+        &portal,
+        "BindShortcuts",
+        &bind_token,
+        &(ObjectPath::from(&session), shortcuts, "", options),
+    )?;
+
+    for signal in portal.receive_signal("Activated")? {
+        let (session_handle, shortcut_id, _timestamp, _options): (
+            OwnedObjectPath,
+            String,
+            u64,
+            Results,
+        ) = signal.body().deserialize()?;
+        if session_handle != session {
+            continue;
+        }
+        if let Ok(index) = shortcut_id.parse() {
+            on_activate(index);
+        }
+    }
+    Ok(())
+}
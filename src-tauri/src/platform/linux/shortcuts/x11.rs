@@ -0,0 +1,168 @@
+use super::ShortcutError;
+use crate::settings::ShortcutBinding;
+use std::ffi::CString;
+use std::mem::MaybeUninit;
+use std::os::raw::{c_int, c_uint};
+use std::ptr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use x11_dl::xlib::{self, Xlib};
+
+/// NumLock and CapsLock would otherwise make grabs miss; grab every combination.
+const IGNORED_MODIFIERS: [c_uint; 4] = [
+    0,
+    xlib::LockMask,
+    xlib::Mod2Mask,
+    xlib::LockMask | xlib::Mod2Mask,
+];
+
+/// The last error code the X server reported while grabbing; errors arrive
+/// asynchronously, so they are collected by [`record_error`] and checked
+/// after `XSync`.
+static GRAB_ERROR: AtomicU8 = AtomicU8::new(xlib::Success);
+
+/// Xlib's default handler exits the process on any error, which a key
+/// combination grabbed by another client (`BadAccess`) would trigger.
+unsafe extern "C" fn record_error(
+    _display: *mut xlib::Display,
+    event: *mut xlib::XErrorEvent,
+) -> c_int {
+    // SAFETY: Xlib passes a valid event for the duration of the call.
+    GRAB_ERROR.store(unsafe { (*event).error_code }, Ordering::Relaxed);
+    0
+}
+
+struct Grab {
+    keycode: u32,
+    modifiers: c_uint,
+}
+
+/// Parse `CTRL+ALT+m` style accelerators into X modifier mask and keysym name.
+fn parse_accelerator(accelerator: &str) -> Option<(c_uint, &str)> {
+    let mut parts: Vec<&str> = accelerator.split('+').map(str::trim).collect();
+    let key = parts.pop().filter(|key| !key.is_empty())?;
+    let mut modifiers = 0;
+    for part in parts {
+        modifiers |= match part.to_ascii_uppercase().as_str() {
+            "CTRL" | "CONTROL" => xlib::ControlMask,
+            "ALT" => xlib::Mod1Mask,
+            "SHIFT" => xlib::ShiftMask,
+            "LOGO" | "SUPER" => xlib::Mod4Mask,
+            _ => return None,
+        };
+    }
+    Some((modifiers, key))
+}
+
+pub(super) fn listen(
+    bindings: &[ShortcutBinding],
+    on_activate: &dyn Fn(usize),
+) -> Result<(), ShortcutError> {
+    let xlib = Xlib::open().map_err(|err| ShortcutError::X11 {
+        detail: err.to_string(),
+    })?;
+
+    // SAFETY: all calls go through the dynamically loaded libX11 with a display
+    // pointer checked for null; the event union is read according to its type.
+    unsafe {
+        let display = (xlib.XOpenDisplay)(ptr::null());
+        if display.is_null() {
+            return Err(ShortcutError::X11 {
+                detail: "cannot open display".into(),
+            });
+        }
+        let root = (xlib.XDefaultRootWindow)(display);
+
+        let previous = (xlib.XSetErrorHandler)(Some(record_error));
+        let grabs = grab(&xlib, display, root, bindings);
+        (xlib.XSetErrorHandler)(previous);
+        let grabs = match grabs {
+            Ok(grabs) => grabs,
+            Err(err) => {
+                (xlib.XCloseDisplay)(display);
+                return Err(err);
+            }
+        };
+
+        let ignored = xlib::LockMask | xlib::Mod2Mask;
+        loop {
+            let mut event = MaybeUninit::<xlib::XEvent>::uninit();
+            (xlib.XNextEvent)(display, event.as_mut_ptr());
+            let event = event.assume_init();
+            if event.get_type() != xlib::KeyPress {
+                continue;
+            }
+            let key = event.key;
+            if let Some(index) = grabs.iter().position(|grab| {
+                grab.keycode == key.keycode && grab.modifiers == key.state & !ignored
+            }) {
+                on_activate(index);
+            }
+        }
+    }
+}
+
+/// Grab the keys of every binding on `root`, waiting for the server to
+/// answer each one so a combination taken by another client is reported
+/// for its binding.
+///
+/// # Safety
+///
+/// `display` must be an open display and [`record_error`] the installed
+/// error handler.
+unsafe fn grab(
+    xlib: &Xlib,
+    display: *mut xlib::Display,
+    root: xlib::Window,
+    bindings: &[ShortcutBinding],
+) -> Result<Vec<Grab>, ShortcutError> {
+    let mut grabs = Vec::with_capacity(bindings.len());
+    for binding in bindings {
+        let invalid = || ShortcutError::InvalidAccelerator {
+            accelerator: binding.accelerator.clone(),
+        };
+        let (modifiers, key) = parse_accelerator(&binding.accelerator).ok_or_else(invalid)?;
+        let name = CString::new(key).map_err(|_| invalid())?;
+        // SAFETY: guaranteed by the caller; `name` outlives the call.
+        unsafe {
+            let keysym = (xlib.XStringToKeysym)(name.as_ptr());
+            let keycode = (xlib.XKeysymToKeycode)(display, keysym);
+            if keycode == 0 {
+                return Err(invalid());
+            }
+            GRAB_ERROR.store(xlib::Success, Ordering::Relaxed);
+            for extra in IGNORED_MODIFIERS {
+                (xlib.XGrabKey)(
+                    display,
+                    i32::from(keycode),
+                    modifiers | extra,
+                    root,
+                    xlib::True,
+                    xlib::GrabModeAsync,
+                    xlib::GrabModeAsync,
+                );
+            }
+            (xlib.XSync)(display, xlib::False);
+            match GRAB_ERROR.load(Ordering::Relaxed) {
+                xlib::Success => {}
+                xlib::BadAccess => {
+                    return Err(ShortcutError::Taken {
+                        accelerator: binding.accelerator.clone(),
+                    })
+                }
+                code => {
+                    return Err(ShortcutError::X11 {
+                        detail: format!(
+                            "grabbing '{}' failed with error {code}",
+                            binding.accelerator
+                        ),
+                    })
+                }
+            }
+            grabs.push(Grab {
+                keycode: u32::from(keycode),
+                modifiers,
+            });
+        }
+    }
+    Ok(grabs)
+}
@@ -0,0 +1,160 @@
+//! Installs the headless daemon (`--headless`) as a systemd user service so
+//! settings are restored on login without the window being open.
+
+use super::sandbox::{self, SandboxKind};
+use serde::Serialize;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const UNIT_NAME: &str = "hyperx-pilot.service";
+
+#[derive(Debug)]
+pub enum SystemdError {
+    Sandboxed { kind: SandboxKind },
+    NoConfigDir,
+    Io { path: PathBuf, source: io::Error },
+    Systemctl { args: String, stderr: String },
+}
+
+impl fmt::Display for SystemdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SystemdError::Sandboxed { kind } => write!(
+                f,
+                "user services cannot be managed from inside the {} sandbox",
+                kind.label()
+            ),
+            SystemdError::NoConfigDir => write!(f, "cannot determine the configuration directory"),
+            SystemdError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            SystemdError::Systemctl { args, stderr } => {
+                write!(f, "systemctl --user {args} failed: {}", stderr.trim())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SystemdError {}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceStatus {
+    pub installed: bool,
+    pub active: bool,
+}
+
+fn unit_path() -> Result<PathBuf, SystemdError> {
+    dirs::config_dir()
+        .map(|dir| dir.join("systemd/user").join(UNIT_NAME))
+        .ok_or(SystemdError::NoConfigDir)
+}
+
+/// The binary to start. AppImages run from a temporary mount, so prefer the
+/// stable image path they export.
+fn executable() -> Result<PathBuf, SystemdError> {
+    if let Some(appimage) = env::var_os("APPIMAGE") {
+        return Ok(PathBuf::from(appimage));
+    }
+    env::current_exe().map_err(|source| SystemdError::Io {
+        path: PathBuf::from("/proc/self/exe"),
+        source,
+    })
+}
+
+/// Quote a path for `ExecStart=`; `%` starts a specifier and must be doubled.
+fn quote(path: &Path) -> String {
+    let escaped = path
+        .to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%");
+    format!("\"{escaped}\"")
+}
+
+pub fn render_unit(executable: &Path) -> String {
+    format!(
+        "[Unit]
+Description=HyperX Pilot headset daemon
+Documentation=https://github.com/datascaled/hyperx-pilot
+# Needs the desktop session for shortcuts and the audio server for device switching.
+PartOf=graphical-session.target
+After=graphical-session.target pipewire.service pipewire-pulse.service pulseaudio.service
+
+[Service]
+Type=simple
+ExecStart={} --headless
+Restart=on-failure
+RestartSec=5
+
+[Install]
+WantedBy=graphical-session.target
+",
+        quote(executable)
+    )
+}
+
+fn systemctl(args: &[&str]) -> Result<String, SystemdError> {
+    let output = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .output()
+        .map_err(|source| SystemdError::Io {
+            path: PathBuf::from("systemctl"),
+            source,
+        })?;
+    if !output.status.success() {
+        return Err(SystemdError::Systemctl {
+            args: args.join(" "),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn ensure_unsandboxed() -> Result<(), SystemdError> {
+    match sandbox::detect().kind {
+        SandboxKind::None => Ok(()),
+        kind => Err(SystemdError::Sandboxed { kind }),
+    }
+}
+
+pub fn install() -> Result<(), SystemdError> {
+    ensure_unsandboxed()?;
+    let path = unit_path()?;
+    let io_error = |source| SystemdError::Io {
+        path: path.clone(),
+        source,
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(io_error)?;
+    }
+    fs::write(&path, render_unit(&executable()?)).map_err(io_error)?;
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", "--now", UNIT_NAME])?;
+    Ok(())
+}
+
+pub fn uninstall() -> Result<(), SystemdError> {
+    ensure_unsandboxed()?;
+    let path = unit_path()?;
+    if !path.exists() {
+        return Ok(());
+    }
+    // Disabling fails when the unit was never loaded; removing it is what matters.
+    let _ = systemctl(&["disable", "--now", UNIT_NAME]);
+    fs::remove_file(&path).map_err(|source| SystemdError::Io {
+        path: path.clone(),
+        source,
+    })?;
+    systemctl(&["daemon-reload"])?;
+    Ok(())
+}
+
+pub fn status() -> ServiceStatus {
+    ServiceStatus {
+        installed: unit_path().is_ok_and(|path| path.exists()),
+        active: systemctl(&["is-active", UNIT_NAME]).is_ok_and(|state| state == "active"),
+    }
+}
@@ -0,0 +1,124 @@
+use super::sandbox::{self, SandboxKind};
+use crate::hyperx;
+use serde::Serialize;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io::{self, Write as _};
+use std::process::{Command, Stdio};
+
+/// Location of the generated rules. The `70-` prefix keeps them ahead of
+/// `73-seat-late.rules`, which is where the `uaccess` tag is evaluated.
+pub const RULES_PATH: &str = "/etc/udev/rules.d/70-hyperx-pilot.rules";
+
+/// Runs as root through pkexec: `$1` is the target path, the rules arrive on stdin.
+const INSTALL_SCRIPT: &str = "umask 022 && cat > \"$1\" && udevadm control --reload-rules \
+     && udevadm trigger --subsystem-match=hidraw --subsystem-match=usb";
+
+#[derive(Debug)]
+pub enum UdevError {
+    Spawn {
+        source: io::Error,
+    },
+    /// pkexec cannot reach the host from inside Flatpak/Snap.
+    Sandboxed {
+        kind: SandboxKind,
+    },
+    NotAuthorized,
+    InstallFailed {
+        code: Option<i32>,
+    },
+}
+
+impl fmt::Display for UdevError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UdevError::Spawn { source } => write!(f, "failed to run pkexec: {source}"),
+            UdevError::Sandboxed { kind } => write!(
+                f,
+                "udev rules cannot be installed from inside the {} sandbox; install them on the host",
+                kind.label()
+            ),
+            UdevError::NotAuthorized => write!(f, "administrator authorisation was denied"),
+            UdevError::InstallFailed { code: Some(code) } => {
+                write!(f, "installing udev rules failed (exit code {code})")
+            }
+            UdevError::InstallFailed { code: None } => {
+                write!(f, "installing udev rules was interrupted")
+            }
+        }
+    }
+}
+
+impl std::error::Error for UdevError {}
+
+/// Generated rules together with their install state, for previewing in the UI.
+#[derive(Debug, Clone, Serialize)]
+pub struct UdevRules {
+    pub path: &'static str,
+    pub contents: String,
+    pub installed: bool,
+}
+
+/// Render udev rules granting the active seat user access to every supported
+/// device, both through hidraw and through libusb.
+pub fn render_rules() -> String {
+    let mut rules = String::from(
+        "# Generated by HyperX Pilot. Grants the logged-in user access to supported headsets.\n",
+    );
+    for device in hyperx::supported_devices() {
+        let (vendor_id, product_id) = hyperx::usb_id(device.id);
+        let _ = writeln!(rules, "\n# {}", device.label);
+        let _ = writeln!(
+            rules,
+            "KERNEL==\"hidraw*\", ATTRS{{idVendor}}==\"{vendor_id:04x}\", ATTRS{{idProduct}}==\"{product_id:04x}\", MODE=\"0660\", TAG+=\"uaccess\""
+        );
+        let _ = writeln!(
+            rules,
+            "SUBSYSTEM==\"usb\", ATTR{{idVendor}}==\"{vendor_id:04x}\", ATTR{{idProduct}}==\"{product_id:04x}\", MODE=\"0660\", TAG+=\"uaccess\""
+        );
+    }
+    rules
+}
+
+/// Whether the installed rules file exists and matches what [`render_rules`] produces.
+pub fn rules_installed() -> bool {
+    fs::read_to_string(RULES_PATH).is_ok_and(|installed| installed == render_rules())
+}
+
+pub fn rules_preview() -> UdevRules {
+    UdevRules {
+        path: RULES_PATH,
+        contents: render_rules(),
+        installed: rules_installed(),
+    }
+}
+
+/// Write the rules to [`RULES_PATH`] via pkexec and reload udev so already
+/// connected devices pick up the new permissions.
+pub fn install_rules() -> Result<(), UdevError> {
+    let kind = sandbox::detect().kind;
+    if kind != SandboxKind::None {
+        return Err(UdevError::Sandboxed { kind });
+    }
+
+    let mut child = Command::new("pkexec")
+        .args(["sh", "-c", INSTALL_SCRIPT, "sh", RULES_PATH])
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|source| UdevError::Spawn { source })?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(render_rules().as_bytes())
+            .map_err(|source| UdevError::Spawn { source })?;
+    }
+
+    let status = child.wait().map_err(|source| UdevError::Spawn { source })?;
+
+    match status.code() {
+        Some(0) => Ok(()),
+        // pkexec reports a dismissed or refused authentication dialog as 126/127.
+        Some(126 | 127) => Err(UdevError::NotAuthorized),
+        code => Err(UdevError::InstallFailed { code }),
+    }
+}
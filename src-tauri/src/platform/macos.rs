@@ -0,0 +1,57 @@
+//! macOS specifics: shared (non-exclusive) device access and the Input
+//! Monitoring privacy permission that gates HID access since 10.15.
+
+use serde::Serialize;
+use std::env;
+
+/// `kIOHIDRequestTypeListenEvent` from IOKit/hid/IOHIDLib.h.
+const REQUEST_TYPE_LISTEN_EVENT: u32 = 1;
+/// `IOHIDAccessType` values.
+const ACCESS_GRANTED: u32 = 0;
+const ACCESS_DENIED: u32 = 1;
+
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IOHIDCheckAccess(request_type: u32) -> u32;
+    fn IOHIDRequestAccess(request_type: u32) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessState {
+    Granted,
+    Denied,
+    /// The user has not been asked yet.
+    Undetermined,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PermissionReport {
+    pub input_monitoring: AccessState,
+    /// Running inside the App Sandbox, which additionally needs the
+    /// `com.apple.security.device.usb` entitlement.
+    pub app_sandbox: bool,
+}
+
+pub fn input_monitoring_access() -> AccessState {
+    // SAFETY: plain IOKit call without pointers; available since macOS 10.15.
+    match unsafe { IOHIDCheckAccess(REQUEST_TYPE_LISTEN_EVENT) } {
+        ACCESS_GRANTED => AccessState::Granted,
+        ACCESS_DENIED => AccessState::Denied,
+        _ => AccessState::Undetermined,
+    }
+}
+
+/// Show the system prompt if the user has not decided yet. Returns whether
+/// access is granted afterwards.
+pub fn request_input_monitoring() -> bool {
+    // SAFETY: see `input_monitoring_access`.
+    unsafe { IOHIDRequestAccess(REQUEST_TYPE_LISTEN_EVENT) }
+}
+
+pub fn permission_report() -> PermissionReport {
+    PermissionReport {
+        input_monitoring: input_monitoring_access(),
+        app_sandbox: env::var_os("APP_SANDBOX_CONTAINER_ID").is_some(),
+    }
+}
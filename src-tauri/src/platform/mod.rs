@@ -0,0 +1,11 @@
+//! Platform-specific integrations. Everything in here is optional glue around
+//! the HID logic in [`crate::hyperx`].
+
+#[cfg(target_os = "linux")]
+pub mod linux;
+
+#[cfg(target_os = "macos")]
+pub mod macos;
+
+#[cfg(windows)]
+pub mod windows;
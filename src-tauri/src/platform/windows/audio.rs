@@ -0,0 +1,165 @@
+//! Two-way sync between the headset's hardware volume and the master volume of
+//! its WASAPI render endpoint, so the wheel and the OS slider never disagree.
+//! Core Audio change callbacks need a COM object of our own, so both sides are
+//! polled instead and whichever moved since the last poll wins.
+
+use crate::hyperx::{self, ControlError, DeviceId};
+use std::collections::HashMap;
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+use windows::Win32::Devices::Properties::{DEVPKEY_Device_ContainerId, DEVPROPKEY};
+use windows::Win32::Media::Audio::Endpoints::IAudioEndpointVolume;
+use windows::Win32::Media::Audio::{
+    eRender, IMMDevice, IMMDeviceEnumerator, MMDeviceEnumerator, DEVICE_STATE_ACTIVE,
+};
+use windows::Win32::System::Com::StructuredStorage::PropVariantToGUID;
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_MULTITHREADED, STGM_READ,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+pub enum VolumeSyncError {
+    Com(windows::core::Error),
+    Control(ControlError),
+    /// No active render endpoint shares the headset's container ID.
+    MissingEndpoint,
+}
+
+impl fmt::Display for VolumeSyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VolumeSyncError::Com(err) => write!(f, "Core Audio call failed: {err}"),
+            VolumeSyncError::Control(err) => err.fmt(f),
+            VolumeSyncError::MissingEndpoint => {
+                write!(f, "no playback device belongs to the headset")
+            }
+        }
+    }
+}
+
+impl std::error::Error for VolumeSyncError {}
+
+impl From<windows::core::Error> for VolumeSyncError {
+    fn from(err: windows::core::Error) -> Self {
+        VolumeSyncError::Com(err)
+    }
+}
+
+impl From<ControlError> for VolumeSyncError {
+    fn from(err: ControlError) -> Self {
+        VolumeSyncError::Control(err)
+    }
+}
+
+/// One sync thread per connected device, stopped through its flag.
+#[derive(Default)]
+pub struct VolumeSync {
+    workers: Mutex<HashMap<DeviceId, Arc<AtomicBool>>>,
+}
+
+impl VolumeSync {
+    /// Start syncing `device_id`, replacing a previous worker for it. Devices
+    /// without a hardware volume report are ignored.
+    pub fn start(&self, device_id: DeviceId) {
+        if !hyperx::devices().get(device_id).has_hardware_volume() {
+            return;
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        if let Some(previous) = self.workers.lock().unwrap().insert(device_id, stop.clone()) {
+            previous.store(true, Ordering::Relaxed);
+        }
+        thread::spawn(move || {
+            if let Err(err) = sync_loop(device_id, &stop) {
+                if !stop.load(Ordering::Relaxed) {
+                    warn!("volume sync for {device_id} stopped: {err}");
+                }
+            }
+        });
+    }
+
+    pub fn stop(&self, device_id: DeviceId) {
+        if let Some(stop) = self.workers.lock().unwrap().remove(&device_id) {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+fn sync_loop(device_id: DeviceId, stop: &AtomicBool) -> Result<(), VolumeSyncError> {
+    // SAFETY: COM is initialised once for this thread and only used from it.
+    unsafe { CoInitializeEx(None, COINIT_MULTITHREADED).ok()? };
+
+    let container = hyperx::container_id(device_id)?.ok_or(VolumeSyncError::MissingEndpoint)?;
+    let endpoint = find_endpoint(container)?.ok_or(VolumeSyncError::MissingEndpoint)?;
+
+    // The headset is the source of truth when syncing starts.
+    let mut os_level = endpoint_percent(&endpoint)?;
+    let mut device_level = hyperx::devices().get(device_id).read_volume()?;
+    if let Some(level) = device_level {
+        set_endpoint_percent(&endpoint, level)?;
+        os_level = level;
+    }
+
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(POLL_INTERVAL);
+        let Some(device_now) = hyperx::devices().get(device_id).read_volume()? else {
+            continue;
+        };
+        let os_now = endpoint_percent(&endpoint)?;
+        if device_level != Some(device_now) {
+            set_endpoint_percent(&endpoint, device_now)?;
+            os_level = device_now;
+        } else if os_now != os_level {
+            hyperx::devices().get(device_id).set_volume(os_now)?;
+            os_level = os_now;
+        }
+        device_level = Some(os_level);
+    }
+    Ok(())
+}
+
+/// Active render endpoint whose `DEVPKEY_Device_ContainerId` matches the HID interface's.
+fn find_endpoint(container: u128) -> Result<Option<IAudioEndpointVolume>, VolumeSyncError> {
+    // SAFETY: plain COM calls on interfaces owned by this function.
+    unsafe {
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+        let devices = enumerator.EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE)?;
+        for index in 0..devices.GetCount()? {
+            let device = devices.Item(index)?;
+            if endpoint_container(&device).ok() == Some(container) {
+                return Ok(Some(device.Activate(CLSCTX_ALL, None)?));
+            }
+        }
+    }
+    Ok(None)
+}
+
+unsafe fn endpoint_container(device: &IMMDevice) -> windows::core::Result<u128> {
+    let store = device.OpenPropertyStore(STGM_READ)?;
+    // PROPERTYKEY and DEVPROPKEY share their layout.
+    let value = store.GetValue(&DEVPKEY_Device_ContainerId as *const DEVPROPKEY as *const _)?;
+    Ok(PropVariantToGUID(&value)?.to_u128())
+}
+
+fn endpoint_percent(endpoint: &IAudioEndpointVolume) -> Result<u8, VolumeSyncError> {
+    // SAFETY: `endpoint` is a live interface pointer.
+    let scalar = unsafe { endpoint.GetMasterVolumeLevelScalar()? };
+    Ok((scalar.clamp(0.0, 1.0) * 100.0).round() as u8)
+}
+
+fn set_endpoint_percent(
+    endpoint: &IAudioEndpointVolume,
+    percent: u8,
+) -> Result<(), VolumeSyncError> {
+    let scalar = f32::from(percent.min(100)) / 100.0;
+    // SAFETY: `endpoint` is a live interface pointer; no event context is passed.
+    unsafe { endpoint.SetMasterVolumeLevelScalar(scalar, ptr::null())? };
+    Ok(())
+}
@@ -0,0 +1,261 @@
+//! Maps hidapi's interface paths onto PnP devnodes via CfgMgr, so the several
+//! HID interfaces of one headset can be shown as one device under the name
+//! Device Manager uses.
+
+use crate::hyperx::{self, ControlError, DeviceId};
+use hidapi::HidApi;
+use serde::Serialize;
+use windows::core::{GUID, PCWSTR};
+use windows::Win32::Devices::DeviceAndDriverInstallation::{
+    CM_Get_DevNode_PropertyW, CM_Get_DevNode_Status, CM_Get_Device_ID_ListW,
+    CM_Get_Device_ID_List_SizeW, CM_Get_Device_Interface_PropertyW, CM_Get_Parent,
+    CM_Locate_DevNodeW, CM_GETIDLIST_FILTER_ENUMERATOR, CM_GETIDLIST_FILTER_PRESENT,
+    CM_LOCATE_DEVNODE_NORMAL, CR_SUCCESS,
+};
+use windows::Win32::Devices::Properties::{
+    DEVPKEY_Device_BusReportedDeviceDesc, DEVPKEY_Device_ContainerId, DEVPKEY_Device_DeviceDesc,
+    DEVPKEY_Device_FriendlyName, DEVPKEY_Device_InstanceId, DEVPROPKEY, DEVPROPTYPE,
+};
+
+/// All HID interfaces of one physical headset.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogicalDevice {
+    pub device_id: DeviceId,
+    /// `DEVPKEY_Device_ContainerId` in registry format, shared by every
+    /// interface (HID and audio) of the headset.
+    pub container_id: Option<String>,
+    pub friendly_name: Option<String>,
+    /// hidapi paths of the grouped interfaces.
+    pub interfaces: Vec<String>,
+}
+
+/// Group the enumerated interfaces of supported devices by container.
+pub fn logical_devices() -> Result<Vec<LogicalDevice>, ControlError> {
+    hyperx::refresh_devices()?;
+    hyperx::with_api(group_interfaces)
+}
+
+fn group_interfaces(api: &HidApi) -> Vec<LogicalDevice> {
+    let mut devices: Vec<LogicalDevice> = Vec::new();
+    for metadata in hyperx::supported_devices() {
+        let (vendor_id, product_id) = hyperx::usb_id(metadata.id);
+        let infos = api
+            .device_list()
+            .filter(|info| info.vendor_id() == vendor_id && info.product_id() == product_id);
+        for info in infos {
+            let path = info.path().to_string_lossy().into_owned();
+            let devnode = interface_devnode(&path);
+            let container_id = devnode
+                .and_then(container_id)
+                .map(|guid| format!("{{{guid:?}}}"));
+
+            // Interfaces without a container stay on their own.
+            let existing = devices.iter_mut().find(|device| {
+                device.device_id == metadata.id
+                    && container_id.is_some()
+                    && device.container_id == container_id
+            });
+            match existing {
+                Some(device) => device.interfaces.push(path),
+                None => devices.push(LogicalDevice {
+                    device_id: metadata.id,
+                    friendly_name: devnode.and_then(friendly_name),
+                    container_id,
+                    interfaces: vec![path],
+                }),
+            }
+        }
+    }
+    devices
+}
+
+/// Container IDs (see [`crate::hyperx::container_id`]) of all enumerated
+/// interfaces with this VID/PID; found without opening the device.
+pub fn usb_containers(vendor_id: u16, product_id: u16) -> Vec<u128> {
+    let Ok(mut containers) = hyperx::with_api(|api| {
+        api.device_list()
+            .filter(|info| info.vendor_id() == vendor_id && info.product_id() == product_id)
+            .filter_map(|info| interface_devnode(&info.path().to_string_lossy()))
+            .filter_map(container_id)
+            .map(|guid| guid.to_u128())
+            .collect::<Vec<u128>>()
+    }) else {
+        return Vec::new();
+    };
+    containers.sort_unstable();
+    containers.dedup();
+    containers
+}
+
+/// Hardware IDs (e.g. `USB\VID_03F0&PID_089D&MI_03`) of present USB
+/// functions with this VID/PID. Those with a PnP problem code, typically a
+/// missing driver, are listed alone when there are any.
+pub fn usb_hardware_ids(vendor_id: u16, product_id: u16) -> Vec<String> {
+    let prefix = format!("USB\\VID_{vendor_id:04X}&PID_{product_id:04X}");
+    let mut healthy = Vec::new();
+    let mut failing = Vec::new();
+    for instance_id in usb_instance_ids() {
+        if !instance_id.to_uppercase().starts_with(&prefix) {
+            continue;
+        }
+        // Instance IDs are `<hardware id>\<instance>`.
+        let hardware_id = instance_id
+            .rsplit_once('\\')
+            .map_or(instance_id.as_str(), |(id, _)| id)
+            .to_string();
+        if has_problem(&instance_id) {
+            failing.push(hardware_id);
+        } else {
+            healthy.push(hardware_id);
+        }
+    }
+    let mut ids = if failing.is_empty() { healthy } else { failing };
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+/// Instance IDs of all present devices enumerated by the USB bus driver.
+fn usb_instance_ids() -> Vec<String> {
+    let filter = wide("USB");
+    let flags = CM_GETIDLIST_FILTER_ENUMERATOR | CM_GETIDLIST_FILTER_PRESENT;
+    let mut length = 0u32;
+    // SAFETY: the filter is NUL-terminated and the buffer is sized by the first call.
+    unsafe {
+        if CM_Get_Device_ID_List_SizeW(&mut length, PCWSTR(filter.as_ptr()), flags) != CR_SUCCESS {
+            return Vec::new();
+        }
+        let mut buffer = vec![0u16; length as usize];
+        if CM_Get_Device_ID_ListW(PCWSTR(filter.as_ptr()), &mut buffer, flags) != CR_SUCCESS {
+            return Vec::new();
+        }
+        // A list of NUL-terminated strings, closed by an empty one.
+        buffer
+            .split(|&unit| unit == 0)
+            .filter(|id| !id.is_empty())
+            .map(String::from_utf16_lossy)
+            .collect()
+    }
+}
+
+fn has_problem(instance_id: &str) -> bool {
+    let instance_id = wide(instance_id);
+    let mut devnode = 0u32;
+    let mut status = Default::default();
+    let mut problem = Default::default();
+    // SAFETY: the instance id is NUL-terminated; outputs are plain integers.
+    unsafe {
+        CM_Locate_DevNodeW(
+            &mut devnode,
+            PCWSTR(instance_id.as_ptr()),
+            CM_LOCATE_DEVNODE_NORMAL,
+        ) == CR_SUCCESS
+            && CM_Get_DevNode_Status(&mut status, &mut problem, devnode, 0) == CR_SUCCESS
+            && problem.0 != 0
+    }
+}
+
+fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(Some(0)).collect()
+}
+
+fn wide_string(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Devnode owning a device interface path such as `\\?\HID#VID_03F0&PID_089D&MI_03#…`.
+fn interface_devnode(path: &str) -> Option<u32> {
+    let path = wide(path);
+    let mut kind = DEVPROPTYPE::default();
+    let mut buffer = vec![0u8; 512];
+    let mut size = buffer.len() as u32;
+    // SAFETY: the path is NUL-terminated and the buffer size is passed along.
+    let result = unsafe {
+        CM_Get_Device_Interface_PropertyW(
+            PCWSTR(path.as_ptr()),
+            &DEVPKEY_Device_InstanceId,
+            &mut kind,
+            Some(buffer.as_mut_ptr()),
+            &mut size,
+            0,
+        )
+    };
+    if result != CR_SUCCESS {
+        return None;
+    }
+    let instance_id = wide(&wide_string(&buffer[..size as usize]));
+    let mut devnode = 0u32;
+    // SAFETY: the instance id is NUL-terminated.
+    let result = unsafe {
+        CM_Locate_DevNodeW(
+            &mut devnode,
+            PCWSTR(instance_id.as_ptr()),
+            CM_LOCATE_DEVNODE_NORMAL,
+        )
+    };
+    (result == CR_SUCCESS).then_some(devnode)
+}
+
+fn devnode_property(devnode: u32, key: &DEVPROPKEY) -> Option<Vec<u8>> {
+    let mut kind = DEVPROPTYPE::default();
+    let mut size = 0u32;
+    // SAFETY: a size query without a buffer.
+    unsafe { CM_Get_DevNode_PropertyW(devnode, key, &mut kind, None, &mut size, 0) };
+    if size == 0 {
+        return None;
+    }
+    let mut buffer = vec![0u8; size as usize];
+    // SAFETY: `buffer` holds the `size` bytes requested above.
+    let result = unsafe {
+        CM_Get_DevNode_PropertyW(
+            devnode,
+            key,
+            &mut kind,
+            Some(buffer.as_mut_ptr()),
+            &mut size,
+            0,
+        )
+    };
+    (result == CR_SUCCESS).then_some(buffer)
+}
+
+fn container_id(devnode: u32) -> Option<GUID> {
+    let bytes = devnode_property(devnode, &DEVPKEY_Device_ContainerId)?;
+    let bytes: [u8; 16] = bytes.get(..16)?.try_into().ok()?;
+    Some(GUID::from_values(
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        u16::from_le_bytes([bytes[4], bytes[5]]),
+        u16::from_le_bytes([bytes[6], bytes[7]]),
+        bytes[8..].try_into().ok()?,
+    ))
+}
+
+/// The nearest friendly name or reported product string up the devnode tree,
+/// without leaving the headset's container (hubs belong to the PC), else the
+/// HID interface's generic description.
+fn friendly_name(devnode: u32) -> Option<String> {
+    let container = container_id(devnode);
+    let mut current = devnode;
+    loop {
+        if let Some(name) = devnode_property(current, &DEVPKEY_Device_FriendlyName) {
+            return Some(wide_string(&name));
+        }
+        if let Some(name) = devnode_property(current, &DEVPKEY_Device_BusReportedDeviceDesc) {
+            return Some(wide_string(&name));
+        }
+        let mut parent = 0u32;
+        // SAFETY: `current` is a devnode handle returned by CfgMgr.
+        if unsafe { CM_Get_Parent(&mut parent, current, 0) } != CR_SUCCESS
+            || container_id(parent) != container
+        {
+            break;
+        }
+        current = parent;
+    }
+    devnode_property(devnode, &DEVPKEY_Device_DeviceDesc).map(|name| wide_string(&name))
+}
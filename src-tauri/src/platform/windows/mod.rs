@@ -0,0 +1,4 @@
+pub mod audio;
+pub mod devices;
+pub mod startup;
+pub mod toast;
@@ -0,0 +1,169 @@
+//! Starts the app after login through a Task Scheduler task rather than the
+//! `Run` registry key: the task waits a little so the HID stack and the shell
+//! are ready, and runs with the highest privileges available to the user.
+
+use serde::Serialize;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const TASK_NAME: &str = "HyperX Pilot";
+pub const DEFAULT_DELAY_SECS: u32 = 30;
+
+#[derive(Debug)]
+pub enum StartupError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// `schtasks` refused, typically because registering a highest-privilege
+    /// task for an administrator needs an elevated process.
+    Schtasks {
+        args: String,
+        stderr: String,
+    },
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartupError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            StartupError::Schtasks { args, stderr } if stderr.to_lowercase().contains("denied") => {
+                write!(
+                    f,
+                    "schtasks {args} was denied; start HyperX Pilot as administrator once to register the startup task"
+                )
+            }
+            StartupError::Schtasks { args, stderr } => {
+                write!(f, "schtasks {args} failed: {}", stderr.trim())
+            }
+        }
+    }
+}
+
+impl std::error::Error for StartupError {}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StartupStatus {
+    pub registered: bool,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// `DOMAIN\user` the logon trigger and principal refer to.
+fn current_user() -> String {
+    let user = env::var("USERNAME").unwrap_or_default();
+    match env::var("USERDOMAIN") {
+        Ok(domain) => format!("{domain}\\{user}"),
+        Err(_) => user,
+    }
+}
+
+pub fn render_task(executable: &Path, user: &str, delay_secs: u32) -> String {
+    let user = escape(user);
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <RegistrationInfo>
+    <Description>Starts HyperX Pilot after login.</Description>
+  </RegistrationInfo>
+  <Triggers>
+    <LogonTrigger>
+      <Enabled>true</Enabled>
+      <UserId>{user}</UserId>
+      <Delay>PT{delay_secs}S</Delay>
+    </LogonTrigger>
+  </Triggers>
+  <Principals>
+    <Principal id="Author">
+      <UserId>{user}</UserId>
+      <LogonType>InteractiveToken</LogonType>
+      <RunLevel>HighestAvailable</RunLevel>
+    </Principal>
+  </Principals>
+  <Settings>
+    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>
+    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>
+    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>
+    <ExecutionTimeLimit>PT0S</ExecutionTimeLimit>
+  </Settings>
+  <Actions Context="Author">
+    <Exec>
+      <Command>{}</Command>
+    </Exec>
+  </Actions>
+</Task>
+"#,
+        escape(&executable.to_string_lossy())
+    )
+}
+
+fn schtasks(args: &[&str]) -> Result<String, StartupError> {
+    let output = Command::new("schtasks")
+        .args(args)
+        .output()
+        .map_err(|source| StartupError::Io {
+            path: PathBuf::from("schtasks.exe"),
+            source,
+        })?;
+    if !output.status.success() {
+        return Err(StartupError::Schtasks {
+            args: args.join(" "),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Register (or replace) the task, starting `delay_secs` after login.
+pub fn install(delay_secs: u32) -> Result<(), StartupError> {
+    let executable = env::current_exe().map_err(|source| StartupError::Io {
+        path: PathBuf::from("current_exe"),
+        source,
+    })?;
+    let path = env::temp_dir().join("hyperx-pilot-task.xml");
+    let io_error = |source| StartupError::Io {
+        path: path.clone(),
+        source,
+    };
+
+    // schtasks only reliably reads task XML as UTF-16 with a BOM.
+    let xml = render_task(&executable, &current_user(), delay_secs);
+    let encoded: Vec<u8> = Some(0xFEFF)
+        .into_iter()
+        .chain(xml.encode_utf16())
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    fs::write(&path, encoded).map_err(io_error)?;
+
+    let result = schtasks(&[
+        "/Create",
+        "/TN",
+        TASK_NAME,
+        "/XML",
+        &path.to_string_lossy(),
+        "/F",
+    ]);
+    let _ = fs::remove_file(&path);
+    result.map(|_| ())
+}
+
+pub fn uninstall() -> Result<(), StartupError> {
+    if !status().registered {
+        return Ok(());
+    }
+    schtasks(&["/Delete", "/TN", TASK_NAME, "/F"]).map(|_| ())
+}
+
+pub fn status() -> StartupStatus {
+    StartupStatus {
+        registered: schtasks(&["/Query", "/TN", TASK_NAME]).is_ok(),
+    }
+}
@@ -0,0 +1,150 @@
+//! Actionable toast notifications. Button presses come back through the
+//! toast's `Activated` event while the app runs and are handed to the callback
+//! given to [`Notifier::new`] as a [`ToastAction`].
+
+use crate::daemon::APP_IDENTIFIER;
+use crate::hyperx::{self, DeviceId};
+use std::sync::Arc;
+use windows::core::{IInspectable, Interface, Ref, Result, HSTRING};
+use windows::Data::Xml::Dom::XmlDocument;
+use windows::Foundation::TypedEventHandler;
+use windows::Win32::System::Com::{CoInitializeEx, COINIT_MULTITHREADED};
+use windows::UI::Notifications::{
+    ToastActivatedEventArgs, ToastNotification, ToastNotificationManager, ToastNotifier,
+};
+
+/// What a toast button asks the backend to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToastAction {
+    /// Re-apply the last-known settings once the device is back.
+    Retry {
+        device_id: DeviceId,
+    },
+    ApplyProfile {
+        name: String,
+    },
+}
+
+impl ToastAction {
+    /// Encoded into the button's `arguments` attribute.
+    fn encode(&self) -> String {
+        match self {
+            ToastAction::Retry { device_id } => format!("retry:{}", device_id.as_str()),
+            ToastAction::ApplyProfile { name } => format!("profile:{name}"),
+        }
+    }
+
+    fn decode(arguments: &str) -> Option<Self> {
+        match arguments.split_once(':')? {
+            ("retry", device_id) => Some(ToastAction::Retry {
+                device_id: device_id.parse().ok()?,
+            }),
+            ("profile", name) => Some(ToastAction::ApplyProfile {
+                name: name.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+type ActionHandler = Arc<dyn Fn(ToastAction) + Send + Sync>;
+
+pub struct Notifier {
+    notifier: ToastNotifier,
+    on_action: ActionHandler,
+}
+
+impl Notifier {
+    /// Initialise COM for the calling thread and register under the app's
+    /// AppUserModelID, which the installer assigns to the Start menu shortcut.
+    pub fn new(on_action: impl Fn(ToastAction) + Send + Sync + 'static) -> Result<Self> {
+        // SAFETY: COM is initialised once for the notification thread.
+        unsafe { CoInitializeEx(None, COINIT_MULTITHREADED).ok()? };
+        let notifier =
+            ToastNotificationManager::CreateToastNotifierWithId(&HSTRING::from(APP_IDENTIFIER))?;
+        Ok(Notifier {
+            notifier,
+            on_action: Arc::new(on_action),
+        })
+    }
+
+    pub fn disconnected(&self, device_id: DeviceId) -> Result<()> {
+        self.show(
+            &format!("{} disconnected", label(device_id)),
+            "Reconnect the headset and press Retry to apply your settings again.",
+            &[("Retry", ToastAction::Retry { device_id })],
+        )
+    }
+
+    /// Offers `power_saver_profile` as a button when one is configured.
+    pub fn battery_low(
+        &self,
+        device_id: DeviceId,
+        percent: u8,
+        power_saver_profile: Option<&str>,
+    ) -> Result<()> {
+        let actions: Vec<_> = power_saver_profile
+            .map(|name| {
+                let name = name.to_string();
+                (
+                    "Enable power saver profile",
+                    ToastAction::ApplyProfile { name },
+                )
+            })
+            .into_iter()
+            .collect();
+        self.show(
+            &format!("{} battery low", label(device_id)),
+            &format!("{percent}% remaining."),
+            &actions,
+        )
+    }
+
+    fn show(&self, title: &str, body: &str, actions: &[(&str, ToastAction)]) -> Result<()> {
+        let buttons: String = actions
+            .iter()
+            .map(|(content, action)| {
+                format!(
+                    r#"<action content="{}" arguments="{}" activationType="foreground"/>"#,
+                    escape(content),
+                    escape(&action.encode())
+                )
+            })
+            .collect();
+        let payload = format!(
+            r#"<toast><visual><binding template="ToastGeneric"><text>{}</text><text>{}</text></binding></visual><actions>{buttons}</actions></toast>"#,
+            escape(title),
+            escape(body)
+        );
+
+        let document = XmlDocument::new()?;
+        document.LoadXml(&HSTRING::from(payload))?;
+        let toast = ToastNotification::CreateToastNotification(&document)?;
+
+        let on_action = self.on_action.clone();
+        toast.Activated(&TypedEventHandler::new(
+            move |_, args: Ref<'_, IInspectable>| {
+                let arguments = args.ok()?.cast::<ToastActivatedEventArgs>()?.Arguments()?;
+                if let Some(action) = ToastAction::decode(&arguments.to_string()) {
+                    on_action(action);
+                }
+                Ok(())
+            },
+        ))?;
+        self.notifier.Show(&toast)
+    }
+}
+
+fn label(device_id: DeviceId) -> &'static str {
+    hyperx::supported_devices()
+        .iter()
+        .find(|device| device.id == device_id)
+        .map_or("Headset", |device| device.label)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,103 @@
+//! Publishes [`DeviceEvent::Resumed`] after the system wakes from sleep, since
+//! many headsets and dongles come back with their power-on defaults.
+
+use crate::events::{DeviceEvent, EventBus};
+use std::sync::Arc;
+
+/// Start listening for resume notifications on a background thread.
+#[cfg(target_os = "linux")]
+pub fn spawn(events: Arc<EventBus>) {
+    use tracing::warn;
+    use zbus::blocking::{Connection, Proxy};
+
+    std::thread::spawn(move || {
+        // logind announces sleep with `PrepareForSleep(true)` and wake-up with `false`.
+        let listen = || -> zbus::Result<()> {
+            let connection = Connection::system()?;
+            let manager = Proxy::new(
+                &connection,
+                "org.freedesktop.login1",
+                "/org/freedesktop/login1",
+                "org.freedesktop.login1.Manager",
+            )?;
+            for signal in manager.receive_signal("PrepareForSleep")? {
+                let sleeping: bool = signal.body().deserialize()?;
+                if !sleeping {
+                    events.publish(DeviceEvent::Resumed);
+                }
+            }
+            Ok(())
+        };
+        if let Err(err) = listen() {
+            warn!("resume detection disabled: {err}");
+        }
+    });
+}
+
+#[cfg(windows)]
+pub fn spawn(events: Arc<EventBus>) {
+    use std::ffi::c_void;
+    use tracing::warn;
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::System::Power::{
+        PowerRegisterSuspendResumeNotification, DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{DEVICE_NOTIFY_CALLBACK, PBT_APMRESUMEAUTOMATIC};
+
+    unsafe extern "system" fn on_power_event(
+        context: *const c_void,
+        kind: u32,
+        _setting: *const c_void,
+    ) -> u32 {
+        if kind == PBT_APMRESUMEAUTOMATIC {
+            // SAFETY: `context` is the bus leaked at registration.
+            let events = unsafe { &*context.cast::<EventBus>() };
+            events.publish(DeviceEvent::Resumed);
+        }
+        0
+    }
+
+    // The registration lasts for the whole process, so both the bus reference
+    // and the parameters are leaked on purpose.
+    let parameters = Box::leak(Box::new(DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS {
+        Callback: Some(on_power_event),
+        Context: Arc::into_raw(events) as *mut c_void,
+    }));
+    let mut registration = std::ptr::null_mut();
+    // SAFETY: `parameters` outlives the registration, see above.
+    let result = unsafe {
+        PowerRegisterSuspendResumeNotification(
+            DEVICE_NOTIFY_CALLBACK,
+            HANDLE(parameters as *mut DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS as *mut c_void),
+            &mut registration,
+        )
+    };
+    if let Err(err) = result.ok() {
+        warn!("resume detection disabled: {err}");
+    }
+}
+
+/// Elsewhere a resume shows up as the wall clock jumping ahead of the
+/// monotonic clock, which stops while the machine sleeps.
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn spawn(events: Arc<EventBus>) {
+    use std::thread;
+    use std::time::{Duration, Instant, SystemTime};
+
+    const TICK: Duration = Duration::from_secs(5);
+    const SLEEP_THRESHOLD: Duration = Duration::from_secs(30);
+
+    thread::spawn(move || {
+        let mut wall = SystemTime::now();
+        let mut monotonic = Instant::now();
+        loop {
+            thread::sleep(TICK);
+            let wall_elapsed = wall.elapsed().unwrap_or_default();
+            if wall_elapsed.saturating_sub(monotonic.elapsed()) > SLEEP_THRESHOLD {
+                events.publish(DeviceEvent::Resumed);
+            }
+            wall = SystemTime::now();
+            monotonic = Instant::now();
+        }
+    });
+}
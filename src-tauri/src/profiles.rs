@@ -0,0 +1,133 @@
+use crate::audio::{self, AudioError};
+use crate::hyperx::{self, Button, ButtonAction, ControlError, DeviceId};
+use crate::settings::SettingsStore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use tracing::warn;
+
+/// Values to write to a device. Fields left as `None` are not touched.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceSettings {
+    pub sidetone: Option<bool>,
+    /// The receiver's low-latency mode, on wireless models that have one.
+    pub game_mode: Option<bool>,
+    /// Remapped buttons, on models that allow it; buttons left out keep
+    /// their action.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub buttons: BTreeMap<Button, ButtonAction>,
+}
+
+impl DeviceSettings {
+    /// Overwrite the fields that are set in `other`.
+    pub fn merge(&mut self, other: &DeviceSettings) {
+        if other.sidetone.is_some() {
+            self.sidetone = other.sidetone;
+        }
+        if other.game_mode.is_some() {
+            self.game_mode = other.game_mode;
+        }
+        self.buttons.extend(&other.buttons);
+    }
+}
+
+/// A named set of device settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub device_id: DeviceId,
+    #[serde(flatten)]
+    pub settings: DeviceSettings,
+    /// Make the device's associated playback/recording devices the OS
+    /// defaults when the profile is applied.
+    #[serde(default)]
+    pub switch_audio_defaults: bool,
+}
+
+#[derive(Debug)]
+pub enum ProfileError {
+    NotFound { name: String },
+    Control(ControlError),
+    Audio(AudioError),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::NotFound { name } => write!(f, "no profile named '{name}'"),
+            ProfileError::Control(err) => err.fmt(f),
+            ProfileError::Audio(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+/// Write `values` to the device without recording them.
+fn write(device_id: DeviceId, values: &DeviceSettings) -> Result<(), ControlError> {
+    if let Some(enabled) = values.sidetone {
+        hyperx::devices().get(device_id).set_sidetone(enabled)?;
+    }
+    if let Some(enabled) = values.game_mode {
+        hyperx::devices().get(device_id).set_game_mode(enabled)?;
+    }
+    if !values.buttons.is_empty() {
+        hyperx::devices()
+            .get(device_id)
+            .set_button_mapping(&values.buttons)?;
+    }
+    Ok(())
+}
+
+/// Write `values` to the device and remember them as its last-known state,
+/// which is what gets restored when the device reconnects.
+pub fn apply(
+    settings: &SettingsStore,
+    device_id: DeviceId,
+    values: &DeviceSettings,
+) -> Result<(), ControlError> {
+    write(device_id, values)?;
+    record(settings, device_id, values);
+    Ok(())
+}
+
+/// Remember values that were written to the device by other means.
+pub fn record(settings: &SettingsStore, device_id: DeviceId, values: &DeviceSettings) {
+    let result = settings.update(|settings| {
+        settings
+            .last_known
+            .entry(device_id)
+            .or_default()
+            .merge(values);
+    });
+    if let Err(err) = result {
+        warn!("failed to record device state: {err}");
+    }
+}
+
+/// Re-apply the last-known state of a device, e.g. after it was replugged.
+pub fn restore(settings: &SettingsStore, device_id: DeviceId) -> Result<(), ControlError> {
+    match settings.get().last_known.get(&device_id) {
+        Some(values) => write(device_id, values),
+        None => Ok(()),
+    }
+}
+
+pub fn apply_named(settings: &SettingsStore, name: &str) -> Result<(), ProfileError> {
+    let profile = settings
+        .get()
+        .profiles
+        .into_iter()
+        .find(|profile| profile.name == name)
+        .ok_or_else(|| ProfileError::NotFound {
+            name: name.to_string(),
+        })?;
+    apply(settings, profile.device_id, &profile.settings).map_err(ProfileError::Control)?;
+    if profile.switch_audio_defaults {
+        let endpoints =
+            audio::device_endpoints(settings, profile.device_id).map_err(ProfileError::Audio)?;
+        audio::set_default_endpoints(&endpoints).map_err(ProfileError::Audio)?;
+    }
+    Ok(())
+}
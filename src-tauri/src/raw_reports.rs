@@ -0,0 +1,112 @@
+//! The raw feature report console for protocol work: arbitrary reports in
+//! and out as hex, so captures can be tried out without switching to
+//! hidapitester. Off unless `developer.raw_reports` is set, and every
+//! exchange is logged.
+
+use crate::hyperx::{self, ControlError, DeviceId};
+use crate::recorder;
+use crate::settings::SettingsStore;
+use serde::Serialize;
+use std::fmt;
+use tracing::info;
+
+#[derive(Debug)]
+pub enum RawReportError {
+    Disabled,
+    InvalidHex(String),
+    Control(ControlError),
+}
+
+impl fmt::Display for RawReportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawReportError::Disabled => {
+                write!(
+                    f,
+                    "raw feature reports are disabled (developer.raw_reports)"
+                )
+            }
+            RawReportError::InvalidHex(token) => write!(f, "'{token}' is not a hex byte"),
+            RawReportError::Control(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for RawReportError {}
+
+/// Bytes of one exchange as space-separated hex.
+#[derive(Debug, Clone, Serialize)]
+pub struct RawExchange {
+    pub sent: String,
+    pub received: Option<String>,
+}
+
+/// Parse `20 86 01`, `208601` or `0x20,0x86,0x01`.
+fn parse_hex(text: &str) -> Result<Vec<u8>, RawReportError> {
+    let tokens: Vec<&str> = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty())
+        .collect();
+    let mut bytes = Vec::new();
+    for token in tokens {
+        let digits = token.trim_start_matches("0x").trim_start_matches("0X");
+        if digits.is_empty() || digits.len() % 2 != 0 {
+            return Err(RawReportError::InvalidHex(token.to_string()));
+        }
+        for pair in digits.as_bytes().chunks(2) {
+            let pair = std::str::from_utf8(pair).unwrap_or_default();
+            let byte = u8::from_str_radix(pair, 16)
+                .map_err(|_| RawReportError::InvalidHex(token.to_string()))?;
+            bytes.push(byte);
+        }
+    }
+    Ok(bytes)
+}
+
+fn ensure_enabled(settings: &SettingsStore) -> Result<(), RawReportError> {
+    if settings.get().developer.raw_reports {
+        Ok(())
+    } else {
+        Err(RawReportError::Disabled)
+    }
+}
+
+/// Send `data`, report ID first, zero-padded to the declared report length.
+pub fn send(
+    settings: &SettingsStore,
+    device_id: DeviceId,
+    data: &str,
+) -> Result<RawExchange, RawReportError> {
+    ensure_enabled(settings)?;
+    let data = parse_hex(data)?;
+    let sent = hyperx::devices()
+        .get(device_id)
+        .send_raw_feature_report(&data)
+        .map_err(RawReportError::Control)?;
+    let sent = recorder::hex(&sent);
+    info!("raw write to {device_id}: {sent}");
+    Ok(RawExchange {
+        sent,
+        received: None,
+    })
+}
+
+/// Read feature report `report_id`; `length` defaults to its declared size.
+pub fn get(
+    settings: &SettingsStore,
+    device_id: DeviceId,
+    report_id: u8,
+    length: Option<usize>,
+) -> Result<RawExchange, RawReportError> {
+    ensure_enabled(settings)?;
+    let received = hyperx::devices()
+        .get(device_id)
+        .get_raw_feature_report(report_id, length)
+        .map_err(RawReportError::Control)?;
+    let received = recorder::hex(&received);
+    info!("raw read of report 0x{report_id:02X} from {device_id}: {received}");
+    Ok(RawExchange {
+        sent: format!("{report_id:02x}"),
+        received: Some(received),
+    })
+}
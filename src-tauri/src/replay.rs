@@ -0,0 +1,250 @@
+//! Replays a [`crate::recorder`] capture through the mock backend
+//! (`--replay capture.jsonl`), so a protocol bug from a user's capture can be
+//! reproduced without their headset. Reads are answered with the recorded
+//! responses in order, per device and report id, repeating the last one once
+//! they run out. Writes are compared against the recorded ones; differences
+//! are logged and kept in [`divergences`].
+
+use crate::hyperx::DeviceId;
+use crate::mock::{self, MockBackend};
+use crate::recorder::{self, Header, Operation, Transaction};
+use hidapi::HidError;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use tracing::warn;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+    UnsupportedFormat(u32),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "cannot read capture: {err}"),
+            ReplayError::Parse { line, message } => {
+                write!(f, "invalid capture at line {line}: {message}")
+            }
+            ReplayError::UnsupportedFormat(format) => write!(
+                f,
+                "capture format {format} is not supported (expected {})",
+                recorder::FORMAT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// A parsed capture file.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    pub header: Header,
+    pub transactions: Vec<Transaction>,
+}
+
+impl Capture {
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        Capture::parse(&fs::read_to_string(path).map_err(ReplayError::Io)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, ReplayError> {
+        let mut lines = source
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let parse_error = |line: usize, err: serde_json::Error| ReplayError::Parse {
+            line: line + 1,
+            message: err.to_string(),
+        };
+        let (index, first) = lines.next().ok_or(ReplayError::Parse {
+            line: 1,
+            message: "missing header".into(),
+        })?;
+        let header: Header = serde_json::from_str(first).map_err(|err| parse_error(index, err))?;
+        if header.format != recorder::FORMAT_VERSION {
+            return Err(ReplayError::UnsupportedFormat(header.format));
+        }
+        let transactions = lines
+            .map(|(index, line)| serde_json::from_str(line).map_err(|err| parse_error(index, err)))
+            .collect::<Result<_, _>>()?;
+        Ok(Capture {
+            header,
+            transactions,
+        })
+    }
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    text.split_whitespace()
+        .map(|byte| u8::from_str_radix(byte, 16).ok())
+        .collect()
+}
+
+type Response = Result<Vec<u8>, String>;
+/// Written bytes and the error the write failed with, if any.
+type Write = (Vec<u8>, Option<String>);
+
+#[derive(Default)]
+struct State {
+    devices: Vec<DeviceId>,
+    responses: HashMap<(DeviceId, u8), VecDeque<Response>>,
+    last_responses: HashMap<(DeviceId, u8), Response>,
+    writes: HashMap<DeviceId, VecDeque<Write>>,
+    divergences: Vec<String>,
+}
+
+impl State {
+    fn new(capture: &Capture) -> Self {
+        let mut state = State::default();
+        for transaction in &capture.transactions {
+            let device_id = transaction.device_id;
+            if !state.devices.contains(&device_id) {
+                state.devices.push(device_id);
+            }
+            let sent = parse_hex(&transaction.sent).unwrap_or_default();
+            match transaction.operation {
+                Operation::SendFeatureReport => state
+                    .writes
+                    .entry(device_id)
+                    .or_default()
+                    .push_back((sent, transaction.error.clone())),
+                Operation::GetFeatureReport => {
+                    let response = match (&transaction.received, &transaction.error) {
+                        (_, Some(error)) => Err(error.clone()),
+                        (Some(received), None) => parse_hex(received)
+                            .ok_or_else(|| format!("unreadable response '{received}'")),
+                        (None, None) => Err("the capture holds no response".to_string()),
+                    };
+                    let report_id = sent.first().copied().unwrap_or(0);
+                    state
+                        .responses
+                        .entry((device_id, report_id))
+                        .or_default()
+                        .push_back(response);
+                }
+            }
+        }
+        state
+    }
+
+    fn diverged(&mut self, message: String) {
+        warn!("replay diverged: {message}");
+        self.divergences.push(message);
+    }
+}
+
+struct Replay {
+    state: Mutex<State>,
+}
+
+static REPLAY: OnceLock<Replay> = OnceLock::new();
+
+fn replay_error(message: String) -> HidError {
+    HidError::HidApiError { message }
+}
+
+impl MockBackend for Replay {
+    fn connected(&self) -> Vec<DeviceId> {
+        self.state.lock().unwrap().devices.clone()
+    }
+
+    fn send_feature_report(&self, device_id: DeviceId, data: &[u8]) -> Result<(), HidError> {
+        let mut state = self.state.lock().unwrap();
+        let expected = state
+            .writes
+            .get_mut(&device_id)
+            .and_then(VecDeque::pop_front);
+        match expected {
+            Some((expected, error)) => {
+                if expected != data {
+                    state.diverged(format!(
+                        "{device_id} wrote [{}], the capture has [{}]",
+                        recorder::hex(data),
+                        recorder::hex(&expected)
+                    ));
+                }
+                error.map_or(Ok(()), |message| Err(replay_error(message)))
+            }
+            None => {
+                state.diverged(format!(
+                    "{device_id} wrote [{}] after the captured writes ran out",
+                    recorder::hex(data)
+                ));
+                Ok(())
+            }
+        }
+    }
+
+    fn get_feature_report(
+        &self,
+        device_id: DeviceId,
+        buffer: &mut [u8],
+    ) -> Result<usize, HidError> {
+        let report_id = buffer.first().copied().unwrap_or(0);
+        let key = (device_id, report_id);
+        let mut state = self.state.lock().unwrap();
+        let response = match state.responses.get_mut(&key).and_then(VecDeque::pop_front) {
+            Some(response) => {
+                state.last_responses.insert(key, response.clone());
+                response
+            }
+            None => state.last_responses.get(&key).cloned().ok_or_else(|| {
+                replay_error(format!(
+                    "the capture has no response for report 0x{report_id:02X}"
+                ))
+            })?,
+        };
+        let data = response.map_err(replay_error)?;
+        let length = data.len().min(buffer.len());
+        buffer[..length].copy_from_slice(&data[..length]);
+        Ok(length)
+    }
+}
+
+/// Make `capture` the HID backend, replacing a capture replayed before.
+pub fn enable(capture: &Capture) {
+    let replay = REPLAY.get_or_init(|| Replay {
+        state: Mutex::new(State::default()),
+    });
+    *replay.state.lock().unwrap() = State::new(capture);
+    mock::install(replay);
+}
+
+/// Writes that did not match the capture, oldest first.
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn divergences() -> Vec<String> {
+    REPLAY.get().map_or_else(Vec::new, |replay| {
+        replay.state.lock().unwrap().divergences.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hyperx::{self, Model};
+
+    // Captures copied from bug reports become regression tests like this one.
+    const SIDETONE_ON: &str = r#"{"format":1,"app_version":"0.1.0","os":"linux","hid_backend":"hidraw","started_at":0}
+{"time_ms":0,"device_id":"cloud_iii_wired","operation":"get_feature_report","sent":"20 00 00 00","received":"20 86 01 00"}
+"#;
+
+    #[test]
+    fn replays_sidetone_state() {
+        enable(&Capture::parse(SIDETONE_ON).unwrap());
+        assert_eq!(
+            hyperx::devices()
+                .get(DeviceId::first(Model::CloudIiiWired))
+                .read_sidetone_state()
+                .unwrap(),
+            Some(true)
+        );
+        assert!(divergences().is_empty());
+    }
+}
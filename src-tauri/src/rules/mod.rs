@@ -0,0 +1,394 @@
+//! Automation rules loaded from `scripts/*.rules` in the config directory at
+//! startup; see [`parser`] for the language. Rules can only read device
+//! state and trigger the app's own actions, plus `run`, which uses the same
+//! shell as hook scripts and only works with `rules.allow_run` set in the
+//! settings file. A rule file can therefore do no more than the settings
+//! file already allows.
+
+mod parser;
+
+use crate::actions;
+use crate::audio;
+use crate::audit::{self, Source};
+use crate::events::{DeviceEvent, EventBus};
+use crate::hooks;
+use crate::hyperx::{self, DeviceId};
+use crate::profiles::{self, DeviceSettings};
+use crate::settings::SettingsStore;
+use parser::{Comparison, EventKind, Expr, Rule, RuleAction, Trigger, Variable};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+pub const SCRIPTS_DIR: &str = "scripts";
+/// Wait used when no rule has a timer.
+const IDLE_WAIT: Duration = Duration::from_secs(3600);
+
+/// Rules of every `.rules` file in `dir`, in file name order. Files with
+/// errors are skipped as a whole and their errors logged.
+fn load(dir: &Path) -> Vec<Rule> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<_> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "rules")
+        })
+        .collect();
+    paths.sort();
+
+    let mut rules = Vec::new();
+    for path in paths {
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                warn!("cannot read {}: {err}", path.display());
+                continue;
+            }
+        };
+        match parser::parse(&source) {
+            Ok(parsed) => rules.extend(parsed),
+            Err(errors) => {
+                for error in errors {
+                    warn!("{} skipped, {error}", path.display());
+                }
+            }
+        }
+    }
+    rules
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct DeviceState {
+    connected: bool,
+    battery: Option<u8>,
+    muted: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(f64),
+    Bool(bool),
+    Text(String),
+    /// A value not known yet, e.g. the battery of a wired headset.
+    Missing,
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Number(number) => *number != 0.0,
+            Value::Bool(value) => *value,
+            Value::Text(text) => !text.is_empty(),
+            Value::Missing => false,
+        }
+    }
+}
+
+fn evaluate(expr: &Expr, device_id: DeviceId, state: &DeviceState) -> Value {
+    match expr {
+        Expr::Number(number) => Value::Number(*number),
+        Expr::Bool(value) => Value::Bool(*value),
+        Expr::Text(text) => Value::Text(text.clone()),
+        Expr::Variable(Variable::Battery) => state
+            .battery
+            .map_or(Value::Missing, |percent| Value::Number(f64::from(percent))),
+        Expr::Variable(Variable::Muted) => state.muted.map_or(Value::Missing, Value::Bool),
+        Expr::Variable(Variable::Connected) => Value::Bool(state.connected),
+        Expr::Variable(Variable::Device) => Value::Text(device_id.to_string()),
+        Expr::Not(inner) => Value::Bool(!evaluate(inner, device_id, state).truthy()),
+        Expr::And(left, right) => Value::Bool(
+            evaluate(left, device_id, state).truthy() && evaluate(right, device_id, state).truthy(),
+        ),
+        Expr::Or(left, right) => Value::Bool(
+            evaluate(left, device_id, state).truthy() || evaluate(right, device_id, state).truthy(),
+        ),
+        Expr::Compare(comparison, left, right) => {
+            let left = evaluate(left, device_id, state);
+            let right = evaluate(right, device_id, state);
+            Value::Bool(compare(*comparison, &left, &right))
+        }
+    }
+}
+
+/// Comparisons with a missing value, or between different types, are false.
+fn compare(comparison: Comparison, left: &Value, right: &Value) -> bool {
+    let ordering = match (left, right) {
+        (Value::Number(left), Value::Number(right)) => left.partial_cmp(right),
+        (Value::Text(left), Value::Text(right)) => Some(left.cmp(right)),
+        (Value::Bool(left), Value::Bool(right)) => Some(left.cmp(right)),
+        _ => None,
+    };
+    let Some(ordering) = ordering else {
+        return false;
+    };
+    match comparison {
+        Comparison::Less => ordering == Ordering::Less,
+        Comparison::LessEqual => ordering != Ordering::Greater,
+        Comparison::Greater => ordering == Ordering::Greater,
+        Comparison::GreaterEqual => ordering != Ordering::Less,
+        Comparison::Equal => ordering == Ordering::Equal,
+        Comparison::NotEqual => ordering != Ordering::Equal,
+    }
+}
+
+/// Load the rules under `config_dir` and run them until the process exits.
+pub fn spawn(settings: Arc<SettingsStore>, events: &EventBus, config_dir: &Path) {
+    let rules = load(&config_dir.join(SCRIPTS_DIR));
+    if rules.is_empty() {
+        return;
+    }
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        let mut states: HashMap<DeviceId, DeviceState> = HashMap::new();
+        let started = Instant::now();
+        let mut timers: Vec<(usize, Duration, Instant)> = rules
+            .iter()
+            .enumerate()
+            .filter_map(|(index, rule)| match rule.trigger {
+                Trigger::Every(interval) => Some((index, interval, started + interval)),
+                Trigger::On(_) => None,
+            })
+            .collect();
+
+        loop {
+            let wait = timers
+                .iter()
+                .map(|(_, _, due)| due.saturating_duration_since(Instant::now()))
+                .min()
+                .unwrap_or(IDLE_WAIT);
+            match receiver.recv_timeout(wait) {
+                Ok(event) => {
+                    let Some((kind, devices)) = apply_event(&mut states, &event) else {
+                        continue;
+                    };
+                    for rule in &rules {
+                        if rule.trigger == Trigger::On(kind) {
+                            for &device_id in &devices {
+                                run_rule(&settings, rule, device_id, &states);
+                            }
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+
+            let now = Instant::now();
+            for (index, interval, due) in &mut timers {
+                if *due > now {
+                    continue;
+                }
+                *due = now + *interval;
+                for device in hyperx::list_devices() {
+                    run_rule(&settings, &rules[*index], device.id, &states);
+                }
+            }
+        }
+    });
+}
+
+/// Fold `event` into `states`; returns the trigger it fires and the devices
+/// to evaluate rules for.
+fn apply_event(
+    states: &mut HashMap<DeviceId, DeviceState>,
+    event: &DeviceEvent,
+) -> Option<(EventKind, Vec<DeviceId>)> {
+    let (kind, device_id) = match *event {
+        DeviceEvent::Connected { device_id } => {
+            states.entry(device_id).or_default().connected = true;
+            (EventKind::Connected, device_id)
+        }
+        DeviceEvent::Disconnected { device_id } => {
+            states.insert(device_id, DeviceState::default());
+            (EventKind::Disconnected, device_id)
+        }
+        DeviceEvent::BatteryChanged { device_id, percent } => {
+            states.entry(device_id).or_default().battery = Some(percent);
+            (EventKind::Battery, device_id)
+        }
+        DeviceEvent::MicMuteChanged { device_id, muted } => {
+            states.entry(device_id).or_default().muted = Some(muted);
+            (EventKind::Mute, device_id)
+        }
+        DeviceEvent::Resumed => {
+            let connected = states
+                .iter()
+                .filter(|(_, state)| state.connected)
+                .map(|(&device_id, _)| device_id)
+                .collect();
+            return Some((EventKind::Resume, connected));
+        }
+        DeviceEvent::BatteryLow { .. }
+        | DeviceEvent::HeadsetPoweredOn { .. }
+        | DeviceEvent::HeadsetPoweredOff { .. }
+        | DeviceEvent::FeatureChanged { .. }
+        | DeviceEvent::BoomMoved { .. }
+        | DeviceEvent::ButtonPressed { .. }
+        | DeviceEvent::DialTurned { .. }
+        | DeviceEvent::SessionChanged { .. } => return None,
+    };
+    Some((kind, vec![device_id]))
+}
+
+fn run_rule(
+    settings: &SettingsStore,
+    rule: &Rule,
+    device_id: DeviceId,
+    states: &HashMap<DeviceId, DeviceState>,
+) {
+    let state = states.get(&device_id).copied().unwrap_or_default();
+    let applies = rule
+        .condition
+        .as_ref()
+        .is_none_or(|condition| evaluate(condition, device_id, &state).truthy());
+    if !applies {
+        return;
+    }
+    for action in &rule.actions {
+        let result = execute(settings, action, device_id, &state);
+        let result = match audited(action) {
+            Some((command, value)) => {
+                audit::record(Source::Rule, Some(device_id), command, value, result)
+            }
+            None => result,
+        };
+        if let Err(err) = result {
+            warn!("rule action {action:?} failed for {device_id}: {err}");
+        }
+    }
+}
+
+/// Audit log command and value of actions that change state.
+fn audited(action: &RuleAction) -> Option<(&'static str, serde_json::Value)> {
+    use serde_json::json;
+    Some(match action {
+        RuleAction::FlashLed => ("flash_mute_led", json!(null)),
+        RuleAction::Sidetone(enabled) => ("set_sidetone", json!(enabled)),
+        RuleAction::Mute(muted) => ("set_mic_mute", json!(muted)),
+        RuleAction::ToggleMute => ("toggle_mic_mute", json!(null)),
+        RuleAction::ApplyProfile(name) => ("apply_profile", json!(name)),
+        RuleAction::Run(command) => ("run", json!(command)),
+        RuleAction::Log(_) => return None,
+    })
+}
+
+fn execute(
+    settings: &SettingsStore,
+    action: &RuleAction,
+    device_id: DeviceId,
+    state: &DeviceState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        RuleAction::FlashLed => actions::flash_mute_led(device_id)?,
+        RuleAction::Sidetone(enabled) => {
+            let values = DeviceSettings {
+                sidetone: Some(*enabled),
+                ..DeviceSettings::default()
+            };
+            profiles::apply(settings, device_id, &values)?;
+        }
+        RuleAction::Mute(muted) => audio::set_mic_mute(device_id, *muted)?,
+        RuleAction::ToggleMute => audio::toggle_mic_mute(device_id)?,
+        RuleAction::ApplyProfile(name) => profiles::apply_named(settings, name)?,
+        RuleAction::Run(_) if !settings.get().rules.allow_run => {
+            return Err("commands from rules are off, set rules.allow_run to run them".into());
+        }
+        RuleAction::Run(command) => {
+            let mut environment = vec![("HYPERX_PILOT_DEVICE", device_id.to_string())];
+            if let Some(percent) = state.battery {
+                environment.push(("HYPERX_PILOT_BATTERY", percent.to_string()));
+            }
+            if let Some(muted) = state.muted {
+                environment.push(("HYPERX_PILOT_MUTED", u8::from(muted).to_string()));
+            }
+            hooks::run_command(command, &environment);
+        }
+        RuleAction::Log(message) => info!("rule: {message}"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hyperx::Model;
+
+    const DEVICE: DeviceId = DeviceId::first(Model::CloudIiiWired);
+
+    fn holds(condition: &str, state: &DeviceState) -> bool {
+        let rules = parser::parse(&format!("on mute if {condition}: mute")).unwrap();
+        let condition = rules[0].condition.as_ref().unwrap();
+        evaluate(condition, DEVICE, state).truthy()
+    }
+
+    #[test]
+    fn evaluates_conditions() {
+        let state = DeviceState {
+            connected: true,
+            battery: Some(12),
+            muted: Some(false),
+        };
+        assert!(holds("battery < 15 and not muted", &state));
+        assert!(!holds("battery >= 15 or muted", &state));
+        assert!(holds("connected == true", &state));
+        assert!(holds(r#"device == "cloud_iii_wired""#, &state));
+        assert!(holds(r#"device != "cloud_iii_wireless""#, &state));
+        assert!(holds("not (battery > 50 and connected)", &state));
+    }
+
+    #[test]
+    fn missing_and_mismatched_values_compare_false() {
+        let state = DeviceState {
+            connected: true,
+            battery: None,
+            muted: None,
+        };
+        assert!(!holds("battery < 15", &state));
+        assert!(!holds("battery >= 15", &state));
+        assert!(!holds("muted", &state));
+        assert!(!holds("connected == 1", &state));
+        assert!(!holds(r#"battery == "12""#, &state));
+    }
+
+    #[test]
+    fn events_update_the_state_they_trigger_on() {
+        let mut states = HashMap::new();
+        let connected = DeviceEvent::Connected { device_id: DEVICE };
+        assert_eq!(
+            apply_event(&mut states, &connected),
+            Some((EventKind::Connected, vec![DEVICE]))
+        );
+        let battery = DeviceEvent::BatteryChanged {
+            device_id: DEVICE,
+            percent: 40,
+        };
+        assert_eq!(
+            apply_event(&mut states, &battery),
+            Some((EventKind::Battery, vec![DEVICE]))
+        );
+        assert_eq!(states[&DEVICE].battery, Some(40));
+        assert_eq!(
+            apply_event(&mut states, &DeviceEvent::Resumed),
+            Some((EventKind::Resume, vec![DEVICE]))
+        );
+
+        let disconnected = DeviceEvent::Disconnected { device_id: DEVICE };
+        apply_event(&mut states, &disconnected);
+        assert!(!states[&DEVICE].connected);
+        assert_eq!(states[&DEVICE].battery, None);
+        assert_eq!(
+            apply_event(&mut states, &DeviceEvent::Resumed),
+            Some((EventKind::Resume, Vec::new()))
+        );
+    }
+}
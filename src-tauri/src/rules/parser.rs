@@ -0,0 +1,505 @@
+//! Parser for rule files. One rule per line, `#` starts a comment:
+//!
+//! ```text
+//! on battery if battery < 15 and not muted: flash_led, run "notify-send 'Charge me'"
+//! every 10m if connected: apply_profile "Default"
+//! ```
+//!
+//! Triggers are `on connected|disconnected|battery|mute|resume` and
+//! `every <n>s|m|h`. Conditions compare `battery`, `muted`, `connected` and
+//! `device` with `< <= > >= == !=`, combined with `and`, `or`, `not` and
+//! parentheses.
+
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Connected,
+    Disconnected,
+    Battery,
+    Mute,
+    Resume,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trigger {
+    On(EventKind),
+    Every(Duration),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variable {
+    Battery,
+    Muted,
+    Connected,
+    Device,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Bool(bool),
+    Text(String),
+    Variable(Variable),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(Comparison, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleAction {
+    FlashLed,
+    Sidetone(bool),
+    Mute(bool),
+    ToggleMute,
+    ApplyProfile(String),
+    Run(String),
+    Log(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub trigger: Trigger,
+    pub condition: Option<Expr>,
+    pub actions: Vec<RuleAction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Number(f64),
+    Text(String),
+    Operator(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "'{word}'"),
+            Token::Number(number) => write!(f, "{number}"),
+            Token::Text(text) => write!(f, "{text:?}"),
+            Token::Operator(operator) => write!(f, "'{operator}'"),
+        }
+    }
+}
+
+const OPERATORS: [&str; 10] = ["<=", ">=", "==", "!=", "<", ">", "(", ")", ":", ","];
+
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+    while let Some(next) = rest.chars().next() {
+        if next == '#' {
+            break;
+        }
+        if let Some(operator) = OPERATORS
+            .iter()
+            .find(|operator| rest.starts_with(**operator))
+        {
+            tokens.push(Token::Operator(operator));
+            rest = &rest[operator.len()..];
+        } else if next == '"' {
+            let end = rest[1..].find('"').ok_or("unterminated string")? + 1;
+            tokens.push(Token::Text(rest[1..end].to_string()));
+            rest = &rest[end + 1..];
+        } else if next.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let number = rest[..end]
+                .parse()
+                .map_err(|_| format!("invalid number '{}'", &rest[..end]))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if next.is_alphabetic() || next == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            return Err(format!("unexpected character '{next}'"));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self.peek().cloned().ok_or("unexpected end of line")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        let matches = matches!(self.peek(), Some(Token::Word(next)) if next == word);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn eat_operator(&mut self, operator: &str) -> bool {
+        let matches = matches!(self.peek(), Some(Token::Operator(next)) if *next == operator);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn expect_operator(&mut self, operator: &str) -> Result<(), String> {
+        if self.eat_operator(operator) {
+            return Ok(());
+        }
+        match self.peek() {
+            Some(token) => Err(format!("expected '{operator}', found {token}")),
+            None => Err(format!("expected '{operator}'")),
+        }
+    }
+
+    fn word(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            token => Err(format!("expected a name, found {token}")),
+        }
+    }
+
+    fn text(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Text(text) => Ok(text),
+            token => Err(format!("expected a quoted string, found {token}")),
+        }
+    }
+
+    fn trigger(&mut self) -> Result<Trigger, String> {
+        match self.word()?.as_str() {
+            "on" => {
+                let event = match self.word()?.as_str() {
+                    "connected" => EventKind::Connected,
+                    "disconnected" => EventKind::Disconnected,
+                    "battery" => EventKind::Battery,
+                    "mute" => EventKind::Mute,
+                    "resume" => EventKind::Resume,
+                    other => return Err(format!("unknown event '{other}'")),
+                };
+                Ok(Trigger::On(event))
+            }
+            "every" => {
+                let Token::Number(amount) = self.next()? else {
+                    return Err("expected an interval like 30s, 5m or 1h".into());
+                };
+                let unit = match self.word()?.as_str() {
+                    "s" => 1.0,
+                    "m" => 60.0,
+                    "h" => 3600.0,
+                    other => return Err(format!("unknown time unit '{other}'")),
+                };
+                let seconds = amount * unit;
+                if seconds < 1.0 {
+                    return Err("intervals must be at least one second".into());
+                }
+                let interval =
+                    Duration::try_from_secs_f64(seconds).map_err(|_| "interval is too long")?;
+                Ok(Trigger::Every(interval))
+            }
+            other => Err(format!("rules start with 'on' or 'every', not '{other}'")),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, String> {
+        let mut left = self.conjunction()?;
+        while self.eat_word("or") {
+            left = Expr::Or(Box::new(left), Box::new(self.conjunction()?));
+        }
+        Ok(left)
+    }
+
+    fn conjunction(&mut self) -> Result<Expr, String> {
+        let mut left = self.negation()?;
+        while self.eat_word("and") {
+            left = Expr::And(Box::new(left), Box::new(self.negation()?));
+        }
+        Ok(left)
+    }
+
+    fn negation(&mut self) -> Result<Expr, String> {
+        if self.eat_word("not") {
+            return Ok(Expr::Not(Box::new(self.negation()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.term()?;
+        let comparison = match self.peek() {
+            Some(Token::Operator("<")) => Comparison::Less,
+            Some(Token::Operator("<=")) => Comparison::LessEqual,
+            Some(Token::Operator(">")) => Comparison::Greater,
+            Some(Token::Operator(">=")) => Comparison::GreaterEqual,
+            Some(Token::Operator("==")) => Comparison::Equal,
+            Some(Token::Operator("!=")) => Comparison::NotEqual,
+            _ => return Ok(left),
+        };
+        self.position += 1;
+        let right = self.term()?;
+        Ok(Expr::Compare(comparison, Box::new(left), Box::new(right)))
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        match self.next()? {
+            Token::Number(number) => Ok(Expr::Number(number)),
+            Token::Text(text) => Ok(Expr::Text(text)),
+            Token::Operator("(") => {
+                let inner = self.expression()?;
+                self.expect_operator(")")?;
+                Ok(inner)
+            }
+            Token::Word(word) => match word.as_str() {
+                "true" => Ok(Expr::Bool(true)),
+                "false" => Ok(Expr::Bool(false)),
+                "battery" => Ok(Expr::Variable(Variable::Battery)),
+                "muted" => Ok(Expr::Variable(Variable::Muted)),
+                "connected" => Ok(Expr::Variable(Variable::Connected)),
+                "device" => Ok(Expr::Variable(Variable::Device)),
+                other => Err(format!("unknown variable '{other}'")),
+            },
+            token => Err(format!("unexpected {token}")),
+        }
+    }
+
+    fn action(&mut self) -> Result<RuleAction, String> {
+        match self.word()?.as_str() {
+            "flash_led" => Ok(RuleAction::FlashLed),
+            "sidetone" => match self.word()?.as_str() {
+                "on" => Ok(RuleAction::Sidetone(true)),
+                "off" => Ok(RuleAction::Sidetone(false)),
+                other => Err(format!("sidetone takes 'on' or 'off', not '{other}'")),
+            },
+            "mute" => Ok(RuleAction::Mute(true)),
+            "unmute" => Ok(RuleAction::Mute(false)),
+            "toggle_mute" => Ok(RuleAction::ToggleMute),
+            "apply_profile" => Ok(RuleAction::ApplyProfile(self.text()?)),
+            "run" => Ok(RuleAction::Run(self.text()?)),
+            "log" => Ok(RuleAction::Log(self.text()?)),
+            other => Err(format!("unknown action '{other}'")),
+        }
+    }
+
+    fn rule(&mut self) -> Result<Rule, String> {
+        let trigger = self.trigger()?;
+        let condition = if self.eat_word("if") {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.expect_operator(":")?;
+        let mut actions = vec![self.action()?];
+        while self.eat_operator(",") {
+            actions.push(self.action()?);
+        }
+        if let Some(token) = self.peek() {
+            return Err(format!("unexpected {token} after the actions"));
+        }
+        Ok(Rule {
+            trigger,
+            condition,
+            actions,
+        })
+    }
+}
+
+/// Parse a whole rule file, collecting every broken line instead of
+/// stopping at the first.
+pub fn parse(source: &str) -> Result<Vec<Rule>, Vec<ParseError>> {
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let result = tokenize(line).and_then(|tokens| {
+            if tokens.is_empty() {
+                return Ok(None);
+            }
+            Parser {
+                tokens,
+                position: 0,
+            }
+            .rule()
+            .map(Some)
+        });
+        match result {
+            Ok(Some(rule)) => rules.push(rule),
+            Ok(None) => {}
+            Err(message) => errors.push(ParseError {
+                line: index + 1,
+                message,
+            }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(rules)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_one(line: &str) -> Rule {
+        let mut rules = parse(line).unwrap();
+        assert_eq!(rules.len(), 1);
+        rules.remove(0)
+    }
+
+    fn errors(source: &str) -> Vec<(usize, String)> {
+        parse(source)
+            .unwrap_err()
+            .into_iter()
+            .map(|error| (error.line, error.message))
+            .collect()
+    }
+
+    #[test]
+    fn parses_conditions_and_actions() {
+        let rule = parse_one(
+            r#"on battery if battery < 15 and not muted: flash_led, run "notify-send 'Charge me'""#,
+        );
+        assert_eq!(rule.trigger, Trigger::On(EventKind::Battery));
+        assert_eq!(
+            rule.condition,
+            Some(Expr::And(
+                Box::new(Expr::Compare(
+                    Comparison::Less,
+                    Box::new(Expr::Variable(Variable::Battery)),
+                    Box::new(Expr::Number(15.0)),
+                )),
+                Box::new(Expr::Not(Box::new(Expr::Variable(Variable::Muted)))),
+            ))
+        );
+        assert_eq!(
+            rule.actions,
+            vec![
+                RuleAction::FlashLed,
+                RuleAction::Run("notify-send 'Charge me'".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let rule = parse_one(r#"on mute if not connected or battery >= 50 and muted: log "x""#);
+        assert_eq!(
+            rule.condition,
+            Some(Expr::Or(
+                Box::new(Expr::Not(Box::new(Expr::Variable(Variable::Connected)))),
+                Box::new(Expr::And(
+                    Box::new(Expr::Compare(
+                        Comparison::GreaterEqual,
+                        Box::new(Expr::Variable(Variable::Battery)),
+                        Box::new(Expr::Number(50.0)),
+                    )),
+                    Box::new(Expr::Variable(Variable::Muted)),
+                )),
+            ))
+        );
+    }
+
+    #[test]
+    fn parses_intervals() {
+        let rule = parse_one("every 10m: sidetone off");
+        assert_eq!(rule.trigger, Trigger::Every(Duration::from_secs(600)));
+        assert_eq!(rule.condition, None);
+        assert_eq!(rule.actions, vec![RuleAction::Sidetone(false)]);
+    }
+
+    #[test]
+    fn skips_blank_lines_and_comments() {
+        assert_eq!(parse("\n# only a comment\n   \n"), Ok(Vec::new()));
+        let rule = parse_one("on resume: unmute # after sleep");
+        assert_eq!(rule.actions, vec![RuleAction::Mute(false)]);
+    }
+
+    #[test]
+    fn reports_every_broken_line() {
+        let source = "on battery: flash_led\nevery 5x: mute\non sunrise: mute\n";
+        assert_eq!(
+            errors(source),
+            vec![
+                (2, "unknown time unit 'x'".to_string()),
+                (3, "unknown event 'sunrise'".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_intervals_out_of_range() {
+        assert_eq!(
+            errors("every 0.5s: mute"),
+            vec![(1, "intervals must be at least one second".to_string())]
+        );
+        assert_eq!(
+            errors("every 99999999999999999999999999999h: mute"),
+            vec![(1, "interval is too long".to_string())]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert_eq!(
+            errors(r#"on mute: log "oops"#),
+            vec![(1, "unterminated string".to_string())]
+        );
+        assert_eq!(
+            errors("on mute: mute unmute"),
+            vec![(1, "unexpected 'unmute' after the actions".to_string())]
+        );
+        assert_eq!(
+            errors("on mute if battery < : mute"),
+            vec![(1, "unexpected ':'".to_string())]
+        );
+        assert_eq!(
+            errors("on mute flash_led"),
+            vec![(1, "expected ':', found 'flash_led'".to_string())]
+        );
+        assert_eq!(
+            errors("on mute: sidetone loud"),
+            vec![(1, "sidetone takes 'on' or 'off', not 'loud'".to_string())]
+        );
+    }
+}
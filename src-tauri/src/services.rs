@@ -0,0 +1,290 @@
+//! Background work shared by the GUI and the headless daemon.
+
+use crate::actions;
+use crate::api;
+use crate::audio::{self, SoftwareSidetone};
+use crate::audit::{self, Source};
+use crate::calibration;
+use crate::discord;
+use crate::events::{DeviceEvent, EventBus};
+use crate::history;
+use crate::hooks;
+use crate::instance::Instance;
+use crate::macros;
+use crate::media_keys;
+use crate::mqtt;
+use crate::obs;
+use crate::power;
+use crate::profiles;
+use crate::rules;
+use crate::session;
+use crate::settings::SettingsStore;
+use crate::state_poll;
+use crate::watcher;
+use serde_json::json;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+pub const SETTINGS_FILE: &str = "settings.json";
+
+pub struct Services {
+    pub settings: Arc<SettingsStore>,
+    pub events: Arc<EventBus>,
+    pub sidetone: Arc<SoftwareSidetone>,
+}
+
+impl Services {
+    /// Load settings from `config_dir` and start every event consumer, as the
+    /// running `instance`, whose requests to show the window go to `show`.
+    /// The hotplug watcher is left to [`Services::start_watcher`] so callers
+    /// can subscribe first and still see devices present at startup.
+    pub fn start(
+        config_dir: &Path,
+        instance: Instance,
+        show: impl Fn() -> Result<(), String> + Send + 'static,
+    ) -> Self {
+        audit::open(config_dir);
+        let services = Services {
+            settings: Arc::new(SettingsStore::load(config_dir.join(SETTINGS_FILE))),
+            events: Arc::new(EventBus::default()),
+            sidetone: Arc::new(SoftwareSidetone::default()),
+        };
+        instance.serve(services.settings.clone(), show);
+
+        if let Err(err) = audio::unload_stale_loopbacks() {
+            warn!("failed to clean up stale sidetone loopbacks: {err}");
+        }
+
+        history::spawn(&services.events);
+        calibration::spawn(services.settings.clone(), &services.events);
+        spawn_restore(services.settings.clone(), &services.events);
+        spawn_audio_integration(
+            services.settings.clone(),
+            services.sidetone.clone(),
+            &services.events,
+        );
+        audio::spawn_mute_monitor(services.events.clone());
+        hooks::spawn(services.settings.clone(), &services.events);
+        macros::spawn(services.settings.clone(), &services.events);
+        media_keys::spawn(services.settings.clone(), &services.events);
+        rules::spawn(services.settings.clone(), &services.events, config_dir);
+
+        #[cfg(target_os = "linux")]
+        {
+            use crate::platform::linux::{dbus, shortcuts};
+
+            dbus::spawn(services.settings.clone(), &services.events);
+
+            let settings = services.settings.clone();
+            let bindings = settings.get().shortcuts;
+            let actions: Vec<_> = bindings.iter().map(|b| b.action.clone()).collect();
+            shortcuts::spawn(bindings, move |index| {
+                if let Err(err) = actions::perform(&settings, Source::Shortcut, &actions[index]) {
+                    warn!("shortcut action failed: {err}");
+                }
+            });
+        }
+
+        if services.settings.get().api.enabled {
+            api::spawn(services.settings.clone(), services.events.clone());
+        }
+        if services.settings.get().mqtt.enabled {
+            mqtt::spawn(services.settings.clone(), &services.events);
+        }
+        if services.settings.get().obs.enabled {
+            obs::spawn(services.settings.clone(), &services.events);
+        }
+        if services.settings.get().discord.enabled {
+            discord::spawn(services.settings.clone(), &services.events);
+        }
+
+        #[cfg(windows)]
+        spawn_notifications(services.settings.clone(), &services.events);
+
+        services
+    }
+
+    pub fn start_watcher(&self) {
+        let settings = self.settings.get();
+        let idle_timeout = match settings.handles.idle_timeout_mins {
+            0 => None,
+            mins => Some(Duration::from_secs(mins * 60)),
+        };
+        watcher::spawn(self.events.clone(), idle_timeout);
+        let poll = settings.state_poll;
+        if poll.enabled {
+            state_poll::spawn(self.events.clone(), Duration::from_secs(poll.interval_secs));
+        }
+        power::spawn(self.events.clone());
+        session::spawn(self.events.clone());
+    }
+
+    /// Undo changes that must not outlive the process.
+    pub fn shutdown(&self) {
+        self.sidetone.disable_all();
+    }
+}
+
+/// Re-apply last-known settings to devices as they (re)connect, and to
+/// wireless headsets as they are switched on.
+fn spawn_restore(settings: Arc<SettingsStore>, events: &EventBus) {
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        for event in receiver {
+            let (DeviceEvent::Connected { device_id }
+            | DeviceEvent::HeadsetPoweredOn { device_id }) = event
+            else {
+                continue;
+            };
+            if !settings.get().restore.on_connect {
+                continue;
+            }
+            let values = json!(settings.get().last_known.get(&device_id));
+            let result = profiles::restore(&settings, device_id);
+            if let Err(err) =
+                audit::record(Source::Restore, Some(device_id), "restore", values, result)
+            {
+                warn!("failed to restore settings of {device_id}: {err}");
+            }
+        }
+    });
+}
+
+/// Switch OS default audio devices on connect/disconnect, and on wireless
+/// headsets being switched on and off, when enabled, sync
+/// endpoint volume on Windows, and drop software sidetone loopbacks of devices
+/// that went away.
+fn spawn_audio_integration(
+    settings: Arc<SettingsStore>,
+    sidetone: Arc<SoftwareSidetone>,
+    events: &EventBus,
+) {
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        let switcher = audio::DefaultSwitcher::default();
+        #[cfg(windows)]
+        let volume_sync = crate::platform::windows::audio::VolumeSync::default();
+        for event in receiver {
+            let result = match event {
+                DeviceEvent::Connected { device_id } => {
+                    let preferences = settings.get().audio;
+                    #[cfg(windows)]
+                    if preferences.sync_endpoint_volume {
+                        volume_sync.start(device_id);
+                    }
+                    if !preferences.switch_default_on_connect {
+                        continue;
+                    }
+                    switcher.on_connected(&settings, device_id)
+                }
+                DeviceEvent::Disconnected { device_id } => {
+                    #[cfg(windows)]
+                    volume_sync.stop(device_id);
+                    sidetone
+                        .disable(device_id)
+                        .and_then(|()| switcher.on_disconnected(device_id))
+                }
+                DeviceEvent::HeadsetPoweredOn { device_id } => {
+                    if !settings.get().audio.switch_default_on_power {
+                        continue;
+                    }
+                    switcher.on_connected(&settings, device_id)
+                }
+                // Only undoes a switch that was made.
+                DeviceEvent::HeadsetPoweredOff { device_id } => switcher.on_disconnected(device_id),
+                DeviceEvent::BatteryChanged { .. }
+                | DeviceEvent::BatteryLow { .. }
+                | DeviceEvent::MicMuteChanged { .. }
+                | DeviceEvent::FeatureChanged { .. }
+                | DeviceEvent::BoomMoved { .. }
+                | DeviceEvent::ButtonPressed { .. }
+                | DeviceEvent::DialTurned { .. }
+                | DeviceEvent::Resumed
+                | DeviceEvent::SessionChanged { .. } => continue,
+            };
+            if let Err(err) = result {
+                warn!("audio integration failed: {err}");
+            }
+        }
+    });
+}
+
+/// Toasts for disconnects and low battery, with buttons that act on the device.
+#[cfg(windows)]
+fn spawn_notifications(settings: Arc<SettingsStore>, events: &EventBus) {
+    use crate::platform::windows::toast::{self, ToastAction};
+
+    let receiver = events.subscribe();
+    let on_action = {
+        let settings = settings.clone();
+        move |action: ToastAction| {
+            let result = match &action {
+                ToastAction::Retry { device_id } => audit::record(
+                    Source::Notification,
+                    Some(*device_id),
+                    "restore",
+                    json!(settings.get().last_known.get(device_id)),
+                    profiles::restore(&settings, *device_id),
+                )
+                .map_err(profiles::ProfileError::Control),
+                ToastAction::ApplyProfile { name } => audit::record(
+                    Source::Notification,
+                    None,
+                    "apply_profile",
+                    json!(name),
+                    profiles::apply_named(&settings, name),
+                ),
+            };
+            if let Err(err) = result {
+                warn!("notification action {action:?} failed: {err}");
+            }
+        }
+    };
+    thread::spawn(move || {
+        let notifier = match toast::Notifier::new(on_action) {
+            Ok(notifier) => notifier,
+            Err(err) => {
+                warn!("notifications disabled: {err}");
+                return;
+            }
+        };
+        // The watcher reports devices as disconnected when the session goes
+        // inactive; those are not worth a notification.
+        let mut session_active = true;
+        for event in receiver {
+            if let DeviceEvent::SessionChanged { active } = event {
+                session_active = active;
+                continue;
+            }
+            let preferences = settings.get().notifications;
+            if !preferences.enabled || !session_active {
+                continue;
+            }
+            let result = match event {
+                DeviceEvent::Connected { .. }
+                | DeviceEvent::BatteryChanged { .. }
+                | DeviceEvent::MicMuteChanged { .. }
+                | DeviceEvent::HeadsetPoweredOn { .. }
+                | DeviceEvent::HeadsetPoweredOff { .. }
+                | DeviceEvent::FeatureChanged { .. }
+                | DeviceEvent::BoomMoved { .. }
+                | DeviceEvent::ButtonPressed { .. }
+                | DeviceEvent::DialTurned { .. }
+                | DeviceEvent::Resumed
+                | DeviceEvent::SessionChanged { .. } => continue,
+                DeviceEvent::Disconnected { device_id } => notifier.disconnected(device_id),
+                DeviceEvent::BatteryLow { device_id, percent } => notifier.battery_low(
+                    device_id,
+                    percent,
+                    preferences.power_saver_profile.as_deref(),
+                ),
+            };
+            if let Err(err) = result {
+                warn!("failed to show notification: {err}");
+            }
+        }
+    });
+}
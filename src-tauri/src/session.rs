@@ -0,0 +1,119 @@
+//! Publishes [`DeviceEvent::SessionChanged`] when the user's session is locked,
+//! unlocked or switched away from, so a second user's instance can take over
+//! the headset without both fighting over it.
+
+use crate::crash;
+use crate::events::{DeviceEvent, EventBus};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Poll the session state on a background thread and publish every change.
+pub fn spawn(events: Arc<EventBus>) {
+    crash::supervise("session-monitor", move || {
+        let probe = match Probe::new() {
+            Ok(probe) => probe,
+            Err(err) => {
+                warn!("session monitoring disabled: {err}");
+                return;
+            }
+        };
+        let mut active = true;
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let Some(now) = probe.is_active() else {
+                continue;
+            };
+            if now != active {
+                active = now;
+                events.publish(DeviceEvent::SessionChanged { active });
+            }
+        }
+    });
+}
+
+/// logind's view of the session this process belongs to.
+#[cfg(target_os = "linux")]
+struct Probe {
+    session: zbus::blocking::Proxy<'static>,
+}
+
+#[cfg(target_os = "linux")]
+impl Probe {
+    fn new() -> zbus::Result<Self> {
+        let connection = zbus::blocking::Connection::system()?;
+        let session = zbus::blocking::proxy::Builder::new(&connection)
+            .destination("org.freedesktop.login1")?
+            .path("/org/freedesktop/login1/session/auto")?
+            .interface("org.freedesktop.login1.Session")?
+            .cache_properties(zbus::proxy::CacheProperties::No)
+            .build()?;
+        Ok(Probe { session })
+    }
+
+    /// Active means on the foreground seat and not locked.
+    fn is_active(&self) -> Option<bool> {
+        let active: bool = self.session.get_property("Active").ok()?;
+        let locked: bool = self.session.get_property("LockedHint").unwrap_or(false);
+        Some(active && !locked)
+    }
+}
+
+/// The Terminal Services state of the current session.
+#[cfg(windows)]
+struct Probe;
+
+#[cfg(windows)]
+impl Probe {
+    fn new() -> windows::core::Result<Self> {
+        Ok(Probe)
+    }
+
+    /// Active means connected to the console (not switched away from) and unlocked.
+    fn is_active(&self) -> Option<bool> {
+        use windows::core::PWSTR;
+        use windows::Win32::System::RemoteDesktop::{
+            WTSActive, WTSFreeMemory, WTSQuerySessionInformationW, WTSSessionInfoEx, WTSINFOEXW,
+            WTS_CURRENT_SERVER_HANDLE, WTS_CURRENT_SESSION, WTS_SESSIONSTATE_LOCK,
+        };
+
+        let mut buffer = PWSTR::null();
+        let mut size = 0u32;
+        // SAFETY: the buffer is allocated by WTS, read as the documented
+        // WTSINFOEXW level 1 layout and freed right after.
+        unsafe {
+            WTSQuerySessionInformationW(
+                Some(WTS_CURRENT_SERVER_HANDLE),
+                WTS_CURRENT_SESSION,
+                WTSSessionInfoEx,
+                &mut buffer,
+                &mut size,
+            )
+            .ok()?;
+            let info = &*(buffer.0 as *const WTSINFOEXW);
+            let level = info.Data.WTSInfoExLevel1;
+            let active = level.SessionState == WTSActive
+                && level.SessionFlags != WTS_SESSIONSTATE_LOCK as i32;
+            WTSFreeMemory(buffer.0.cast());
+            Some(active)
+        }
+    }
+}
+
+/// No session tracking on this platform; the session counts as always active.
+#[cfg(not(any(target_os = "linux", windows)))]
+struct Probe;
+
+#[cfg(not(any(target_os = "linux", windows)))]
+impl Probe {
+    fn new() -> Result<Self, std::convert::Infallible> {
+        Ok(Probe)
+    }
+
+    fn is_active(&self) -> Option<bool> {
+        Some(true)
+    }
+}
@@ -0,0 +1,435 @@
+use crate::actions::Action;
+use crate::audio::Endpoints;
+use crate::calibration::BatteryCurve;
+use crate::groups::DeviceGroup;
+use crate::hooks::Hook;
+use crate::hyperx::{DeviceId, FirmwareTarget, FirmwareVersion};
+use crate::macros::ButtonMacro;
+use crate::profiles::{DeviceSettings, Profile};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// User preferences persisted as JSON in the app config directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub audio: AudioSettings,
+    pub shortcuts: Vec<ShortcutBinding>,
+    pub profiles: Vec<Profile>,
+    /// Devices changed together, see [`crate::groups`].
+    pub groups: Vec<DeviceGroup>,
+    pub restore: RestoreSettings,
+    pub notifications: NotificationSettings,
+    pub api: ApiSettings,
+    pub mqtt: MqttSettings,
+    pub obs: ObsSettings,
+    pub discord: DiscordSettings,
+    pub developer: DeveloperSettings,
+    pub telemetry: TelemetrySettings,
+    pub firmware: FirmwareSettings,
+    pub battery: BatterySettings,
+    pub state_poll: StatePollSettings,
+    pub handles: HandleSettings,
+    /// Shell commands run on device events.
+    pub hooks: Vec<Hook>,
+    /// App-side actions bound to headset button presses.
+    pub macros: Vec<ButtonMacro>,
+    pub media_keys: MediaKeySettings,
+    pub rules: RuleSettings,
+    /// Last values written to each device, restored on reconnect.
+    pub last_known: HashMap<DeviceId, DeviceSettings>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestoreSettings {
+    /// Re-apply the last-known settings whenever a device (re)connects.
+    pub on_connect: bool,
+}
+
+impl Default for RestoreSettings {
+    fn default() -> Self {
+        RestoreSettings { on_connect: true }
+    }
+}
+
+/// The localhost HTTP API, see [`crate::api`]. Changes apply on restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiSettings {
+    pub enabled: bool,
+    pub port: u16,
+    /// Bearer token clients must send; generated when the API first starts.
+    pub token: Option<String>,
+    /// Also serve Prometheus metrics at `/metrics`.
+    pub metrics: bool,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        ApiSettings {
+            enabled: false,
+            port: 7447,
+            token: None,
+            metrics: false,
+        }
+    }
+}
+
+/// Polling device state for changes made on the headset, see
+/// [`crate::state_poll`]. Changes apply on restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatePollSettings {
+    pub enabled: bool,
+    pub interval_secs: u64,
+}
+
+impl Default for StatePollSettings {
+    fn default() -> Self {
+        StatePollSettings {
+            enabled: false,
+            interval_secs: 5,
+        }
+    }
+}
+
+/// How long device handles stay open unused. Changes apply on restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HandleSettings {
+    /// Close a handle after this many minutes without an operation; `0`
+    /// keeps handles open until the device is unplugged.
+    pub idle_timeout_mins: u64,
+}
+
+impl Default for HandleSettings {
+    fn default() -> Self {
+        HandleSettings {
+            idle_timeout_mins: 5,
+        }
+    }
+}
+
+/// Media keys pressed for headset controls, see [`crate::media_keys`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MediaKeySettings {
+    /// Press media keys for the presses and dial turns of headsets that
+    /// report them to the app rather than as standard consumer controls.
+    pub enabled: bool,
+}
+
+/// Automation rules, see [`crate::rules`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleSettings {
+    /// Let the `run` action start shell commands. Off unless turned on, so
+    /// a rule file copied from elsewhere only triggers the app's actions.
+    pub allow_run: bool,
+}
+
+/// Tools for protocol work that can misconfigure a headset when misused.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeveloperSettings {
+    /// Allow the raw feature report console, see [`crate::raw_reports`].
+    pub raw_reports: bool,
+}
+
+/// Battery reading corrections, see [`crate::calibration`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatterySettings {
+    /// Learned by calibrating each device.
+    pub curves: HashMap<DeviceId, BatteryCurve>,
+}
+
+/// Opt-in device reports, see [`crate::telemetry`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetrySettings {
+    /// Allow submitting reports; off by default, and even then only sent on request.
+    pub enabled: bool,
+    /// HTTPS URL reports are POSTed to.
+    pub endpoint: Option<String>,
+}
+
+/// Firmware update checks, see [`crate::updater`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FirmwareSettings {
+    /// HTTPS URL of a signed manifest to use instead of the bundled one.
+    pub manifest_url: Option<String>,
+    /// Versions the user keeps; checks stop offering updates for these.
+    pub pinned: Vec<FirmwarePin>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FirmwarePin {
+    pub device_id: DeviceId,
+    #[serde(default)]
+    pub target: FirmwareTarget,
+    pub version: FirmwareVersion,
+}
+
+impl FirmwareSettings {
+    /// The version pinned for `target` of `device_id`.
+    pub fn pinned(&self, device_id: DeviceId, target: FirmwareTarget) -> Option<FirmwareVersion> {
+        self.pinned
+            .iter()
+            .find(|pin| pin.device_id == device_id && pin.target == target)
+            .map(|pin| pin.version)
+    }
+}
+
+/// Mute sync with Discord, see [`crate::discord`]. Changes apply on restart.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscordSettings {
+    pub enabled: bool,
+    /// Id of the user's Discord application with RPC access.
+    pub client_id: Option<String>,
+    /// OAuth2 token of that application with the `rpc` and `rpc.voice.write` scopes.
+    pub access_token: Option<String>,
+}
+
+/// The OBS WebSocket client, see [`crate::obs`]. Changes apply on restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ObsSettings {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub password: Option<String>,
+    /// OBS audio inputs muted and unmuted along with a headset microphone.
+    pub sources: Vec<ObsSource>,
+    /// Flash the mute LED of connected headsets when a recording starts.
+    pub flash_led_on_record: bool,
+}
+
+impl Default for ObsSettings {
+    fn default() -> Self {
+        ObsSettings {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: 4455,
+            password: None,
+            sources: Vec::new(),
+            flash_led_on_record: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObsSource {
+    pub device_id: DeviceId,
+    /// Name of the OBS audio input.
+    pub input: String,
+    /// Only follow the headset while this scene is on program; `None` for all scenes.
+    #[serde(default)]
+    pub scene: Option<String>,
+}
+
+/// The MQTT client, see [`crate::mqtt`]. Changes apply on restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttSettings {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub client_id: String,
+    /// Prefix of the state and command topics.
+    pub base_topic: String,
+    /// Home Assistant's discovery prefix.
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttSettings {
+    fn default() -> Self {
+        MqttSettings {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: 1883,
+            username: None,
+            password: None,
+            client_id: "hyperx-pilot".to_string(),
+            base_topic: "hyperx-pilot".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationSettings {
+    /// Show desktop notifications for disconnects and low battery (Windows).
+    pub enabled: bool,
+    /// Profile offered by the low-battery notification's power saver button.
+    pub power_saver_profile: Option<String>,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        NotificationSettings {
+            enabled: true,
+            power_saver_profile: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    /// Make the headset the OS default output/input while it is connected.
+    pub switch_default_on_connect: bool,
+    /// Also make a wireless headset the default when it is switched on behind
+    /// its plugged-in receiver, and restore the previous devices when it is
+    /// switched off.
+    pub switch_default_on_power: bool,
+    /// Keep the headset volume and the Windows endpoint volume in sync.
+    pub sync_endpoint_volume: bool,
+    /// Playback/recording devices associated with each headset.
+    pub endpoints: HashMap<DeviceId, Endpoints>,
+}
+
+/// A global hotkey. Accelerators use the XDG shortcut notation, e.g. `CTRL+ALT+m`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShortcutBinding {
+    pub accelerator: String,
+    pub action: Action,
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Write { path: PathBuf, source: io::Error },
+    Encode { source: serde_json::Error },
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Write { path, source } => {
+                write!(
+                    f,
+                    "failed to write settings to {}: {source}",
+                    path.display()
+                )
+            }
+            SettingsError::Encode { source } => write!(f, "failed to encode settings: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+pub struct SettingsStore {
+    path: PathBuf,
+    current: Mutex<Settings>,
+}
+
+impl SettingsStore {
+    /// Load settings from `path`, falling back to defaults when the file is
+    /// missing or unreadable so a corrupt file never blocks startup.
+    pub fn load(path: PathBuf) -> Self {
+        let current = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        SettingsStore {
+            path,
+            current: Mutex::new(current),
+        }
+    }
+
+    pub fn get(&self) -> Settings {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Apply `change` to a copy of the current settings and persist the result.
+    /// The store stays locked until then, so concurrent updates apply one
+    /// after the other instead of overwriting each other; `change` must not
+    /// use the store itself.
+    pub fn update<F: FnOnce(&mut Settings)>(&self, change: F) -> Result<(), SettingsError> {
+        let mut current = self.current.lock().unwrap();
+        let mut settings = current.clone();
+        change(&mut settings);
+        self.write(&settings)?;
+        *current = settings;
+        Ok(())
+    }
+
+    pub fn replace(&self, settings: Settings) -> Result<(), SettingsError> {
+        let mut current = self.current.lock().unwrap();
+        self.write(&settings)?;
+        *current = settings;
+        Ok(())
+    }
+
+    fn write(&self, settings: &Settings) -> Result<(), SettingsError> {
+        let encoded = serde_json::to_string_pretty(&settings)
+            .map_err(|source| SettingsError::Encode { source })?;
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|source| SettingsError::Write {
+                path: parent.to_path_buf(),
+                source,
+            })?;
+        }
+        write_private(&self.path, encoded.as_bytes()).map_err(|source| SettingsError::Write {
+            path: self.path.clone(),
+            source,
+        })
+    }
+}
+
+/// Replace `path` with `contents` so that a crash or power loss leaves
+/// either the old or the new file, never a truncated one: the contents go to
+/// a temporary file in the same directory, which is flushed to disk and then
+/// renamed over `path`. On Unix only the user can read the file, as it holds
+/// secrets such as the API token and the MQTT and OBS passwords.
+pub fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let mut temporary_name = OsString::from(".");
+    temporary_name.push(name);
+    temporary_name.push(".tmp");
+    let temporary = path.with_file_name(temporary_name);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let written = options.open(&temporary).and_then(|mut file| {
+        // The mode only applies to new files, not to one left behind by an
+        // interrupted write.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        }
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    if let Err(err) = written.and_then(|()| fs::rename(&temporary, path)) {
+        let _ = fs::remove_file(&temporary);
+        return Err(err);
+    }
+    // Make the rename itself durable.
+    #[cfg(unix)]
+    if let Some(directory) = path.parent() {
+        fs::File::open(directory)?.sync_all()?;
+    }
+    Ok(())
+}
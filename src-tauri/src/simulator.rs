@@ -0,0 +1,558 @@
+//! Virtual headsets for `--simulate`: a Cloud III (wired) and a wireless
+//! model with battery, volume and mute LED. Their state lives in memory, so
+//! frontend work, demos and CI need no hardware. The battery drains by 1% every
+//! [`DRAIN_INTERVAL`] and recharges after reaching [`EMPTY_PERCENT`], so low
+//! battery handling can be watched too. The wireless headset switches off for
+//! one step at [`EMPTY_PERCENT`] while its receiver stays connected, so its
+//! power events can be watched as well. The wireless model and its receiver
+//! also have bootloaders that accept any image through
+//! [`hyperx::DfuSession`] (the headset's reads back the last one), and the
+//! receiver pairs a few seconds after entering pairing mode, so the update
+//! and pairing flows can be tried without risking a real headset. The
+//! wireless headset starts out paired with two Bluetooth sources. Values out
+//! of a feature's range are refused with [`REJECTED_OUT_OF_RANGE`] in the
+//! status byte of the next response, as a real firmware would. The wireless
+//! headset also sends an input report whenever its charge changes, and its
+//! microphone boom is flipped up for [`BOOM_UP`] of every [`BOOM_CYCLE`].
+//! Its buttons keep whatever actions are assigned to them, without checking
+//! them against the model's list, and its multi-function button is pressed
+//! every [`PRESS_INTERVAL`], single, double and long in turn. Its volume
+//! dial is turned a step up and back down again in turns every
+//! [`DIAL_INTERVAL`].
+
+use crate::hyperx::{self, DeviceId, DfuCommand, DfuState, DfuStatus, FeatureKind};
+use crate::mock::{self, MockBackend};
+use hidapi::HidError;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DRAIN_INTERVAL: Duration = Duration::from_secs(20);
+/// How long a receiver in pairing mode takes to find the headset.
+const PAIRING_DELAY: Duration = Duration::from_secs(3);
+const EMPTY_PERCENT: u64 = 5;
+/// Board revision answered by the simulated hardware report, made in 2024-W14.
+const HARDWARE_REVISION: u8 = 2;
+/// Status byte answered after refusing a value.
+const REJECTED_OUT_OF_RANGE: u8 = 0x01;
+/// Size of the made-up image installed at startup.
+const FACTORY_IMAGE_SIZE: usize = 16 * 1024;
+/// How often a wait for an input report checks for a new one.
+const INPUT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const BOOM_CYCLE: Duration = Duration::from_secs(60);
+/// How long the boom stays up at the end of each [`BOOM_CYCLE`].
+const BOOM_UP: Duration = Duration::from_secs(10);
+const PRESS_INTERVAL: Duration = Duration::from_secs(30);
+const DIAL_INTERVAL: Duration = Duration::from_secs(45);
+
+static SIMULATOR: OnceLock<Simulator> = OnceLock::new();
+
+struct Simulator {
+    started: Instant,
+    values: Mutex<HashMap<(DeviceId, FeatureKind), u16>>,
+    /// Status of the last write of each feature, 0 when it was applied.
+    statuses: Mutex<HashMap<(DeviceId, FeatureKind), u8>>,
+    muted: Mutex<HashMap<DeviceId, bool>>,
+    bootloaders: Mutex<HashMap<BootloaderId, Bootloader>>,
+    /// Installed firmware images, replaced by successful flashes.
+    images: Mutex<HashMap<BootloaderId, Vec<u8>>>,
+    /// Block requested by the last read command, answered by the next report read.
+    pending_reads: Mutex<HashMap<BootloaderId, (usize, usize)>>,
+    /// When each receiver was put into pairing mode.
+    pairing: Mutex<HashMap<DeviceId, Instant>>,
+    /// Paired Bluetooth sources and the slot the next read returns.
+    bluetooth: Mutex<HashMap<DeviceId, (Vec<BluetoothSource>, u8)>>,
+    /// Charge in the last battery input report per headset.
+    reported_battery: Mutex<HashMap<DeviceId, u16>>,
+    /// Button presses reported per headset.
+    reported_presses: Mutex<HashMap<DeviceId, u64>>,
+    /// Dial turns reported per headset.
+    reported_turns: Mutex<HashMap<DeviceId, u64>>,
+    buttons: Mutex<HashMap<DeviceId, Buttons>>,
+}
+
+#[derive(Default)]
+struct Buttons {
+    /// Action code of each remapped button by button code.
+    actions: HashMap<u8, u8>,
+    /// The button the next read returns.
+    selected: u8,
+}
+
+struct BluetoothSource {
+    address: [u8; 6],
+    name: &'static str,
+    connected: bool,
+}
+
+/// What each multipoint headset starts out paired with.
+fn default_sources() -> Vec<BluetoothSource> {
+    vec![
+        BluetoothSource {
+            address: [0xA4, 0xC1, 0x38, 0x12, 0x34, 0x56],
+            name: "Phone",
+            connected: true,
+        },
+        BluetoothSource {
+            address: [0x3C, 0x22, 0xFB, 0xAB, 0xCD, 0xEF],
+            name: "Laptop",
+            connected: false,
+        },
+    ]
+}
+
+/// The headset and the DFU report ID, which tells its bootloader and its
+/// receiver's apart.
+type BootloaderId = (DeviceId, u8);
+
+/// An upload in progress; the simulated firmware is never actually replaced.
+struct Bootloader {
+    size: u32,
+    checksum: u32,
+    received: Vec<u8>,
+    status: DfuStatus,
+}
+
+/// Install the simulator as the HID backend.
+pub fn enable() {
+    mock::install(SIMULATOR.get_or_init(|| Simulator {
+        started: Instant::now(),
+        values: Mutex::new(HashMap::new()),
+        statuses: Mutex::new(HashMap::new()),
+        muted: Mutex::new(HashMap::new()),
+        bootloaders: Mutex::new(HashMap::new()),
+        images: Mutex::new(HashMap::new()),
+        pending_reads: Mutex::new(HashMap::new()),
+        pairing: Mutex::new(HashMap::new()),
+        bluetooth: Mutex::new(HashMap::new()),
+        reported_battery: Mutex::new(HashMap::new()),
+        reported_presses: Mutex::new(HashMap::new()),
+        reported_turns: Mutex::new(HashMap::new()),
+        buttons: Mutex::new(HashMap::new()),
+    }));
+}
+
+pub fn is_enabled() -> bool {
+    SIMULATOR.get().is_some()
+}
+
+impl Simulator {
+    fn battery(&self) -> u16 {
+        let steps = self.started.elapsed().as_secs() / DRAIN_INTERVAL.as_secs();
+        (100 - steps % (100 - EMPTY_PERCENT + 1)) as u16
+    }
+
+    fn value(&self, device_id: DeviceId, kind: FeatureKind) -> u16 {
+        if kind == FeatureKind::Battery {
+            return self.battery();
+        }
+        if kind == FeatureKind::Boom {
+            let within = self.started.elapsed().as_secs() % BOOM_CYCLE.as_secs();
+            return u16::from(within >= (BOOM_CYCLE - BOOM_UP).as_secs());
+        }
+        let default = match kind {
+            FeatureKind::Volume => 50,
+            FeatureKind::Firmware => 0x0107,
+            FeatureKind::Multipoint
+            | FeatureKind::VoicePrompts
+            | FeatureKind::NotificationBeeps => 1,
+            FeatureKind::LedBrightness | FeatureKind::MuteBeepVolume => 100,
+            FeatureKind::AncLevel => 1,
+            _ => 0,
+        };
+        *self
+            .values
+            .lock()
+            .unwrap()
+            .get(&(device_id, kind))
+            .unwrap_or(&default)
+    }
+
+    fn bootloader_command(&self, id: BootloaderId, data: &[u8]) -> Result<(), HidError> {
+        let command = DfuCommand::parse(data).ok_or_else(|| HidError::HidApiError {
+            message: "malformed bootloader command".into(),
+        })?;
+        let mut bootloaders = self.bootloaders.lock().unwrap();
+        match command {
+            DfuCommand::Enter { size, checksum } => {
+                bootloaders.insert(
+                    id,
+                    Bootloader {
+                        size,
+                        checksum,
+                        received: Vec::new(),
+                        status: DfuStatus::Ok,
+                    },
+                );
+            }
+            DfuCommand::Data {
+                offset,
+                chunk,
+                checksum,
+            } => {
+                let Some(bootloader) = bootloaders.get_mut(&id) else {
+                    return Err(HidError::HidApiError {
+                        message: "bootloader is not running".into(),
+                    });
+                };
+                bootloader.status = if offset as usize != bootloader.received.len() {
+                    DfuStatus::BadOffset
+                } else if hyperx::dfu_checksum(chunk) != checksum {
+                    DfuStatus::ChecksumError
+                } else {
+                    bootloader.received.extend_from_slice(chunk);
+                    DfuStatus::Ok
+                };
+            }
+            DfuCommand::Finish { checksum } => {
+                if let Some(bootloader) = bootloaders.get(&id) {
+                    let complete = bootloader.received.len() == bootloader.size as usize
+                        && hyperx::dfu_checksum(&bootloader.received) == bootloader.checksum
+                        && checksum == bootloader.checksum;
+                    if complete {
+                        if let Some(bootloader) = bootloaders.remove(&id) {
+                            self.images.lock().unwrap().insert(id, bootloader.received);
+                        }
+                    } else if let Some(bootloader) = bootloaders.get_mut(&id) {
+                        bootloader.status = DfuStatus::Rejected;
+                    }
+                }
+            }
+            DfuCommand::Abort => {
+                bootloaders.remove(&id);
+            }
+            DfuCommand::Read { offset, length } => {
+                self.pending_reads
+                    .lock()
+                    .unwrap()
+                    .insert(id, (offset as usize, usize::from(length)));
+            }
+        }
+        Ok(())
+    }
+
+    fn bootloader_response(&self, id: BootloaderId) -> Vec<u8> {
+        let Some((offset, length)) = self.pending_reads.lock().unwrap().remove(&id) else {
+            return hyperx::dfu_status_response(id.1, self.bootloader_state(id));
+        };
+        let mut images = self.images.lock().unwrap();
+        let image = images.entry(id).or_insert_with(|| {
+            (0..FACTORY_IMAGE_SIZE)
+                .map(|index| (index * 7 % 251) as u8)
+                .collect()
+        });
+        let start = offset.min(image.len());
+        let end = (start + length).min(image.len());
+        hyperx::dfu_read_response(id.1, image.len(), &image[start..end])
+    }
+
+    fn bootloader_state(&self, id: BootloaderId) -> DfuState {
+        match self.bootloaders.lock().unwrap().get(&id) {
+            Some(bootloader) => DfuState {
+                in_bootloader: true,
+                status: bootloader.status,
+                next_offset: bootloader.received.len() as u32,
+            },
+            None => DfuState {
+                in_bootloader: false,
+                status: DfuStatus::Ok,
+                next_offset: 0,
+            },
+        }
+    }
+}
+
+fn unknown_report(report_id: u8) -> HidError {
+    HidError::HidApiError {
+        message: format!("simulated device has no feature report 0x{report_id:02X}"),
+    }
+}
+
+impl MockBackend for Simulator {
+    fn connected(&self) -> Vec<DeviceId> {
+        hyperx::supported_devices()
+            .iter()
+            .map(|device| device.id)
+            .collect()
+    }
+
+    fn send_feature_report(&self, device_id: DeviceId, data: &[u8]) -> Result<(), HidError> {
+        let (&report_id, &selector) = (data.first().unwrap_or(&0), data.get(1).unwrap_or(&0));
+        if hyperx::is_dfu_report(device_id, report_id) {
+            return self.bootloader_command((device_id, report_id), data);
+        }
+        if hyperx::pairing_report(device_id).is_some_and(|(id, _)| id == report_id) {
+            let mut pairing = self.pairing.lock().unwrap();
+            if data.get(2) == Some(&1) {
+                pairing.insert(device_id, Instant::now());
+            } else {
+                pairing.remove(&device_id);
+            }
+            return Ok(());
+        }
+        if hyperx::pairings_report(device_id).is_some_and(|(id, _)| id == report_id) {
+            let mut bluetooth = self.bluetooth.lock().unwrap();
+            let (sources, selected) = bluetooth
+                .entry(device_id)
+                .or_insert_with(|| (default_sources(), 0));
+            let slot = data.get(3).copied().unwrap_or(0);
+            if data.get(2) == Some(&1) {
+                if usize::from(slot) < sources.len() {
+                    sources.remove(usize::from(slot));
+                }
+            } else {
+                *selected = slot;
+            }
+            return Ok(());
+        }
+        if hyperx::buttons_report(device_id).is_some_and(|(id, _)| id == report_id) {
+            let mut buttons = self.buttons.lock().unwrap();
+            let buttons = buttons.entry(device_id).or_default();
+            let button = data.get(3).copied().unwrap_or(0);
+            if data.get(2) == Some(&1) {
+                buttons
+                    .actions
+                    .insert(button, data.get(4).copied().unwrap_or(0));
+            } else {
+                buttons.selected = button;
+            }
+            return Ok(());
+        }
+        if hyperx::game_mode_report(device_id).is_some_and(|(id, _)| id == report_id) {
+            let value = u16::from(data.get(2).copied().unwrap_or(0));
+            self.values
+                .lock()
+                .unwrap()
+                .insert((device_id, FeatureKind::GameMode), value);
+            return Ok(());
+        }
+        if hyperx::identify_report(device_id) == Some(report_id) {
+            return Ok(());
+        }
+        if hyperx::factory_reset_report(device_id) == Some(report_id) {
+            self.values
+                .lock()
+                .unwrap()
+                .retain(|&(id, _), _| id != device_id);
+            self.statuses
+                .lock()
+                .unwrap()
+                .retain(|&(id, _), _| id != device_id);
+            self.buttons.lock().unwrap().remove(&device_id);
+            return Ok(());
+        }
+        let (kind, _) = hyperx::feature_kind(device_id, report_id, Some(selector))
+            .ok_or_else(|| unknown_report(report_id))?;
+        let value = u16::from_le_bytes([
+            data.get(2).copied().unwrap_or(0),
+            data.get(3).copied().unwrap_or(0),
+        ]);
+        if matches!(
+            kind,
+            FeatureKind::Battery | FeatureKind::Firmware | FeatureKind::Boom
+        ) {
+            return Ok(());
+        }
+        let maximum = match kind {
+            FeatureKind::Volume | FeatureKind::LedBrightness | FeatureKind::MuteBeepVolume => 100,
+            FeatureKind::AncLevel => {
+                u16::from(hyperx::devices().get(device_id).anc_levels().unwrap_or(1))
+            }
+            _ => 1,
+        };
+        let status = if value > maximum {
+            REJECTED_OUT_OF_RANGE
+        } else {
+            self.values.lock().unwrap().insert((device_id, kind), value);
+            0
+        };
+        self.statuses
+            .lock()
+            .unwrap()
+            .insert((device_id, kind), status);
+        Ok(())
+    }
+
+    fn get_feature_report(
+        &self,
+        device_id: DeviceId,
+        buffer: &mut [u8],
+    ) -> Result<usize, HidError> {
+        let report_id = buffer.first().copied().unwrap_or(0);
+        let response = if hyperx::is_dfu_report(device_id, report_id) {
+            self.bootloader_response((device_id, report_id))
+        } else if let Some((_, selector)) = hyperx::pairing_report(device_id)
+            .filter(|&(pairing_report_id, _)| pairing_report_id == report_id)
+        {
+            let mut pairing = self.pairing.lock().unwrap();
+            let paired = pairing
+                .get(&device_id)
+                .is_none_or(|started| started.elapsed() >= PAIRING_DELAY);
+            if paired {
+                pairing.remove(&device_id);
+            }
+            vec![report_id, selector, u8::from(!paired), 0]
+        } else if let Some((_, selector)) = hyperx::link_report(device_id)
+            .filter(|&(link_report_id, _)| link_report_id == report_id)
+        {
+            // On Bluetooth the headset is gone from the receiver too.
+            let linked = self.battery() > EMPTY_PERCENT as u16
+                && self.value(device_id, FeatureKind::WirelessMode) == 0;
+            vec![report_id, selector, u8::from(linked), 0]
+        } else if let Some((_, selector)) = hyperx::pairings_report(device_id)
+            .filter(|&(pairings_report_id, _)| pairings_report_id == report_id)
+        {
+            let mut bluetooth = self.bluetooth.lock().unwrap();
+            let (sources, slot) = bluetooth
+                .entry(device_id)
+                .or_insert_with(|| (default_sources(), 0));
+            let mut response = vec![report_id, selector, *slot, sources.len() as u8];
+            match sources.get(usize::from(*slot)) {
+                Some(source) => {
+                    response.push(u8::from(source.connected));
+                    response.extend(source.address);
+                    response.push(source.name.len() as u8);
+                    response.extend(source.name.as_bytes());
+                }
+                None => response.extend([0; 8]),
+            }
+            response
+        } else if let Some((_, selector)) = hyperx::buttons_report(device_id)
+            .filter(|&(buttons_report_id, _)| buttons_report_id == report_id)
+        {
+            let mut buttons = self.buttons.lock().unwrap();
+            let buttons = buttons.entry(device_id).or_default();
+            // Out of the box every button does its default (0).
+            let action = buttons.actions.get(&buttons.selected).copied().unwrap_or(0);
+            vec![report_id, selector, buttons.selected, action]
+        } else if let Some((_, selector)) = hyperx::game_mode_report(device_id)
+            .filter(|&(game_mode_report_id, _)| game_mode_report_id == report_id)
+        {
+            let [low, high] = self.value(device_id, FeatureKind::GameMode).to_le_bytes();
+            vec![report_id, selector, low, high]
+        } else if let Some((_, selector)) = hyperx::hardware_report(device_id)
+            .filter(|&(hardware_report_id, _)| hardware_report_id == report_id)
+        {
+            vec![report_id, selector, HARDWARE_REVISION, 24, 14]
+        } else {
+            let (kind, selector) = hyperx::feature_kind(device_id, report_id, None)
+                .ok_or_else(|| unknown_report(report_id))?;
+            let [low, high] = self.value(device_id, kind).to_le_bytes();
+            let status = self
+                .statuses
+                .lock()
+                .unwrap()
+                .get(&(device_id, kind))
+                .copied()
+                .unwrap_or(0);
+            vec![report_id, selector, low, high, status]
+        };
+        let length = response.len().min(buffer.len());
+        buffer[..length].copy_from_slice(&response[..length]);
+        buffer[length..].fill(0);
+        Ok(buffer.len())
+    }
+
+    fn read_input_report(
+        &self,
+        device_id: DeviceId,
+        buffer: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize, HidError> {
+        let deadline = Instant::now() + timeout;
+        let battery_report = hyperx::battery_input_report(device_id);
+        let button_report = hyperx::button_input_report(device_id);
+        let dial_report = hyperx::dial_input_report(device_id);
+        if battery_report.is_none() && button_report.is_none() && dial_report.is_none() {
+            thread::sleep(timeout);
+            return Ok(0);
+        }
+        let mut answer = |report: [u8; 4]| {
+            let length = report.len().min(buffer.len());
+            buffer[..length].copy_from_slice(&report[..length]);
+            Ok(length)
+        };
+        loop {
+            if let Some((report_id, selector)) = button_report {
+                let due = self.started.elapsed().as_secs() / PRESS_INTERVAL.as_secs();
+                let mut reported = self.reported_presses.lock().unwrap();
+                let presses = reported.entry(device_id).or_default();
+                if *presses < due {
+                    // The multi-function button, pressed single, double or long.
+                    let press = (*presses % 3) as u8 + 1;
+                    *presses += 1;
+                    return answer([report_id, selector, 0x01, press]);
+                }
+            }
+            if let Some((report_id, selector)) = dial_report {
+                let due = self.started.elapsed().as_secs() / DIAL_INTERVAL.as_secs();
+                let mut reported = self.reported_turns.lock().unwrap();
+                let turns = reported.entry(device_id).or_default();
+                if *turns < due {
+                    let steps: i8 = if turns.is_multiple_of(2) { 1 } else { -1 };
+                    *turns += 1;
+                    return answer([report_id, selector, steps as u8, 0]);
+                }
+            }
+            if let Some((report_id, selector)) = battery_report {
+                let battery = self.battery();
+                let changed = self
+                    .reported_battery
+                    .lock()
+                    .unwrap()
+                    .insert(device_id, battery)
+                    != Some(battery);
+                if changed {
+                    let [low, high] = battery.to_le_bytes();
+                    return answer([report_id, selector, low, high]);
+                }
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(0);
+            }
+            thread::sleep(INPUT_POLL_INTERVAL.min(deadline - now));
+        }
+    }
+}
+
+/// Audio source name of a simulated headset's microphone.
+pub fn source_name(device_id: DeviceId) -> String {
+    format!("simulated.{}.source", device_id)
+}
+
+/// Sink name of a simulated headset.
+pub fn sink_name(device_id: DeviceId) -> String {
+    format!("simulated.{}.sink", device_id)
+}
+
+fn source_device(source: &str) -> Option<DeviceId> {
+    hyperx::supported_devices()
+        .iter()
+        .map(|device| device.id)
+        .find(|&device_id| source_name(device_id) == source)
+}
+
+/// Mute state of a simulated microphone; `None` when `source` is not one.
+pub fn source_muted(source: &str) -> Option<bool> {
+    let simulator = SIMULATOR.get()?;
+    let device_id = source_device(source)?;
+    Some(
+        *simulator
+            .muted
+            .lock()
+            .unwrap()
+            .get(&device_id)
+            .unwrap_or(&false),
+    )
+}
+
+/// Mute a simulated microphone; returns `false` when `source` is not one.
+pub fn set_source_muted(source: &str, muted: bool) -> bool {
+    let (Some(simulator), Some(device_id)) = (SIMULATOR.get(), source_device(source)) else {
+        return false;
+    };
+    simulator.muted.lock().unwrap().insert(device_id, muted);
+    true
+}
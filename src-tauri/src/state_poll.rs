@@ -0,0 +1,95 @@
+//! Optional polling of device state, so changes made on the headset itself,
+//! such as sidetone toggled with its button, reach the frontend. Every poll
+//! reads the features each connected device supports and publishes
+//! [`DeviceEvent::FeatureChanged`] only for values that differ from the
+//! previous poll; the first reading of a feature only records where it starts.
+//! Devices are read in parallel, so one slow headset does not delay the
+//! others' events.
+//! Battery and the microphone boom are left to the hotplug watcher, which
+//! reports them already, and the firmware version does not change while the
+//! app runs.
+
+use crate::crash;
+use crate::events::{DeviceEvent, EventBus};
+use crate::hyperx::{self, Device, DeviceId, Feature, FeatureKind, Priority};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Shortest interval accepted from the settings; each poll is a read per
+/// feature per device.
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Poll every `interval` until the process exits.
+pub fn spawn(events: Arc<EventBus>, interval: Duration) {
+    let interval = interval.max(MIN_INTERVAL);
+    let control = events.subscribe();
+    crash::supervise("state-poller", move || {
+        // Devices are let go of while the session is inactive, so they are
+        // not opened again by polling them.
+        let mut session_active = true;
+        let mut snapshots: HashMap<DeviceId, HashMap<FeatureKind, Feature>> = HashMap::new();
+        loop {
+            for event in control.try_iter() {
+                match event {
+                    DeviceEvent::SessionChanged { active } => {
+                        session_active = active;
+                        snapshots.clear();
+                    }
+                    // Restoring settings when a device comes back is not a
+                    // change made on the headset.
+                    DeviceEvent::Connected { device_id }
+                    | DeviceEvent::Disconnected { device_id }
+                    | DeviceEvent::HeadsetPoweredOff { device_id }
+                    | DeviceEvent::HeadsetPoweredOn { device_id } => {
+                        snapshots.remove(&device_id);
+                    }
+                    DeviceEvent::Resumed => snapshots.clear(),
+                    _ => {}
+                }
+            }
+            if session_active {
+                let connected = hyperx::connected_devices().unwrap_or_default();
+                snapshots.retain(|device_id, _| connected.contains(device_id));
+                let readings = hyperx::with_priority(Priority::Background, || {
+                    hyperx::devices().query(&connected, read_features)
+                });
+                for (device_id, features) in connected.into_iter().zip(readings) {
+                    let snapshot = snapshots.entry(device_id).or_default();
+                    publish_changes(&events, device_id, features, snapshot);
+                }
+            }
+            thread::sleep(interval);
+        }
+    });
+}
+
+fn read_features(device: &Device) -> Vec<Feature> {
+    device
+        .supported_features()
+        .into_iter()
+        .filter(|kind| {
+            !matches!(
+                kind,
+                FeatureKind::Battery | FeatureKind::Firmware | FeatureKind::Boom
+            )
+        })
+        // A failed read, e.g. of a headset out of range, keeps the old value.
+        .filter_map(|kind| device.get_feature(kind).ok().flatten())
+        .collect()
+}
+
+fn publish_changes(
+    events: &EventBus,
+    device_id: DeviceId,
+    features: Vec<Feature>,
+    snapshot: &mut HashMap<FeatureKind, Feature>,
+) {
+    for feature in features {
+        let previous = snapshot.insert(feature.kind(), feature);
+        if previous.is_some_and(|previous| previous != feature) {
+            events.publish(DeviceEvent::FeatureChanged { device_id, feature });
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Diagnostics bundle for bug reports: one zip with the app and OS versions,
+//! the detected devices with their HID interfaces and hardware details, the
+//! last HID errors, the log files, crash reports and, when asked for, the
+//! latest HID recording (which is already redacted, see
+//! [`crate::recorder`]). Settings are left out since they hold tokens and
+//! passwords.
+
+use crate::crash;
+use crate::device_info;
+use crate::hyperx;
+use crate::logging;
+use crate::metrics;
+use crate::mock;
+use crate::recorder;
+use serde_json::json;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where bundles are written, below the config directory.
+pub const BUNDLE_DIR: &str = "diagnostics";
+/// Only the end of each log file is included.
+const MAX_LOG_BYTES: usize = 1024 * 1024;
+
+/// Write a new bundle and return its path.
+pub fn export(config_dir: &Path, include_capture: bool) -> io::Result<PathBuf> {
+    let created_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let mut zip = ZipWriter::default();
+
+    let info = json!({
+        "app_version": env!("CARGO_PKG_VERSION"),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "os_version": os_version(),
+        "hid_backend": hyperx::hid_backend(),
+        "simulated": mock::is_active(),
+        "dry_run": hyperx::is_dry_run(),
+        "created_at": created_at,
+    });
+    zip.add("info.json", &to_json(&info))?;
+
+    let devices: Vec<_> = hyperx::list_devices()
+        .iter()
+        .map(|device| match device_info::dump_device_info(device.id) {
+            Ok(dump) => json!(dump),
+            Err(err) => json!({ "device_id": device.id, "error": err }),
+        })
+        .collect();
+    let connected = match hyperx::connected_devices() {
+        Ok(connected) => json!(connected),
+        Err(err) => json!({ "error": err }),
+    };
+    let devices = json!({ "connected": connected, "devices": devices });
+    zip.add("devices.json", &to_json(&devices))?;
+    zip.add("errors.json", &to_json(&json!(metrics::recent_errors())))?;
+
+    for path in files_in(&config_dir.join(logging::LOG_DIR)) {
+        let Ok(contents) = fs::read(&path) else {
+            continue;
+        };
+        let tail = &contents[contents.len().saturating_sub(MAX_LOG_BYTES)..];
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        zip.add(&format!("logs/{name}"), tail)?;
+    }
+
+    for path in files_in(&config_dir.join(crash::CRASH_DIR)) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        zip.add(&format!("crashes/{name}"), &fs::read(&path)?)?;
+    }
+
+    if include_capture {
+        let latest = files_in(&config_dir.join(recorder::RECORDINGS_DIR))
+            .into_iter()
+            .max_by_key(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok());
+        if let Some(path) = latest {
+            zip.add("capture.jsonl", &fs::read(path)?)?;
+        }
+    }
+
+    let directory = config_dir.join(BUNDLE_DIR);
+    fs::create_dir_all(&directory)?;
+    let path = directory.join(format!("hyperx-pilot-diagnostics-{created_at}.zip"));
+    fs::write(&path, zip.finish())?;
+    Ok(path)
+}
+
+fn to_json(value: &serde_json::Value) -> Vec<u8> {
+    serde_json::to_vec_pretty(value).unwrap_or_default()
+}
+
+fn files_in(directory: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    let mut files: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(target_os = "linux")]
+fn os_version() -> Option<String> {
+    let release = fs::read_to_string("/etc/os-release").ok()?;
+    let name = release
+        .lines()
+        .find_map(|line| line.strip_prefix("PRETTY_NAME="))?
+        .trim_matches('"');
+    let kernel = fs::read_to_string("/proc/sys/kernel/osrelease").unwrap_or_default();
+    Some(format!("{name} (kernel {})", kernel.trim()))
+}
+
+#[cfg(target_os = "macos")]
+fn os_version() -> Option<String> {
+    let output = std::process::Command::new("sw_vers")
+        .arg("-productVersion")
+        .output()
+        .ok()?;
+    Some(format!(
+        "macOS {}",
+        String::from_utf8_lossy(&output.stdout).trim()
+    ))
+}
+
+#[cfg(windows)]
+fn os_version() -> Option<String> {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    let output = std::process::Command::new("cmd")
+        .args(["/C", "ver"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .ok()?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn os_version() -> Option<String> {
+    None
+}
+
+/// Just enough of the zip format for a bundle: stored (uncompressed) entries
+/// with UTF-8 names.
+#[derive(Default)]
+struct ZipWriter {
+    data: Vec<u8>,
+    central_directory: Vec<u8>,
+    entries: u16,
+}
+
+const ZIP_VERSION: u16 = 20;
+const ZIP_UTF8_NAMES: u16 = 1 << 11;
+/// 1980-01-01, the earliest DOS date; entry times carry no information here.
+const ZIP_DOS_DATE: u16 = (1 << 5) | 1;
+
+impl ZipWriter {
+    fn add(&mut self, name: &str, contents: &[u8]) -> io::Result<()> {
+        let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "bundle too large");
+        let offset = u32::try_from(self.data.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(contents.len()).map_err(|_| too_large())?;
+        let name_length = u16::try_from(name.len()).map_err(|_| too_large())?;
+        self.entries = self.entries.checked_add(1).ok_or_else(too_large)?;
+        let crc = crc32(contents);
+
+        // Fields shared by the local header and the central directory entry,
+        // from "version needed" to "extra field length".
+        let mut common = Vec::new();
+        for field in [ZIP_VERSION, ZIP_UTF8_NAMES, 0, 0, ZIP_DOS_DATE] {
+            common.write_all(&field.to_le_bytes())?;
+        }
+        for field in [crc, size, size] {
+            common.write_all(&field.to_le_bytes())?;
+        }
+        common.write_all(&name_length.to_le_bytes())?;
+        common.write_all(&0u16.to_le_bytes())?;
+
+        self.data.write_all(&0x0403_4b50u32.to_le_bytes())?;
+        self.data.write_all(&common)?;
+        self.data.write_all(name.as_bytes())?;
+        self.data.write_all(contents)?;
+
+        let directory = &mut self.central_directory;
+        directory.write_all(&0x0201_4b50u32.to_le_bytes())?;
+        directory.write_all(&ZIP_VERSION.to_le_bytes())?;
+        directory.write_all(&common)?;
+        // Comment length, disk number, internal and external attributes.
+        directory.write_all(&[0; 10])?;
+        directory.write_all(&offset.to_le_bytes())?;
+        directory.write_all(name.as_bytes())?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let offset = self.data.len() as u32;
+        let size = self.central_directory.len() as u32;
+        self.data.append(&mut self.central_directory);
+        self.data.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        self.data.extend_from_slice(&[0; 4]);
+        self.data.extend_from_slice(&self.entries.to_le_bytes());
+        self.data.extend_from_slice(&self.entries.to_le_bytes());
+        self.data.extend_from_slice(&size.to_le_bytes());
+        self.data.extend_from_slice(&offset.to_le_bytes());
+        self.data.extend_from_slice(&0u16.to_le_bytes());
+        self.data
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
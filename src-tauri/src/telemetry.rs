@@ -0,0 +1,136 @@
+//! Opt-in device report: which models and firmware versions people use and
+//! which features worked on them, to decide which models and quirks to
+//! support next. Nothing is sent unless `telemetry.enabled` is set and the
+//! user submits a report; [`submit`] only sends the body [`preview`]
+//! returned, and only while it still matches the connected devices. A report holds USB IDs, release numbers and per-feature
+//! results only: no serial numbers, paths, names or settings. Reports are
+//! POSTed as JSON through [`crate::curl`].
+
+use crate::curl;
+use crate::hyperx::{self, ControlError, FeatureKind, FirmwareTarget};
+use crate::mock;
+use crate::settings::SettingsStore;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use tracing::info;
+
+/// Bumped when the report format changes.
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum TelemetryError {
+    Disabled,
+    NoEndpoint,
+    /// Reports of `--simulate` devices would skew the numbers.
+    Simulated,
+    /// The report built for sending differs from the one previewed, e.g.
+    /// because a headset was plugged in since.
+    Changed,
+    Control(ControlError),
+    Upload(String),
+}
+
+impl fmt::Display for TelemetryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TelemetryError::Disabled => write!(f, "device reports are off (telemetry.enabled)"),
+            TelemetryError::NoEndpoint => {
+                write!(f, "no report endpoint is set (telemetry.endpoint)")
+            }
+            TelemetryError::Simulated => write!(f, "simulated devices are not reported"),
+            TelemetryError::Changed => write!(
+                f,
+                "the report changed since it was previewed; preview it again"
+            ),
+            TelemetryError::Control(err) => err.fmt(f),
+            TelemetryError::Upload(message) => write!(f, "failed to send the report: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for TelemetryError {}
+
+#[derive(Debug, Clone, Serialize)]
+struct Report {
+    format: u32,
+    app_version: &'static str,
+    os: &'static str,
+    devices: Vec<DeviceReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DeviceReport {
+    /// Hex, e.g. `03f0`.
+    vendor_id: String,
+    product_id: String,
+    /// As `1.07`, see [`hyperx::Device::read_firmware_version`].
+    firmware: Option<String>,
+    /// Whether each feature the app knows for the model answered a read.
+    features: BTreeMap<FeatureKind, bool>,
+}
+
+fn build() -> Result<Report, TelemetryError> {
+    if mock::is_active() {
+        return Err(TelemetryError::Simulated);
+    }
+    let mut devices = Vec::new();
+    for device_id in hyperx::connected_devices().map_err(TelemetryError::Control)? {
+        let (vendor_id, product_id) = hyperx::usb_id(device_id);
+        let firmware = hyperx::devices()
+            .get(device_id)
+            .read_firmware_version(FirmwareTarget::Headset)
+            .ok()
+            .flatten()
+            .map(|version| version.to_string());
+        let features = match hyperx::devices().get(device_id).run_self_test(|| false) {
+            Ok(report) => report
+                .features
+                .iter()
+                .map(|check| (check.feature, check.passed))
+                .collect(),
+            Err(_) => BTreeMap::new(),
+        };
+        devices.push(DeviceReport {
+            vendor_id: format!("{vendor_id:04x}"),
+            product_id: format!("{product_id:04x}"),
+            firmware,
+            features,
+        });
+    }
+    Ok(Report {
+        format: FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        devices,
+    })
+}
+
+fn render(report: &Report) -> String {
+    serde_json::to_string_pretty(report).unwrap_or_default()
+}
+
+/// The report for the connected devices, byte for byte as it would be sent.
+/// Works while reports are off, so users can look before opting in.
+pub fn preview() -> Result<String, TelemetryError> {
+    build().map(|report| render(&report))
+}
+
+/// Send `previewed`, the body [`preview`] returned, to `telemetry.endpoint`
+/// after building the report again: when that differs, nothing is sent, so
+/// what leaves the machine is always what the user looked at. Returns the
+/// body that was sent.
+pub fn submit(settings: &SettingsStore, previewed: String) -> Result<String, TelemetryError> {
+    let telemetry = settings.get().telemetry;
+    if !telemetry.enabled {
+        return Err(TelemetryError::Disabled);
+    }
+    let endpoint = telemetry.endpoint.ok_or(TelemetryError::NoEndpoint)?;
+    if render(&build()?) != previewed {
+        return Err(TelemetryError::Changed);
+    }
+    curl::post_json(&endpoint, &previewed)
+        .map_err(|err| TelemetryError::Upload(err.to_string()))?;
+    info!("device report sent to {endpoint}");
+    Ok(previewed)
+}
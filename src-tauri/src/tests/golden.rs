@@ -0,0 +1,254 @@
+//! Golden snapshots of the exact bytes every device operation sends, so
+//! protocol refactors cannot silently change what reaches the hardware. The
+//! operations run against a mock backend that logs the reports; the logs are
+//! compared with `golden/<device>.txt`. After an intended protocol change,
+//! regenerate them with `UPDATE_GOLDEN=1 cargo test golden` and review the diff.
+
+use crate::hyperx::{self, ControlError, DeviceId};
+use crate::mock::{self, MockBackend};
+use crate::recorder;
+use hidapi::HidError;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Logs every report and answers reads with nothing, so writes are not
+/// followed by retries or conflict checks.
+struct ReportLog {
+    lines: Mutex<Vec<String>>,
+}
+
+impl MockBackend for ReportLog {
+    fn connected(&self) -> Vec<DeviceId> {
+        hyperx::supported_devices()
+            .iter()
+            .map(|device| device.id)
+            .collect()
+    }
+
+    fn send_feature_report(&self, _device_id: DeviceId, data: &[u8]) -> Result<(), HidError> {
+        let line = format!("  send {}", recorder::hex(data));
+        self.lines.lock().unwrap().push(line);
+        Ok(())
+    }
+
+    fn get_feature_report(
+        &self,
+        _device_id: DeviceId,
+        buffer: &mut [u8],
+    ) -> Result<usize, HidError> {
+        let line = format!("  get  {}", recorder::hex(buffer));
+        self.lines.lock().unwrap().push(line);
+        Ok(0)
+    }
+}
+
+static LOG: ReportLog = ReportLog {
+    lines: Mutex::new(Vec::new()),
+};
+
+type Operation = (&'static str, fn(DeviceId) -> Result<(), ControlError>);
+
+const OPERATIONS: &[Operation] = &[
+    ("set_sidetone(true)", |id| {
+        hyperx::devices().get(id).set_sidetone(true)
+    }),
+    ("set_sidetone(false)", |id| {
+        hyperx::devices().get(id).set_sidetone(false)
+    }),
+    ("read_sidetone_state", |id| {
+        hyperx::devices().get(id).read_sidetone_state().map(drop)
+    }),
+    ("set_volume(0)", |id| {
+        hyperx::devices().get(id).set_volume(0)
+    }),
+    ("set_volume(55)", |id| {
+        hyperx::devices().get(id).set_volume(55)
+    }),
+    ("set_volume(150)", |id| {
+        hyperx::devices().get(id).set_volume(150)
+    }),
+    ("read_volume", |id| {
+        hyperx::devices().get(id).read_volume().map(drop)
+    }),
+    ("read_battery", |id| {
+        hyperx::devices().get(id).read_battery().map(drop)
+    }),
+    ("set_mute_led(true)", |id| {
+        hyperx::devices().get(id).set_mute_led(true)
+    }),
+    ("set_mute_led(false)", |id| {
+        hyperx::devices().get(id).set_mute_led(false)
+    }),
+    ("read_firmware_version", |id| {
+        hyperx::devices()
+            .get(id)
+            .read_firmware_version(hyperx::FirmwareTarget::Headset)
+            .map(drop)
+    }),
+    ("factory_reset", |id| {
+        hyperx::devices().get(id).factory_reset()
+    }),
+    ("identify", |id| hyperx::devices().get(id).identify()),
+    ("read_headset_link", |id| {
+        hyperx::devices().get(id).read_headset_link().map(drop)
+    }),
+    ("set_wireless_mode(bluetooth)", |id| {
+        hyperx::devices()
+            .get(id)
+            .set_wireless_mode(hyperx::WirelessMode::Bluetooth)
+    }),
+    ("read_wireless_mode", |id| {
+        hyperx::devices().get(id).read_wireless_mode().map(drop)
+    }),
+    ("set_multipoint(true)", |id| {
+        hyperx::devices().get(id).set_multipoint(true)
+    }),
+    ("list_bluetooth_pairings", |id| {
+        hyperx::devices()
+            .get(id)
+            .list_bluetooth_pairings()
+            .map(drop)
+    }),
+    ("remove_bluetooth_pairing(1)", |id| {
+        hyperx::devices().get(id).remove_bluetooth_pairing(1)
+    }),
+    ("set_output_mute(true)", |id| {
+        hyperx::devices().get(id).set_output_mute(true)
+    }),
+    ("read_output_mute", |id| {
+        hyperx::devices().get(id).read_output_mute().map(drop)
+    }),
+    ("set_voice_prompts(false)", |id| {
+        hyperx::devices().get(id).set_voice_prompts(false)
+    }),
+    ("read_voice_prompts", |id| {
+        hyperx::devices().get(id).read_voice_prompts().map(drop)
+    }),
+    ("set_led_brightness(30)", |id| {
+        hyperx::devices().get(id).set_led_brightness(30)
+    }),
+    ("read_led_brightness", |id| {
+        hyperx::devices().get(id).read_led_brightness().map(drop)
+    }),
+    ("set_notification_beeps(false)", |id| {
+        hyperx::devices().get(id).set_notification_beeps(false)
+    }),
+    ("read_notification_beeps", |id| {
+        hyperx::devices()
+            .get(id)
+            .read_notification_beeps()
+            .map(drop)
+    }),
+    ("set_mute_beep_volume(20)", |id| {
+        hyperx::devices().get(id).set_mute_beep_volume(20)
+    }),
+    ("read_mute_beep_volume", |id| {
+        hyperx::devices().get(id).read_mute_beep_volume().map(drop)
+    }),
+    ("read_boom_position", |id| {
+        hyperx::devices().get(id).read_boom_position().map(drop)
+    }),
+    ("set_button_action(multi_function, play_pause)", |id| {
+        hyperx::devices().get(id).set_button_action(
+            hyperx::Button::MultiFunction,
+            hyperx::ButtonAction::PlayPause,
+        )
+    }),
+    ("read_button_mapping", |id| {
+        hyperx::devices().get(id).read_button_mapping().map(drop)
+    }),
+    ("set_features(voice_prompts, led_brightness)", |id| {
+        hyperx::devices()
+            .get(id)
+            .set_features(&[
+                hyperx::Feature::VoicePrompts(true),
+                hyperx::Feature::LedBrightness(0),
+            ])
+            .map(drop)
+    }),
+    ("set_anc(true)", |id| {
+        hyperx::devices().get(id).set_anc(true)
+    }),
+    ("read_anc", |id| {
+        hyperx::devices().get(id).read_anc().map(drop)
+    }),
+    ("set_anc_level(2)", |id| {
+        hyperx::devices().get(id).set_anc_level(2)
+    }),
+    ("set_anc_level(9)", |id| {
+        hyperx::devices().get(id).set_anc_level(9)
+    }),
+    ("read_anc_level", |id| {
+        hyperx::devices().get(id).read_anc_level().map(drop)
+    }),
+    ("set_noise_mode(ambient)", |id| {
+        hyperx::devices()
+            .get(id)
+            .set_noise_mode(hyperx::NoiseMode::Ambient)
+    }),
+    ("read_noise_mode", |id| {
+        hyperx::devices().get(id).read_noise_mode().map(drop)
+    }),
+    ("set_auto_answer(true)", |id| {
+        hyperx::devices().get(id).set_auto_answer(true)
+    }),
+    ("read_auto_answer", |id| {
+        hyperx::devices().get(id).read_auto_answer().map(drop)
+    }),
+    ("set_game_mode(true)", |id| {
+        hyperx::devices().get(id).set_game_mode(true)
+    }),
+    ("read_game_mode", |id| {
+        hyperx::devices().get(id).read_game_mode().map(drop)
+    }),
+];
+
+fn snapshot(device_id: DeviceId) -> String {
+    let mut snapshot = format!("# {}\n", device_id);
+    for (label, operation) in OPERATIONS {
+        let result = operation(device_id);
+        let _ = writeln!(snapshot, "{label}");
+        for line in LOG.lines.lock().unwrap().drain(..) {
+            let _ = writeln!(snapshot, "{line}");
+        }
+        if let Err(err) = result {
+            let _ = writeln!(snapshot, "  error {}", err.kind());
+        }
+    }
+    snapshot
+}
+
+fn golden_path(device_id: DeviceId) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/tests/golden")
+        .join(format!("{}.txt", device_id))
+}
+
+#[test]
+fn payloads_match_golden_snapshots() {
+    mock::install(&LOG);
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut mismatches = Vec::new();
+    for device in hyperx::supported_devices() {
+        let path = golden_path(device.id);
+        let actual = snapshot(device.id);
+        if update {
+            fs::write(&path, &actual).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_default();
+        if actual != expected {
+            mismatches.push(format!(
+                "{}:\n--- expected\n{expected}--- actual\n{actual}",
+                path.display()
+            ));
+        }
+    }
+    assert!(
+        mismatches.is_empty(),
+        "payloads changed; rerun with UPDATE_GOLDEN=1 if intended\n{}",
+        mismatches.join("\n")
+    );
+}
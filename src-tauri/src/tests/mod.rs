@@ -0,0 +1,5 @@
+//! Tests that exercise several modules together.
+
+mod golden;
+#[cfg(target_os = "linux")]
+mod uhid;
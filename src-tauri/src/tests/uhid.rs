@@ -0,0 +1,258 @@
+//! End-to-end tests through hidapi against virtual devices created with
+//! `/dev/uhid`, so open, descriptor parsing, and feature report round trips
+//! run against the kernel's hidraw driver instead of the mock backend.
+//!
+//! Creating uhid devices needs write access to `/dev/uhid` and the hidraw
+//! nodes (root, or matching udev rules); without it the tests are skipped.
+
+use crate::hyperx::{self, DeviceId, Model};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Event types and layout of `struct uhid_event` from <linux/uhid.h>. The
+// struct is packed; every union member starts right after the 4-byte type.
+const UHID_DESTROY: u32 = 1;
+const UHID_STOP: u32 = 3;
+const UHID_GET_REPORT: u32 = 9;
+const UHID_GET_REPORT_REPLY: u32 = 10;
+const UHID_CREATE2: u32 = 11;
+const UHID_SET_REPORT: u32 = 13;
+const UHID_SET_REPORT_REPLY: u32 = 14;
+const UHID_FEATURE_REPORT: u8 = 0;
+const UHID_DATA_MAX: usize = 4096;
+const UHID_EVENT_SIZE: usize = 4 + 128 + 64 + 64 + 2 + 2 + 4 * 4 + UHID_DATA_MAX;
+
+const BUS_USB: u16 = 0x03;
+const EIO: u16 = 5;
+
+const DEVICE: DeviceId = DeviceId::first(Model::CloudIiiWired);
+
+/// Vendor-defined collection with the Cloud III's single 62-byte feature
+/// report (ID 0x20).
+const CLOUD_III_DESCRIPTOR: &[u8] = &[
+    0x06, 0x00, 0xFF, // Usage Page (Vendor 0xFF00)
+    0x09, 0x01, // Usage (1)
+    0xA1, 0x01, // Collection (Application)
+    0x85, 0x20, //   Report ID (0x20)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x3D, //   Report Count (61)
+    0x09, 0x01, //   Usage (1)
+    0xB1, 0x02, //   Feature (Data, Variable, Absolute)
+    0xC0, // End Collection
+];
+const CLOUD_III_REPORT_LENGTH: usize = 62;
+
+const APPEAR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// uhid devices share a VID/PID, so tests using them must not overlap.
+static DEVICES: Mutex<()> = Mutex::new(());
+
+/// Emulates a headset that stores every feature report it is sent and
+/// answers reads of that report ID with it, like the Cloud III does.
+#[derive(Default)]
+struct FeatureReports {
+    reports: HashMap<u8, Vec<u8>>,
+    /// Every report written, in order.
+    writes: Vec<Vec<u8>>,
+}
+
+struct VirtualDevice {
+    file: File,
+    state: Arc<Mutex<FeatureReports>>,
+}
+
+fn put_u16(event: &mut [u8], offset: usize, value: u16) {
+    event[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(event: &mut [u8], offset: usize, value: u32) {
+    event[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn event(kind: u32) -> Vec<u8> {
+    let mut event = vec![0u8; UHID_EVENT_SIZE];
+    put_u32(&mut event, 0, kind);
+    event
+}
+
+impl VirtualDevice {
+    /// `Ok(None)` when `/dev/uhid` cannot be opened.
+    fn create(
+        name: &str,
+        vendor_id: u16,
+        product_id: u16,
+        descriptor: &[u8],
+        report_length: usize,
+    ) -> io::Result<Option<Self>> {
+        let mut file = match OpenOptions::new().read(true).write(true).open("/dev/uhid") {
+            Ok(file) => file,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+                ) =>
+            {
+                return Ok(None)
+            }
+            Err(err) => return Err(err),
+        };
+
+        let mut create = event(UHID_CREATE2);
+        create[4..4 + name.len()].copy_from_slice(name.as_bytes());
+        put_u16(&mut create, 260, descriptor.len() as u16);
+        put_u16(&mut create, 262, BUS_USB);
+        put_u32(&mut create, 264, u32::from(vendor_id));
+        put_u32(&mut create, 268, u32::from(product_id));
+        create[280..280 + descriptor.len()].copy_from_slice(descriptor);
+        file.write_all(&create)?;
+
+        let state = Arc::new(Mutex::new(FeatureReports::default()));
+        let mut reader = file.try_clone()?;
+        let mut writer = file.try_clone()?;
+        let shared = state.clone();
+        thread::spawn(move || {
+            let mut event = vec![0u8; UHID_EVENT_SIZE];
+            while reader.read(&mut event).is_ok_and(|length| length >= 4) {
+                let kind = u32::from_le_bytes([event[0], event[1], event[2], event[3]]);
+                let reply = match kind {
+                    UHID_GET_REPORT => {
+                        let report_id = event[8];
+                        let mut reply = event_reply(UHID_GET_REPORT_REPLY, &event);
+                        let state = shared.lock().unwrap();
+                        match state.reports.get(&report_id) {
+                            _ if event[9] != UHID_FEATURE_REPORT => put_u16(&mut reply, 8, EIO),
+                            Some(report) => {
+                                put_u16(&mut reply, 10, report.len() as u16);
+                                reply[12..12 + report.len()].copy_from_slice(report);
+                            }
+                            None => {
+                                let mut report = vec![0u8; report_length];
+                                report[0] = report_id;
+                                put_u16(&mut reply, 10, report.len() as u16);
+                                reply[12..12 + report.len()].copy_from_slice(&report);
+                            }
+                        }
+                        reply
+                    }
+                    UHID_SET_REPORT => {
+                        let size = usize::from(u16::from_le_bytes([event[10], event[11]]));
+                        let report = event[12..12 + size.min(UHID_DATA_MAX)].to_vec();
+                        let mut state = shared.lock().unwrap();
+                        state.writes.push(report.clone());
+                        state.reports.insert(event[8], report);
+                        event_reply(UHID_SET_REPORT_REPLY, &event)
+                    }
+                    UHID_STOP => break,
+                    _ => continue,
+                };
+                if writer.write_all(&reply).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Some(VirtualDevice { file, state }))
+    }
+
+    fn writes(&self) -> Vec<Vec<u8>> {
+        self.state.lock().unwrap().writes.clone()
+    }
+}
+
+/// A reply event carrying the request id of `request`.
+fn event_reply(kind: u32, request: &[u8]) -> Vec<u8> {
+    let mut reply = event(kind);
+    reply[4..8].copy_from_slice(&request[4..8]);
+    reply
+}
+
+impl Drop for VirtualDevice {
+    fn drop(&mut self) {
+        let _ = self.file.write_all(&event(UHID_DESTROY));
+    }
+}
+
+fn cloud_iii() -> Option<VirtualDevice> {
+    let (vendor_id, product_id) = hyperx::usb_id(DEVICE);
+    let device = VirtualDevice::create(
+        "HyperX Cloud III (uhid)",
+        vendor_id,
+        product_id,
+        CLOUD_III_DESCRIPTOR,
+        CLOUD_III_REPORT_LENGTH,
+    )
+    .expect("failed to create uhid device");
+    if device.is_none() {
+        eprintln!("skipping: /dev/uhid is not accessible");
+    }
+    device
+}
+
+/// Wait until the hidraw node is enumerated and accepts reads.
+fn wait_until_present(device_id: DeviceId, present: bool) -> bool {
+    let started = Instant::now();
+    while started.elapsed() < APPEAR_TIMEOUT {
+        // What the hotplug watcher does on every poll.
+        let _ = hyperx::refresh_devices();
+        let listed = hyperx::connected_devices()
+            .map(|devices| devices.contains(&device_id))
+            .unwrap_or(false);
+        let ready = !present
+            || hyperx::devices()
+                .get(device_id)
+                .read_sidetone_state()
+                .is_ok();
+        if listed == present && ready {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+#[test]
+fn sidetone_round_trip() {
+    let _guard = DEVICES.lock().unwrap_or_else(|err| err.into_inner());
+    let Some(device) = cloud_iii() else {
+        return;
+    };
+    assert!(wait_until_present(DEVICE, true));
+
+    hyperx::devices().get(DEVICE).set_sidetone(true).unwrap();
+    assert_eq!(
+        hyperx::devices().get(DEVICE).read_sidetone_state().unwrap(),
+        Some(true)
+    );
+    hyperx::devices().get(DEVICE).set_sidetone(false).unwrap();
+    assert_eq!(
+        hyperx::devices().get(DEVICE).read_sidetone_state().unwrap(),
+        Some(false)
+    );
+
+    // The length comes from the descriptor, as on real hardware.
+    let mut expected = vec![0u8; CLOUD_III_REPORT_LENGTH];
+    expected[..4].copy_from_slice(&[0x20, 0x86, 0x01, 0x00]);
+    assert_eq!(device.writes().first(), Some(&expected));
+}
+
+#[test]
+fn unplugged_device_fails_to_open() {
+    let _guard = DEVICES.lock().unwrap_or_else(|err| err.into_inner());
+    let Some(device) = cloud_iii() else {
+        return;
+    };
+    assert!(wait_until_present(DEVICE, true));
+    drop(device);
+    assert!(wait_until_present(DEVICE, false));
+
+    let err = hyperx::devices()
+        .get(DEVICE)
+        .read_sidetone_state()
+        .unwrap_err();
+    assert_eq!(err.kind(), "device_open");
+}
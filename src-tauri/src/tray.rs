@@ -0,0 +1,82 @@
+//! Tray icon. Its menu switches the log filter, so trace-level HID payload
+//! logging can be turned on while reproducing a problem and off again
+//! without a restart.
+
+use crate::logging;
+use tauri::menu::{CheckMenuItem, Menu, PredefinedMenuItem, Submenu};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Manager, Wry};
+
+/// Menu label and filter directives of each log level entry.
+const LOG_LEVELS: [(&str, &str); 4] = [
+    ("Warnings only", "warn"),
+    ("Normal", logging::DEFAULT_FILTER),
+    ("Debug", "warn,hyperx_pilot_lib=debug"),
+    ("Trace (HID payloads)", logging::TRACE_FILTER),
+];
+
+const LOG_LEVEL_ID: &str = "log_level:";
+
+/// The log level entries, kept to tick the active filter.
+pub struct LogLevelMenu {
+    items: Vec<CheckMenuItem<Wry>>,
+}
+
+impl LogLevelMenu {
+    /// Tick the entry matching the active filter; none for custom filters.
+    pub fn sync(&self) {
+        let active = logging::filter();
+        for (item, (_, directives)) in self.items.iter().zip(LOG_LEVELS) {
+            let _ = item.set_checked(directives == active);
+        }
+    }
+}
+
+pub fn build(app: &AppHandle) -> tauri::Result<()> {
+    let items = LOG_LEVELS
+        .iter()
+        .enumerate()
+        .map(|(index, (label, _))| {
+            CheckMenuItem::with_id(
+                app,
+                format!("{LOG_LEVEL_ID}{index}"),
+                *label,
+                true,
+                false,
+                None::<&str>,
+            )
+        })
+        .collect::<tauri::Result<Vec<_>>>()?;
+    let entries: Vec<&dyn tauri::menu::IsMenuItem<Wry>> = items
+        .iter()
+        .map(|item| item as &dyn tauri::menu::IsMenuItem<Wry>)
+        .collect();
+    let log_level = Submenu::with_items(app, "Log level", true, &entries)?;
+    let quit = PredefinedMenuItem::quit(app, None)?;
+    let menu = Menu::with_items(app, &[&log_level, &quit])?;
+
+    let mut tray = TrayIconBuilder::new()
+        .tooltip("HyperX Pilot")
+        .menu(&menu)
+        .on_menu_event(|app, event| {
+            let Some(index) = event.id().as_ref().strip_prefix(LOG_LEVEL_ID) else {
+                return;
+            };
+            let Some((_, directives)) = index.parse().ok().and_then(|i: usize| LOG_LEVELS.get(i))
+            else {
+                return;
+            };
+            // The presets are valid filters.
+            let _ = logging::set_filter(directives);
+            app.state::<LogLevelMenu>().sync();
+        });
+    if let Some(icon) = app.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+    tray.build(app)?;
+
+    let log_levels = LogLevelMenu { items };
+    log_levels.sync();
+    app.manage(log_levels);
+    Ok(())
+}
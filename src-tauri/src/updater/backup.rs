@@ -0,0 +1,109 @@
+//! Firmware backups. On models whose bootloader can read back the installed
+//! image, it is saved to the `firmware-backups` folder of the config
+//! directory before every flash, and on request, so a bad update can be
+//! undone by flashing the backup.
+
+use super::UpdateError;
+use crate::hyperx::{self, DeviceId, DfuSession, FirmwareTarget, FirmwareVersion};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// Below the config directory.
+pub const BACKUP_DIR: &str = "firmware-backups";
+/// Times one block is read again after arriving damaged.
+const BLOCK_RETRIES: u32 = 3;
+
+/// Read the installed image of `target` of `device_id` and save it; returns
+/// the file's path.
+pub fn backup(
+    config_dir: &Path,
+    device_id: DeviceId,
+    target: FirmwareTarget,
+) -> Result<PathBuf, UpdateError> {
+    if !hyperx::supports_firmware_backup(device_id, target) {
+        return Err(UpdateError::BackupUnsupported);
+    }
+    let device = hyperx::devices().get(device_id);
+    // Read before the session holds the device.
+    let installed = device.read_firmware_version(target).ok().flatten();
+    let session = DfuSession::open(&device, target).map_err(UpdateError::Control)?;
+    save(
+        config_dir,
+        device_id,
+        target,
+        installed,
+        &session,
+        |_, _| {},
+    )
+}
+
+/// [`backup`] through an open session, reporting bytes read and the image
+/// size. `installed` names the file; it cannot be read through the session.
+pub(super) fn save(
+    config_dir: &Path,
+    device_id: DeviceId,
+    target: FirmwareTarget,
+    installed: Option<FirmwareVersion>,
+    session: &DfuSession,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<PathBuf, UpdateError> {
+    let image = read_image(session, &mut progress)?;
+    let version = installed.map_or_else(|| "unknown".to_string(), |version| version.to_string());
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis());
+    let directory = config_dir.join(BACKUP_DIR);
+    let name = match target {
+        FirmwareTarget::Headset => device_id.to_string(),
+        FirmwareTarget::Dongle => format!("{device_id}-{}", target.as_str()),
+    };
+    let path = directory.join(format!("{name}-{version}-{time}.bin"));
+    fs::create_dir_all(&directory)
+        .and_then(|()| fs::write(&path, &image))
+        .map_err(|err| UpdateError::Backup(err.to_string()))?;
+    info!(
+        bytes = image.len(),
+        "firmware backed up to {}",
+        path.display()
+    );
+    Ok(path)
+}
+
+fn read_image(
+    session: &DfuSession,
+    progress: &mut impl FnMut(usize, usize),
+) -> Result<Vec<u8>, UpdateError> {
+    let block_size = session.chunk_size().map_err(UpdateError::Control)?;
+    let mut image = Vec::new();
+    let mut size = None;
+    let mut retries = 0;
+    while size.is_none_or(|size| image.len() < size) {
+        let Some((total, block)) = session
+            .read_block(image.len(), block_size)
+            .map_err(UpdateError::Control)?
+        else {
+            if retries == BLOCK_RETRIES {
+                return Err(UpdateError::Backup(format!(
+                    "the block at byte {} kept arriving damaged",
+                    image.len()
+                )));
+            }
+            retries += 1;
+            warn!(offset = image.len(), "reading firmware block again");
+            continue;
+        };
+        if block.is_empty() && image.len() < total {
+            return Err(UpdateError::Backup(format!(
+                "the bootloader returned nothing at byte {} of {total}",
+                image.len()
+            )));
+        }
+        retries = 0;
+        size = Some(total);
+        image.extend(block);
+        progress(image.len(), total);
+    }
+    Ok(image)
+}
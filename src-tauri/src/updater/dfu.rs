@@ -0,0 +1,280 @@
+//! Firmware flashing. The image of a manifest release is downloaded and
+//! checked against the manifest's SHA-256, the installed image is backed up
+//! where the bootloader allows it (see [`super::backup`]), then the new one is
+//! uploaded in checksummed chunks, see [`hyperx::DfuSession`].
+//! Chunks the bootloader reports as damaged or out of order are resent from
+//! where it wants to continue. When the flash is cancelled (see
+//! [`crate::operations`]) it stops after the current chunk; on that or any
+//! other failure the upload is cancelled, and the headset keeps running its
+//! old image.
+//!
+//! Receivers (dongles) of wireless models are flashed the same way through
+//! their own product ID, and paired with the headset again afterwards.
+
+use super::{backup, UpdateError};
+use crate::hyperx::{self, DeviceId, DfuSession, DfuStatus, FirmwareTarget, FirmwareVersion};
+use crate::operations::{self, CancelToken, OperationId, OperationKind};
+use crate::pairing;
+use crate::settings::SettingsStore;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{info, warn};
+
+/// Name of the Tauri event carrying each [`Progress`] to the frontend.
+pub const PROGRESS_EVENT: &str = "firmware-progress";
+/// Times one chunk is resent before the upload is given up.
+const CHUNK_RETRIES: u32 = 3;
+
+static FLASHING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Downloading,
+    BackingUp,
+    Writing,
+    /// The bootloader checks the finished image.
+    Verifying,
+    /// A flashed receiver is pairing with the headset.
+    Pairing,
+    Done,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Progress {
+    pub operation_id: OperationId,
+    pub device_id: DeviceId,
+    pub target: FirmwareTarget,
+    pub stage: Stage,
+    /// Bytes uploaded so far, or read while backing up.
+    pub written: usize,
+    pub total: usize,
+}
+
+impl Stage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stage::Downloading => "downloading",
+            Stage::BackingUp => "backing_up",
+            Stage::Writing => "writing",
+            Stage::Verifying => "verifying",
+            Stage::Pairing => "pairing",
+            Stage::Done => "done",
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            Stage::Downloading => "Downloading the firmware image",
+            Stage::BackingUp => "Backing up the installed firmware",
+            Stage::Writing => "Writing the new firmware; keep the headset connected",
+            Stage::Verifying => "The bootloader is checking the new firmware",
+            Stage::Pairing => "Pairing the receiver with the headset; keep the headset on",
+            Stage::Done => "The firmware was updated",
+        }
+    }
+}
+
+impl Progress {
+    /// This step as an [`operations::Progress`].
+    pub fn summary(&self) -> operations::Progress {
+        operations::Progress {
+            operation_id: self.operation_id,
+            kind: OperationKind::FirmwareFlash,
+            device_id: self.device_id,
+            step: self.stage.as_str(),
+            percent: match self.stage {
+                Stage::Done => Some(100),
+                _ => operations::percent(self.written, self.total),
+            },
+            message: Some(self.stage.message()),
+        }
+    }
+}
+
+/// Clears [`FLASHING`] however the flash ends.
+struct FlashGuard;
+
+impl Drop for FlashGuard {
+    fn drop(&mut self) {
+        FLASHING.store(false, Ordering::SeqCst);
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Flash firmware `version` from the manifest to `target` of `device_id`,
+/// reporting every step to `progress`. The flash is registered with
+/// [`crate::operations`], so it can be cancelled. Backups go below `config_dir`. Only
+/// one flash runs at a time, and versions older than the installed one need
+/// `allow_downgrade`.
+pub fn flash(
+    settings: &SettingsStore,
+    config_dir: &Path,
+    device_id: DeviceId,
+    target: FirmwareTarget,
+    version: FirmwareVersion,
+    allow_downgrade: bool,
+    mut progress: impl FnMut(Progress),
+) -> Result<(), UpdateError> {
+    if FLASHING.swap(true, Ordering::SeqCst) {
+        return Err(UpdateError::Busy);
+    }
+    let _guard = FlashGuard;
+    let operation = operations::begin(OperationKind::FirmwareFlash, device_id);
+
+    let manifest_url = settings.get().firmware.manifest_url;
+    let manifest = super::load_manifest(manifest_url.as_deref())?;
+    let Some(release) = manifest
+        .releases(device_id, target)
+        .into_iter()
+        .find(|release| release.version == version)
+    else {
+        let published = manifest
+            .firmware
+            .iter()
+            .any(|release| release.version == version);
+        return Err(if published {
+            UpdateError::ModelMismatch(version)
+        } else {
+            UpdateError::UnknownRelease(version)
+        });
+    };
+    let device = hyperx::devices().get(device_id);
+    let installed = device
+        .read_firmware_version(target)
+        .map_err(UpdateError::Control)?;
+    if let Some(installed) = installed {
+        if version < installed {
+            if !allow_downgrade {
+                return Err(UpdateError::Downgrade {
+                    installed,
+                    requested: version,
+                });
+            }
+            warn!(installed = %installed, version = %version, "downgrading firmware");
+        }
+    }
+
+    let mut report = |stage, written, total| {
+        progress(Progress {
+            operation_id: operation.id(),
+            device_id,
+            target,
+            stage,
+            written,
+            total,
+        })
+    };
+    report(Stage::Downloading, 0, 0);
+    let image = super::fetch(&release.url)?;
+    if !sha256_hex(&image).eq_ignore_ascii_case(release.sha256.trim()) {
+        return Err(UpdateError::ImageMismatch);
+    }
+    if hyperx::is_dry_run() {
+        info!(
+            version = %version,
+            bytes = image.len(),
+            "dry run: not flashing"
+        );
+        report(Stage::Done, image.len(), image.len());
+        return Ok(());
+    }
+
+    let session = DfuSession::open(&device, target).map_err(UpdateError::Control)?;
+    if hyperx::supports_firmware_backup(device_id, target) {
+        report(Stage::BackingUp, 0, 0);
+        backup::save(
+            config_dir,
+            device_id,
+            target,
+            installed,
+            &session,
+            |read, total| report(Stage::BackingUp, read, total),
+        )?;
+    }
+    if let Err(err) = upload(&session, &image, operation.token(), &mut report) {
+        if let Err(err) = session.abort() {
+            warn!("failed to cancel the firmware upload: {err}");
+        }
+        return Err(err);
+    }
+    // The receiver reboots into its new image; pairing opens it again.
+    drop(session);
+    info!(target = target.as_str(), version = %version, "firmware flashed");
+
+    if target == FirmwareTarget::Dongle && device.needs_pairing() {
+        report(Stage::Pairing, image.len(), image.len());
+        pairing::pair(device_id, pairing::TIMEOUT, &operation, |_| {})
+            .map_err(UpdateError::Pairing)?;
+    }
+    report(Stage::Done, image.len(), image.len());
+    Ok(())
+}
+
+fn upload(
+    session: &DfuSession,
+    image: &[u8],
+    cancel: &CancelToken,
+    report: &mut impl FnMut(Stage, usize, usize),
+) -> Result<(), UpdateError> {
+    let total = image.len();
+    let state = session.enter(image).map_err(UpdateError::Control)?;
+    if !state.in_bootloader {
+        return Err(UpdateError::Flash(format!(
+            "the bootloader did not start ({})",
+            state.status
+        )));
+    }
+
+    let chunk_size = session.chunk_size().map_err(UpdateError::Control)?;
+    let mut offset = 0;
+    let mut retries = 0;
+    report(Stage::Writing, 0, total);
+    while offset < total {
+        if cancel.is_cancelled() {
+            return Err(UpdateError::Aborted);
+        }
+        let end = (offset + chunk_size).min(total);
+        let state = session
+            .write_chunk(offset, &image[offset..end])
+            .map_err(UpdateError::Control)?;
+        match state.status {
+            DfuStatus::Ok => {
+                offset = end;
+                retries = 0;
+                report(Stage::Writing, offset, total);
+            }
+            DfuStatus::ChecksumError | DfuStatus::BadOffset if retries < CHUNK_RETRIES => {
+                retries += 1;
+                warn!(offset, status = %state.status, "resending firmware chunk");
+                offset = (state.next_offset as usize).min(end);
+            }
+            status => {
+                return Err(UpdateError::Flash(format!(
+                    "the bootloader stopped at byte {offset}: {status}"
+                )))
+            }
+        }
+    }
+
+    report(Stage::Verifying, total, total);
+    let state = session.finish(image).map_err(UpdateError::Control)?;
+    if state.status != DfuStatus::Ok {
+        return Err(UpdateError::Flash(format!(
+            "the bootloader did not accept the image ({})",
+            state.status
+        )));
+    }
+    Ok(())
+}
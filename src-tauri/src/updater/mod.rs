@@ -0,0 +1,386 @@
+//! Firmware updates. A manifest lists the firmware images published for
+//! each model; the check compares the newest one with the version the
+//! headset reports and says whether an update exists, with the release notes
+//! of the newer image. Installing it is up to [`dfu`], which flashes the
+//! image after [`backup`] saved the installed one.
+//!
+//! The manifest is `firmware/manifest.json`, bundled with the app, or the
+//! file at `firmware.manifest_url` (HTTPS, fetched through [`crate::curl`]).
+//! Either way it must come with a detached Ed25519 signature by
+//! [`MANIFEST_KEY`], base64-encoded in a `.sig` file next to it, so a
+//! compromised mirror cannot point users at a modified image.
+
+pub mod backup;
+pub mod dfu;
+
+use crate::curl;
+use crate::hyperx::{self, ControlError, DeviceId, FirmwareTarget, FirmwareVersion};
+use crate::pairing::PairingError;
+use crate::settings::SettingsStore;
+use base64::Engine;
+use ed25519_dalek::{Signature, VerifyingKey, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tracing::warn;
+
+const BUNDLED_MANIFEST: &[u8] = include_bytes!("../../firmware/manifest.json");
+const BUNDLED_SIGNATURE: &str = include_str!("../../firmware/manifest.json.sig");
+/// Public half of the key manifests are signed with, base64.
+///
+/// This is a development key, generated when manifest signing was added so
+/// the bundled manifest (an empty list) could be signed at all. Nobody on the
+/// project holds its private half, so no published image can be signed with
+/// it: before the first firmware is listed, the release maintainers must
+/// generate the real key, keep its private half offline, put the public half
+/// here and re-sign `firmware/manifest.json`.
+const MANIFEST_KEY: &str = "mwggkym+/vHq4CPX41huRAXOpXHqF+wZfvs0q9JmH8o=";
+/// Manifests with a newer format are rejected rather than misread.
+const MANIFEST_FORMAT: u32 = 1;
+
+#[derive(Debug)]
+pub enum UpdateError {
+    Control(ControlError),
+    Fetch {
+        url: String,
+        message: String,
+    },
+    BadSignature,
+    InvalidManifest(String),
+    UnknownRelease(FirmwareVersion),
+    /// The version is only published for other models.
+    ModelMismatch(FirmwareVersion),
+    /// Flashing would replace newer firmware; needs the caller's confirmation.
+    Downgrade {
+        installed: FirmwareVersion,
+        requested: FirmwareVersion,
+    },
+    /// The downloaded image does not match the manifest's checksum.
+    ImageMismatch,
+    /// Another flash is running.
+    Busy,
+    Aborted,
+    Flash(String),
+    BackupUnsupported,
+    Backup(String),
+    /// A flashed receiver did not pair with the headset again.
+    Pairing(PairingError),
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateError::Control(err) => err.fmt(f),
+            UpdateError::Fetch { url, message } => write!(f, "failed to fetch {url}: {message}"),
+            UpdateError::BadSignature => {
+                write!(
+                    f,
+                    "the firmware manifest is not signed by HyperX Pilot's key"
+                )
+            }
+            UpdateError::InvalidManifest(reason) => {
+                write!(f, "invalid firmware manifest: {reason}")
+            }
+            UpdateError::UnknownRelease(version) => {
+                write!(
+                    f,
+                    "the manifest lists no firmware {version} for this headset"
+                )
+            }
+            UpdateError::ModelMismatch(version) => write!(
+                f,
+                "firmware {version} is for another model and cannot be flashed to this one"
+            ),
+            UpdateError::Downgrade {
+                installed,
+                requested,
+            } => write!(
+                f,
+                "firmware {requested} is older than the installed {installed}; allow the downgrade to flash it anyway"
+            ),
+            UpdateError::ImageMismatch => write!(
+                f,
+                "the downloaded firmware image does not match the manifest's checksum"
+            ),
+            UpdateError::Busy => write!(f, "a firmware update is already running"),
+            UpdateError::Aborted => {
+                write!(
+                    f,
+                    "the firmware update was cancelled; the headset keeps its old firmware"
+                )
+            }
+            UpdateError::BackupUnsupported => {
+                write!(f, "this headset's bootloader cannot read back its firmware")
+            }
+            UpdateError::Backup(reason) => write!(f, "firmware backup failed: {reason}"),
+            UpdateError::Pairing(err) => write!(
+                f,
+                "the receiver was updated but did not pair with the headset ({err}); pair it again"
+            ),
+            UpdateError::Flash(reason) => write!(
+                f,
+                "flashing failed: {reason}; the headset keeps its old firmware"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UpdateError {}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Manifest {
+    format: u32,
+    firmware: Vec<FirmwareRelease>,
+}
+
+impl Manifest {
+    /// The releases for the headset or receiver `target` of `device_id`.
+    fn releases(&self, device_id: DeviceId, target: FirmwareTarget) -> Vec<FirmwareRelease> {
+        let Some((vendor_id, product_id)) = hyperx::firmware_usb_id(device_id, target) else {
+            return Vec::new();
+        };
+        self.firmware
+            .iter()
+            .filter(|release| release.is_for(vendor_id, product_id))
+            .cloned()
+            .collect()
+    }
+}
+
+/// One published firmware image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FirmwareRelease {
+    /// Hex, e.g. `03f0`. Receiver images carry the receiver's product ID.
+    pub vendor_id: String,
+    pub product_id: String,
+    pub version: FirmwareVersion,
+    /// Where the image is downloaded from.
+    pub url: String,
+    /// Hex SHA-256 of the image.
+    pub sha256: String,
+    /// Where this version's release notes (plain text or Markdown) are.
+    #[serde(default)]
+    pub notes_url: Option<String>,
+}
+
+impl FirmwareRelease {
+    fn is_for(&self, vendor_id: u16, product_id: u16) -> bool {
+        let matches = |hex: &str, id: u16| u16::from_str_radix(hex, 16) == Ok(id);
+        matches(&self.vendor_id, vendor_id) && matches(&self.product_id, product_id)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateCheck {
+    pub device_id: DeviceId,
+    pub target: FirmwareTarget,
+    /// `None` when the device does not report its version.
+    pub installed: Option<FirmwareVersion>,
+    /// The newest image in the manifest for this model.
+    pub latest: Option<FirmwareRelease>,
+    /// Never set while a version is pinned.
+    pub update_available: bool,
+    /// The version the user pinned, see [`crate::settings::FirmwareSettings::pinned`].
+    pub pinned: Option<FirmwareVersion>,
+    /// Release notes of `latest`, fetched only when an update is available.
+    pub release_notes: Option<String>,
+    /// `None` for the bundled manifest.
+    pub manifest_url: Option<String>,
+}
+
+fn decode_base64(value: &str) -> Option<Vec<u8>> {
+    base64::engine::general_purpose::STANDARD
+        .decode(value.trim())
+        .ok()
+}
+
+/// Parse `manifest` after checking `signature` (base64) against [`MANIFEST_KEY`].
+fn verify(manifest: &[u8], signature: &str) -> Result<Manifest, UpdateError> {
+    let key: [u8; PUBLIC_KEY_LENGTH] = decode_base64(MANIFEST_KEY)
+        .and_then(|key| key.try_into().ok())
+        .expect("MANIFEST_KEY is a valid key");
+    let signature: [u8; SIGNATURE_LENGTH] = decode_base64(signature)
+        .and_then(|signature| signature.try_into().ok())
+        .ok_or(UpdateError::BadSignature)?;
+    if !is_signed_by(&key, manifest, &signature) {
+        return Err(UpdateError::BadSignature);
+    }
+    let manifest: Manifest = serde_json::from_slice(manifest)
+        .map_err(|err| UpdateError::InvalidManifest(err.to_string()))?;
+    if manifest.format > MANIFEST_FORMAT {
+        return Err(UpdateError::InvalidManifest(format!(
+            "format {} is newer than this app supports; update HyperX Pilot",
+            manifest.format
+        )));
+    }
+    Ok(manifest)
+}
+
+/// Whether `signature` is a valid Ed25519 signature of `message` by `key`.
+/// Strict verification refuses non-canonical scalars and small-order keys, so
+/// a valid signature cannot be turned into a second one for the same manifest.
+fn is_signed_by(
+    key: &[u8; PUBLIC_KEY_LENGTH],
+    message: &[u8],
+    signature: &[u8; SIGNATURE_LENGTH],
+) -> bool {
+    let Ok(key) = VerifyingKey::from_bytes(key) else {
+        return false;
+    };
+    key.verify_strict(message, &Signature::from_bytes(signature))
+        .is_ok()
+}
+
+fn fetch(url: &str) -> Result<Vec<u8>, UpdateError> {
+    curl::get(url).map_err(|err| UpdateError::Fetch {
+        url: url.to_string(),
+        message: err.to_string(),
+    })
+}
+
+fn load_manifest(url: Option<&str>) -> Result<Manifest, UpdateError> {
+    let Some(url) = url else {
+        return verify(BUNDLED_MANIFEST, BUNDLED_SIGNATURE);
+    };
+    let manifest = fetch(url)?;
+    let signature = fetch(&format!("{url}.sig"))?;
+    verify(&manifest, &String::from_utf8_lossy(&signature))
+}
+
+/// The notes at `release.notes_url`. A missing page only loses the notes, so it
+/// does not fail the check.
+fn release_notes(release: &FirmwareRelease) -> Option<String> {
+    let url = release.notes_url.as_deref()?;
+    match fetch(url) {
+        Ok(notes) => Some(String::from_utf8_lossy(&notes).trim().to_string()),
+        Err(err) => {
+            warn!(version = %release.version, "no release notes: {err}");
+            None
+        }
+    }
+}
+
+/// Whether newer firmware than the installed one is published for
+/// `device_id`, and for its receiver on wireless models.
+pub fn check(
+    settings: &SettingsStore,
+    device_id: DeviceId,
+) -> Result<Vec<UpdateCheck>, UpdateError> {
+    let firmware = settings.get().firmware;
+    let manifest_url = firmware.manifest_url.clone();
+    let manifest = load_manifest(manifest_url.as_deref())?;
+    let mut checks = Vec::new();
+    for target in hyperx::firmware_targets(device_id) {
+        let installed = hyperx::devices()
+            .get(device_id)
+            .read_firmware_version(target)
+            .map_err(UpdateError::Control)?;
+        let latest = manifest
+            .releases(device_id, target)
+            .into_iter()
+            .max_by_key(|release| release.version);
+        let pinned = firmware.pinned(device_id, target);
+        let update_available = match (installed, &latest) {
+            (Some(installed), Some(latest)) => pinned.is_none() && latest.version > installed,
+            _ => false,
+        };
+        let release_notes = match &latest {
+            Some(release) if update_available => release_notes(release),
+            _ => None,
+        };
+        checks.push(UpdateCheck {
+            device_id,
+            target,
+            installed,
+            latest,
+            update_available,
+            pinned,
+            release_notes,
+            manifest_url: manifest_url.clone(),
+        });
+    }
+    Ok(checks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn bytes<const N: usize>(hex: &str) -> [u8; N] {
+        decode(hex).try_into().unwrap()
+    }
+
+    /// Public key, message and signature of TEST 1 to 3 of RFC 8032, 7.1.
+    const RFC_8032: [(&str, &str, &str); 3] = [
+        (
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            "",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+             5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        ),
+        (
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            "72",
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+             085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        ),
+        (
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            "af82",
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac\
+             18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        ),
+    ];
+
+    #[test]
+    fn accepts_rfc_8032_vectors() {
+        for (key, text, signature) in RFC_8032 {
+            assert!(is_signed_by(&bytes(key), &decode(text), &bytes(signature)));
+        }
+    }
+
+    #[test]
+    fn rejects_other_messages() {
+        for (key, text, signature) in RFC_8032 {
+            let mut text = decode(text);
+            text.push(0);
+            assert!(!is_signed_by(&bytes(key), &text, &bytes(signature)));
+        }
+    }
+
+    #[test]
+    fn rejects_non_canonical_scalar() {
+        // TEST 1 with the group order added to S: the same point equation
+        // holds, but only the reduced scalar is a valid encoding.
+        let (key, _, _) = RFC_8032[0];
+        let malleated = bytes(
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+             4c8c7872aa064e049dbb3013fbf29380d25bf5f0595bbe24655141438e7a101b",
+        );
+        assert!(!is_signed_by(&bytes(key), b"", &malleated));
+    }
+
+    #[test]
+    fn accepts_bundled_manifest() {
+        assert!(verify(BUNDLED_MANIFEST, BUNDLED_SIGNATURE).is_ok());
+    }
+
+    #[test]
+    fn rejects_tampered_manifest() {
+        let tampered = String::from_utf8_lossy(BUNDLED_MANIFEST).replace(
+            r#""firmware": []"#,
+            r#""firmware": [{"model": "cloud_iii_wired"}]"#,
+        );
+        assert_ne!(tampered.as_bytes(), BUNDLED_MANIFEST);
+        assert!(matches!(
+            verify(tampered.as_bytes(), BUNDLED_SIGNATURE),
+            Err(UpdateError::BadSignature)
+        ));
+    }
+}
@@ -0,0 +1,353 @@
+use crate::crash;
+use crate::events::{DeviceEvent, EventBus};
+use crate::hyperx::{self, BoomPosition, DeviceId, InputReport, Priority};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// How long an input listener waits for a report before checking whether
+/// it should stop.
+const LISTEN_TIMEOUT: Duration = Duration::from_secs(1);
+/// Charge below which [`DeviceEvent::BatteryLow`] is published, once per discharge.
+pub const LOW_BATTERY_PERCENT: u8 = 15;
+
+/// Start the hotplug watcher. Devices already present on the first scan are
+/// reported as connected, so subscribers don't need a separate initial query.
+/// After a resume the watcher starts over, so every present device is
+/// reported as connected again and its settings get restored. While the
+/// session is inactive (locked or switched away) it stops polling and reports
+/// every device as disconnected, so all consumers let go of them.
+///
+/// Wireless headsets are also watched through their receiver, which stays
+/// plugged in while the headset is off; switching the headset on or off is
+/// reported as [`DeviceEvent::HeadsetPoweredOn`] and
+/// [`DeviceEvent::HeadsetPoweredOff`]. A headset that is off when its
+/// receiver connects is reported as powered off right after the connect.
+///
+/// Batteries and links of several headsets are read in parallel, so a slow
+/// receiver does not delay the others' events. Headsets that report charge
+/// changes on their own are only read once when they connect; after that
+/// their reports are listened for instead, unless listening fails. Button
+/// presses and dial turns are listened for the same way and published as
+/// [`DeviceEvent::ButtonPressed`] and [`DeviceEvent::DialTurned`] as they
+/// arrive, not with the next poll.
+///
+/// The microphone boom of flip-to-mute headsets is read on every poll too;
+/// its position is published as [`DeviceEvent::BoomMoved`] once on connect
+/// and whenever it changes.
+///
+/// With an `idle_timeout`, each poll also closes device handles unused for
+/// that long. Handles of headsets whose battery or link the watcher reads
+/// are used by every poll and so stay open.
+pub fn spawn(events: Arc<EventBus>, idle_timeout: Option<Duration>) {
+    let control = events.subscribe();
+    crash::supervise("hotplug-watcher", move || {
+        hyperx::with_priority(Priority::Background, || {
+            watch(&events, &control, idle_timeout)
+        })
+    });
+}
+
+fn watch(events: &Arc<EventBus>, control: &Receiver<DeviceEvent>, idle_timeout: Option<Duration>) {
+    if let Err(err) = hyperx::refresh_devices() {
+        warn!("hotplug watcher disabled: failed to initialise HID API: {err}");
+        return;
+    }
+    let mut watching = true;
+    let mut connected = HashSet::new();
+    let mut battery_low = HashSet::new();
+    let mut battery_levels = HashMap::new();
+    let mut links = HashMap::new();
+    let mut booms = HashMap::new();
+    let mut listeners = InputListeners::new(events.clone());
+    loop {
+        for event in control.try_iter() {
+            match event {
+                // Handles and enumeration state may not survive sleep; the
+                // next poll enumerates again.
+                DeviceEvent::Resumed if watching => {
+                    listeners.stop_all();
+                    connected.clear();
+                    battery_low.clear();
+                    battery_levels.clear();
+                    links.clear();
+                    booms.clear();
+                }
+                DeviceEvent::SessionChanged { active: false } => {
+                    for &device_id in &connected {
+                        hyperx::devices().release(device_id);
+                        events.publish(DeviceEvent::Disconnected { device_id });
+                    }
+                    connected.clear();
+                    battery_low.clear();
+                    battery_levels.clear();
+                    links.clear();
+                    booms.clear();
+                    listeners.stop_all();
+                    watching = false;
+                }
+                DeviceEvent::SessionChanged { active: true } => watching = true,
+                _ => {}
+            }
+        }
+        // This also keeps the device list every open goes through current.
+        if watching {
+            if let Ok(present) = present_devices() {
+                for &device_id in present.difference(&connected) {
+                    events.publish(DeviceEvent::Connected { device_id });
+                }
+                for &device_id in connected.difference(&present) {
+                    hyperx::devices().release(device_id);
+                    events.publish(DeviceEvent::Disconnected { device_id });
+                }
+                connected = present;
+                battery_low.retain(|device_id| connected.contains(device_id));
+                battery_levels.retain(|device_id, _| connected.contains(device_id));
+                links.retain(|device_id, _| connected.contains(device_id));
+                booms.retain(|device_id, _| connected.contains(device_id));
+                check_links(events, &connected, &mut links);
+                check_booms(events, &connected, &mut booms);
+                listeners.update(&connected);
+                check_batteries(
+                    events,
+                    &connected,
+                    &listeners,
+                    &mut battery_levels,
+                    &mut battery_low,
+                );
+            }
+            if let Some(idle_timeout) = idle_timeout {
+                hyperx::devices().release_idle(idle_timeout);
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn check_links(
+    events: &EventBus,
+    connected: &HashSet<DeviceId>,
+    links: &mut HashMap<DeviceId, bool>,
+) {
+    let device_ids: Vec<DeviceId> = connected.iter().copied().collect();
+    let readings = hyperx::devices().query(&device_ids, |device| {
+        device
+            .has_link_state()
+            .then(|| device.read_headset_link().ok().flatten())
+            .flatten()
+    });
+    for (device_id, linked) in device_ids.into_iter().zip(readings) {
+        let Some(linked) = linked else {
+            continue;
+        };
+        match (links.insert(device_id, linked), linked) {
+            (Some(false), true) => events.publish(DeviceEvent::HeadsetPoweredOn { device_id }),
+            (None | Some(true), false) => {
+                events.publish(DeviceEvent::HeadsetPoweredOff { device_id })
+            }
+            _ => {}
+        }
+    }
+}
+
+fn check_booms(
+    events: &EventBus,
+    connected: &HashSet<DeviceId>,
+    booms: &mut HashMap<DeviceId, BoomPosition>,
+) {
+    let device_ids: Vec<DeviceId> = connected.iter().copied().collect();
+    let readings = hyperx::devices().query(&device_ids, |device| {
+        device
+            .has_boom_position()
+            .then(|| device.read_boom_position().ok().flatten())
+            .flatten()
+    });
+    for (device_id, position) in device_ids.into_iter().zip(readings) {
+        let Some(position) = position else {
+            continue;
+        };
+        if booms.insert(device_id, position) != Some(position) {
+            events.publish(DeviceEvent::BoomMoved {
+                device_id,
+                position,
+            });
+        }
+    }
+}
+
+fn check_batteries(
+    events: &EventBus,
+    connected: &HashSet<DeviceId>,
+    listeners: &InputListeners,
+    levels: &mut HashMap<DeviceId, u8>,
+    battery_low: &mut HashSet<DeviceId>,
+) {
+    for (device_id, percent) in listeners.reports() {
+        if connected.contains(&device_id) {
+            record_battery(events, device_id, percent, levels, battery_low);
+        }
+    }
+    let listened = |device_id: DeviceId| {
+        listeners.is_listening(device_id) && hyperx::devices().get(device_id).pushes_battery()
+    };
+    let device_ids: Vec<DeviceId> = connected
+        .iter()
+        .copied()
+        .filter(|&device_id| !listened(device_id) || !levels.contains_key(&device_id))
+        .collect();
+    let readings = hyperx::devices().query(&device_ids, |device| {
+        device
+            .has_battery()
+            .then(|| device.read_battery().ok().flatten())
+            .flatten()
+    });
+    for (device_id, percent) in device_ids.into_iter().zip(readings) {
+        if let Some(percent) = percent {
+            record_battery(events, device_id, percent, levels, battery_low);
+        }
+    }
+}
+
+fn record_battery(
+    events: &EventBus,
+    device_id: DeviceId,
+    percent: u8,
+    levels: &mut HashMap<DeviceId, u8>,
+    battery_low: &mut HashSet<DeviceId>,
+) {
+    if levels.insert(device_id, percent) != Some(percent) {
+        events.publish(DeviceEvent::BatteryChanged { device_id, percent });
+    }
+    if percent >= LOW_BATTERY_PERCENT {
+        battery_low.remove(&device_id);
+    } else if battery_low.insert(device_id) {
+        events.publish(DeviceEvent::BatteryLow { device_id, percent });
+    }
+}
+
+/// A thread per connected headset that reports its charge or button presses
+/// on its own, reading those reports, see
+/// [`hyperx::Device::input_listener`]. Battery reports are collected for the
+/// next poll; button presses are published right away. A listener that
+/// stopped, e.g. because the headset could not be opened, is not restarted
+/// until the headset reconnects; its battery is polled meanwhile.
+struct InputListeners {
+    running: HashMap<DeviceId, (Arc<AtomicBool>, JoinHandle<()>)>,
+    events: Arc<EventBus>,
+    sender: Sender<(DeviceId, u8)>,
+    reports: Receiver<(DeviceId, u8)>,
+}
+
+impl InputListeners {
+    fn new(events: Arc<EventBus>) -> Self {
+        let (sender, reports) = mpsc::channel();
+        InputListeners {
+            running: HashMap::new(),
+            events,
+            sender,
+            reports,
+        }
+    }
+
+    /// Listen to the headsets among `connected` that send input reports,
+    /// and stop listening to those that are gone.
+    fn update(&mut self, connected: &HashSet<DeviceId>) {
+        self.running.retain(|device_id, (stop, _)| {
+            let keep = connected.contains(device_id);
+            if !keep {
+                stop.store(true, Ordering::Relaxed);
+            }
+            keep
+        });
+        for &device_id in connected {
+            let device = hyperx::devices().get(device_id);
+            if self.running.contains_key(&device_id)
+                || !(device.pushes_battery() || device.pushes_buttons())
+            {
+                continue;
+            }
+            let stop = Arc::new(AtomicBool::new(false));
+            let thread = {
+                let stop = stop.clone();
+                let events = self.events.clone();
+                let sender = self.sender.clone();
+                thread::spawn(move || listen(device_id, &stop, &events, &sender))
+            };
+            self.running.insert(device_id, (stop, thread));
+        }
+    }
+
+    /// Stop every listener and wait for it to exit, at most
+    /// [`LISTEN_TIMEOUT`], so one started next for the same headset does not
+    /// publish its button presses a second time.
+    fn stop_all(&mut self) {
+        for (stop, _) in self.running.values() {
+            stop.store(true, Ordering::Relaxed);
+        }
+        for (device_id, (_, thread)) in self.running.drain() {
+            if thread.join().is_err() {
+                warn!("input listener of {device_id} panicked");
+            }
+        }
+    }
+
+    fn is_listening(&self, device_id: DeviceId) -> bool {
+        self.running
+            .get(&device_id)
+            .is_some_and(|(_, thread)| !thread.is_finished())
+    }
+
+    /// The battery reports received since the last call, oldest first.
+    fn reports(&self) -> Vec<(DeviceId, u8)> {
+        self.reports.try_iter().collect()
+    }
+}
+
+fn listen(
+    device_id: DeviceId,
+    stop: &AtomicBool,
+    events: &EventBus,
+    reports: &Sender<(DeviceId, u8)>,
+) {
+    let listener = match hyperx::devices().get(device_id).input_listener() {
+        Ok(listener) => listener,
+        Err(err) => {
+            debug!(
+                "polling the battery of {device_id}: cannot listen for its input reports: {err}"
+            );
+            return;
+        }
+    };
+    while !stop.load(Ordering::Relaxed) {
+        match listener.next(LISTEN_TIMEOUT) {
+            Ok(Some(InputReport::Battery(percent))) => {
+                if reports.send((device_id, percent)).is_err() {
+                    return;
+                }
+            }
+            Ok(Some(InputReport::Button { button, press })) => {
+                events.publish(DeviceEvent::ButtonPressed {
+                    device_id,
+                    button,
+                    press,
+                });
+            }
+            Ok(Some(InputReport::Dial { steps })) => {
+                events.publish(DeviceEvent::DialTurned { device_id, steps });
+            }
+            Ok(None) => {}
+            // Unplugged; the watcher notices on its next poll.
+            Err(_) => return,
+        }
+    }
+}
+
+fn present_devices() -> Result<HashSet<DeviceId>, hyperx::ControlError> {
+    hyperx::refresh_devices()?;
+    Ok(hyperx::connected_devices()?.into_iter().collect())
+}
@@ -0,0 +1,174 @@
+//! The parts of RFC 6455 shared by the local API's event stream (server) and
+//! the OBS client: framing and the handshake.
+
+use base64::Engine;
+use sha1::{Digest, Sha1};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const MAX_PAYLOAD: u64 = 1024 * 1024;
+const MAX_HEADER_LINES: usize = 64;
+
+pub const OPCODE_TEXT: u8 = 0x1;
+pub const OPCODE_CLOSE: u8 = 0x8;
+pub const OPCODE_PING: u8 = 0x9;
+pub const OPCODE_PONG: u8 = 0xA;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+fn invalid(detail: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, detail.to_string())
+}
+
+/// Open a client connection to `ws://{host}:{port}{path}`. The returned reader
+/// must be used for everything read afterwards since it may already hold the
+/// first frames.
+pub fn connect(
+    host: &str,
+    port: u16,
+    path: &str,
+    protocol: Option<&str>,
+) -> io::Result<(TcpStream, BufReader<TcpStream>)> {
+    let mut stream = TcpStream::connect((host, port))?;
+    let key = base64::engine::general_purpose::STANDARD.encode(
+        [RandomState::new(), RandomState::new()]
+            .map(|state| state.build_hasher().finish().to_be_bytes())
+            .concat(),
+    );
+    let protocol = protocol
+        .map(|protocol| format!("Sec-WebSocket-Protocol: {protocol}\r\n"))
+        .unwrap_or_default();
+    write!(
+        stream,
+        "GET {path} HTTP/1.1\r\nHost: {host}:{port}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {key}\r\nSec-WebSocket-Version: 13\r\n{protocol}\r\n"
+    )?;
+    stream.flush()?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.split_whitespace().nth(1) != Some("101") {
+        return Err(invalid("server refused the WebSocket upgrade"));
+    }
+    let expected = accept_key(&key);
+    let mut accepted = false;
+    for _ in 0..MAX_HEADER_LINES {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            accepted |= name.trim().eq_ignore_ascii_case("sec-websocket-accept")
+                && value.trim() == expected;
+        }
+    }
+    if !accepted {
+        return Err(invalid("server sent a wrong Sec-WebSocket-Accept"));
+    }
+    Ok((stream, reader))
+}
+
+/// Read one frame, unmasking it if the peer masked it. Fragmented messages
+/// are not reassembled; neither side of this app sends them.
+pub fn read_frame(reader: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header)?;
+    let opcode = header[0] & 0x0F;
+    let masked = header[1] & 0x80 != 0;
+    let length = match header[1] & 0x7F {
+        126 => {
+            let mut bytes = [0u8; 2];
+            reader.read_exact(&mut bytes)?;
+            u64::from(u16::from_be_bytes(bytes))
+        }
+        127 => {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes)?;
+            u64::from_be_bytes(bytes)
+        }
+        length => u64::from(length),
+    };
+    if length > MAX_PAYLOAD {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame too large",
+        ));
+    }
+    let mut mask = [0u8; 4];
+    if masked {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; length as usize];
+    reader.read_exact(&mut payload)?;
+    for (index, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[index % 4];
+    }
+    Ok((opcode, payload))
+}
+
+/// Write one unfragmented frame. Clients must mask what they send, servers
+/// must not.
+pub fn write_frame(
+    writer: &mut impl Write,
+    opcode: u8,
+    payload: &[u8],
+    role: Role,
+) -> io::Result<()> {
+    let mask_bit = if role == Role::Client { 0x80 } else { 0 };
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length @ 0..=125 => frame.push(mask_bit | length as u8),
+        length @ 126..=0xFFFF => {
+            frame.push(mask_bit | 126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(mask_bit | 127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    if role == Role::Client {
+        let mask = RandomState::new().build_hasher().finish().to_be_bytes();
+        frame.extend_from_slice(&mask[..4]);
+        frame.extend(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(index, byte)| byte ^ mask[index % 4]),
+        );
+    } else {
+        frame.extend_from_slice(payload);
+    }
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+/// The `Sec-WebSocket-Accept` value answering `key`.
+pub fn accept_key(key: &str) -> String {
+    // SHA-1 is what RFC 6455 mandates; nothing security related relies on it.
+    let digest = Sha1::digest(format!("{key}{HANDSHAKE_GUID}").as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_key_matches_rfc_6455() {
+        // The example handshake of RFC 6455, section 1.3.
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+}